@@ -45,11 +45,13 @@ impl super::View for CodeEditor {
             ui.add(crate::egui_github_link_file!());
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Language:");
+            ui.text_edit_singleline(language)
+                .on_hover_text("G-code (gcode, nc, ngc) is always highlighted");
+        });
+
         if cfg!(feature = "syntect") {
-            ui.horizontal(|ui| {
-                ui.label("Language:");
-                ui.text_edit_singleline(language);
-            });
             ui.horizontal_wrapped(|ui| {
                 ui.spacing_mut().item_spacing.x = 0.0;
                 ui.label("Syntax highlighting powered by ");
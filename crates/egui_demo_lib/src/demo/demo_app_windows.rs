@@ -22,6 +22,7 @@ impl Default for Demos {
             Box::<super::images::Images>::default(),
             Box::<super::toolpath::Toolpath>::default(),
             Box::<super::postprocess::Postprocess>::default(),
+            Box::<super::gcode::Gcode>::default(),
             Box::<super::configuration::Configuration>::default(),
             //Box::<super::cad::Cad>::default(),
             //Box::<super::voxels::Voxels>::default(),
@@ -0,0 +1,328 @@
+//! Splits arc moves into straight segments for controllers and views that only know lines, and
+//! fits arcs back onto runs of short segments for controllers that do.
+//!
+//! Segments are as long as they can be while staying within a chord tolerance, the largest
+//! distance allowed between a segment and the true arc. Helical arcs and the extruder move evenly
+//! along the segments.
+
+use super::gcode_interpreter::{Move, MoveKind, E};
+
+/// The moves with every arc replaced by straight [`MoveKind::Linear`] segments.
+pub fn linearize(moves: &[Move], tolerance: f32) -> Vec<Move> {
+    let mut out = Vec::with_capacity(moves.len());
+    for motion in moves {
+        linearize_move(motion, tolerance, &mut out);
+    }
+    out
+}
+
+/// Appends the segments for one move, or the move itself when it is not an arc.
+pub fn linearize_move(motion: &Move, tolerance: f32, out: &mut Vec<Move>) {
+    let MoveKind::Arc {
+        center,
+        clockwise,
+        plane,
+        ..
+    } = motion.kind
+    else {
+        out.push(motion.clone());
+        return;
+    };
+
+    let [a, b, normal] = plane.axes();
+    let (start, end) = (motion.start, motion.end);
+    let start_radius = (start[a] - center[a]).hypot(start[b] - center[b]);
+    let end_radius = (end[a] - center[a]).hypot(end[b] - center[b]);
+    let sweep = motion.sweep();
+
+    // A chord spanning `angle` strays `radius * (1 - cos(angle / 2))` from the arc.
+    let radius = start_radius.max(end_radius);
+    let max_angle = if tolerance > 0.0 && tolerance < radius {
+        2.0 * (1.0 - tolerance / radius).acos()
+    } else {
+        std::f32::consts::FRAC_PI_2
+    };
+    let segments = ((sweep / max_angle).ceil() as usize).max(1);
+
+    let start_angle = (start[b] - center[b]).atan2(start[a] - center[a]);
+    let direction = if clockwise { -1.0 } else { 1.0 };
+    let mut previous = start;
+    for segment in 1..=segments {
+        let point = if segment == segments {
+            end
+        } else {
+            // Radius, height and extrusion blend evenly from start to end.
+            let t = segment as f32 / segments as f32;
+            let angle = start_angle + direction * sweep * t;
+            let radius = start_radius + (end_radius - start_radius) * t;
+            let mut point = start;
+            point[a] = center[a] + radius * angle.cos();
+            point[b] = center[b] + radius * angle.sin();
+            point[normal] = start[normal] + (end[normal] - start[normal]) * t;
+            point[E] = start[E] + (end[E] - start[E]) * t;
+            point
+        };
+        out.push(Move {
+            kind: MoveKind::Linear,
+            start: previous,
+            end: point,
+            ..motion.clone()
+        });
+        previous = point;
+    }
+}
+
+/// Fewest segments worth replacing with an arc.
+const MIN_ARC_SEGMENTS: usize = 3;
+
+/// A piece of a polyline from [`fit_arcs`], ending at the point with index `end`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Piece {
+    Line {
+        end: usize,
+    },
+    Arc {
+        end: usize,
+        center: [f32; 2],
+        clockwise: bool,
+    },
+}
+
+impl Piece {
+    pub fn end(&self) -> usize {
+        match *self {
+            Piece::Line { end } | Piece::Arc { end, .. } => end,
+        }
+    }
+}
+
+/// Covers a polyline with lines and arcs, replacing each run of at least three segments whose
+/// points and chords all lie within `tolerance` of one circle with a single arc.
+///
+/// Runs are grown greedily from the start. Runs that are straight within the tolerance stay
+/// lines. A run that fails to fit is taken to stay unfit as it grows, so each start gallops to
+/// the first run that fails and searches back for the longest that fits, checking `O(log n)`
+/// runs instead of every one.
+pub fn fit_arcs(points: &[[f32; 2]], tolerance: f32) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut start = 0;
+    while start + 1 < points.len() {
+        let fit = |end: usize| fit_run(&points[start..=end], tolerance);
+        let last = points.len() - 1;
+
+        // Runs up to `fits` fit, the run up to `fails` does not.
+        let mut fits = None;
+        let mut fails = None;
+        let mut end = start + MIN_ARC_SEGMENTS;
+        while end <= last {
+            if fit(end).is_none() {
+                fails = Some(end);
+                break;
+            }
+            fits = Some(end);
+            end = start + 2 * (end - start);
+        }
+        if fails.is_none() && fits.map_or(last >= start + MIN_ARC_SEGMENTS, |fits| fits < last) {
+            if fit(last).is_some() {
+                fits = Some(last);
+            } else {
+                fails = Some(last);
+            }
+        }
+        if let (Some(mut low), Some(mut high)) = (fits, fails) {
+            while high - low > 1 {
+                let middle = (low + high) / 2;
+                if fit(middle).is_some() {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            fits = Some(low);
+        }
+
+        match fits.and_then(|end| Some((end, fit(end)?))) {
+            Some((end, Fit::Arc { center, clockwise })) => {
+                pieces.push(Piece::Arc {
+                    end,
+                    center,
+                    clockwise,
+                });
+                start = end;
+            }
+            // Straight all the way: no arc starts before its last segment.
+            Some((end, Fit::Straight)) => {
+                pieces.extend((start + 1..end).map(|end| Piece::Line { end }));
+                start = end - 1;
+            }
+            None => {
+                pieces.push(Piece::Line { end: start + 1 });
+                start += 1;
+            }
+        }
+    }
+    pieces
+}
+
+/// How a run of points fits, when it does.
+enum Fit {
+    /// Straight so far: it may still bend into an arc further on.
+    Straight,
+    Arc {
+        center: [f32; 2],
+        clockwise: bool,
+    },
+}
+
+fn fit_run(points: &[[f32; 2]], tolerance: f32) -> Option<Fit> {
+    if is_straight(points, tolerance) {
+        Some(Fit::Straight)
+    } else {
+        arc_through(points, tolerance).map(|(center, clockwise)| Fit::Arc { center, clockwise })
+    }
+}
+
+/// Whether every point is within `tolerance` of the line through the first and the last.
+fn is_straight(points: &[[f32; 2]], tolerance: f32) -> bool {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let chord = [last[0] - first[0], last[1] - first[1]];
+    let length = chord[0].hypot(chord[1]);
+    points.iter().all(|p| {
+        let offset = [p[0] - first[0], p[1] - first[1]];
+        let across = if length > 0.0 {
+            (chord[0] * offset[1] - chord[1] * offset[0]) / length
+        } else {
+            offset[0].hypot(offset[1])
+        };
+        across.abs() <= tolerance
+    })
+}
+
+/// Centre and direction of the arc from the first to the last point that passes within
+/// `tolerance` of every point and chord, turning one way by less than a full circle.
+fn arc_through(points: &[[f32; 2]], tolerance: f32) -> Option<([f32; 2], bool)> {
+    // Work relative to the first point to keep the precision of small segments.
+    let origin = points[0];
+    let local = |p: [f32; 2]| [p[0] - origin[0], p[1] - origin[1]];
+    let cross = |a: [f32; 2], b: [f32; 2]| a[0] * b[1] - a[1] * b[0];
+    let (middle, last) = (
+        local(points[points.len() / 2]),
+        local(points[points.len() - 1]),
+    );
+
+    // Circle through the origin, the middle and the last point.
+    let determinant = 2.0 * cross(middle, last);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let (m2, l2) = (
+        middle[0] * middle[0] + middle[1] * middle[1],
+        last[0] * last[0] + last[1] * last[1],
+    );
+    let center = [
+        (m2 * last[1] - l2 * middle[1]) / determinant,
+        (l2 * middle[0] - m2 * last[0]) / determinant,
+    ];
+    let radius = center[0].hypot(center[1]);
+    let clockwise = determinant < 0.0;
+    let off_circle = |p: [f32; 2]| ((p[0] - center[0]).hypot(p[1] - center[1]) - radius).abs();
+
+    let mut swept = 0.0;
+    for pair in points.windows(2).filter(|pair| pair[0] != pair[1]) {
+        let (a, b) = (local(pair[0]), local(pair[1]));
+        let mid = [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+        if off_circle(b) > tolerance || off_circle(mid) > tolerance {
+            return None;
+        }
+        let (ra, rb) = (
+            [a[0] - center[0], a[1] - center[1]],
+            [b[0] - center[0], b[1] - center[1]],
+        );
+        let turn = cross(ra, rb).atan2(ra[0] * rb[0] + ra[1] * rb[1]);
+        let turn = if clockwise { -turn } else { turn };
+        if turn <= 0.0 {
+            return None;
+        }
+        swept += turn;
+    }
+    (swept < std::f32::consts::TAU - 1e-3)
+        .then_some([center[0] + origin[0], center[1] + origin[1]])
+        .map(|center| (center, clockwise))
+}
+
+#[test]
+fn test_gcode_arc() {
+    use super::gcode_interpreter::{Interpreter, X, Y, Z};
+    use super::gcode_parser::parse;
+
+    let tolerance = 0.01;
+    let blocks = parse(
+        "G0 X10 Y0\n\
+         G2 X0 Y-10 R10 F100\n\
+         G3 X0 Y-10 I0 J10 Z5\n\
+         G18 G2 X0 Z5 I5 K0 P2\n\
+         G17 G3 X10 Y0 R-10",
+    )
+    .unwrap();
+    let moves = Interpreter::default().run(&blocks);
+    let lines = linearize(&moves, tolerance);
+    assert_eq!(lines[0].kind, MoveKind::Rapid);
+    assert!(lines[1..].iter().all(|line| line.kind == MoveKind::Linear));
+    for pair in lines.windows(2) {
+        assert_eq!(pair[0].end, pair[1].start);
+    }
+
+    let arcs: Vec<_> = moves.iter().skip(1).collect();
+    let segments = |index: usize| lines.iter().filter(move |line| line.line == index + 2);
+
+    // Quarter circle clockwise around the origin, every chord midpoint within tolerance.
+    for line in segments(0) {
+        let mid = [
+            (line.start[X] + line.end[X]) / 2.0,
+            (line.start[Y] + line.end[Y]) / 2.0,
+        ];
+        let error = 10.0 - mid[X].hypot(mid[Y]);
+        assert!(error >= -1e-3 && error <= tolerance + 1e-3, "{}", error);
+        assert!(line.end[X] >= -1e-3 && line.end[Y] <= 1e-3);
+    }
+
+    // Full helical circle, rising evenly.
+    let helix: Vec<_> = segments(1).collect();
+    assert!((arcs[1].sweep() - std::f32::consts::TAU).abs() < 1e-4);
+    assert_eq!(helix.last().unwrap().end[Z], 5.0);
+    let half = helix.len() / 2;
+    assert!((helix[half - 1].end[Z] - 2.5).abs() < 0.1);
+
+    // Two turns in the ZX plane stay in that plane.
+    assert!((arcs[2].sweep() - 2.0 * std::f32::consts::TAU).abs() < 1e-4);
+    assert!(segments(2).all(|line| line.end[Y] == -10.0));
+
+    // A negative radius takes the long way round: three quarters of a circle.
+    assert!((arcs[3].sweep() - 1.5 * std::f32::consts::PI).abs() < 1e-4);
+    assert!(segments(3).any(|line| line.end[Y] < -19.9));
+
+    // A half circle of short segments becomes one arc, and a long straight run stays lines
+    // without checking every run that starts along it.
+    let half_circle: Vec<_> = (0..=90)
+        .map(|i| {
+            let angle = std::f32::consts::PI * i as f32 / 90.0;
+            [10.0 * angle.cos(), 10.0 * angle.sin()]
+        })
+        .collect();
+    let pieces = fit_arcs(&half_circle, tolerance);
+    assert_eq!(pieces.len(), 1);
+    assert!(matches!(
+        pieces[0],
+        Piece::Arc {
+            end: 90,
+            clockwise: false,
+            ..
+        }
+    ));
+    let straight: Vec<_> = (0..=20_000).map(|i| [i as f32 * 0.1, 0.0]).collect();
+    let pieces = fit_arcs(&straight, tolerance);
+    assert_eq!(pieces.len(), 20_000);
+    assert!(pieces
+        .iter()
+        .all(|piece| matches!(piece, Piece::Line { .. })));
+}
@@ -0,0 +1,392 @@
+//! Cutter radius compensation: runs the tool beside the programmed contour instead of on it.
+//!
+//! Moves made under `G41`/`G42` are joined into one polyline and offset by the tool radius with
+//! `cavalier_contours`, the same way `shrink_toolpath` offsets drawings. The first of those moves
+//! is the entry: it starts where the tool is and ends on the offset path. The first move after
+//! `G40` is the exit, leaving the offset path for its programmed end point. A contour that ends
+//! where its entry move ended is closed, and the offset path goes all the way round.
+//!
+//! Compensation only works in the XY plane. An inside arc tighter than the tool, or an offset
+//! that loses the start or end of the path, would cut into the part and is reported as a gouge.
+
+use std::f64::consts::PI;
+use std::fmt;
+
+use cavalier_contours::polyline::{PlineSource, PlineVertex, Polyline};
+
+use super::gcode_interpreter::{Compensation, Move, MoveKind, Plane, Position, X, Y};
+
+/// Distance in mm below which two points are the same.
+const EPSILON: f64 = 1e-3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompensationError {
+    /// Source line of the move where compensation fails.
+    pub line: usize,
+
+    pub kind: CompensationErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompensationErrorKind {
+    /// The tool does not fit the path here and would cut into the part.
+    Gouge,
+
+    /// An arc outside the XY plane with compensation on.
+    Plane,
+}
+
+impl fmt::Display for CompensationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.line)?;
+        match self.kind {
+            CompensationErrorKind::Gouge => write!(f, "the tool is too big for the path here"),
+            CompensationErrorKind::Plane => {
+                write!(f, "cutter compensation only works in the XY plane")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompensationError {}
+
+/// The moves with cutter compensation applied, all with [`Compensation::Off`].
+pub fn compensate(moves: &[Move]) -> Result<Vec<Move>, CompensationError> {
+    let mut out = Vec::with_capacity(moves.len());
+    let mut index = 0;
+    while index < moves.len() {
+        let motion = &moves[index];
+        // Positive offsets go to the left of the path.
+        let offset = match motion.compensation {
+            Compensation::Off => 0.0,
+            Compensation::Left { radius } => radius as f64,
+            Compensation::Right { radius } => -radius as f64,
+        };
+        if offset != 0.0 {
+            if let MoveKind::Arc { plane, .. } = motion.kind {
+                if plane != Plane::XY {
+                    return Err(CompensationError {
+                        line: motion.line,
+                        kind: CompensationErrorKind::Plane,
+                    });
+                }
+            }
+        }
+        if offset == 0.0 || !is_contour(motion) {
+            push(&mut out, motion.clone());
+            index += 1;
+            continue;
+        }
+
+        let end = index
+            + moves[index..]
+                .iter()
+                .take_while(|other| other.compensation == motion.compensation && is_contour(other))
+                .count();
+        offset_run(&moves[index..end], offset, &mut out)?;
+        index = end;
+    }
+    Ok(out)
+}
+
+/// Moves in the XY plane that the offset path follows.
+fn is_contour(motion: &Move) -> bool {
+    let planar = match motion.kind {
+        MoveKind::Rapid | MoveKind::Linear => true,
+        MoveKind::Arc { plane, .. } => plane == Plane::XY,
+        _ => false,
+    };
+    planar && distance(point(motion.start), point(motion.end)) > EPSILON
+}
+
+/// Appends a move, starting it where the tool is. Moves with no XY motion stay there.
+fn push(out: &mut Vec<Move>, mut motion: Move) {
+    if let Some(previous) = out.last() {
+        let still = distance(point(motion.start), point(motion.end)) <= EPSILON;
+        for axis in [X, Y] {
+            motion.start[axis] = previous.end[axis];
+            if still {
+                motion.end[axis] = previous.end[axis];
+            }
+        }
+    }
+    motion.compensation = Compensation::Off;
+    out.push(motion);
+}
+
+fn point(position: Position) -> [f64; 2] {
+    [position[X] as f64, position[Y] as f64]
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (b[0] - a[0]).hypot(b[1] - a[1])
+}
+
+/// Where the offset path passes `at`, the start or end of the move: `offset` to the left of the
+/// direction of travel there.
+fn offset_point(motion: &Move, at: Position, offset: f64) -> [f64; 2] {
+    let p = point(at);
+    let tangent = match motion.kind {
+        MoveKind::Arc {
+            center, clockwise, ..
+        } => {
+            let c = point(center);
+            let (rx, ry) = (p[0] - c[0], p[1] - c[1]);
+            if clockwise {
+                [ry, -rx]
+            } else {
+                [-ry, rx]
+            }
+        }
+        _ => {
+            let (s, e) = (point(motion.start), point(motion.end));
+            [e[0] - s[0], e[1] - s[1]]
+        }
+    };
+    let length = tangent[0].hypot(tangent[1]);
+    [
+        p[0] - tangent[1] / length * offset,
+        p[1] + tangent[0] / length * offset,
+    ]
+}
+
+/// Adds the polyline vertices for one move: its start and as many bulges as an arc needs.
+fn add_vertices(polyline: &mut Polyline<f64>, motion: &Move) {
+    let start = point(motion.start);
+    let MoveKind::Arc {
+        center, clockwise, ..
+    } = motion.kind
+    else {
+        polyline.vertex_data.push(PlineVertex {
+            x: start[0],
+            y: start[1],
+            bulge: 0.0,
+        });
+        return;
+    };
+
+    // Bulges grow without bound towards a full circle, so arcs go in pieces of at most half.
+    let c = point(center);
+    let radius = distance(start, c);
+    let start_angle = (start[1] - c[1]).atan2(start[0] - c[0]);
+    let sweep = (motion.sweep() as f64).min(2.0 * PI);
+    let pieces = (sweep / PI).ceil().max(1.0);
+    let step = if clockwise { -sweep } else { sweep } / pieces;
+    for piece in 0..pieces as usize {
+        let angle = start_angle + step * piece as f64;
+        polyline.vertex_data.push(PlineVertex {
+            x: c[0] + radius * angle.cos(),
+            y: c[1] + radius * angle.sin(),
+            bulge: (step / 4.0).tan(),
+        });
+    }
+}
+
+/// An inside arc smaller than the tool cannot be followed.
+fn check_arcs(run: &[Move], offset: f64) -> Result<(), CompensationError> {
+    for motion in run {
+        if let MoveKind::Arc {
+            center, clockwise, ..
+        } = motion.kind
+        {
+            let inside = (offset > 0.0) != clockwise;
+            if inside && distance(point(motion.start), point(center)) < offset.abs() - EPSILON {
+                return Err(CompensationError {
+                    line: motion.line,
+                    kind: CompensationErrorKind::Gouge,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Offsets one run of compensated moves, entry move first, and appends the result.
+fn offset_run(run: &[Move], offset: f64, out: &mut Vec<Move>) -> Result<(), CompensationError> {
+    let gouge = |motion: &Move| CompensationError {
+        line: motion.line,
+        kind: CompensationErrorKind::Gouge,
+    };
+    check_arcs(run, offset)?;
+
+    let first = &run[0];
+    let last = &run[run.len() - 1];
+    let closed = run.len() > 2 && distance(point(first.end), point(last.end)) < EPSILON;
+    // The entry move leads into a closed contour rather than being part of it.
+    let contour = if closed { &run[1..] } else { run };
+
+    let mut polyline = Polyline {
+        vertex_data: Vec::new(),
+        is_closed: closed,
+    };
+    for motion in contour {
+        add_vertices(&mut polyline, motion);
+    }
+    if !closed {
+        let end = point(last.end);
+        polyline.vertex_data.push(PlineVertex {
+            x: end[0],
+            y: end[1],
+            bulge: 0.0,
+        });
+    }
+
+    let mut offsets = polyline.parallel_offset(offset);
+    if offsets.len() != 1 {
+        return Err(gouge(first));
+    }
+    let mut path = offsets.remove(0).vertex_data;
+    if path.is_empty() {
+        return Err(gouge(first));
+    }
+
+    let start = offset_point(&contour[0], contour[0].start, offset);
+    let vertex = |vertex: &PlineVertex<f64>| [vertex.x, vertex.y];
+    if closed {
+        // Start going round from the corner nearest the start of the contour, and come back
+        // to it at the end.
+        let nearest = (0..path.len())
+            .min_by(|&a, &b| {
+                let a = distance(vertex(&path[a]), start);
+                let b = distance(vertex(&path[b]), start);
+                a.total_cmp(&b)
+            })
+            .unwrap_or(0);
+        path.rotate_left(nearest);
+        let mut end = path[0];
+        end.bulge = 0.0;
+        path.push(end);
+    } else {
+        let end = offset_point(last, last.end, offset);
+        if distance(vertex(&path[0]), start) > EPSILON {
+            return Err(gouge(first));
+        }
+        if distance(vertex(&path[path.len() - 1]), end) > EPSILON {
+            return Err(gouge(last));
+        }
+    }
+
+    // Straight in from wherever the tool is.
+    let here = out
+        .last()
+        .map_or(first.start, |previous: &Move| previous.end);
+    if closed {
+        let mut entry = first.clone();
+        entry.kind = MoveKind::Linear;
+        entry.end[X] = path[0].x as f32;
+        entry.end[Y] = path[0].y as f32;
+        push(out, entry);
+    } else {
+        path[0].x = here[X] as f64;
+        path[0].y = here[Y] as f64;
+        path[0].bulge = 0.0;
+    }
+
+    for pair in path.windows(2) {
+        let (a, b) = (vertex(&pair[0]), vertex(&pair[1]));
+        if distance(a, b) <= EPSILON {
+            continue;
+        }
+        // The source move the segment lies beside lends it its line, Z and feedrate.
+        let middle = [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+        let source = contour
+            .iter()
+            .min_by(|m, n| {
+                let m = distance_to_chord(middle, m);
+                let n = distance_to_chord(middle, n);
+                m.total_cmp(&n)
+            })
+            .unwrap_or(first);
+
+        let mut motion = source.clone();
+        motion.end[X] = b[0] as f32;
+        motion.end[Y] = b[1] as f32;
+        let bulge = pair[0].bulge;
+        if bulge != 0.0 {
+            // The centre lies off the middle of the chord, to the left for positive bulges.
+            let chord = distance(a, b);
+            let h = chord * (1.0 - bulge * bulge) / (4.0 * bulge);
+            let mut center = motion.end;
+            center[X] = (middle[0] - (b[1] - a[1]) / chord * h) as f32;
+            center[Y] = (middle[1] + (b[0] - a[0]) / chord * h) as f32;
+            motion.kind = MoveKind::Arc {
+                center,
+                clockwise: bulge < 0.0,
+                plane: Plane::XY,
+                turns: 0,
+            };
+        } else if motion.kind != MoveKind::Rapid {
+            motion.kind = MoveKind::Linear;
+        }
+        push(out, motion);
+    }
+    Ok(())
+}
+
+fn distance_to_chord(p: [f64; 2], motion: &Move) -> f64 {
+    let (a, b) = (point(motion.start), point(motion.end));
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 {
+        (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    distance(p, [a[0] + t * dx, a[1] + t * dy])
+}
+
+#[test]
+fn test_gcode_compensation() {
+    use super::gcode_interpreter::Interpreter;
+    use super::gcode_parser::parse;
+
+    let run = |program: &str| compensate(&Interpreter::default().run(&parse(program).unwrap()));
+
+    // A 4 mm cutter inside a square traced counter-clockwise, entering from outside.
+    let moves = run("G0 X-5 Y-5\n\
+         G41.1 D4\n\
+         G1 X0 Y0 F100\n\
+         X20\n\
+         Y20\n\
+         X0\n\
+         Y0\n\
+         G40\n\
+         G1 X-5 Y-5")
+    .unwrap();
+    let ends: Vec<_> = moves
+        .iter()
+        .map(|motion| [motion.end[X], motion.end[Y]])
+        .collect();
+    assert_eq!(
+        ends,
+        vec![
+            [-5.0, -5.0],
+            [2.0, 2.0],
+            [18.0, 2.0],
+            [18.0, 18.0],
+            [2.0, 18.0],
+            [2.0, 2.0],
+            [-5.0, -5.0]
+        ]
+    );
+    assert!(moves.windows(2).all(|pair| pair[0].end == pair[1].start));
+    assert!(moves
+        .iter()
+        .all(|motion| motion.compensation == Compensation::Off));
+
+    // A 1 mm inside arc cannot take a 2 mm radius tool.
+    let error = run("G10 L1 P1 R2\n\
+         T1 M6\n\
+         G41\n\
+         G1 X10\n\
+         G3 X11 Y1 I0 J1\n\
+         G40")
+    .unwrap_err();
+    assert_eq!(
+        error,
+        CompensationError {
+            line: 5,
+            kind: CompensationErrorKind::Gouge
+        }
+    );
+}
@@ -0,0 +1,676 @@
+//! What each firmware accepts, and rewriting programs from one firmware for another.
+//!
+//! [`GCode`] holds codes from several firmwares at once, such as `M593` input shaping from
+//! RepRapFirmware and the `G81`–`G89` drilling cycles from LinuxCNC. A [`Dialect`] knows which
+//! codes one target accepts and which parameters they take, and [`validate`] reports everything
+//! else. Marlin leaves many codes out of the build unless they are enabled, and those are
+//! warnings naming the option.
+//!
+//! Prusa firmware's `G81`–`G88` mesh bed leveling and babystepping codes are not modeled: they
+//! parse as the drilling cycles, and Marlin reports them as unknown.
+//!
+//! [`translate`] rewrites what differs between firmwares but has an equivalent:
+//!
+//! - `G4 P` is milliseconds on Marlin and RepRapFirmware but seconds on GRBL and LinuxCNC;
+//! - canned cycles become the rapids, feeds and dwells they stand for;
+//! - `M2`/`M30` end a CNC program, but `M30` deletes a file on Marlin;
+//! - `M400` waits for moves to finish, which `G4 P0` does on GRBL and LinuxCNC.
+//!
+//! Whatever is left that the target does not accept, such as temperatures for a mill, is reported
+//! by validating the result.
+
+use std::fmt;
+
+use super::gcode_emitter::{emit, EmitOptions};
+use super::gcode_interpreter::{self, Distance, Interpreter, MoveKind, Position, X, Y, Z};
+use super::gcode_lint::{Diagnostic, Severity};
+use super::gcode_parser::Block;
+use super::GCode;
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Dialect {
+    #[default]
+    Marlin,
+    RepRapFirmware,
+    Grbl,
+    LinuxCnc,
+}
+
+impl Dialect {
+    pub const ALL: [Dialect; 4] = [
+        Dialect::Marlin,
+        Dialect::RepRapFirmware,
+        Dialect::Grbl,
+        Dialect::LinuxCnc,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dialect::Marlin => "Marlin",
+            Dialect::RepRapFirmware => "RepRapFirmware",
+            Dialect::Grbl => "GRBL",
+            Dialect::LinuxCnc => "LinuxCNC",
+        }
+    }
+
+    /// Machine controllers rather than printer firmware: `G4 P` is in seconds and there is no
+    /// extruder.
+    pub fn is_cnc(self) -> bool {
+        matches!(self, Dialect::Grbl | Dialect::LinuxCnc)
+    }
+
+    /// Whether the dialect has the `G81`–`G89` drilling cycles.
+    pub fn has_canned_cycles(self) -> bool {
+        self == Dialect::LinuxCnc
+    }
+
+    fn profile(self) -> &'static Profile {
+        match self {
+            Dialect::Marlin => &MARLIN,
+            Dialect::RepRapFirmware => &REPRAPFIRMWARE,
+            Dialect::Grbl => &GRBL,
+            Dialect::LinuxCnc => &LINUXCNC,
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The codes one firmware accepts, by the command word they are written with.
+struct Profile {
+    /// Commands with every parameter letter they take.
+    codes: &'static [(&'static str, &'static str)],
+
+    /// Commands whose parameters are not checked, separated by spaces.
+    any: &'static str,
+
+    /// Commands that are only there when the firmware was built with an option.
+    optional: &'static [(&'static str, &'static str)],
+}
+
+impl Profile {
+    /// The parameter letters a command takes, `Some("")` for none and `Some("*")` for any, or
+    /// `None` if the firmware does not know it.
+    fn parameters(&self, command: &str) -> Option<&'static str> {
+        self.codes
+            .iter()
+            .find(|(known, _)| *known == command)
+            .map(|(_, letters)| *letters)
+            .or_else(|| {
+                self.any
+                    .split(' ')
+                    .any(|known| known == command)
+                    .then_some("*")
+            })
+    }
+
+    fn option(&self, command: &str) -> Option<&'static str> {
+        self.optional
+            .iter()
+            .find(|(known, _)| *known == command)
+            .map(|(_, option)| *option)
+    }
+}
+
+const MARLIN: Profile = Profile {
+    codes: &[
+        ("G0", "XYZEFS"),
+        ("G1", "XYZEFS"),
+        ("G2", "XYZIJKRPEFS"),
+        ("G3", "XYZIJKRPEFS"),
+        ("G4", "PS"),
+        ("G10", "S"),
+        ("G11", ""),
+        ("G17", ""),
+        ("G18", ""),
+        ("G19", ""),
+        ("G20", ""),
+        ("G21", ""),
+        ("G28", "XYZLOR"),
+        ("G38.2", "XYZF"),
+        ("G38.3", "XYZF"),
+        ("G38.4", "XYZF"),
+        ("G38.5", "XYZF"),
+        ("G53", ""),
+        ("G54", ""),
+        ("G55", ""),
+        ("G56", ""),
+        ("G57", ""),
+        ("G58", ""),
+        ("G59", ""),
+        ("G80", ""),
+        ("G90", ""),
+        ("G91", ""),
+        ("G92", "XYZE"),
+        ("M0", "PS"),
+        ("M1", "PS"),
+        ("M3", "SOI"),
+        ("M4", "SOI"),
+        ("M5", ""),
+        ("M7", ""),
+        ("M8", ""),
+        ("M9", ""),
+        ("M10", ""),
+        ("M11", ""),
+        ("M17", "XYZE"),
+        ("M18", "XYZES"),
+        ("M82", ""),
+        ("M83", ""),
+        ("M84", "XYZES"),
+        ("M400", ""),
+        ("T", ""),
+    ],
+    any: "G6 G12 G26 G27 G29 G30 G31 G32 G33 G34 G42 G60 G76 G425 M16 M20 M21 M22 M23 M24 M25 \
+          M26 M27 M28 M29 M30 M31 M32 M33 M34 M42 M43 M48 M73 M75 M76 M80 M81 M85 M86 M87 M92 \
+          M104 M105 M106 M107 M108 M109 M110 M111 M112 M113 M114 M115 M117 M118 M119 M120 M121 \
+          M122 M126 M127 M128 M129 M140 M141 M143 M149 M150 M155 M163 M164 M165 M190 M191 M200 \
+          M201 M203 M204 M205 M206 M207 M208 M209 M211 M217 M218 M220 M221 M226 M240 M250 M256 \
+          M260 M261 M280 M281 M290 M300 M301 M302 M303 M304 M305 M306 M350 M351 M355 M360 M361 \
+          M362 M363 M364 M380 M381 M401 M402 M403 M404 M405 M406 M407 M410 M412 M413 M420 M421 \
+          M422 M423 M425 M486 M493 M500 M501 M502 M503 M504 M510 M511 M512 M524 M540 M569 M575 \
+          M592 M593",
+    optional: &[
+        ("G10", "FWRETRACT"),
+        ("G11", "FWRETRACT"),
+        ("G12", "NOZZLE_CLEAN_FEATURE"),
+        ("G17", "CNC_WORKSPACE_PLANES"),
+        ("G18", "CNC_WORKSPACE_PLANES"),
+        ("G19", "CNC_WORKSPACE_PLANES"),
+        ("G20", "INCH_MODE_SUPPORT"),
+        ("G21", "INCH_MODE_SUPPORT"),
+        ("G26", "G26_MESH_VALIDATION"),
+        ("G27", "NOZZLE_PARK_FEATURE"),
+        ("G33", "DELTA_AUTO_CALIBRATION"),
+        ("G34", "Z_STEPPER_AUTO_ALIGN"),
+        ("G38.2", "G38_PROBE_TARGET"),
+        ("G38.3", "G38_PROBE_TARGET"),
+        ("G38.4", "G38_PROBE_TARGET"),
+        ("G38.5", "G38_PROBE_TARGET"),
+        ("G53", "CNC_COORDINATE_SYSTEMS"),
+        ("G54", "CNC_COORDINATE_SYSTEMS"),
+        ("G55", "CNC_COORDINATE_SYSTEMS"),
+        ("G56", "CNC_COORDINATE_SYSTEMS"),
+        ("G57", "CNC_COORDINATE_SYSTEMS"),
+        ("G58", "CNC_COORDINATE_SYSTEMS"),
+        ("G59", "CNC_COORDINATE_SYSTEMS"),
+        ("G80", "GCODE_MOTION_MODES"),
+        ("M3", "SPINDLE_FEATURE or LASER_FEATURE"),
+        ("M4", "SPINDLE_FEATURE or LASER_FEATURE"),
+        ("M5", "SPINDLE_FEATURE or LASER_FEATURE"),
+        ("M7", "COOLANT_MIST"),
+        ("M8", "COOLANT_FLOOD"),
+        ("M9", "COOLANT_CONTROL"),
+        ("M10", "AIR_EVACUATION"),
+        ("M11", "AIR_EVACUATION"),
+        ("M493", "FT_MOTION"),
+        ("M593", "INPUT_SHAPING_X or INPUT_SHAPING_Y"),
+    ],
+};
+
+const REPRAPFIRMWARE: Profile = Profile {
+    codes: &[
+        ("G0", "XYZEFHR"),
+        ("G1", "XYZEFHRS"),
+        ("G2", "XYZIJKREF"),
+        ("G3", "XYZIJKREF"),
+        ("G4", "PS"),
+        ("G17", ""),
+        ("G18", ""),
+        ("G19", ""),
+        ("G20", ""),
+        ("G21", ""),
+        ("G28", "XYZ"),
+        ("G38.2", "XYZFK"),
+        ("G38.3", "XYZFK"),
+        ("G38.4", "XYZFK"),
+        ("G38.5", "XYZFK"),
+        ("G53", ""),
+        ("G54", ""),
+        ("G55", ""),
+        ("G56", ""),
+        ("G57", ""),
+        ("G58", ""),
+        ("G59", ""),
+        ("G60", "S"),
+        ("G90", ""),
+        ("G91", ""),
+        ("G92", "XYZE"),
+        ("M0", "HS"),
+        ("M1", "HS"),
+        ("M3", "SPR"),
+        ("M4", "SPR"),
+        ("M5", "P"),
+        ("M82", ""),
+        ("M83", ""),
+        ("M400", ""),
+        ("T", ""),
+    ],
+    any: "G10 G11 G29 G30 G31 G32 G68 G69 M17 M18 M20 M21 M22 M23 M24 M25 M26 M27 M28 M29 M30 \
+          M32 M36 M37 M38 M39 M42 M73 M80 M81 M84 M92 M98 M99 M104 M106 M107 M108 M109 M110 M111 \
+          M112 M114 M115 M116 M117 M118 M119 M120 M121 M122 M140 M141 M143 M144 M150 M190 M191 \
+          M200 M201 M203 M204 M205 M206 M207 M208 M220 M221 M226 M260 M261 M280 M290 M292 M300 \
+          M301 M302 M303 M304 M305 M307 M308 M309 M350 M374 M375 M376 M401 M402 M404 M408 M409 \
+          M450 M451 M452 M453 M470 M471 M472 M486 M500 M501 M502 M503 M505 M540 M550 M551 M552 \
+          M553 M554 M555 M556 M557 M558 M559 M560 M561 M562 M563 M564 M566 M567 M568 M569 M570 \
+          M571 M572 M573 M574 M575 M577 M578 M579 M580 M581 M582 M584 M585 M586 M587 M588 M589 \
+          M591 M592 M593 M594 M595 M596",
+    optional: &[],
+};
+
+const GRBL: Profile = Profile {
+    codes: &[
+        ("G0", "XYZF"),
+        ("G1", "XYZF"),
+        ("G2", "XYZIJKRF"),
+        ("G3", "XYZIJKRF"),
+        ("G4", "P"),
+        ("G10", "LPXYZ"),
+        ("G17", ""),
+        ("G18", ""),
+        ("G19", ""),
+        ("G20", ""),
+        ("G21", ""),
+        ("G28", "XYZ"),
+        ("G30", "XYZ"),
+        ("G38.2", "XYZF"),
+        ("G38.3", "XYZF"),
+        ("G38.4", "XYZF"),
+        ("G38.5", "XYZF"),
+        ("G40", ""),
+        ("G53", ""),
+        ("G54", ""),
+        ("G55", ""),
+        ("G56", ""),
+        ("G57", ""),
+        ("G58", ""),
+        ("G59", ""),
+        ("G80", ""),
+        ("G90", ""),
+        ("G91", ""),
+        ("G92", "XYZ"),
+        ("G93", ""),
+        ("G94", ""),
+        ("M0", ""),
+        ("M1", ""),
+        ("M2", ""),
+        ("M3", "S"),
+        ("M4", "S"),
+        ("M5", ""),
+        ("M7", ""),
+        ("M8", ""),
+        ("M9", ""),
+        ("M30", ""),
+        ("T", ""),
+    ],
+    any: "",
+    optional: &[],
+};
+
+const LINUXCNC: Profile = Profile {
+    codes: &[
+        ("G0", "XYZF"),
+        ("G1", "XYZF"),
+        ("G2", "XYZIJKRPF"),
+        ("G3", "XYZIJKRPF"),
+        ("G4", "P"),
+        ("G10", "LPXYZR"),
+        ("G17", ""),
+        ("G18", ""),
+        ("G19", ""),
+        ("G20", ""),
+        ("G21", ""),
+        ("G28", "XYZ"),
+        ("G30", "XYZ"),
+        ("G33", "XYZK"),
+        ("G38.2", "XYZF"),
+        ("G38.3", "XYZF"),
+        ("G38.4", "XYZF"),
+        ("G38.5", "XYZF"),
+        ("G40", ""),
+        ("G41", "D"),
+        ("G41.1", "DL"),
+        ("G42", "D"),
+        ("G42.1", "DL"),
+        ("G53", ""),
+        ("G54", ""),
+        ("G55", ""),
+        ("G56", ""),
+        ("G57", ""),
+        ("G58", ""),
+        ("G59", ""),
+        ("G80", ""),
+        ("G81", "XYZRLF"),
+        ("G82", "XYZRLPF"),
+        ("G83", "XYZRLQF"),
+        ("G84", "XYZRLPF"),
+        ("G85", "XYZRLF"),
+        ("G86", "XYZRLPF"),
+        ("G87", "XYZRLQF"),
+        ("G88", "XYZRLPF"),
+        ("G89", "XYZRLPF"),
+        ("G90", ""),
+        ("G91", ""),
+        ("G92", "XYZ"),
+        ("G93", ""),
+        ("G94", ""),
+        ("G98", ""),
+        ("G99", ""),
+        ("M0", ""),
+        ("M1", ""),
+        ("M2", ""),
+        ("M3", "S"),
+        ("M4", "S"),
+        ("M5", ""),
+        ("M6", "T"),
+        ("M7", ""),
+        ("M8", ""),
+        ("M9", ""),
+        ("M30", ""),
+        ("T", ""),
+    ],
+    any: "",
+    optional: &[],
+};
+
+/// The command word of a code, such as `G38.2` or `T`, and the letters of its parameters.
+fn command(code: &GCode) -> (String, Vec<char>) {
+    let text = emit(code, &EmitOptions::default());
+    let mut words = text.split_whitespace();
+    let command = match words.next() {
+        Some(word) if word.starts_with('T') => "T".to_owned(),
+        Some(word) => word.to_owned(),
+        None => String::new(),
+    };
+    let letters = words
+        .filter_map(|word| word.chars().next())
+        .filter(char::is_ascii_alphabetic)
+        .collect();
+    (command, letters)
+}
+
+fn diagnostic(line: usize, severity: Severity, message: String) -> Diagnostic {
+    Diagnostic {
+        line,
+        column: 0,
+        severity,
+        message,
+    }
+}
+
+/// Everything in the program the dialect does not accept, sorted by line.
+pub fn validate(blocks: &[Block], dialect: Dialect) -> Vec<Diagnostic> {
+    let profile = dialect.profile();
+    let mut diagnostics = vec![];
+    for block in blocks {
+        for code in &block.codes {
+            let (command, letters) = command(code);
+            let Some(parameters) = profile.parameters(&command) else {
+                diagnostics.push(diagnostic(
+                    block.line,
+                    Severity::Error,
+                    format!("{} is not supported by {}", command, dialect),
+                ));
+                continue;
+            };
+            if let Some(option) = profile.option(&command) {
+                diagnostics.push(diagnostic(
+                    block.line,
+                    Severity::Warning,
+                    format!("{} needs {} in the {} build", command, option, dialect),
+                ));
+            }
+            if parameters == "*" {
+                continue;
+            }
+            for letter in letters {
+                if !parameters.contains(letter) {
+                    diagnostics.push(diagnostic(
+                        block.line,
+                        Severity::Error,
+                        format!("{} does not take {} on {}", command, letter, dialect),
+                    ));
+                }
+            }
+        }
+    }
+    diagnostics.sort();
+    diagnostics.dedup();
+    diagnostics
+}
+
+/// The program rewritten for `to`, with what was changed on the way and what `to` still does not
+/// accept.
+pub fn translate(blocks: &[Block], from: Dialect, to: Dialect) -> (Vec<Block>, Vec<Diagnostic>) {
+    let (blocks, mut notes) = convert(blocks, from, to);
+    notes.extend(validate(&blocks, to));
+    notes.sort();
+    notes.dedup();
+    (blocks, notes)
+}
+
+/// The program rewritten for `to` and what was changed on the way, without checking the result.
+pub fn convert(blocks: &[Block], from: Dialect, to: Dialect) -> (Vec<Block>, Vec<Diagnostic>) {
+    let mut notes = vec![];
+    let mut blocks = blocks.to_vec();
+
+    // Dwells in milliseconds from here on, as the interpreter reads them.
+    if from.is_cnc() {
+        for code in blocks.iter_mut().flat_map(|block| &mut block.codes) {
+            if let GCode::G4 { p: p @ Some(_), .. } = code {
+                *p = p.map(|seconds| seconds * 1000.0);
+            }
+        }
+    }
+
+    if !to.has_canned_cycles() {
+        blocks = expand_cycles(&blocks);
+    }
+
+    for block in &mut blocks {
+        let line = block.line;
+        let mut codes = Vec::with_capacity(block.codes.len());
+        for code in block.codes.drain(..) {
+            match code {
+                GCode::G4 { p, s } if to.is_cnc() => codes.push(GCode::G4 {
+                    p: Some(p.unwrap_or(0.0) / 1000.0 + s.unwrap_or(0.0)),
+                    s: None,
+                }),
+                GCode::M2 {} | GCode::M30 { .. } if from.is_cnc() && !to.is_cnc() => {
+                    if to == Dialect::RepRapFirmware {
+                        codes.push(GCode::M0 { p: None, s: None });
+                    } else {
+                        notes.push(diagnostic(
+                            line,
+                            Severity::Warning,
+                            format!(
+                                "program end removed: {} has no M2 and M30 deletes a file",
+                                to
+                            ),
+                        ));
+                    }
+                }
+                GCode::M400 if to.is_cnc() => codes.push(GCode::G4 {
+                    p: Some(0.0),
+                    s: None,
+                }),
+                GCode::G28 { .. } if from.is_cnc() != to.is_cnc() => {
+                    notes.push(diagnostic(
+                        line,
+                        Severity::Warning,
+                        if to.is_cnc() {
+                            format!("G28 goes to the stored G28 position on {}, not home", to)
+                        } else {
+                            format!("G28 homes on {} instead of going to a stored position", to)
+                        },
+                    ));
+                    codes.push(code);
+                }
+                code => codes.push(code),
+            }
+        }
+        block.codes = codes;
+    }
+    blocks.retain(|block| !block.codes.is_empty() || !block.comments.is_empty());
+    (blocks, notes)
+}
+
+/// Replaces canned cycles with the moves they make, and drops the codes that only matter to
+/// cycles.
+pub(super) fn expand_cycles(blocks: &[Block]) -> Vec<Block> {
+    let mut interpreter = Interpreter::default();
+    let mut out = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        let mut moves = vec![];
+        interpreter.run_block(block, &mut moves);
+        let cycle_only = |code: &GCode| {
+            gcode_interpreter::is_canned_cycle(code)
+                || matches!(code, GCode::G80 | GCode::G98 | GCode::G99)
+        };
+        if !block.codes.iter().any(cycle_only) {
+            out.push(block.clone());
+            continue;
+        }
+
+        let kept = Block {
+            codes: block
+                .codes
+                .iter()
+                .filter(|code| !cycle_only(code))
+                .cloned()
+                .collect(),
+            ..block.clone()
+        };
+        if !kept.codes.is_empty() || !kept.comments.is_empty() {
+            out.push(kept);
+        }
+        if !block.codes.iter().any(gcode_interpreter::is_canned_cycle) {
+            continue;
+        }
+
+        // Moves come out in machine millimetres, the program works in its own coordinates.
+        let scale = interpreter.state.units.scale();
+        let program = interpreter.program_position();
+        let offset: Position =
+            [0, 1, 2, 3].map(|axis| interpreter.position[axis] - program[axis] * scale);
+        let at = |position: Position, axis: usize| Some((position[axis] - offset[axis]) / scale);
+
+        let line = |code| Block {
+            line: block.line,
+            codes: vec![code],
+            ..Default::default()
+        };
+        let relative = interpreter.state.distance == Distance::Relative;
+        if relative {
+            out.push(line(GCode::G90));
+        }
+        let mut feedrate = None;
+        for motion in &moves {
+            let (x, y, z) = (at(motion.end, X), at(motion.end, Y), at(motion.end, Z));
+            out.push(line(match motion.kind {
+                MoveKind::Dwell { seconds } => GCode::G4 {
+                    p: Some(seconds * 1000.0),
+                    s: None,
+                },
+                MoveKind::Rapid => GCode::G0 {
+                    x,
+                    y,
+                    z,
+                    e: None,
+                    f: None,
+                },
+                _ => {
+                    let f = motion.feedrate.map(|f| f / scale);
+                    GCode::G1 {
+                        x,
+                        y,
+                        z,
+                        e: None,
+                        f: if f == feedrate { None } else { f },
+                    }
+                }
+            }));
+            if motion.kind == MoveKind::Linear {
+                feedrate = motion.feedrate.map(|f| f / scale);
+            }
+        }
+        if relative {
+            out.push(line(GCode::G91));
+        }
+    }
+    out
+}
+
+#[test]
+fn test_gcode_dialect() {
+    use super::gcode_emitter::emit_program;
+    use super::gcode_parser::parse;
+
+    let messages = |diagnostics: Vec<Diagnostic>| -> Vec<String> {
+        diagnostics.into_iter().map(|d| d.to_string()).collect()
+    };
+
+    let printer = parse("G21\nM104 S200\nG1 X10 E5 F1500\nM593 F40\nG4 P1500\nM400").unwrap();
+    assert_eq!(
+        messages(validate(&printer, Dialect::Marlin)),
+        [
+            "1: G21 needs INCH_MODE_SUPPORT in the Marlin build",
+            "4: M593 needs INPUT_SHAPING_X or INPUT_SHAPING_Y in the Marlin build",
+        ]
+    );
+    assert!(validate(&printer, Dialect::RepRapFirmware).is_empty());
+    assert_eq!(
+        messages(validate(&printer, Dialect::Grbl)),
+        [
+            "2: M104 is not supported by GRBL",
+            "3: G1 does not take E on GRBL",
+            "4: M593 is not supported by GRBL",
+            "6: M400 is not supported by GRBL",
+        ]
+    );
+
+    // Dwells become seconds and M400 a zero dwell; the rest cannot be translated.
+    let (blocks, notes) = translate(&printer, Dialect::Marlin, Dialect::Grbl);
+    assert_eq!(
+        emit_program(&blocks[4..], &EmitOptions::default()),
+        "G4 P1.5\nG4 P0\n"
+    );
+    assert_eq!(
+        messages(notes),
+        [
+            "2: M104 is not supported by GRBL",
+            "3: G1 does not take E on GRBL",
+            "4: M593 is not supported by GRBL",
+        ]
+    );
+
+    // Drilling on LinuxCNC becomes plain moves on GRBL, and the program end goes for Marlin.
+    let mill = parse("G90 G21\nG0 Z10\nG99 G82 X5 Y5 Z-2 R1 P1 F100\nG80\nG4 P3\nM30").unwrap();
+    assert!(validate(&mill, Dialect::LinuxCnc).is_empty());
+    let (blocks, notes) = translate(&mill, Dialect::LinuxCnc, Dialect::Grbl);
+    assert!(notes.is_empty(), "{:?}", notes);
+    assert_eq!(
+        emit_program(&blocks, &EmitOptions::default()),
+        "G90 G21\n\
+         G0 Z10\n\
+         G0 X5 Y5 Z10\n\
+         G0 X5 Y5 Z1\n\
+         G1 X5 Y5 Z-2 F100\n\
+         G4 P1\n\
+         G0 X5 Y5 Z1\n\
+         G4 P3\n\
+         M30\n"
+    );
+    let (blocks, notes) = translate(&mill, Dialect::LinuxCnc, Dialect::Marlin);
+    assert_eq!(
+        emit_program(&blocks[blocks.len() - 1..], &EmitOptions::default()),
+        "G4 P3000\n"
+    );
+    assert!(notes
+        .iter()
+        .any(|note| note.line == 6 && note.message.starts_with("program end removed")));
+}
@@ -0,0 +1,2263 @@
+//! Writes [`GCode`] values and parsed programs back out as G-code text.
+//!
+//! The output of [`emit_program`] parses back to the same codes, comments and line numbers with
+//! [`super::gcode_parser`], so a program can be read, transformed and saved without losing
+//! anything.
+
+use std::fmt::{self, Write as _};
+
+use super::gcode_parser::{Block, Comment};
+use super::{Cycle, GCode};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct EmitOptions {
+    /// Digits after the decimal point, with trailing zeros dropped.
+    ///
+    /// `None` writes the fewest digits that read back as the exact same `f32`.
+    pub precision: Option<usize>,
+
+    /// Renumber the blocks that carry codes with this step, so `Some(10)` gives `N10`, `N20`, …
+    ///
+    /// `None` keeps the `N` words the blocks were parsed with.
+    pub line_numbers: Option<u32>,
+
+    /// Append a `*` checksum to every block that carries codes.
+    ///
+    /// Blocks that were parsed with a checksum always get a fresh one.
+    pub checksums: bool,
+}
+
+/// A single code, e.g. `G1 X10 Y20 F1500`.
+pub fn emit(code: &GCode, options: &EmitOptions) -> String {
+    let mut line = Writer::new(options.precision);
+    write_code(&mut line, code);
+    line.out
+}
+
+/// One line, without the trailing newline.
+pub fn emit_block(block: &Block, options: &EmitOptions) -> String {
+    block_text(block, block.line_number, options)
+}
+
+/// A whole program, one line per block.
+pub fn emit_program(blocks: &[Block], options: &EmitOptions) -> String {
+    let mut out = String::new();
+    let mut line_number = 0;
+    for block in blocks {
+        let number = match options.line_numbers {
+            Some(step) if !block.codes.is_empty() => {
+                line_number += step;
+                Some(line_number)
+            }
+            Some(_) => None,
+            None => block.line_number,
+        };
+        out.push_str(&block_text(block, number, options));
+        out.push('\n');
+    }
+    out
+}
+
+impl fmt::Display for GCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&emit(self, &EmitOptions::default()))
+    }
+}
+
+fn block_text(block: &Block, line_number: Option<u32>, options: &EmitOptions) -> String {
+    if block.percent {
+        return "%".to_owned();
+    }
+
+    let mut line = Writer::new(options.precision);
+    line.comments = block
+        .comments
+        .iter()
+        .filter_map(|comment| match comment {
+            Comment::Parenthesis { text, word } => Some((*word, text.clone())),
+            Comment::Semicolon(_) => None,
+        })
+        .collect();
+    if let Some(number) = line_number {
+        line.word(&format!("N{}", number));
+    }
+    if let Some(number) = block.program_number {
+        line.word(&format!("O{}", number));
+    }
+    for code in &block.codes {
+        write_code(&mut line, code);
+    }
+    line.comments_before(usize::MAX);
+
+    let mut out = line.out;
+    if block.block_delete {
+        out.insert(0, '/');
+    }
+    if (options.checksums && !block.codes.is_empty()) || block.checksum.is_some() {
+        let checksum = out.bytes().fold(0, |sum, b| sum ^ b);
+        write!(out, "*{}", checksum).ok();
+    }
+    for comment in &block.comments {
+        if let Comment::Semicolon(text) = comment {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push(';');
+            if !text.is_empty() {
+                out.push(' ');
+                out.push_str(text);
+            }
+        }
+    }
+    out
+}
+
+/// A parameter value that knows how to write itself.
+trait Value: Copy {
+    fn write(self, out: &mut String, precision: Option<usize>);
+}
+
+impl Value for f32 {
+    fn write(self, out: &mut String, precision: Option<usize>) {
+        let text = match precision {
+            Some(precision) => {
+                let text = format!("{:.*}", precision, self);
+                if text.contains('.') {
+                    text.trim_end_matches('0').trim_end_matches('.').to_owned()
+                } else {
+                    text
+                }
+            }
+            None => self.to_string(),
+        };
+        out.push_str(if text == "-0" { "0" } else { &text });
+    }
+}
+
+macro_rules! impl_integer_value {
+    ($($t:ty),*) => {
+        $(
+            impl Value for $t {
+                fn write(self, out: &mut String, _precision: Option<usize>) {
+                    write!(out, "{}", self).ok();
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_value!(u8, u16, u32, i32);
+
+/// Builds one line of words separated by spaces.
+struct Writer {
+    out: String,
+    precision: Option<usize>,
+
+    /// Code and parameter words written so far.
+    words: usize,
+
+    /// `( )` comments still to write, with the number of words that come before each.
+    comments: Vec<(usize, String)>,
+}
+
+impl Writer {
+    fn new(precision: Option<usize>) -> Self {
+        Self {
+            out: String::new(),
+            precision,
+            words: 0,
+            comments: vec![],
+        }
+    }
+
+    fn word(&mut self, word: &str) {
+        if !self.out.is_empty() {
+            self.out.push(' ');
+        }
+        self.out.push_str(word);
+    }
+
+    /// Writes the comments that come before word number `word`.
+    fn comments_before(&mut self, word: usize) {
+        while self
+            .comments
+            .first()
+            .map_or(false, |(before, _)| *before < word)
+        {
+            let (_, text) = self.comments.remove(0);
+            self.word(&format!("({})", text));
+        }
+    }
+
+    /// Counts a code or parameter word that is about to be written.
+    fn next_word(&mut self) {
+        self.words += 1;
+        self.comments_before(self.words);
+    }
+
+    fn code(&mut self, code: &str) {
+        self.next_word();
+        self.word(code);
+    }
+
+    fn letter(&mut self, letter: char) {
+        self.next_word();
+        if !self.out.is_empty() {
+            self.out.push(' ');
+        }
+        self.out.push(letter);
+    }
+
+    fn number<T: Value>(&mut self, letter: char, value: Option<T>) {
+        if let Some(value) = value {
+            self.letter(letter);
+            value.write(&mut self.out, self.precision);
+        }
+    }
+
+    fn cycle(&mut self, code: &str, cycle: &Cycle) {
+        self.code(code);
+        self.number('X', cycle.x);
+        self.number('Y', cycle.y);
+        self.number('Z', cycle.z);
+        self.number('R', cycle.r);
+        self.number('Q', cycle.q);
+        self.number('P', cycle.p);
+        self.number('L', cycle.l);
+        self.number('F', cycle.f);
+    }
+
+    fn flag(&mut self, letter: char, value: bool) {
+        if value {
+            self.letter(letter);
+        }
+    }
+
+    fn switch(&mut self, letter: char, value: Option<bool>) {
+        if let Some(value) = value {
+            self.letter(letter);
+            self.out.push(if value { '1' } else { '0' });
+        }
+    }
+
+    fn text<S: AsRef<str>>(&mut self, letter: char, value: Option<S>) {
+        if let Some(value) = value {
+            let value = value.as_ref();
+            self.letter(letter);
+            if value.is_empty() || value.contains(char::is_whitespace) || needs_quotes(value) {
+                push_quoted(&mut self.out, value);
+            } else {
+                self.out.push_str(value);
+            }
+        }
+    }
+
+    fn list<T: Value, V: AsRef<[T]>>(&mut self, letter: char, values: Option<V>) {
+        if let Some(values) = values {
+            self.letter(letter);
+            for (index, value) in values.as_ref().iter().enumerate() {
+                if index > 0 {
+                    self.out.push(':');
+                }
+                value.write(&mut self.out, self.precision);
+            }
+        }
+    }
+
+    /// Free text after a file or message code.
+    fn tail(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.out.push(' ');
+            if text.trim() != text || needs_quotes(text) {
+                push_quoted(&mut self.out, text);
+            } else {
+                self.out.push_str(text);
+            }
+        }
+    }
+}
+
+/// Text the parser would otherwise cut short or read as a comment or checksum.
+fn needs_quotes(text: &str) -> bool {
+    text.starts_with('"') || text.contains([';', '(', '*'])
+}
+
+fn push_quoted(out: &mut String, text: &str) {
+    out.push('"');
+    out.push_str(&text.replace('"', "\"\""));
+    out.push('"');
+}
+
+#[allow(clippy::too_many_lines)]
+fn write_code(line: &mut Writer, code: &GCode) {
+    match code {
+        GCode::G0 { x, y, z, e, f } => {
+            line.code("G0");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+            line.number('F', *f);
+        }
+        GCode::G1 { x, y, z, e, f } => {
+            line.code("G1");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+            line.number('F', *f);
+        }
+        GCode::G2 {
+            x,
+            y,
+            z,
+            i,
+            j,
+            k,
+            r,
+            p,
+            e,
+            f,
+        } => {
+            line.code("G2");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('I', *i);
+            line.number('J', *j);
+            line.number('K', *k);
+            line.number('R', *r);
+            line.number('P', *p);
+            line.number('E', *e);
+            line.number('F', *f);
+        }
+        GCode::G3 {
+            x,
+            y,
+            z,
+            i,
+            j,
+            k,
+            r,
+            p,
+            e,
+            f,
+        } => {
+            line.code("G3");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('I', *i);
+            line.number('J', *j);
+            line.number('K', *k);
+            line.number('R', *r);
+            line.number('P', *p);
+            line.number('E', *e);
+            line.number('F', *f);
+        }
+        GCode::G4 { p, s } => {
+            line.code("G4");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::G6 { a, b, c, r } => {
+            line.code("G6");
+            line.number('A', *a);
+            line.number('B', *b);
+            line.number('C', *c);
+            line.switch('R', *r);
+        }
+        GCode::G10 {
+            l,
+            p,
+            x,
+            y,
+            z,
+            uvwabc,
+            r,
+            s,
+        } => {
+            line.code("G10");
+            line.number('L', *l);
+            line.number('P', *p);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            for (letter, value) in "UVWABC".chars().zip(uvwabc.unwrap_or_default()) {
+                line.number(letter, value);
+            }
+            line.number('R', *r);
+            line.number('S', *s);
+        }
+        GCode::G11 { s } => {
+            line.code("G11");
+            line.number('S', *s);
+        }
+        GCode::G12 { p, s, t } => {
+            line.code("G12");
+            line.number('P', *p);
+            line.number('S', *s);
+            line.number('T', *t);
+        }
+        GCode::G17 => line.code("G17"),
+        GCode::G18 => line.code("G18"),
+        GCode::G19 => line.code("G19"),
+        GCode::G20 => line.code("G20"),
+        GCode::G21 => line.code("G21"),
+        GCode::G22 => line.code("G22"),
+        GCode::G23 => line.code("G23"),
+        GCode::G26 { c, p, o } => {
+            line.code("G26");
+            line.switch('C', *c);
+            line.switch('P', *p);
+            line.number('O', *o);
+        }
+        GCode::G27 { p } => {
+            line.code("G27");
+            line.number('P', *p);
+        }
+        GCode::G28 { x, y, z, p, i } => {
+            line.code("G28");
+            line.flag('X', *x);
+            line.flag('Y', *y);
+            line.flag('Z', *z);
+            line.switch('P', *p);
+            line.switch('I', *i);
+        }
+        GCode::G29 { s, p } => {
+            line.code("G29");
+            line.number('S', *s);
+            line.text('P', p.as_deref());
+        }
+        GCode::G30 { p, x, y, z, h, s } => {
+            line.code("G30");
+            line.number('P', *p);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('H', *h);
+            line.number('S', *s);
+        }
+        GCode::G31 {
+            p,
+            x,
+            y,
+            z,
+            c,
+            s,
+            t,
+        } => {
+            line.code("G31");
+            line.number('P', *p);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('C', *c);
+            line.number('S', *s);
+            line.number('T', *t);
+        }
+        GCode::G32 { s, p } => {
+            line.code("G32");
+            line.number('S', *s);
+            line.number('P', *p);
+        }
+        GCode::G33 { l, r, x, y, z } => {
+            line.code("G33");
+            line.number('L', *l);
+            line.number('R', *r);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::G34 { i, t, a } => {
+            line.code("G34");
+            line.number('I', *i);
+            line.number('T', *t);
+            line.number('A', *a);
+        }
+        GCode::G38_2 { x, y, z, f } => {
+            line.code("G38.2");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('F', *f);
+        }
+        GCode::G38_3 { x, y, z, f } => {
+            line.code("G38.3");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('F', *f);
+        }
+        GCode::G38_4 { x, y, z, f } => {
+            line.code("G38.4");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('F', *f);
+        }
+        GCode::G38_5 { x, y, z, f } => {
+            line.code("G38.5");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('F', *f);
+        }
+        GCode::G40 => line.code("G40"),
+        GCode::G41 { d } => {
+            line.code("G41");
+            line.number('D', *d);
+        }
+        GCode::G41_1 { d } => {
+            line.code("G41.1");
+            line.number('D', Some(*d));
+        }
+        GCode::G42 { d } => {
+            line.code("G42");
+            line.number('D', *d);
+        }
+        GCode::G42_1 { d } => {
+            line.code("G42.1");
+            line.number('D', Some(*d));
+        }
+        GCode::G42MeshMove { i, j, p, f } => {
+            line.code("G42");
+            line.number('I', *i);
+            line.number('J', *j);
+            line.switch('P', *p);
+            line.number('F', *f);
+        }
+        GCode::G53 => line.code("G53"),
+        GCode::G54 => line.code("G54"),
+        GCode::G55 => line.code("G55"),
+        GCode::G56 => line.code("G56"),
+        GCode::G57 => line.code("G57"),
+        GCode::G58 => line.code("G58"),
+        GCode::G59 => line.code("G59"),
+        GCode::G60 { s } => {
+            line.code("G60");
+            line.number('S', *s);
+        }
+        GCode::G68 { x, y, r, i } => {
+            line.code("G68");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('R', *r);
+            line.switch('I', *i);
+        }
+        GCode::G69 => line.code("G69"),
+        GCode::G75 => line.code("G75"),
+        GCode::G76 { b, p } => {
+            line.code("G76");
+            line.switch('B', *b);
+            line.switch('P', *p);
+        }
+        GCode::G80 => line.code("G80"),
+        GCode::G81(cycle) => line.cycle("G81", cycle),
+        GCode::G82(cycle) => line.cycle("G82", cycle),
+        GCode::G83(cycle) => line.cycle("G83", cycle),
+        GCode::G84(cycle) => line.cycle("G84", cycle),
+        GCode::G85(cycle) => line.cycle("G85", cycle),
+        GCode::G86(cycle) => line.cycle("G86", cycle),
+        GCode::G87(cycle) => line.cycle("G87", cycle),
+        GCode::G88(cycle) => line.cycle("G88", cycle),
+        GCode::G89(cycle) => line.cycle("G89", cycle),
+        GCode::G90 => line.code("G90"),
+        GCode::G91 => line.code("G91"),
+        GCode::G92 { x, y, z, e } => {
+            line.code("G92");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+        }
+        GCode::G93 => line.code("G93"),
+        GCode::G94 => line.code("G94"),
+        GCode::G98 => line.code("G98"),
+        GCode::G99 => line.code("G99"),
+        GCode::G100 { x, y, z, r } => {
+            line.code("G100");
+            line.switch('X', *x);
+            line.switch('Y', *y);
+            line.switch('Z', *z);
+            line.number('R', *r);
+        }
+        GCode::G130 { x, y, z, a, b } => {
+            line.code("G130");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('A', *a);
+            line.number('B', *b);
+        }
+        GCode::G161 { x, y, z, f } => {
+            line.code("G161");
+            line.switch('X', *x);
+            line.switch('Y', *y);
+            line.switch('Z', *z);
+            line.number('F', *f);
+        }
+        GCode::G162 { x, y, z, f } => {
+            line.code("G162");
+            line.switch('X', *x);
+            line.switch('Y', *y);
+            line.switch('Z', *z);
+            line.number('F', *f);
+        }
+        GCode::G425 { b, t, v, u } => {
+            line.code("G425");
+            line.switch('B', *b);
+            line.number('T', *t);
+            line.switch('V', *v);
+            line.number('U', *u);
+        }
+        GCode::M0 { p, s } => {
+            line.code("M0");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M1 { message } => {
+            line.code("M1");
+            line.tail(message.as_deref().unwrap_or_default());
+        }
+        GCode::M2 {} => line.code("M2"),
+        GCode::M3 { s } => {
+            line.code("M3");
+            line.number('S', *s);
+        }
+        GCode::M4 { s } => {
+            line.code("M4");
+            line.number('S', *s);
+        }
+        GCode::M5 {} => line.code("M5"),
+        GCode::M6 {} => line.code("M6"),
+        GCode::M7 {} => line.code("M7"),
+        GCode::M8 {} => line.code("M8"),
+        GCode::M9 {} => line.code("M9"),
+        GCode::M10 {} => line.code("M10"),
+        GCode::M11 {} => line.code("M11"),
+        GCode::M13 {} => line.code("M13"),
+        GCode::M16 { machine_name } => {
+            line.code("M16");
+            line.tail(machine_name);
+        }
+        GCode::M17 { x, y, z, e } => {
+            line.code("M17");
+            line.switch('X', *x);
+            line.switch('Y', *y);
+            line.switch('Z', *z);
+            line.switch('E', *e);
+        }
+        GCode::M18 { x, y, z, e, s } => {
+            line.code("M18");
+            line.switch('X', *x);
+            line.switch('Y', *y);
+            line.switch('Z', *z);
+            line.switch('E', *e);
+            line.number('S', *s);
+        }
+        GCode::M20 { s, r, p, l, t } => {
+            line.code("M20");
+            line.number('S', *s);
+            line.number('R', *r);
+            line.text('P', p.as_deref());
+            line.flag('L', l.is_some());
+            line.flag('T', t.is_some());
+        }
+        GCode::M21 { p } => {
+            line.code("M21");
+            line.number('P', *p);
+        }
+        GCode::M22 { p } => {
+            line.code("M22");
+            line.number('P', *p);
+        }
+        GCode::M23 { filename } => {
+            line.code("M23");
+            line.tail(filename);
+        }
+        GCode::M24 => line.code("M24"),
+        GCode::M25 => line.code("M25"),
+        GCode::M26 { s, p } => {
+            line.code("M26");
+            line.number('S', *s);
+            line.number('P', *p);
+        }
+        GCode::M27 { c, s } => {
+            line.code("M27");
+            line.switch('C', *c);
+            line.number('S', *s);
+        }
+        GCode::M28 { filename } => {
+            line.code("M28");
+            line.tail(filename);
+        }
+        GCode::M29 { filename } => {
+            line.code("M29");
+            line.tail(filename);
+        }
+        GCode::M30 { filename } => {
+            line.code("M30");
+            line.tail(filename);
+        }
+        GCode::M31 => line.code("M31"),
+        GCode::M32 { filename } => {
+            line.code("M32");
+            line.tail(filename);
+        }
+        GCode::M33 { path } => {
+            line.code("M33");
+            line.tail(path);
+        }
+        GCode::M34 {} => line.code("M34"),
+        GCode::M35 {} => line.code("M35"),
+        GCode::M36 { filename } => {
+            line.code("M36");
+            line.tail(filename.as_deref().unwrap_or_default());
+        }
+        GCode::M36_1 { p, s } => {
+            line.code("M36.1");
+            line.text('P', p.as_deref());
+            line.number('S', *s);
+        }
+        GCode::M37 { s, p } => {
+            line.code("M37");
+            line.number('S', *s);
+            line.text('P', p.as_deref());
+        }
+        GCode::M38 { target_file } => {
+            line.code("M38");
+            line.tail(target_file);
+        }
+        GCode::M39 { p, s } => {
+            line.code("M39");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M40 {} => line.code("M40"),
+        GCode::M41 {} => line.code("M41"),
+        GCode::M42 { p, s } => {
+            line.code("M42");
+            line.number('P', Some(*p));
+            line.number('S', Some(*s));
+        }
+        GCode::M43MaterialExhausted => line.code("M43"),
+        GCode::M43PinReport { e, p, w, i } => {
+            line.code("M43");
+            line.switch('E', *e);
+            line.number('P', *p);
+            line.switch('W', *w);
+            line.switch('I', *i);
+        }
+        GCode::M44CodesDebug { i, j } => {
+            line.code("M44");
+            line.text('I', i.as_deref());
+            line.text('J', j.as_deref());
+        }
+        GCode::M44ResetBedCalibration => line.code("M44"),
+        GCode::M45 { v } => {
+            line.code("M45");
+            line.number('V', *v);
+        }
+        GCode::M46 => line.code("M46"),
+        GCode::M47 => line.code("M47"),
+        GCode::M48 {
+            p,
+            x,
+            y,
+            v,
+            e,
+            l,
+            s,
+        } => {
+            line.code("M48");
+            line.number('P', *p);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('V', *v);
+            line.switch('E', *e);
+            line.number('L', *l);
+            line.switch('S', *s);
+        }
+        GCode::M49 { s } => {
+            line.code("M49");
+            line.switch('S', *s);
+        }
+        GCode::M70 { p, message } => {
+            line.code("M70");
+            line.number('P', *p);
+            line.text('S', message.as_deref());
+        }
+        GCode::M72 { p } => {
+            line.code("M72");
+            line.number('P', *p);
+        }
+        GCode::M73B { p, r, q, s, c, d } => {
+            line.code("M73");
+            line.number('P', *p);
+            line.number('R', *r);
+            line.number('Q', *q);
+            line.number('S', *s);
+            line.number('C', *c);
+            line.number('D', *d);
+        }
+        GCode::M75 => line.code("M75"),
+        GCode::M76 => line.code("M76"),
+        GCode::M80 { c } => {
+            line.code("M80");
+            line.text('C', c.as_deref());
+        }
+        GCode::M81 { p, r, s } => {
+            line.code("M81");
+            line.switch('P', *p);
+            line.switch('R', *r);
+            line.number('S', *s);
+        }
+        GCode::M82 => line.code("M82"),
+        GCode::M83 => line.code("M83"),
+        GCode::M84 { i } => {
+            line.code("M84");
+            line.number('I', *i);
+        }
+        GCode::M85 { s } => {
+            line.code("M85");
+            line.number('S', *s);
+        }
+        GCode::M86 { s } => {
+            line.code("M86");
+            line.number('S', *s);
+        }
+        GCode::M87 => line.code("M87"),
+        GCode::M92 { x, y, z, e, s } => {
+            line.code("M92");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+            line.number('S', *s);
+        }
+        GCode::M93 => line.code("M93"),
+        GCode::M98 { p, l } => {
+            line.code("M98");
+            line.text('P', Some(p));
+            line.number('L', *l);
+        }
+        GCode::M99 => line.code("M99"),
+        GCode::M101 => line.code("M101"),
+        GCode::M102 { s } => {
+            line.code("M102");
+            line.number('S', *s);
+        }
+        GCode::M103 => line.code("M103"),
+        GCode::M104 { c, d, s, r } => {
+            line.code("M104");
+            line.number('C', *c);
+            line.number('D', *d);
+            line.number('S', *s);
+            line.number('R', *r);
+        }
+        GCode::M105 => line.code("M105"),
+        GCode::M106 {
+            p,
+            s,
+            i,
+            f,
+            l,
+            x,
+            b,
+            h,
+            r,
+            t,
+            c,
+        } => {
+            line.code("M106");
+            line.number('P', *p);
+            line.number('S', *s);
+            line.number('I', *i);
+            line.number('F', *f);
+            line.number('L', *l);
+            line.number('X', *x);
+            line.number('B', *b);
+            line.text('H', h.as_deref());
+            line.number('R', *r);
+            line.number('T', *t);
+            line.text('C', c.as_deref());
+        }
+        GCode::M107 => line.code("M107"),
+        GCode::M108 => line.code("M108"),
+        GCode::M109 { c, s, r, t, f } => {
+            line.code("M109");
+            line.switch('C', *c);
+            line.number('S', *s);
+            line.number('R', *r);
+            line.number('T', *t);
+            line.switch('F', *f);
+        }
+        GCode::M110 { n } => {
+            line.code("M110");
+            line.number('N', *n);
+        }
+        GCode::M111 { p, s } => {
+            line.code("M111");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M112 => line.code("M112"),
+        GCode::M113 { s } => {
+            line.code("M113");
+            line.number('S', *s);
+        }
+        GCode::M114 => line.code("M114"),
+        GCode::M115 { b, p, v, u } => {
+            line.code("M115");
+            line.number('B', *b);
+            line.number('P', *p);
+            line.switch('V', *v);
+            line.text('U', u.as_deref());
+        }
+        GCode::M116 { p, h, c } => {
+            line.code("M116");
+            line.number('P', *p);
+            line.number('H', *h);
+            line.number('C', *c);
+        }
+        GCode::M117_GetZeroPosition => line.code("M117"),
+        GCode::M117_DisplayMessage { message } => {
+            line.code("M117");
+            line.tail(message);
+        }
+        GCode::M118 { p, s } => {
+            line.code("M118");
+            line.number('P', *p);
+            line.text('S', s.as_deref());
+        }
+        GCode::M119 => line.code("M119"),
+        GCode::M120 => line.code("M120"),
+        GCode::M121 => line.code("M121"),
+        GCode::M122 { b, p } => {
+            line.code("M122");
+            line.number('B', *b);
+            line.number('P', *p);
+        }
+        GCode::M124_FirmwareDependent => line.code("M124"),
+        GCode::M124_ImmediateMotorStop => line.code("M124"),
+        GCode::M124_SetEndstopPullup {
+            x,
+            y,
+            z,
+            i,
+            j,
+            k,
+            p,
+            d,
+        } => {
+            line.code("M124");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('I', *i);
+            line.number('J', *j);
+            line.number('K', *k);
+            line.number('P', *p);
+            line.number('D', *d);
+        }
+        GCode::M126_OpenValve { p } => {
+            line.code("M126");
+            line.number('P', *p);
+        }
+        GCode::M126_MakerBot { t } => {
+            line.code("M126");
+            line.number('T', *t);
+        }
+        GCode::M127_CloseValve { p } => {
+            line.code("M127");
+            line.number('P', *p);
+        }
+        GCode::M127_MakerBot { t } => {
+            line.code("M127");
+            line.number('T', *t);
+        }
+        GCode::M128_ExtruderPressurePWM { s } => {
+            line.code("M128");
+            line.number('S', *s);
+        }
+        GCode::M129_ExtruderPressureOff { p } => {
+            line.code("M129");
+            line.number('P', *p);
+        }
+        GCode::M130_SetPID_PValue { p, s } => {
+            line.code("M130");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M131_SetPID_IValue { p, s } => {
+            line.code("M131");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M132_SetPID_DValue { p, s } => {
+            line.code("M132");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M132_MakerBot { x, y, z, a, b } => {
+            line.code("M132");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('A', *a);
+            line.number('B', *b);
+        }
+        GCode::M133_SetPID_ILimitValue { p, s } => {
+            line.code("M133");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M133_MakerBot { t, p } => {
+            line.code("M133");
+            line.number('T', *t);
+            line.number('P', *p);
+        }
+        GCode::M134_WritePIDValuesToEEPROM => line.code("M134"),
+        GCode::M134_MakerBot { t, p } => {
+            line.code("M134");
+            line.number('T', *t);
+            line.number('P', *p);
+        }
+        GCode::M135_SetPIDSampleInterval { s } => {
+            line.code("M135");
+            line.number('S', *s);
+        }
+        GCode::M135_MakerBot { t } => {
+            line.code("M135");
+            line.number('T', *t);
+        }
+        GCode::M133 { t, p } => {
+            line.code("M133");
+            line.number('T', *t);
+            line.number('P', *p);
+        }
+        GCode::M134 => line.code("M134"),
+        GCode::M135 { s } => {
+            line.code("M135");
+            line.number('S', *s);
+        }
+        GCode::M136 => line.code("M136"),
+        GCode::M140 { p, h, t, s, r } => {
+            line.code("M140");
+            line.number('P', *p);
+            line.number('H', *h);
+            line.number('T', *t);
+            line.number('S', *s);
+            line.number('R', *r);
+        }
+        GCode::M141 { p, h, t, s, r } => {
+            line.code("M141");
+            line.number('P', *p);
+            line.number('H', *h);
+            line.number('T', *t);
+            line.number('S', *s);
+            line.number('R', *r);
+        }
+        GCode::M142 => line.code("M142"),
+        GCode::M143 { h, s } => {
+            line.code("M143");
+            line.number('H', *h);
+            line.number('S', *s);
+        }
+        GCode::M144 { p, s } => {
+            line.code("M144");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M146 { r } => {
+            line.code("M146");
+            line.number('R', *r);
+        }
+        GCode::M149 { c, k } => {
+            line.code("M149");
+            line.flag('C', *c);
+            line.flag('K', *k);
+        }
+        GCode::M150 { r, u, b, w, p } => {
+            line.code("M150");
+            line.number('R', *r);
+            line.number('U', *u);
+            line.number('B', *b);
+            line.number('W', *w);
+            line.number('P', *p);
+        }
+        GCode::M155 { s, c } => {
+            line.code("M155");
+            line.number('S', *s);
+            line.number('C', *c);
+        }
+        GCode::M160 { s } => {
+            line.code("M160");
+            line.number('S', *s);
+        }
+        GCode::M163 { s, p } => {
+            line.code("M163");
+            line.number('S', *s);
+            line.number('P', *p);
+        }
+        GCode::M164 { s, p } => {
+            line.code("M164");
+            line.number('S', *s);
+            line.number('P', *p);
+        }
+        GCode::M165 { a, b, c, d, h, i } => {
+            line.code("M165");
+            line.number('A', *a);
+            line.number('B', *b);
+            line.number('C', *c);
+            line.number('D', *d);
+            line.number('H', *h);
+            line.number('I', *i);
+        }
+        GCode::M190 { s, r } => {
+            line.code("M190");
+            line.number('S', *s);
+            line.number('R', *r);
+        }
+        GCode::M191 { s, r } => {
+            line.code("M191");
+            line.number('S', *s);
+            line.number('R', *r);
+        }
+        GCode::M200 { d, t, s, l } => {
+            line.code("M200");
+            line.number('D', *d);
+            line.number('T', *t);
+            line.number('S', *s);
+            line.number('L', *l);
+        }
+        GCode::M201 { x, y, z, e } => {
+            line.code("M201");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+        }
+        GCode::M202 { x, y } => {
+            line.code("M202");
+            line.number('X', *x);
+            line.number('Y', *y);
+        }
+        GCode::M203 { x, y, z, e, i } => {
+            line.code("M203");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+            line.number('I', *i);
+        }
+        GCode::M204 { p, t } => {
+            line.code("M204");
+            line.number('P', *p);
+            line.number('T', *t);
+        }
+        GCode::M205 { s, t, b, x, z, e } => {
+            line.code("M205");
+            line.number('S', *s);
+            line.number('T', *t);
+            line.number('B', *b);
+            line.number('X', *x);
+            line.number('Z', *z);
+            line.number('E', *e);
+        }
+        GCode::M206 { x, y, z } => {
+            line.code("M206");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::M207 { s, r, f, t, z } => {
+            line.code("M207");
+            line.number('S', *s);
+            line.number('R', *r);
+            line.number('F', *f);
+            line.number('T', *t);
+            line.number('Z', *z);
+        }
+        GCode::M208 { s, x, y, z } => {
+            line.code("M208");
+            line.number('S', *s);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::M209 { s } => {
+            line.code("M209");
+            line.number('S', *s);
+        }
+        GCode::M210 { x, y } => {
+            line.code("M210");
+            line.number('X', *x);
+            line.number('Y', *y);
+        }
+        GCode::M211 { s, x, y, z } => {
+            line.code("M211");
+            line.number('S', *s);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::M212 { z } => {
+            line.code("M212");
+            line.number('Z', *z);
+        }
+        GCode::M214 { p, s, n, r, f } => {
+            line.code("M214");
+            line.number('P', *p);
+            line.number('S', *s);
+            line.number('N', *n);
+            line.number('R', *r);
+            line.number('F', *f);
+        }
+        GCode::M217 { s, p, r, x, y, z } => {
+            line.code("M217");
+            line.number('S', *s);
+            line.number('P', *p);
+            line.number('R', *r);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::M218 { t, x, y } => {
+            line.code("M218");
+            line.number('T', *t);
+            line.number('X', *x);
+            line.number('Y', *y);
+        }
+        GCode::M220 { s } => {
+            line.code("M220");
+            line.number('S', *s);
+        }
+        GCode::M221 { s, d } => {
+            line.code("M221");
+            line.number('S', *s);
+            line.number('D', *d);
+        }
+        GCode::M222 { speed } => {
+            line.code("M222");
+            line.number('S', Some(*speed));
+        }
+        GCode::M223 { speed } => {
+            line.code("M223");
+            line.number('S', Some(*speed));
+        }
+        GCode::M224 { enable } => {
+            line.code("M224");
+            line.flag('S', *enable);
+        }
+        GCode::M225 { disable } => {
+            line.code("M225");
+            line.flag('S', *disable);
+        }
+        GCode::M226 { message } => {
+            line.code("M226");
+            line.text('P', message.as_deref());
+        }
+        GCode::M226Pin {
+            pin_number,
+            pin_state,
+        } => {
+            line.code("M226");
+            line.number('P', Some(*pin_number));
+            line.number('S', Some(*pin_state));
+        }
+        GCode::M227 { steps } => {
+            line.code("M227");
+            line.number('P', Some(steps.0));
+            line.number('S', Some(steps.1));
+        }
+        GCode::M228 { disabled } => {
+            line.code("M228");
+            line.flag('S', *disabled);
+        }
+        GCode::M229 { extruder_params } => {
+            line.code("M229");
+            line.number('P', Some(extruder_params.0));
+            line.number('S', Some(extruder_params.1));
+        }
+        GCode::M230 { enable } => {
+            line.code("M230");
+            line.flag('S', *enable);
+        }
+        GCode::M231 {
+            ops_mode,
+            min_distance,
+            retract,
+            backslash,
+            retract_move,
+        } => {
+            line.code("M231");
+            line.number('S', Some(*ops_mode));
+            line.number('X', Some(*min_distance));
+            line.number('Y', Some(*retract));
+            line.number('Z', Some(*backslash));
+            line.number('F', Some(*retract_move));
+        }
+        GCode::M232 => line.code("M232"),
+        GCode::M240 { message } => {
+            line.code("M240");
+            line.text('P', message.as_deref());
+        }
+        GCode::M240Belt { disabled } => {
+            line.code("M240");
+            // Always written, a bare `M240` is the camera trigger.
+            line.switch('S', Some(*disabled));
+        }
+        GCode::M241 { enabled } => {
+            line.code("M241");
+            line.flag('S', *enabled);
+        }
+        GCode::M245 => line.code("M245"),
+        GCode::M246 => line.code("M246"),
+        GCode::M250 { c } => {
+            line.code("M250");
+            line.number('C', Some(*c));
+        }
+        GCode::M256 { b } => {
+            line.code("M256");
+            line.number('B', Some(*b));
+        }
+        GCode::M251 { s } => {
+            line.code("M251");
+            line.number('S', Some(*s));
+        }
+        GCode::M260 { a, bytes } => {
+            line.code("M260");
+            line.number('A', Some(*a));
+            line.list('B', Some(bytes));
+        }
+        GCode::M261 { a, b, s } => {
+            line.code("M261");
+            line.number('A', Some(*a));
+            line.number('B', Some(*b));
+            line.number('S', Some(*s));
+        }
+        GCode::M280 { p, s, i } => {
+            line.code("M280");
+            line.number('P', Some(*p));
+            line.number('S', Some(*s));
+            line.switch('I', *i);
+        }
+        GCode::M281 {
+            p,
+            angle_1,
+            angle_2,
+            angle_3,
+            angle_4,
+            angle_5,
+            angle_6,
+            angle_7,
+            angle_8,
+        } => {
+            line.code("M281");
+            line.number('P', Some(*p));
+            let angles: Vec<f32> = std::iter::once(*angle_1)
+                .chain(
+                    [
+                        angle_2, angle_3, angle_4, angle_5, angle_6, angle_7, angle_8,
+                    ]
+                    .into_iter()
+                    .map_while(|angle| *angle),
+                )
+                .collect();
+            line.list('S', Some(&angles));
+        }
+        GCode::M292 { p } => {
+            line.code("M292");
+            line.number('P', *p);
+        }
+        GCode::M300 { s, p, v } => {
+            line.code("M300");
+            line.number('S', *s);
+            line.number('P', *p);
+            line.number('V', *v);
+        }
+        GCode::M301 { h, p, i, d } => {
+            line.code("M301");
+            line.number('H', Some(*h));
+            line.number('P', *p);
+            line.number('I', *i);
+            line.number('D', *d);
+        }
+        GCode::M302 { s, p, r } => {
+            line.code("M302");
+            line.number('S', *s);
+            line.number('P', *p);
+            line.number('R', *r);
+        }
+        GCode::M303 { s, c, h, p } => {
+            line.code("M303");
+            line.number('S', *s);
+            line.number('C', *c);
+            line.number('H', *h);
+            line.number('P', *p);
+        }
+        GCode::M304 { p, i, d } => {
+            line.code("M304");
+            line.number('P', *p);
+            line.number('I', *i);
+            line.number('D', *d);
+        }
+        GCode::M305 {
+            p,
+            s,
+            t,
+            c,
+            b,
+            r,
+            l,
+            h,
+            x,
+            f,
+        } => {
+            line.code("M305");
+            line.number('P', Some(*p));
+            line.text('S', s.as_deref());
+            line.number('T', *t);
+            line.number('C', *c);
+            line.number('B', *b);
+            line.number('R', *r);
+            line.number('L', *l);
+            line.number('H', *h);
+            line.number('X', *x);
+            line.number('F', *f);
+        }
+        GCode::M306 { z } => {
+            line.code("M306");
+            line.number('Z', *z);
+        }
+        GCode::M307 {
+            h,
+            a,
+            c,
+            d,
+            f,
+            b,
+            s,
+            v,
+        } => {
+            line.code("M307");
+            line.number('H', Some(*h));
+            line.number('A', *a);
+            line.number('C', *c);
+            line.number('D', *d);
+            line.number('F', *f);
+            line.number('B', *b);
+            line.number('S', *s);
+            line.number('V', *v);
+        }
+        GCode::M308 { s, p, y, a } => {
+            line.code("M308");
+            line.number('S', Some(*s));
+            line.text('P', p.as_deref());
+            line.text('Y', y.as_deref());
+            line.text('A', a.as_deref());
+        }
+        GCode::M309 { p, s } => {
+            line.code("M309");
+            line.number('P', *p);
+            line.list('S', s.as_deref());
+        }
+        GCode::M310 {
+            a,
+            f,
+            s,
+            i,
+            r,
+            p,
+            c,
+            b,
+            e,
+            w,
+            t,
+        } => {
+            line.code("M310");
+            line.flag('A', *a);
+            line.flag('F', *f);
+            line.number('S', *s);
+            line.number('I', *i);
+            line.number('R', *r);
+            line.number('P', *p);
+            line.number('C', *c);
+            line.number('B', *b);
+            line.number('E', *e);
+            line.number('W', *w);
+            line.number('T', *t);
+        }
+        GCode::M320 { s } => {
+            line.code("M320");
+            line.number('S', *s);
+        }
+        GCode::M321 { s } => {
+            line.code("M321");
+            line.number('S', *s);
+        }
+        GCode::M322 { s } => {
+            line.code("M322");
+            line.number('S', *s);
+        }
+        GCode::M323 { s, p } => {
+            line.code("M323");
+            line.number('S', *s);
+            line.number('P', *p);
+        }
+        GCode::M340 { p, s } => {
+            line.code("M340");
+            line.number('P', Some(*p));
+            line.number('S', Some(*s));
+        }
+        GCode::M350 {
+            s,
+            x,
+            y,
+            z,
+            e,
+            b,
+            i,
+        } => {
+            line.code("M350");
+            line.number('S', *s);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.text('E', e.as_deref());
+            line.number('B', *b);
+            line.number('I', *i);
+        }
+        GCode::M351 => line.code("M351"),
+        GCode::M355 { s, p } => {
+            line.code("M355");
+            line.number('S', *s);
+            line.number('P', *p);
+        }
+        GCode::M360 { p } => {
+            line.code("M360");
+            line.number('P', *p);
+        }
+        GCode::M361 { p } => {
+            line.code("M361");
+            line.number('P', *p);
+        }
+        GCode::M362 => line.code("M362"),
+        GCode::M363 => line.code("M363"),
+        GCode::M364 { p } => {
+            line.code("M364");
+            line.number('P', *p);
+        }
+        GCode::M365 { s } => {
+            line.code("M365");
+            line.number('S', *s);
+        }
+        GCode::M366 => line.code("M366"),
+        GCode::M370 { x, y } => {
+            line.code("M370");
+            line.number('X', *x);
+            line.number('Y', *y);
+        }
+        GCode::M371 => line.code("M371"),
+        GCode::M372 => line.code("M372"),
+        GCode::M373 => line.code("M373"),
+        GCode::M374 {
+            extension,
+            filename,
+            z,
+        } => {
+            line.code("M374");
+            line.text('E', extension.as_deref());
+            line.text('P', filename.as_deref());
+            line.flag('Z', *z);
+        }
+        GCode::M375 {
+            extension,
+            filename,
+        } => {
+            line.code("M375");
+            line.text('E', extension.as_deref());
+            line.text('P', filename.as_deref());
+        }
+        GCode::M376 { h } => {
+            line.code("M376");
+            line.number('H', *h);
+        }
+        GCode::M380 => line.code("M380"),
+        GCode::M381 => line.code("M381"),
+        GCode::M400 => line.code("M400"),
+        GCode::M401 { p, s, h } => {
+            line.code("M401");
+            line.number('P', *p);
+            line.switch('S', *s);
+            line.flag('H', *h);
+        }
+        GCode::M402 { p } => {
+            line.code("M402");
+            line.number('P', *p);
+        }
+        GCode::M403 { e, f } => {
+            line.code("M403");
+            line.number('E', Some(*e));
+            line.text('F', Some(f));
+        }
+        GCode::M404 { n, w } => {
+            line.code("M404");
+            line.number('N', *n);
+            line.number('W', *w);
+        }
+        GCode::M405 { delay } => {
+            line.code("M405");
+            line.number('D', *delay);
+        }
+        GCode::M406 => line.code("M406"),
+        GCode::M407 => line.code("M407"),
+        GCode::M408 {
+            response_type,
+            sequence_num,
+        } => {
+            line.code("M408");
+            line.number('S', Some(*response_type));
+            line.number('R', *sequence_num);
+        }
+        GCode::M409 { key, flags } => {
+            line.code("M409");
+            line.text('K', key.as_deref());
+            line.text('F', flags.as_deref());
+        }
+        GCode::M410 => line.code("M410"),
+        GCode::M412 { s } => {
+            line.code("M412");
+            line.switch('S', *s);
+        }
+        GCode::M413 { s } => {
+            line.code("M413");
+            line.switch('S', *s);
+        }
+        GCode::M415 { s, z } => {
+            line.code("M415");
+            line.switch('S', *s);
+            line.number('Z', *z);
+        }
+        GCode::M416 => line.code("M416"),
+        GCode::M420 { r, e, b } => {
+            line.code("M420");
+            line.number('R', Some(*r));
+            line.number('E', Some(*e));
+            line.number('B', Some(*b));
+        }
+        GCode::M421 { i, j, z, q } => {
+            line.code("M421");
+            line.number('I', Some(*i));
+            line.number('J', Some(*j));
+            line.number('Z', Some(*z));
+            line.number('Q', *q);
+        }
+        GCode::M422 { s, x, y } => {
+            line.code("M422");
+            line.number('S', Some(*s));
+            line.number('X', Some(*x));
+            line.number('Y', Some(*y));
+        }
+        GCode::M423 { r, x, z, a, i } => {
+            line.code("M423");
+            line.flag('R', *r);
+            line.number('X', *x);
+            line.number('Z', *z);
+            line.number('A', *a);
+            line.number('I', *i);
+        }
+        GCode::M424 { z } => {
+            line.code("M424");
+            line.number('Z', *z);
+        }
+        GCode::M425 {
+            f,
+            s,
+            x,
+            y,
+            z,
+            x_meas,
+            y_meas,
+            z_meas,
+        } => {
+            line.code("M425");
+            line.number('F', *f);
+            line.number('S', *s);
+            for (letter, value, measured) in [('X', x, x_meas), ('Y', y, y_meas), ('Z', z, z_meas)]
+            {
+                if *measured {
+                    line.flag(letter, true);
+                } else {
+                    line.number(letter, *value);
+                }
+            }
+        }
+        GCode::M450 => line.code("M450"),
+        GCode::M451 => line.code("M451"),
+        GCode::M452 => line.code("M452"),
+        GCode::M453 {
+            spindle_index,
+            spindle_pin_cw,
+            spindle_pin_ccw,
+            invert,
+            spindle_rpm,
+            pwm_freq,
+            assign_spindle,
+        } => {
+            line.code("M453");
+            line.number('S', *spindle_index);
+            line.number('C', *spindle_pin_cw);
+            line.number('D', *spindle_pin_ccw);
+            line.flag('I', *invert);
+            line.number('R', *spindle_rpm);
+            line.number('Q', *pwm_freq);
+            line.number('T', *assign_spindle);
+        }
+        GCode::M460 { min_temp, max_temp } => {
+            line.code("M460");
+            line.number('X', Some(*min_temp));
+            line.number('Y', Some(*max_temp));
+        }
+        GCode::M470 { name } => {
+            line.code("M470");
+            line.text('P', Some(name));
+        }
+        GCode::M471 {
+            source,
+            dest,
+            delete,
+        } => {
+            line.code("M471");
+            line.text('S', Some(source));
+            line.text('T', Some(dest));
+            line.flag('D', *delete);
+        }
+        GCode::M472 { name, recursive } => {
+            line.code("M472");
+            line.text('P', Some(name));
+            line.flag('R', *recursive);
+        }
+        GCode::M486 { t, s, a, p, u, c } => {
+            line.code("M486");
+            line.number('T', *t);
+            line.number('S', *s);
+            line.text('A', a.as_deref());
+            line.number('P', *p);
+            line.number('U', *u);
+            line.flag('C', *c);
+        }
+        GCode::M493 {
+            s,
+            p,
+            k,
+            d,
+            a,
+            f,
+            b,
+            h,
+        } => {
+            line.code("M493");
+            line.number('S', *s);
+            line.switch('P', *p);
+            line.number('K', *k);
+            line.number('D', *d);
+            line.number('A', *a);
+            line.number('F', *f);
+            line.number('B', *b);
+            line.number('H', *h);
+        }
+        GCode::M500 => line.code("M500"),
+        GCode::M501 { s } => {
+            line.code("M501");
+            line.number('S', *s);
+        }
+        GCode::M502 => line.code("M502"),
+        GCode::M503 { s } => {
+            line.code("M503");
+            line.flag('S', *s);
+        }
+        GCode::M504 => line.code("M504"),
+        GCode::M505_ClearEepromReset => line.code("M505"),
+        GCode::M505_SetConfigFolder { name } => {
+            line.code("M505");
+            line.text('P', Some(name));
+        }
+        GCode::M505_SetEepromValue { varname, value } => {
+            line.code("M505");
+            line.text('S', Some(varname));
+            line.text('V', Some(value));
+        }
+        GCode::M509 => line.code("M509"),
+        GCode::M510 => line.code("M510"),
+        GCode::M511 { passcode } => {
+            line.code("M511");
+            line.text('P', passcode.as_deref());
+        }
+        GCode::M512 { oldpass, newpass } => {
+            line.code("M512");
+            line.text('P', oldpass.as_deref());
+            line.text('S', newpass.as_deref());
+        }
+        GCode::M513 {
+            currpass,
+            removal_hash,
+        } => {
+            line.code("M513");
+            line.text('P', currpass.as_deref());
+            line.text('S', removal_hash.as_deref());
+        }
+        GCode::M524 => line.code("M524"),
+        GCode::M530 { state, layers } => {
+            line.code("M530");
+            line.flag('S', *state);
+            line.number('L', Some(*layers));
+        }
+        GCode::M531(value) => {
+            line.code("M531");
+            line.tail(value);
+        }
+        GCode::M532 { progress, layer } => {
+            line.code("M532");
+            line.number('X', Some(*progress));
+            line.number('L', Some(*layer));
+        }
+        GCode::M540(value) => {
+            line.code("M540");
+            line.text('P', Some(value));
+        }
+        GCode::M544 { case_insensitive } => {
+            line.code("M544");
+            line.flag('S', *case_insensitive);
+        }
+        GCode::M550(value) => {
+            line.code("M550");
+            line.text('P', Some(value));
+        }
+        GCode::M551(value) => {
+            line.code("M551");
+            line.text('P', Some(value));
+        }
+        GCode::M552 {
+            net_interface,
+            ip_address,
+            enable_state,
+        } => {
+            line.code("M552");
+            line.number('I', *net_interface);
+            line.text('P', ip_address.as_deref());
+            line.number('S', *enable_state);
+        }
+        GCode::M553 {
+            net_interface,
+            net_mask,
+        } => {
+            line.code("M553");
+            line.number('I', *net_interface);
+            line.text('P', net_mask.as_deref());
+        }
+        GCode::M554 {
+            net_interface,
+            gateway,
+            dns,
+        } => {
+            line.code("M554");
+            line.number('I', *net_interface);
+            line.text('P', gateway.as_deref());
+            line.text('S', dns.as_deref());
+        }
+        GCode::M555 { p } => {
+            line.code("M555");
+            line.number('P', Some(*p));
+        }
+        GCode::M556 { s, x, y, z, p } => {
+            line.code("M556");
+            line.number('S', Some(*s));
+            line.number('X', Some(*x));
+            line.number('Y', Some(*y));
+            line.number('Z', Some(*z));
+            line.number('P', Some(*p));
+        }
+        GCode::M557ProbePoint { p, x, y } => {
+            line.code("M557");
+            line.number('P', Some(*p));
+            line.number('X', Some(*x));
+            line.number('Y', Some(*y));
+        }
+        GCode::M557ProbeGrid { x, y, r, s, p } => {
+            line.code("M557");
+            line.number('X', Some(*x));
+            line.number('Y', Some(*y));
+            line.number('R', Some(*r));
+            line.number('S', Some(*s));
+            line.number('P', Some(*p));
+        }
+        GCode::M558 {
+            p,
+            f,
+            h,
+            i,
+            r,
+            t,
+            a,
+            s,
+            b,
+        } => {
+            line.code("M558");
+            line.number('P', Some(*p));
+            line.number('F', Some(*f));
+            line.number('H', Some(*h));
+            line.number('I', Some(*i));
+            line.number('R', Some(*r));
+            line.number('T', Some(*t));
+            line.number('A', Some(*a));
+            line.number('S', Some(*s));
+            line.number('B', Some(*b));
+        }
+        GCode::M559 => line.code("M559"),
+        GCode::M560 => line.code("M560"),
+        GCode::M561 => line.code("M561"),
+        GCode::M562 { p } => {
+            line.code("M562");
+            line.number('P', Some(*p));
+        }
+        GCode::M563 {
+            p,
+            s,
+            d,
+            h,
+            f,
+            x,
+            y,
+            z,
+            u,
+        } => {
+            line.code("M563");
+            line.number('P', Some(*p));
+            line.text('S', s.as_deref());
+            line.number('D', *d);
+            line.number('H', *h);
+            line.number('F', *f);
+            line.text('X', x.as_deref());
+            line.text('Y', y.as_deref());
+            line.text('Z', z.as_deref());
+            line.text('U', u.as_deref());
+        }
+        GCode::M564 { h, s } => {
+            line.code("M564");
+            line.number('H', *h);
+            line.number('S', *s);
+        }
+        GCode::M565 { x, y, z } => {
+            line.code("M565");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::M566 { x, y, z, e } => {
+            line.code("M566");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+        }
+        GCode::M567 { p, e } => {
+            line.code("M567");
+            line.number('P', Some(*p));
+            line.list('E', Some(e));
+        }
+        GCode::M568 { p, r, s, f, a } => {
+            line.code("M568");
+            line.number('P', *p);
+            line.number('R', *r);
+            line.number('S', *s);
+            line.number('F', *f);
+            line.number('A', *a);
+        }
+        GCode::M569 {
+            p,
+            s,
+            r,
+            t,
+            ta,
+            d,
+            f_n,
+            b_n,
+            y,
+            c,
+            h,
+            v,
+        } => {
+            line.code("M569");
+            line.number('P', Some(*p));
+            line.number('S', *s);
+            line.number('R', *r);
+            line.number('T', *t);
+            if let Some((a, b, c, d)) = ta {
+                line.list('T', Some(&[*a, *b, *c, *d]));
+            }
+            line.number('D', *d);
+            line.number('F', *f_n);
+            line.number('B', *b_n);
+            match y {
+                Some((start, end, Some(decrement))) => {
+                    line.list('Y', Some(&[*start, *end, *decrement]))
+                }
+                Some((start, end, None)) => line.list('Y', Some(&[*start, *end])),
+                None => {}
+            }
+            line.number('C', *c);
+            line.number('H', *h);
+            line.number('V', *v);
+        }
+        GCode::M570 { s, h, p, t } => {
+            line.code("M570");
+            line.number('S', *s);
+            line.number('H', *h);
+            line.number('P', *p);
+            line.number('T', *t);
+        }
+        GCode::M571 { s, f, p } => {
+            line.code("M571");
+            line.number('S', *s);
+            line.number('F', *f);
+            line.number('P', *p);
+        }
+        GCode::M572 { d, s } => {
+            line.code("M572");
+            line.number('D', *d);
+            line.number('S', *s);
+        }
+        GCode::M573 { p } => {
+            line.code("M573");
+            line.number('P', *p);
+        }
+        GCode::M574 { x, y, z, s } => {
+            line.code("M574");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('S', *s);
+        }
+        GCode::M575 { p, b, s } => {
+            line.code("M575");
+            line.number('P', *p);
+            line.number('B', *b);
+            line.number('S', *s);
+        }
+        GCode::M576 { s, f, p } => {
+            line.code("M576");
+            line.number('S', *s);
+            line.number('F', *f);
+            line.number('P', *p);
+        }
+        GCode::M577 { s, x, y, z, e } => {
+            line.code("M577");
+            line.number('S', *s);
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+            line.number('E', *e);
+        }
+        GCode::M578 { p, s } => {
+            line.code("M578");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M579 { x, y, z } => {
+            line.code("M579");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::M580 { r, p } => {
+            line.code("M580");
+            line.number('R', *r);
+            line.text('P', p.as_deref());
+        }
+        GCode::M581 {
+            t,
+            x,
+            y,
+            z,
+            e,
+            p,
+            s,
+            c,
+        } => {
+            line.code("M581");
+            line.number('T', *t);
+            line.switch('X', *x);
+            line.switch('Y', *y);
+            line.switch('Z', *z);
+            line.switch('E', *e);
+            line.switch('P', *p);
+            line.number('S', *s);
+            line.number('C', *c);
+        }
+        GCode::M582 { t } => {
+            line.code("M582");
+            line.number('T', *t);
+        }
+        GCode::M584 {
+            x,
+            y,
+            z,
+            u,
+            v,
+            w,
+            a,
+            b,
+            c,
+            e,
+            p,
+        } => {
+            line.code("M584");
+            line.list('X', x.as_deref());
+            line.list('Y', y.as_deref());
+            line.list('Z', z.as_deref());
+            line.list('U', u.as_deref());
+            line.list('V', v.as_deref());
+            line.list('W', w.as_deref());
+            line.list('A', a.as_deref());
+            line.list('B', b.as_deref());
+            line.list('C', c.as_deref());
+            line.list('E', e.as_deref());
+            line.number('P', *p);
+        }
+        GCode::M585 { x, y, z } => {
+            line.code("M585");
+            line.number('X', *x);
+            line.number('Y', *y);
+            line.number('Z', *z);
+        }
+        GCode::M586 { p, s, r, t, c } => {
+            line.code("M586");
+            line.number('P', *p);
+            line.number('S', *s);
+            line.number('R', *r);
+            line.number('T', *t);
+            line.text('C', c.as_deref());
+        }
+        GCode::M587 {
+            s,
+            p,
+            i,
+            j,
+            k,
+            l,
+            c,
+        } => {
+            line.code("M587");
+            line.text('S', s.as_deref());
+            line.text('P', p.as_deref());
+            line.text('I', i.as_deref());
+            line.text('J', j.as_deref());
+            line.text('K', k.as_deref());
+            line.text('L', l.as_deref());
+            line.text('C', c.as_deref());
+        }
+        GCode::M588 { s } => {
+            line.code("M588");
+            line.text('S', Some(s));
+        }
+        GCode::M589 { s, p, i } => {
+            line.code("M589");
+            line.text('S', Some(s));
+            line.text('P', Some(p));
+            line.text('I', Some(i));
+        }
+        GCode::M590 {} => line.code("M590"),
+        GCode::M591 {
+            c,
+            d,
+            p,
+            s,
+            r,
+            e,
+            l,
+        } => {
+            line.code("M591");
+            line.number('C', *c);
+            line.number('D', *d);
+            line.number('P', *p);
+            line.number('S', *s);
+            line.list('R', r.map(|(min, max)| [min, max]));
+            line.number('E', *e);
+            line.number('L', *l);
+        }
+        GCode::M592 { d, a, b, l, t } => {
+            line.code("M592");
+            line.number('D', *d);
+            line.number('A', *a);
+            line.number('B', *b);
+            line.number('L', *l);
+            line.number('T', *t);
+        }
+        GCode::M593 { p, f, s, l, h, t } => {
+            line.code("M593");
+            line.text('P', p.as_deref());
+            line.number('F', *f);
+            line.number('S', *s);
+            line.number('L', *l);
+            line.list('H', h.as_deref());
+            line.list('T', t.as_deref());
+        }
+        GCode::M594 { p } => {
+            line.code("M594");
+            line.number('P', *p);
+        }
+        GCode::M595 { p, s } => {
+            line.code("M595");
+            line.number('P', *p);
+            line.number('S', *s);
+        }
+        GCode::M596 { p } => {
+            line.code("M596");
+            line.number('P', *p);
+        }
+        GCode::T(tool) => line.code(&format!("T{}", tool)),
+    }
+}
+
+#[test]
+fn test_gcode_emitter() {
+    use super::gcode_parser::parse;
+
+    let src = "%\n\
+               O1000 (part)\n\
+               N10 G0 G90 G54 X0 Y-1.5 S12000 M3\n\
+               /G1 X10.125 Y.5 F300 ; cut\n\
+               M117 Layer 1 of 10\n\
+               M32 \"job (copy).g\"\n\
+               G10 P1 X2 A0.5\n\
+               M569 P0 S1 T1:2:3:4 Y2:3\n\
+               T1 M6\n\
+               %\n";
+    let blocks = parse(src).unwrap();
+    let text = emit_program(&blocks, &EmitOptions::default());
+    let reparsed = parse(&text).unwrap();
+    assert_eq!(blocks, reparsed);
+
+    let text = "G1 (plunge) X5 (then cut) Z-1 F300 (done)\n";
+    assert_eq!(
+        emit_program(&parse(text).unwrap(), &EmitOptions::default()),
+        text
+    );
+
+    let options = EmitOptions {
+        precision: Some(2),
+        line_numbers: Some(10),
+        checksums: true,
+    };
+    let block = &parse("g1 x1.2345 y-4.0 f1500").unwrap()[0];
+    let text = emit_program(std::slice::from_ref(block), &options);
+    assert_eq!(text, "N10 G1 X1.23 Y-4 F1500*125\n");
+    assert_eq!(
+        parse(&text).unwrap()[0].codes,
+        parse("G1 X1.23 Y-4 F1500").unwrap()[0].codes
+    );
+}
@@ -0,0 +1,285 @@
+//! Estimates how long a program takes to run and what it uses, before it goes to the machine.
+//!
+//! Moves come from the [`Interpreter`](super::gcode_interpreter::Interpreter), which tracks the
+//! modal state. Their timing follows a constant acceleration planner like grbl's or Marlin's: each
+//! segment speeds up from and slows down to the speed it can take its corners at, found from the
+//! junction deviation, so a program full of short segments and sharp corners takes longer than
+//! its distance over its feedrate.
+//!
+//! Time with the spindle on counts as beam time for lasers and plasma torches, which use `M3` and
+//! `M5` to fire.
+
+use std::collections::BTreeMap;
+
+use super::gcode_arc;
+use super::gcode_interpreter::{Move, MoveKind, Spindle, E, X, Y, Z};
+
+/// Chord tolerance in mm for splitting arcs into the segments a planner would see.
+const ARC_TOLERANCE: f32 = 0.01;
+
+/// How fast the machine can change speed, in mm and seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Dynamics {
+    /// mm/s².
+    pub acceleration: f32,
+
+    /// How far in mm the path may stray from a corner at speed, as in grbl and Marlin.
+    pub junction_deviation: f32,
+
+    /// Speed of rapids and homing in mm/min.
+    pub rapid_feedrate: f32,
+}
+
+impl Default for Dynamics {
+    fn default() -> Self {
+        Self {
+            acceleration: 500.0,
+            junction_deviation: 0.05,
+            rapid_feedrate: 3000.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Estimate {
+    /// Seconds for the whole program.
+    pub time: f32,
+
+    /// mm travelled by rapids and homing.
+    pub rapid_distance: f32,
+
+    /// mm travelled at a programmed feedrate.
+    pub feed_distance: f32,
+
+    /// Seconds spent with each tool in the spindle, `None` before the first tool change.
+    pub tool_time: BTreeMap<Option<u32>, f32>,
+
+    /// Net mm of filament pushed by the extruder.
+    pub extrusion: f32,
+
+    /// Seconds moving or dwelling with the spindle, laser or torch on.
+    pub beam_time: f32,
+
+    /// Feed moves with no feedrate, which take no time here and never finish on a machine.
+    pub unfed_moves: usize,
+}
+
+/// One straight piece of motion for the planner.
+struct Segment {
+    /// Index of the move it comes from.
+    source: usize,
+
+    length: f32,
+
+    /// Direction of travel in XYZ, or of the extruder for extrude-only moves.
+    direction: [f32; 3],
+
+    /// Programmed speed in mm/s.
+    speed: f32,
+}
+
+/// Totals for a program.
+pub fn estimate(moves: &[Move], dynamics: &Dynamics) -> Estimate {
+    let mut estimate = Estimate::default();
+    let mut segments = vec![];
+
+    for (index, motion) in moves.iter().enumerate() {
+        estimate.extrusion += motion.end[E] - motion.start[E];
+        match motion.kind {
+            MoveKind::Rapid | MoveKind::Home => estimate.rapid_distance += motion.length(),
+            MoveKind::Linear | MoveKind::Arc { .. } | MoveKind::Probe => {
+                estimate.feed_distance += motion.length();
+            }
+            MoveKind::Dwell { .. } => {}
+        }
+
+        if let MoveKind::Dwell { seconds } = motion.kind {
+            // The machine stops for a dwell.
+            plan(&segments, moves, dynamics, &mut estimate);
+            segments.clear();
+            add_time(&mut estimate, motion, seconds);
+            continue;
+        }
+
+        let speed = match motion.kind {
+            MoveKind::Rapid | MoveKind::Home => dynamics.rapid_feedrate,
+            _ => motion.feedrate.unwrap_or(0.0),
+        } / 60.0;
+        if speed <= 0.0 {
+            estimate.unfed_moves += 1;
+            continue;
+        }
+
+        let mut pieces = vec![];
+        gcode_arc::linearize_move(motion, ARC_TOLERANCE, &mut pieces);
+        for piece in &pieces {
+            let delta = [
+                piece.end[X] - piece.start[X],
+                piece.end[Y] - piece.start[Y],
+                piece.end[Z] - piece.start[Z],
+            ];
+            let mut length = delta.iter().map(|d| d * d).sum::<f32>().sqrt();
+            let direction = if length > 0.0 {
+                delta.map(|d| d / length)
+            } else {
+                // Extruder moves on their own, such as retractions.
+                length = (piece.end[E] - piece.start[E]).abs();
+                [0.0, 0.0, 0.0]
+            };
+            if length > 0.0 {
+                segments.push(Segment {
+                    source: index,
+                    length,
+                    direction,
+                    speed,
+                });
+            }
+        }
+    }
+    plan(&segments, moves, dynamics, &mut estimate);
+    estimate
+}
+
+fn add_time(estimate: &mut Estimate, motion: &Move, seconds: f32) {
+    estimate.time += seconds;
+    *estimate.tool_time.entry(motion.tool).or_default() += seconds;
+    if motion.spindle != Spindle::Off {
+        estimate.beam_time += seconds;
+    }
+}
+
+/// Fastest speed in mm/s through the corner from `a` to `b`, the grbl way: the speed at which
+/// a circle `junction_deviation` deep into the corner needs no more than the acceleration.
+fn junction_speed(a: &Segment, b: &Segment, dynamics: &Dynamics) -> f32 {
+    if a.direction == [0.0; 3] || b.direction == [0.0; 3] {
+        return 0.0;
+    }
+    let cos_theta = -(0..3)
+        .map(|axis| a.direction[axis] * b.direction[axis])
+        .sum::<f32>();
+    if cos_theta > 0.999_999 {
+        // Reversal.
+        return 0.0;
+    }
+    if cos_theta < -0.999_999 {
+        // Straight on.
+        return f32::INFINITY;
+    }
+    let sin_half = ((1.0 - cos_theta) / 2.0).sqrt();
+    (dynamics.acceleration * dynamics.junction_deviation * sin_half / (1.0 - sin_half)).sqrt()
+}
+
+/// Times a run of segments that starts and ends at rest.
+fn plan(segments: &[Segment], moves: &[Move], dynamics: &Dynamics, estimate: &mut Estimate) {
+    if segments.is_empty() {
+        return;
+    }
+    let accel = dynamics.acceleration.max(1e-3);
+
+    // The most each segment may enter at: its own speed, the previous one's and the corner.
+    let mut entry: Vec<f32> = (0..segments.len())
+        .map(|i| {
+            if i == 0 {
+                0.0
+            } else {
+                let (a, b) = (&segments[i - 1], &segments[i]);
+                junction_speed(a, b, dynamics).min(a.speed).min(b.speed)
+            }
+        })
+        .collect();
+    entry.push(0.0);
+
+    // Slow down in time for what comes next, then respect what can be reached from behind.
+    for i in (0..segments.len()).rev() {
+        let reachable = (entry[i + 1].powi(2) + 2.0 * accel * segments[i].length).sqrt();
+        entry[i] = entry[i].min(reachable);
+    }
+    for i in 0..segments.len() {
+        let reachable = (entry[i].powi(2) + 2.0 * accel * segments[i].length).sqrt();
+        entry[i + 1] = entry[i + 1].min(reachable);
+    }
+
+    for (i, segment) in segments.iter().enumerate() {
+        let (start, end, cruise) = (entry[i], entry[i + 1], segment.speed);
+        let speeding_up = (cruise.powi(2) - start.powi(2)) / (2.0 * accel);
+        let slowing_down = (cruise.powi(2) - end.powi(2)) / (2.0 * accel);
+        let seconds = if speeding_up + slowing_down <= segment.length {
+            (cruise - start) / accel
+                + (cruise - end) / accel
+                + (segment.length - speeding_up - slowing_down) / cruise
+        } else {
+            // Never reaches the programmed speed.
+            let peak = ((2.0 * accel * segment.length + start.powi(2) + end.powi(2)) / 2.0).sqrt();
+            (peak - start) / accel + (peak - end) / accel
+        };
+        add_time(estimate, &moves[segment.source], seconds);
+    }
+}
+
+/// Seconds as `h:mm:ss`.
+pub fn format_duration(seconds: f32) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[test]
+fn test_gcode_estimate() {
+    use super::gcode_interpreter::Interpreter;
+    use super::gcode_parser::parse;
+
+    let dynamics = Dynamics {
+        acceleration: 100.0,
+        junction_deviation: 0.05,
+        rapid_feedrate: 6000.0,
+    };
+    let run = |program: &str| {
+        let moves = Interpreter::default().run(&parse(program).unwrap());
+        estimate(&moves, &dynamics)
+    };
+
+    // 100 mm at 10 mm/s: 0.1 s to speed up over 0.5 mm, 0.1 s to stop, 99 mm cruising.
+    let line = run("G1 X100 F600");
+    assert!((line.time - 10.1).abs() < 1e-3, "{}", line.time);
+    assert_eq!(line.feed_distance, 100.0);
+
+    // A square slows down at every corner, but not to a stop.
+    let square = run("G1 X100 F600\nY100\nX0\nY0");
+    let straight = run("G1 X400 F600");
+    assert!(square.time > straight.time + 0.1);
+    assert!(square.time < 4.0 * line.time - 0.1);
+
+    // Short moves never reach their feedrate: 1 mm speeding up and slowing down at 100 mm/s².
+    let short = run("G1 X1 F6000");
+    assert!((short.time - 0.2).abs() < 1e-3, "{}", short.time);
+
+    let job = run("G0 X10\n\
+         T1 M6\n\
+         M3 S1000\n\
+         G1 X20 F600\n\
+         G4 P500\n\
+         M5\n\
+         G1 X30 E5\n\
+         G1 E3\n\
+         G1 X40 F0");
+    assert_eq!(job.rapid_distance, 10.0);
+    assert_eq!(job.feed_distance, 30.0);
+    assert_eq!(job.extrusion, 3.0);
+    assert_eq!(job.unfed_moves, 1);
+    // The cut carries on at full speed from the rapid and stops for the dwell.
+    let cut = 0.1 + 9.5 / 10.0;
+    assert!(
+        (job.beam_time - (cut + 0.5)).abs() < 1e-3,
+        "{}",
+        job.beam_time
+    );
+    assert!(job.tool_time[&None] > 0.0);
+    assert!((job.tool_time.values().sum::<f32>() - job.time).abs() < 1e-3);
+
+    assert_eq!(format_duration(3725.4), "1:02:05");
+}
@@ -0,0 +1,1046 @@
+//! Resolves a parsed G-code program into absolute machine moves.
+//!
+//! The [`Interpreter`] tracks the modal state a controller would: distance mode (`G90`/`G91`,
+//! `M82`/`M83`), units (`G20`/`G21`), plane (`G17`/`G18`/`G19`), work offsets (`G54`–`G59`,
+//! `G10 L2`/`L20`), `G92` offsets, feed mode (`G93`/`G94`), the tool, tool radii (`G10 L1`),
+//! cutter compensation (`G40`–`G42`) and the spindle. Its output is a list of [`Move`]s in
+//! millimetres and machine coordinates, ready for preview, planning or estimation.
+//!
+//! Canned drilling cycles (`G81`–`G89` with `G98`/`G99`) come out as the rapids, feeds and dwells
+//! they stand for, and [`Move::to_gcode`] turns those back into codes for firmware that only
+//! knows simple motion.
+//!
+//! Codes on one line run in the order a CNC controller runs them, not the order they are
+//! written: settings first, then tool change and spindle, dwell, motion and finally program stops.
+
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use super::gcode_parser::Block;
+use super::{Cycle, GCode};
+
+/// Machine axes in the order used by [`Position`].
+pub const AXES: [char; 4] = ['X', 'Y', 'Z', 'E'];
+
+/// `X`, `Y`, `Z` and `E` in millimetres.
+pub type Position = [f32; 4];
+
+pub const X: usize = 0;
+pub const Y: usize = 1;
+pub const Z: usize = 2;
+pub const E: usize = 3;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Distance {
+    Absolute,
+    Relative,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Units {
+    Millimeters,
+    Inches,
+}
+
+impl Units {
+    /// Millimetres per unit.
+    pub fn scale(self) -> f32 {
+        match self {
+            Units::Millimeters => 1.0,
+            Units::Inches => 25.4,
+        }
+    }
+}
+
+/// The plane arcs are drawn in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Plane {
+    /// `G17`
+    XY,
+
+    /// `G18`
+    ZX,
+
+    /// `G19`
+    YZ,
+}
+
+impl Plane {
+    /// The two axes spanning the plane, counter-clockwise when seen from the positive third axis,
+    /// followed by that third axis.
+    pub fn axes(self) -> [usize; 3] {
+        match self {
+            Plane::XY => [X, Y, Z],
+            Plane::ZX => [Z, X, Y],
+            Plane::YZ => [Y, Z, X],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeedMode {
+    /// `G94`: `F` is in units per minute.
+    UnitsPerMinute,
+
+    /// `G93`: `F` is the number of moves per minute, so each move takes `1/F` minutes.
+    InverseTime,
+}
+
+/// Where a canned cycle leaves the tool after each hole.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CycleReturn {
+    /// `G98`: back to the height the cycle started from, or R if that is higher.
+    Initial,
+
+    /// `G99`: back to R.
+    Retract,
+}
+
+/// Which side of the programmed path the tool runs on, and how far from it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compensation {
+    /// `G40`
+    Off,
+
+    /// `G41`
+    Left { radius: f32 },
+
+    /// `G42`
+    Right { radius: f32 },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Spindle {
+    Off,
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Everything that carries over from one block to the next.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModalState {
+    pub distance: Distance,
+    pub extruder_distance: Distance,
+    pub units: Units,
+    pub plane: Plane,
+
+    /// `0` for `G54` up to `5` for `G59`.
+    pub work_offset: usize,
+
+    pub feed_mode: FeedMode,
+    pub cycle_return: CycleReturn,
+
+    /// Last `F` word: mm/min in `G94`, moves per minute in `G93`.
+    pub feedrate: f32,
+
+    /// Tool chosen by the last `T` word.
+    pub selected_tool: Option<u32>,
+
+    /// Tool in the spindle, changed to the selected tool by `M6`.
+    pub tool: Option<u32>,
+
+    pub spindle: Spindle,
+
+    /// Last `S` word given to `M3` or `M4`.
+    pub spindle_speed: f32,
+
+    pub compensation: Compensation,
+}
+
+impl Default for ModalState {
+    fn default() -> Self {
+        Self {
+            distance: Distance::Absolute,
+            extruder_distance: Distance::Absolute,
+            units: Units::Millimeters,
+            plane: Plane::XY,
+            work_offset: 0,
+            feed_mode: FeedMode::UnitsPerMinute,
+            cycle_return: CycleReturn::Initial,
+            feedrate: 0.0,
+            selected_tool: None,
+            tool: None,
+            spindle: Spindle::Off,
+            spindle_speed: 0.0,
+            compensation: Compensation::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoveKind {
+    /// `G0`
+    Rapid,
+
+    /// `G1`
+    Linear,
+
+    /// `G2` (`clockwise`) or `G3`, around `center` in the given plane.
+    Arc {
+        center: Position,
+        clockwise: bool,
+        plane: Plane,
+
+        /// Full circles before the last partial one, one less than `P`.
+        turns: u32,
+    },
+
+    /// `G38.x`: towards the end point until the probe trips.
+    Probe,
+
+    /// `G28`
+    Home,
+
+    /// `G4`: stand still.
+    Dwell { seconds: f32 },
+}
+
+/// One fully resolved move in machine coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Move {
+    /// Source line of the block the move came from.
+    pub line: usize,
+
+    pub kind: MoveKind,
+    pub start: Position,
+    pub end: Position,
+
+    /// mm/min, `None` for rapids, homing and dwells.
+    pub feedrate: Option<f32>,
+
+    pub tool: Option<u32>,
+    pub spindle: Spindle,
+    pub spindle_speed: f32,
+
+    /// Cutter compensation still to be applied, see [`super::gcode_compensation`].
+    pub compensation: Compensation,
+}
+
+impl Move {
+    /// Length of the tool path in `X`, `Y` and `Z`.
+    pub fn length(&self) -> f32 {
+        match self.kind {
+            MoveKind::Arc { center, plane, .. } => {
+                let [a, b, normal] = plane.axes();
+                let radius = (self.start[a] - center[a]).hypot(self.start[b] - center[b]);
+                (radius * self.sweep()).hypot(self.end[normal] - self.start[normal])
+            }
+            MoveKind::Dwell { .. } => 0.0,
+            _ => {
+                let [dx, dy, dz] = [
+                    self.end[X] - self.start[X],
+                    self.end[Y] - self.start[Y],
+                    self.end[Z] - self.start[Z],
+                ];
+                (dx * dx + dy * dy + dz * dz).sqrt()
+            }
+        }
+    }
+
+    /// Angle an arc turns through in radians, always positive, and `0` for other moves.
+    ///
+    /// An arc that ends where it starts is a full circle.
+    pub fn sweep(&self) -> f32 {
+        let MoveKind::Arc {
+            center,
+            clockwise,
+            plane,
+            turns,
+        } = self.kind
+        else {
+            return 0.0;
+        };
+        let [a, b, _] = plane.axes();
+        let start_angle = (self.start[b] - center[b]).atan2(self.start[a] - center[a]);
+        let end_angle = (self.end[b] - center[b]).atan2(self.end[a] - center[a]);
+        let mut sweep = if clockwise {
+            start_angle - end_angle
+        } else {
+            end_angle - start_angle
+        };
+        if sweep <= 1e-6 {
+            sweep += TAU;
+        }
+        sweep + turns as f32 * TAU
+    }
+
+    /// The move as a single code in machine coordinates, for a controller without offsets.
+    ///
+    /// Arc centres are written as offsets in the arc's plane, which the controller has to be in.
+    pub fn to_gcode(&self) -> GCode {
+        let [x, y, z] = [self.end[X], self.end[Y], self.end[Z]].map(Some);
+        let e = (self.end[E] != self.start[E]).then_some(self.end[E]);
+        match self.kind {
+            MoveKind::Rapid => GCode::G0 {
+                x,
+                y,
+                z,
+                e,
+                f: None,
+            },
+            MoveKind::Linear => GCode::G1 {
+                x,
+                y,
+                z,
+                e,
+                f: self.feedrate,
+            },
+            MoveKind::Arc {
+                center,
+                clockwise,
+                plane,
+                turns,
+            } => {
+                let [a, b, _] = plane.axes();
+                let mut offsets = [None; 3];
+                offsets[a] = Some(center[a] - self.start[a]);
+                offsets[b] = Some(center[b] - self.start[b]);
+                let [i, j, k] = offsets;
+                let p = (turns > 0).then_some(turns + 1);
+                if clockwise {
+                    GCode::G2 {
+                        x,
+                        y,
+                        z,
+                        i,
+                        j,
+                        k,
+                        r: None,
+                        p,
+                        e,
+                        f: self.feedrate,
+                    }
+                } else {
+                    GCode::G3 {
+                        x,
+                        y,
+                        z,
+                        i,
+                        j,
+                        k,
+                        r: None,
+                        p,
+                        e,
+                        f: self.feedrate,
+                    }
+                }
+            }
+            MoveKind::Probe => GCode::G38_2 {
+                x,
+                y,
+                z,
+                f: self.feedrate,
+            },
+            MoveKind::Home => GCode::G28 {
+                x: self.end[X] != self.start[X],
+                y: self.end[Y] != self.start[Y],
+                z: self.end[Z] != self.start[Z],
+                p: None,
+                i: None,
+            },
+            MoveKind::Dwell { seconds } => GCode::G4 {
+                p: Some(seconds * 1000.0),
+                s: None,
+            },
+        }
+    }
+}
+
+/// Words that carry over between the blocks of a canned cycle, as programmed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CannedCycle {
+    /// Machine coordinate on the drilling axis when the cycle started.
+    pub initial: f32,
+
+    /// Hole bottom on the drilling axis (`Z` in `G17`).
+    pub depth: Option<f32>,
+
+    pub r: Option<f32>,
+    pub q: Option<f32>,
+    pub p: Option<f32>,
+}
+
+/// Clearance above the previous peck that `G83` rapids back down to.
+const PECK_CLEARANCE: f32 = 0.254;
+
+/// Runs blocks through the modal state and collects the moves they make.
+///
+/// Dwells are read the Marlin way, with `G4 P` in milliseconds. Programs for GRBL or LinuxCNC,
+/// where `P` is seconds, go through [`gcode_dialect::convert`](super::gcode_dialect::convert)
+/// to [`Dialect::Marlin`](super::gcode_dialect::Dialect::Marlin) first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Interpreter {
+    pub state: ModalState,
+
+    /// Current position in machine coordinates.
+    pub position: Position,
+
+    /// `G54` to `G59`, in machine coordinates.
+    pub work_offsets: [Position; 6],
+
+    /// Offset set by `G92`, on top of the work offset.
+    pub g92_offset: Position,
+
+    /// Tool radii in mm, set with `G10 L1 P<tool> R<radius>`.
+    pub tool_radii: HashMap<u32, f32>,
+
+    /// The canned cycle in progress, until `G80` or another motion code.
+    pub cycle: Option<CannedCycle>,
+
+    /// Set by `M2` or `M30`; later blocks are ignored.
+    pub finished: bool,
+}
+
+impl Interpreter {
+    /// Moves for a whole program, up to its `M2` or `M30`.
+    pub fn run(&mut self, blocks: &[Block]) -> Vec<Move> {
+        let mut moves = vec![];
+        for block in blocks {
+            self.run_block(block, &mut moves);
+        }
+        moves
+    }
+
+    pub fn run_block(&mut self, block: &Block, moves: &mut Vec<Move>) {
+        if self.finished {
+            return;
+        }
+        let mut codes: Vec<&GCode> = block.codes.iter().collect();
+        codes.sort_by_key(|code| execution_order(code));
+        let machine_coordinates = codes.iter().any(|code| matches!(code, GCode::G53));
+        for code in codes {
+            self.execute(code, block.line, machine_coordinates, moves);
+        }
+    }
+
+    /// The current position in the active work coordinates and units, as a program sees it.
+    pub fn program_position(&self) -> Position {
+        let scale = self.state.units.scale();
+        let mut position = self.position;
+        for (axis, value) in position.iter_mut().enumerate() {
+            *value = (*value - self.offset(axis)) / scale;
+        }
+        position
+    }
+
+    fn offset(&self, axis: usize) -> f32 {
+        self.work_offsets[self.state.work_offset][axis] + self.g92_offset[axis]
+    }
+
+    /// Where the axis words take the machine.
+    fn target(&self, words: [Option<f32>; 4], machine_coordinates: bool) -> Position {
+        let scale = self.state.units.scale();
+        let mut target = self.position;
+        for (axis, word) in words.iter().enumerate() {
+            if let Some(value) = word {
+                let distance = if axis == E {
+                    self.state.extruder_distance
+                } else {
+                    self.state.distance
+                };
+                target[axis] = match distance {
+                    Distance::Relative => self.position[axis] + value * scale,
+                    Distance::Absolute if machine_coordinates => value * scale,
+                    Distance::Absolute => value * scale + self.offset(axis),
+                };
+            }
+        }
+        target
+    }
+
+    fn set_feedrate(&mut self, f: Option<f32>) {
+        if let Some(f) = f {
+            self.state.feedrate = match self.state.feed_mode {
+                FeedMode::UnitsPerMinute => f * self.state.units.scale(),
+                FeedMode::InverseTime => f,
+            };
+        }
+    }
+
+    /// Moves one axis, skipping moves that go nowhere.
+    fn move_axis(
+        &mut self,
+        line: usize,
+        kind: MoveKind,
+        axis: usize,
+        value: f32,
+        moves: &mut Vec<Move>,
+    ) {
+        let mut end = self.position;
+        end[axis] = value;
+        if end != self.position {
+            self.push_move(line, kind, end, moves);
+        }
+    }
+
+    fn dwell(&mut self, line: usize, seconds: Option<f32>, moves: &mut Vec<Move>) {
+        if let Some(seconds) = seconds.filter(|seconds| *seconds > 0.0) {
+            let end = self.position;
+            self.push_move(line, MoveKind::Dwell { seconds }, end, moves);
+        }
+    }
+
+    /// Expands one block of a `G81` to `G89` cycle into rapids, feeds and dwells.
+    ///
+    /// Drills along the axis normal to the plane, from R towards the hole bottom, at every hole
+    /// position. `L` repeats the hole, which only moves anywhere in incremental mode.
+    fn canned_cycle(
+        &mut self,
+        code: &GCode,
+        line: usize,
+        words: [Option<f32>; 3],
+        repeats: u32,
+        moves: &mut Vec<Move>,
+    ) {
+        let Some(cycle) = self.cycle else {
+            return;
+        };
+        let (Some(depth), Some(r)) = (cycle.depth, cycle.r) else {
+            return;
+        };
+        let scale = self.state.units.scale();
+        let relative = self.state.distance == Distance::Relative;
+        let [a, b, normal] = self.state.plane.axes();
+
+        // In incremental mode R is measured from the initial level and the bottom from R.
+        let r_level = if relative {
+            cycle.initial + r * scale
+        } else {
+            r * scale + self.offset(normal)
+        };
+        let bottom = if relative {
+            r_level + depth * scale
+        } else {
+            depth * scale + self.offset(normal)
+        };
+        let clear = match self.state.cycle_return {
+            CycleReturn::Initial => cycle.initial.max(r_level),
+            CycleReturn::Retract => r_level,
+        };
+        let spindle = self.state.spindle;
+
+        if self.position[normal] < r_level {
+            self.move_axis(line, MoveKind::Rapid, normal, r_level, moves);
+        }
+        for _ in 0..repeats {
+            // One traverse to the hole, in the plane.
+            let mut hole = self.position;
+            for axis in [a, b] {
+                if let Some(value) = words[axis] {
+                    hole[axis] = if relative {
+                        self.position[axis] + value * scale
+                    } else {
+                        value * scale + self.offset(axis)
+                    };
+                }
+            }
+            if hole != self.position {
+                self.push_move(line, MoveKind::Rapid, hole, moves);
+            }
+
+            if let GCode::G87(_) = code {
+                // Back boring: shift the oriented tool sideways, go down through the hole to R
+                // below the part, shift back and cut upwards to the bottom level.
+                let hole = self.position[a];
+                let shift = hole + cycle.q.unwrap_or(0.0) * scale;
+                self.state.spindle = Spindle::Off;
+                self.move_axis(line, MoveKind::Rapid, a, shift, moves);
+                self.move_axis(line, MoveKind::Rapid, normal, r_level, moves);
+                self.move_axis(line, MoveKind::Rapid, a, hole, moves);
+                self.state.spindle = spindle;
+                self.move_axis(line, MoveKind::Linear, normal, bottom, moves);
+                self.dwell(line, cycle.p, moves);
+                self.state.spindle = Spindle::Off;
+                self.move_axis(line, MoveKind::Rapid, a, shift, moves);
+                self.move_axis(line, MoveKind::Rapid, normal, cycle.initial, moves);
+                self.move_axis(line, MoveKind::Rapid, a, hole, moves);
+                self.state.spindle = spindle;
+                continue;
+            }
+
+            self.move_axis(line, MoveKind::Rapid, normal, r_level, moves);
+            match code {
+                GCode::G83(_) => {
+                    let peck = cycle
+                        .q
+                        .filter(|q| *q > 0.0)
+                        .map_or(f32::INFINITY, |q| q * scale);
+                    let mut reached = r_level;
+                    while reached > bottom {
+                        if reached < r_level {
+                            let approach = reached + PECK_CLEARANCE;
+                            self.move_axis(line, MoveKind::Rapid, normal, approach, moves);
+                        }
+                        reached = (reached - peck).max(bottom);
+                        self.move_axis(line, MoveKind::Linear, normal, reached, moves);
+                        self.move_axis(line, MoveKind::Rapid, normal, r_level, moves);
+                    }
+                }
+                GCode::G84(_) => {
+                    self.move_axis(line, MoveKind::Linear, normal, bottom, moves);
+                    self.dwell(line, cycle.p, moves);
+                    self.state.spindle = match spindle {
+                        Spindle::Clockwise => Spindle::CounterClockwise,
+                        Spindle::CounterClockwise => Spindle::Clockwise,
+                        Spindle::Off => Spindle::Off,
+                    };
+                    self.move_axis(line, MoveKind::Linear, normal, r_level, moves);
+                    self.state.spindle = spindle;
+                }
+                GCode::G85(_) | GCode::G89(_) => {
+                    self.move_axis(line, MoveKind::Linear, normal, bottom, moves);
+                    if let GCode::G89(_) = code {
+                        self.dwell(line, cycle.p, moves);
+                    }
+                    self.move_axis(line, MoveKind::Linear, normal, r_level, moves);
+                }
+                GCode::G86(_) | GCode::G88(_) => {
+                    // The manual retract of `G88` is taken as a rapid.
+                    self.move_axis(line, MoveKind::Linear, normal, bottom, moves);
+                    self.dwell(line, cycle.p, moves);
+                    self.state.spindle = Spindle::Off;
+                }
+                _ => {
+                    self.move_axis(line, MoveKind::Linear, normal, bottom, moves);
+                    if let GCode::G82(_) = code {
+                        self.dwell(line, cycle.p, moves);
+                    }
+                }
+            }
+            self.move_axis(line, MoveKind::Rapid, normal, clear, moves);
+            self.state.spindle = spindle;
+        }
+    }
+
+    fn push_move(&mut self, line: usize, kind: MoveKind, end: Position, moves: &mut Vec<Move>) {
+        let mut motion = Move {
+            line,
+            kind,
+            start: self.position,
+            end,
+            feedrate: None,
+            tool: self.state.tool,
+            spindle: self.state.spindle,
+            spindle_speed: self.state.spindle_speed,
+            compensation: self.state.compensation,
+        };
+        if matches!(
+            kind,
+            MoveKind::Linear | MoveKind::Arc { .. } | MoveKind::Probe
+        ) {
+            motion.feedrate = Some(match self.state.feed_mode {
+                FeedMode::UnitsPerMinute => self.state.feedrate,
+                FeedMode::InverseTime => motion.length() * self.state.feedrate,
+            });
+        }
+        self.position = end;
+        moves.push(motion);
+    }
+
+    fn execute(
+        &mut self,
+        code: &GCode,
+        line: usize,
+        machine_coordinates: bool,
+        moves: &mut Vec<Move>,
+    ) {
+        let scale = self.state.units.scale();
+        if execution_order(code) == 3 && !is_canned_cycle(code) {
+            self.cycle = None;
+        }
+        match code {
+            GCode::G0 { x, y, z, e, f } | GCode::G1 { x, y, z, e, f } => {
+                self.set_feedrate(*f);
+                let end = self.target([*x, *y, *z, *e], machine_coordinates);
+                let kind = if matches!(code, GCode::G0 { .. }) {
+                    MoveKind::Rapid
+                } else {
+                    MoveKind::Linear
+                };
+                self.push_move(line, kind, end, moves);
+            }
+            GCode::G2 {
+                x,
+                y,
+                z,
+                i,
+                j,
+                k,
+                r,
+                p,
+                e,
+                f,
+            }
+            | GCode::G3 {
+                x,
+                y,
+                z,
+                i,
+                j,
+                k,
+                r,
+                p,
+                e,
+                f,
+            } => {
+                self.set_feedrate(*f);
+                let end = self.target([*x, *y, *z, *e], machine_coordinates);
+                let clockwise = matches!(code, GCode::G2 { .. });
+                let plane = self.state.plane;
+                let [a, b, _] = plane.axes();
+                let mut center = self.position;
+                if let Some(r) = r {
+                    // Centre on the perpendicular bisector of the chord, on the side that makes the
+                    // arc shorter than a half circle for a positive radius, longer for a negative.
+                    let (dx, dy) = (end[a] - self.position[a], end[b] - self.position[b]);
+                    let radius = r * scale;
+                    let chord = dx.hypot(dy);
+                    let mut h = -(4.0 * radius * radius - chord * chord).max(0.0).sqrt() / chord;
+                    if !clockwise {
+                        h = -h;
+                    }
+                    if radius < 0.0 {
+                        h = -h;
+                    }
+                    if chord > 0.0 {
+                        center[a] += 0.5 * (dx - dy * h);
+                        center[b] += 0.5 * (dy + dx * h);
+                    }
+                } else {
+                    let offsets = [*i, *j, *k];
+                    center[a] += offsets[a].unwrap_or(0.0) * scale;
+                    center[b] += offsets[b].unwrap_or(0.0) * scale;
+                }
+                let kind = MoveKind::Arc {
+                    center,
+                    clockwise,
+                    plane,
+                    turns: p.map_or(0, |p| p.saturating_sub(1)),
+                };
+                self.push_move(line, kind, end, moves);
+            }
+            GCode::G38_2 { x, y, z, f }
+            | GCode::G38_3 { x, y, z, f }
+            | GCode::G38_4 { x, y, z, f }
+            | GCode::G38_5 { x, y, z, f } => {
+                self.set_feedrate(*f);
+                let end = self.target([*x, *y, *z, None], machine_coordinates);
+                self.push_move(line, MoveKind::Probe, end, moves);
+            }
+            GCode::G28 { x, y, z, .. } => {
+                // A bare `G28` homes every axis.
+                let all = !(*x || *y || *z);
+                let mut end = self.position;
+                for (axis, home) in [(X, *x), (Y, *y), (Z, *z)] {
+                    if all || home {
+                        end[axis] = 0.0;
+                    }
+                }
+                self.push_move(line, MoveKind::Home, end, moves);
+            }
+            GCode::G4 { p, s } => {
+                let seconds = p.map_or(0.0, |ms| ms / 1000.0) + s.unwrap_or(0.0);
+                let end = self.position;
+                self.push_move(line, MoveKind::Dwell { seconds }, end, moves);
+            }
+            GCode::G10 {
+                l: Some(l @ (2 | 20)),
+                p,
+                x,
+                y,
+                z,
+                ..
+            } => {
+                // P0 is the active system, P1 to P6 are G54 to G59.
+                let index = match p {
+                    Some(p @ 1..=6) => *p as usize - 1,
+                    _ => self.state.work_offset,
+                };
+                for (axis, value) in [(X, x), (Y, y), (Z, z)] {
+                    if let Some(value) = value {
+                        self.work_offsets[index][axis] = if *l == 2 {
+                            value * scale
+                        } else {
+                            self.position[axis] - self.g92_offset[axis] - value * scale
+                        };
+                    }
+                }
+            }
+            GCode::G10 {
+                l: Some(1),
+                p: Some(tool),
+                r: Some(radius),
+                ..
+            } => {
+                self.tool_radii.insert(*tool, radius * scale);
+            }
+            GCode::G40 => self.state.compensation = Compensation::Off,
+            GCode::G41 { d } | GCode::G42 { d } => {
+                let tool = d.or(self.state.tool);
+                let radius = tool
+                    .and_then(|tool| self.tool_radii.get(&tool).copied())
+                    .unwrap_or(0.0);
+                self.state.compensation = if let GCode::G41 { .. } = code {
+                    Compensation::Left { radius }
+                } else {
+                    Compensation::Right { radius }
+                };
+            }
+            GCode::G41_1 { d } => {
+                let radius = d * scale / 2.0;
+                self.state.compensation = Compensation::Left { radius };
+            }
+            GCode::G42_1 { d } => {
+                let radius = d * scale / 2.0;
+                self.state.compensation = Compensation::Right { radius };
+            }
+            GCode::G81(programmed)
+            | GCode::G82(programmed)
+            | GCode::G83(programmed)
+            | GCode::G84(programmed)
+            | GCode::G85(programmed)
+            | GCode::G86(programmed)
+            | GCode::G87(programmed)
+            | GCode::G88(programmed)
+            | GCode::G89(programmed) => {
+                let Cycle {
+                    x,
+                    y,
+                    z,
+                    r,
+                    q,
+                    p,
+                    l,
+                    f,
+                } = *programmed;
+                self.set_feedrate(f);
+                let words = [x, y, z];
+                let [_, _, normal] = self.state.plane.axes();
+                let initial = self.position[normal];
+                let cycle = self.cycle.get_or_insert(CannedCycle {
+                    initial,
+                    ..CannedCycle::default()
+                });
+                cycle.depth = words[normal].or(cycle.depth);
+                cycle.r = r.or(cycle.r);
+                cycle.q = q.or(cycle.q);
+                cycle.p = p.or(cycle.p);
+                // Blocks without axis words only update the sticky words.
+                if words.iter().any(Option::is_some) {
+                    self.canned_cycle(code, line, words, l.unwrap_or(1), moves);
+                }
+            }
+            GCode::G80 => self.cycle = None,
+            GCode::G98 => self.state.cycle_return = CycleReturn::Initial,
+            GCode::G99 => self.state.cycle_return = CycleReturn::Retract,
+            GCode::G17 => self.state.plane = Plane::XY,
+            GCode::G18 => self.state.plane = Plane::ZX,
+            GCode::G19 => self.state.plane = Plane::YZ,
+            GCode::G20 => self.state.units = Units::Inches,
+            GCode::G21 => self.state.units = Units::Millimeters,
+            GCode::G54 => self.state.work_offset = 0,
+            GCode::G55 => self.state.work_offset = 1,
+            GCode::G56 => self.state.work_offset = 2,
+            GCode::G57 => self.state.work_offset = 3,
+            GCode::G58 => self.state.work_offset = 4,
+            GCode::G59 => self.state.work_offset = 5,
+            GCode::G90 => {
+                self.state.distance = Distance::Absolute;
+                self.state.extruder_distance = Distance::Absolute;
+            }
+            GCode::G91 => {
+                self.state.distance = Distance::Relative;
+                self.state.extruder_distance = Distance::Relative;
+            }
+            GCode::M82 => self.state.extruder_distance = Distance::Absolute,
+            GCode::M83 => self.state.extruder_distance = Distance::Relative,
+            GCode::G92 { x, y, z, e } => {
+                let work_offset = self.work_offsets[self.state.work_offset];
+                for (axis, value) in [(X, x), (Y, y), (Z, z), (E, e)] {
+                    if let Some(value) = value {
+                        self.g92_offset[axis] =
+                            self.position[axis] - work_offset[axis] - value * scale;
+                    }
+                }
+            }
+            GCode::G93 => self.state.feed_mode = FeedMode::InverseTime,
+            GCode::G94 => self.state.feed_mode = FeedMode::UnitsPerMinute,
+            GCode::T(tool) => self.state.selected_tool = Some(*tool),
+            GCode::M6 {} => self.state.tool = self.state.selected_tool,
+            GCode::M3 { s } | GCode::M4 { s } => {
+                self.state.spindle = if matches!(code, GCode::M3 { .. }) {
+                    Spindle::Clockwise
+                } else {
+                    Spindle::CounterClockwise
+                };
+                if let Some(s) = s {
+                    self.state.spindle_speed = *s as f32;
+                }
+            }
+            GCode::M5 {} => self.state.spindle = Spindle::Off,
+            // A bare `M30` ends the program; with a file name it is Marlin's file delete.
+            code if is_program_end(code) => {
+                self.state.spindle = Spindle::Off;
+                self.finished = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Where a code runs among the others on its line: settings, tool change and spindle, dwell,
+/// motion, program stops.
+fn execution_order(code: &GCode) -> u8 {
+    match code {
+        GCode::M6 {} | GCode::M3 { .. } | GCode::M4 { .. } | GCode::M5 {} => 1,
+        GCode::G4 { .. } => 2,
+        GCode::G0 { .. }
+        | GCode::G1 { .. }
+        | GCode::G2 { .. }
+        | GCode::G3 { .. }
+        | GCode::G28 { .. }
+        | GCode::G38_2 { .. }
+        | GCode::G38_3 { .. }
+        | GCode::G38_4 { .. }
+        | GCode::G38_5 { .. } => 3,
+        code if is_canned_cycle(code) => 3,
+        GCode::M0 { .. } | GCode::M1 { .. } => 4,
+        code if is_program_end(code) => 4,
+        _ => 0,
+    }
+}
+
+/// `M2`, or `M30` without a file name.
+fn is_program_end(code: &GCode) -> bool {
+    match code {
+        GCode::M2 {} => true,
+        GCode::M30 { filename } => filename.is_empty(),
+        _ => false,
+    }
+}
+
+pub(super) fn is_canned_cycle(code: &GCode) -> bool {
+    matches!(
+        code,
+        GCode::G81(_)
+            | GCode::G82(_)
+            | GCode::G83(_)
+            | GCode::G84(_)
+            | GCode::G85(_)
+            | GCode::G86(_)
+            | GCode::G87(_)
+            | GCode::G88(_)
+            | GCode::G89(_)
+    )
+}
+
+#[test]
+fn test_gcode_interpreter() {
+    use super::gcode_parser::parse;
+
+    let blocks = parse(
+        "G10 L2 P2 X100 Y50\n\
+         G20 G55 G90\n\
+         G0 X1 Y1\n\
+         G91 G1 X1 F10\n\
+         G21 G90 G54 T2 M6 S1000 M3\n\
+         G92 X0\n\
+         G1 X5\n\
+         G93 G1 X15 F60\n\
+         M2\n\
+         G0 X0",
+    )
+    .unwrap();
+    let mut interpreter = Interpreter::default();
+    let moves = interpreter.run(&blocks);
+    assert_eq!(moves.len(), 4);
+
+    assert_eq!(moves[0].kind, MoveKind::Rapid);
+    assert_eq!(moves[0].end, [125.4, 75.4, 0.0, 0.0]);
+    assert_eq!(moves[0].feedrate, None);
+
+    assert_eq!(moves[1].end, [150.8, 75.4, 0.0, 0.0]);
+    assert_eq!(moves[1].feedrate, Some(254.0));
+    assert_eq!(moves[1].tool, None);
+
+    // G92 X0 makes the current X the origin.
+    assert_eq!(moves[2].end, [155.8, 75.4, 0.0, 0.0]);
+    assert_eq!(moves[2].tool, Some(2));
+    assert_eq!(moves[2].spindle, Spindle::Clockwise);
+    assert_eq!(moves[2].spindle_speed, 1000.0);
+
+    // 10 mm at one move per second.
+    assert_eq!(moves[3].feedrate, Some(600.0));
+    assert!(interpreter.finished);
+    assert_eq!(interpreter.program_position()[X], 15.0);
+
+    let mut interpreter = Interpreter::default();
+    let moves = interpreter.run(&parse("M3 S1000\nG1 X1 F100\nM30\nG0 X0").unwrap());
+    assert_eq!(moves.len(), 1);
+    assert!(interpreter.finished);
+    assert_eq!(interpreter.state.spindle, Spindle::Off);
+}
+
+#[test]
+fn test_gcode_canned_cycles() {
+    use super::gcode_parser::parse;
+
+    let run = |program: &str| {
+        let moves = Interpreter::default().run(&parse(program).unwrap());
+        let path: Vec<_> = moves
+            .iter()
+            .map(|motion| (motion.kind, motion.end[X], motion.end[Z]))
+            .collect();
+        path
+    };
+    let rapid = |x: f32, z: f32| (MoveKind::Rapid, x, z);
+    let feed = |x: f32, z: f32| (MoveKind::Linear, x, z);
+
+    // G98 returns to the initial level, the modal X repeats the cycle.
+    assert_eq!(
+        run("G0 Z10\nG98 G81 X1 Z-3 R2 F100\nX2"),
+        vec![
+            rapid(0.0, 10.0),
+            rapid(1.0, 10.0),
+            rapid(1.0, 2.0),
+            feed(1.0, -3.0),
+            rapid(1.0, 10.0),
+            rapid(2.0, 10.0),
+            rapid(2.0, 2.0),
+            feed(2.0, -3.0),
+            rapid(2.0, 10.0),
+        ]
+    );
+
+    // G99 pecks back to R, then stops at R.
+    assert_eq!(
+        run("G0 Z10\nG99 G83 X1 Z-4 R2 Q3 F100\nG80"),
+        vec![
+            rapid(0.0, 10.0),
+            rapid(1.0, 10.0),
+            rapid(1.0, 2.0),
+            feed(1.0, -1.0),
+            rapid(1.0, 2.0),
+            rapid(1.0, -1.0 + PECK_CLEARANCE),
+            feed(1.0, -4.0),
+            rapid(1.0, 2.0),
+        ]
+    );
+
+    // Incremental repeats step along X; tapping feeds out with the spindle reversed.
+    let moves = Interpreter::default()
+        .run(&parse("G0 Z5\nM3 S500\nG91 G84 X10 Z-3 R-3 L2 P0.5 F100").unwrap());
+    let feeds: Vec<_> = moves
+        .iter()
+        .enumerate()
+        .filter(|(_, motion)| motion.kind == MoveKind::Linear)
+        .collect();
+    assert_eq!(feeds.len(), 4);
+    assert_eq!((feeds[2].1.end[X], feeds[2].1.end[Z]), (20.0, -1.0));
+    let (out, retract) = feeds[3];
+    assert_eq!(retract.end[Z], 2.0);
+    assert_eq!(retract.spindle, Spindle::CounterClockwise);
+    assert!(matches!(moves[out - 1].kind, MoveKind::Dwell { seconds } if seconds == 0.5));
+    assert_eq!(moves.last().unwrap().spindle, Spindle::Clockwise);
+    assert_eq!(moves.last().unwrap().to_gcode().to_string(), "G0 X20 Y0 Z5");
+}
@@ -0,0 +1,249 @@
+//! Finds mistakes in a G-code program without running it on a machine.
+//!
+//! Every line goes through the [`Parser`] on its own, so all unknown words are reported rather
+//! than only the first. The expanded program then goes through the [`Interpreter`] to find feed
+//! moves with no feedrate, arcs whose ends are not on one circle, moves beyond the machine limits,
+//! cuts with the spindle off and programs switching between inches and millimetres.
+//!
+//! Programs that extrude are taken to be for a printer and are not checked for the spindle.
+
+use std::fmt;
+
+use super::gcode_arc;
+use super::gcode_interpreter::{Interpreter, MoveKind, Spindle, Units, AXES, E};
+use super::gcode_macro;
+use super::gcode_parser::{ParseError, Parser};
+use super::GCode;
+
+/// Difference in mm between the start and end radius of an arc before it is reported.
+const ARC_RADIUS_TOLERANCE: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// The program does not run.
+    Error,
+
+    /// The program runs but probably not as intended.
+    Warning,
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Diagnostic {
+    /// 1-based source line.
+    pub line: usize,
+
+    /// 1-based column, or 0 for the whole line.
+    pub column: usize,
+
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.column > 0 {
+            write!(f, "{}:{}: {}", self.line, self.column, self.message)
+        } else {
+            write!(f, "{}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl From<ParseError> for Diagnostic {
+    fn from(err: ParseError) -> Self {
+        let message = err.to_string();
+        // Drop the position, it has its own fields.
+        let message = message
+            .splitn(3, ':')
+            .nth(2)
+            .map_or(message.clone(), |rest| rest.trim_start().to_owned());
+        Self {
+            line: err.line,
+            column: err.column,
+            severity: Severity::Error,
+            message,
+        }
+    }
+}
+
+/// Travel of the machine in mm, in machine coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Limits {
+    /// Lowest X, Y and Z.
+    pub min: [f32; 3],
+
+    /// Highest X, Y and Z.
+    pub max: [f32; 3],
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            min: [0.0, 0.0, -100.0],
+            max: [300.0, 300.0, 100.0],
+        }
+    }
+}
+
+/// Every problem found in the program, sorted by position.
+pub fn lint(src: &str, limits: &Limits) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    // Lines with parameters or program flow only make sense once expanded. Bad lines are left
+    // out of the expanded program so the rest can still be checked.
+    let mut parser = Parser::default();
+    let mut checked = String::with_capacity(src.len());
+    for (index, text) in src.lines().enumerate() {
+        let flow = text.contains(['#', '[']) || gcode_macro::is_control(text);
+        match parser.parse_line(index + 1, text) {
+            Err(err) if !flow => diagnostics.push(err.into()),
+            _ => checked.push_str(text),
+        }
+        checked.push('\n');
+    }
+
+    match gcode_macro::expand(&checked) {
+        Ok(blocks) => {
+            let mut units = None;
+            for block in &blocks {
+                for code in &block.codes {
+                    let set = match code {
+                        GCode::G20 => Units::Inches,
+                        GCode::G21 => Units::Millimeters,
+                        _ => continue,
+                    };
+                    if units.map_or(false, |units| units != set) {
+                        diagnostics.push(warning(block.line, "switches units mid-program"));
+                    }
+                    units = Some(set);
+                }
+            }
+            check_moves(
+                &Interpreter::default().run(&blocks),
+                limits,
+                &mut diagnostics,
+            );
+        }
+        Err(err) => {
+            let err = Diagnostic::from(err);
+            if !diagnostics.contains(&err) {
+                diagnostics.push(err);
+            }
+        }
+    }
+
+    diagnostics.sort();
+    diagnostics.dedup();
+    diagnostics
+}
+
+fn warning(line: usize, message: impl Into<String>) -> Diagnostic {
+    Diagnostic {
+        line,
+        column: 0,
+        severity: Severity::Warning,
+        message: message.into(),
+    }
+}
+
+fn check_moves(
+    moves: &[super::gcode_interpreter::Move],
+    limits: &Limits,
+    out: &mut Vec<Diagnostic>,
+) {
+    let printing = moves.iter().any(|motion| motion.end[E] != motion.start[E]);
+    let mut spindle_warned = false;
+
+    for motion in moves {
+        let cutting = matches!(motion.kind, MoveKind::Linear | MoveKind::Arc { .. });
+        if cutting && motion.feedrate == Some(0.0) {
+            out.push(warning(motion.line, "feed move with no feedrate"));
+        }
+
+        if motion.spindle != Spindle::Off {
+            spindle_warned = false;
+        } else if cutting && !printing && !spindle_warned {
+            out.push(warning(motion.line, "cutting with the spindle off"));
+            spindle_warned = true;
+        }
+
+        if let MoveKind::Arc { center, plane, .. } = motion.kind {
+            let [a, b, _] = plane.axes();
+            let radius = |p: [f32; 4]| (p[a] - center[a]).hypot(p[b] - center[b]);
+            let difference = (radius(motion.start) - radius(motion.end)).abs();
+            if difference > ARC_RADIUS_TOLERANCE {
+                out.push(warning(
+                    motion.line,
+                    format!(
+                        "arc radius differs by {:.3} mm between start and end",
+                        difference
+                    ),
+                ));
+            }
+        }
+
+        if motion.kind == MoveKind::Home {
+            continue;
+        }
+        let mut segments = vec![];
+        gcode_arc::linearize_move(motion, 0.01, &mut segments);
+        for segment in &segments {
+            for (axis, name) in AXES.iter().enumerate().take(3) {
+                let value = segment.end[axis];
+                let side = if value < limits.min[axis] - 1e-3 {
+                    "below"
+                } else if value > limits.max[axis] + 1e-3 {
+                    "above"
+                } else {
+                    continue;
+                };
+                out.push(warning(
+                    motion.line,
+                    format!("{} goes {} the machine limits", name, side),
+                ));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_gcode_lint() {
+    let program = "G21 G90\n\
+                   G0 X10 Y10 Z5\n\
+                   G1 Z-1\n\
+                   M3 S10000\n\
+                   G1 X20 F300\n\
+                   G2 X30 Y10 I4 J0\n\
+                   G0 X400\n\
+                   G20\n\
+                   G1 X1 K\n\
+                   #1 = [1 + 1]\n\
+                   o100 if [#1 GT 1]\n\
+                   G0 X#1\n\
+                   o100 endif\n\
+                   G0 X20";
+    let diagnostics: Vec<_> = lint(program, &Limits::default())
+        .into_iter()
+        .map(|diagnostic| (diagnostic.line, diagnostic.severity, diagnostic.message))
+        .collect();
+    let warning = |line, message: &str| (line, Severity::Warning, message.to_owned());
+    assert_eq!(diagnostics.len(), 7, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0], warning(3, "cutting with the spindle off"));
+    assert_eq!(diagnostics[1], warning(3, "feed move with no feedrate"));
+    assert_eq!(
+        diagnostics[2],
+        warning(6, "arc radius differs by 2.000 mm between start and end")
+    );
+    assert_eq!(
+        diagnostics[3],
+        warning(7, "X goes above the machine limits")
+    );
+    assert_eq!(diagnostics[4], warning(8, "switches units mid-program"));
+    assert_eq!((diagnostics[5].0, diagnostics[5].1), (9, Severity::Error));
+    // Checking goes on past the bad line, in inches.
+    assert_eq!(
+        diagnostics[6],
+        warning(14, "X goes above the machine limits")
+    );
+}
@@ -0,0 +1,1024 @@
+//! Expands LinuxCNC and Fanuc style program flow into a flat list of blocks.
+//!
+//! Handles numbered (`#1`) and named (`#<depth>`, global `#<_depth>`) parameters, `[expressions]`
+//! with the usual operators and functions, O-word subroutines (`o100 sub` … `o100 endsub`,
+//! `o100 call [1] [2]`), conditions and loops (`if`/`elseif`/`else`, `while`, `do`, `repeat`,
+//! `break`, `continue`) and Fanuc subprograms (`O1000` … `M99`, called with `M98 P1000 L3`).
+//!
+//! What comes out is plain G-code as [`super::gcode_parser`] reads it, with every block keeping
+//! the source line it came from, so programs without any of this come out unchanged.
+
+use std::collections::HashMap;
+
+use super::gcode_parser::{parse_code, takes_text, Block, ParseError, ParseErrorKind, Parser};
+use super::GCode;
+
+/// Source lines run before giving up on a program that never ends.
+const MAX_STEPS: usize = 1_000_000;
+
+/// Nested calls before giving up on runaway recursion.
+const MAX_DEPTH: usize = 100;
+
+/// Expand a whole program, stopping at the first error.
+pub fn expand(src: &str) -> Result<Vec<Block>, ParseError> {
+    let lines: Vec<&str> = src.lines().collect();
+    let statements = lines
+        .iter()
+        .enumerate()
+        .map(|(index, text)| statement(index + 1, text))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut subs = HashMap::new();
+    for (index, statement) in statements.iter().enumerate() {
+        match statement {
+            Statement::Control {
+                label,
+                keyword: Keyword::Sub,
+                ..
+            }
+            | Statement::Program(label) => {
+                subs.entry(label.clone()).or_insert(index);
+            }
+            _ => {}
+        }
+    }
+
+    let mut expander = Expander {
+        lines,
+        statements,
+        subs,
+        parameters: Parameters::default(),
+        frames: vec![Frame::default()],
+        parser: Parser::default(),
+        blocks: vec![],
+    };
+    expander.run()?;
+    Ok(expander.blocks)
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Keyword {
+    Sub,
+    EndSub,
+    Return,
+    Call,
+    If,
+    ElseIf,
+    Else,
+    EndIf,
+    While,
+    EndWhile,
+    Do,
+    Repeat,
+    EndRepeat,
+    Break,
+    Continue,
+}
+
+impl Keyword {
+    fn parse(word: &str) -> Option<Self> {
+        Some(match word.to_ascii_lowercase().as_str() {
+            "sub" => Keyword::Sub,
+            "endsub" => Keyword::EndSub,
+            "return" => Keyword::Return,
+            "call" => Keyword::Call,
+            "if" => Keyword::If,
+            "elseif" => Keyword::ElseIf,
+            "else" => Keyword::Else,
+            "endif" => Keyword::EndIf,
+            "while" => Keyword::While,
+            "endwhile" => Keyword::EndWhile,
+            "do" => Keyword::Do,
+            "repeat" => Keyword::Repeat,
+            "endrepeat" => Keyword::EndRepeat,
+            "break" => Keyword::Break,
+            "continue" => Keyword::Continue,
+            _ => return None,
+        })
+    }
+}
+
+/// What a source line is, worked out once before running.
+enum Statement {
+    /// `o<label> <keyword> <rest>`, with the byte offset of the rest.
+    Control {
+        label: String,
+        keyword: Keyword,
+        rest: usize,
+    },
+
+    /// A bare `O1000` line, which starts a Fanuc subprogram.
+    Program(String),
+
+    /// Anything else, for the parser.
+    Code,
+}
+
+/// Whether a line is program flow (an O-word) rather than G-code for the parser.
+pub(super) fn is_control(text: &str) -> bool {
+    !matches!(statement(0, text), Ok(Statement::Code))
+}
+
+fn statement(line: usize, text: &str) -> Result<Statement, ParseError> {
+    let error = |pos: usize, kind| ParseError {
+        line,
+        column: pos + 1,
+        kind,
+    };
+    let bytes = text.as_bytes();
+    let skip_whitespace = |mut pos: usize| {
+        while bytes.get(pos).map_or(false, u8::is_ascii_whitespace) {
+            pos += 1;
+        }
+        pos
+    };
+
+    let mut pos = skip_whitespace(0);
+    if matches!(bytes.get(pos), Some(b'N' | b'n')) {
+        pos += 1;
+        while bytes.get(pos).map_or(false, u8::is_ascii_digit) {
+            pos += 1;
+        }
+        pos = skip_whitespace(pos);
+    }
+    if !matches!(bytes.get(pos), Some(b'O' | b'o')) {
+        return Ok(Statement::Code);
+    }
+    pos += 1;
+
+    let label = if bytes.get(pos) == Some(&b'<') {
+        let end = text[pos..].find('>').ok_or_else(|| {
+            error(
+                pos,
+                ParseErrorKind::InvalidExpression("unterminated O-word name".to_owned()),
+            )
+        })?;
+        let label = name(&text[pos + 1..pos + end]);
+        pos += end + 1;
+        label
+    } else {
+        let start = pos;
+        while bytes.get(pos).map_or(false, u8::is_ascii_digit) {
+            pos += 1;
+        }
+        match text[start..pos].parse::<u32>() {
+            Ok(number) => number.to_string(),
+            Err(_) => return Ok(Statement::Code),
+        }
+    };
+
+    pos = skip_whitespace(pos);
+    let start = pos;
+    while bytes.get(pos).map_or(false, u8::is_ascii_alphabetic) {
+        pos += 1;
+    }
+    let word = &text[start..pos];
+    if word.is_empty() {
+        let rest = text[pos..].trim_start();
+        if rest.is_empty() || rest.starts_with(';') || rest.starts_with('(') {
+            return Ok(Statement::Program(label));
+        }
+        return Ok(Statement::Code);
+    }
+    match Keyword::parse(word) {
+        Some(keyword) => Ok(Statement::Control {
+            label,
+            keyword,
+            rest: pos,
+        }),
+        None => Err(error(
+            start,
+            ParseErrorKind::UnknownCode(format!("{} {}", o_word(&label), word)),
+        )),
+    }
+}
+
+/// How a label is written, for errors: `o100` or `o<name>`.
+fn o_word(label: &str) -> String {
+    if label.bytes().all(|b| b.is_ascii_digit()) {
+        format!("o{}", label)
+    } else {
+        format!("o<{}>", label)
+    }
+}
+
+/// Parameter names ignore case and spaces.
+fn name(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Parameter {
+    Numbered(u32),
+    Named(String),
+}
+
+#[derive(Default)]
+struct Parameters {
+    numbered: HashMap<u32, f64>,
+
+    /// Names starting with `_`.
+    globals: HashMap<String, f64>,
+
+    /// Other names, one scope per call.
+    locals: Vec<HashMap<String, f64>>,
+}
+
+impl Parameters {
+    fn get(&self, parameter: &Parameter) -> Option<f64> {
+        match parameter {
+            Parameter::Numbered(number) => Some(self.numbered.get(number).copied().unwrap_or(0.0)),
+            Parameter::Named(name) if name.starts_with('_') => self.globals.get(name).copied(),
+            Parameter::Named(name) => self.locals.last()?.get(name).copied(),
+        }
+    }
+
+    fn set(&mut self, parameter: Parameter, value: f64) {
+        match parameter {
+            Parameter::Numbered(number) => {
+                self.numbered.insert(number, value);
+            }
+            Parameter::Named(name) if name.starts_with('_') => {
+                self.globals.insert(name, value);
+            }
+            Parameter::Named(name) => {
+                if self.locals.is_empty() {
+                    self.locals.push(HashMap::new());
+                }
+                if let Some(locals) = self.locals.last_mut() {
+                    locals.insert(name, value);
+                }
+            }
+        }
+    }
+}
+
+/// Reads parameters and expressions out of one line.
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+    parameters: &'a Parameters,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.pos + 1,
+            kind,
+        }
+    }
+
+    fn invalid(&self, message: &str) -> ParseError {
+        self.error(ParseErrorKind::InvalidExpression(message.to_owned()))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.invalid(&format!("expected '{}'", c)))
+        }
+    }
+
+    /// Takes `word` if it comes next, ignoring case.
+    fn keyword(&mut self, word: &str) -> bool {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        let found = rest.len() >= word.len()
+            && rest.is_char_boundary(word.len())
+            && rest[..word.len()].eq_ignore_ascii_case(word);
+        if found {
+            self.pos += word.len();
+        }
+        found
+    }
+
+    /// `#5`, `#<name>`, `##5` or `#[expression]`, with the `#` not yet read.
+    fn parameter(&mut self) -> Result<Parameter, ParseError> {
+        self.expect('#')?;
+        match self.peek() {
+            Some('<') => {
+                let end = self.text[self.pos..]
+                    .find('>')
+                    .ok_or_else(|| self.invalid("unterminated parameter name"))?;
+                let parameter = Parameter::Named(name(&self.text[self.pos + 1..self.pos + end]));
+                self.pos += end + 1;
+                Ok(parameter)
+            }
+            Some('#' | '[') => {
+                let value = self.value()?;
+                self.numbered(value)
+            }
+            _ => {
+                let value = self.number()?;
+                self.numbered(value)
+            }
+        }
+    }
+
+    fn numbered(&self, value: f64) -> Result<Parameter, ParseError> {
+        let number = value.round();
+        if (value - number).abs() > 1e-4 || number < 0.0 || number > u32::MAX as f64 {
+            return Err(self.invalid("parameter numbers are whole numbers"));
+        }
+        Ok(Parameter::Numbered(number as u32))
+    }
+
+    fn read(&self, parameter: &Parameter) -> Result<f64, ParseError> {
+        self.parameters
+            .get(parameter)
+            .ok_or_else(|| match parameter {
+                Parameter::Named(name) => {
+                    self.error(ParseErrorKind::UnknownParameter(name.clone()))
+                }
+                Parameter::Numbered(number) => {
+                    self.error(ParseErrorKind::UnknownParameter(number.to_string()))
+                }
+            })
+    }
+
+    fn number(&mut self) -> Result<f64, ParseError> {
+        self.skip_whitespace();
+        let len = self.text[self.pos..]
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(self.text.len() - self.pos);
+        let value = self.text[self.pos..self.pos + len]
+            .parse()
+            .map_err(|_err| self.invalid("expected a number"))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    /// A number, parameter, `[expression]` or function call, with an optional sign.
+    fn value(&mut self) -> Result<f64, ParseError> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(-self.value()?)
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.value()
+            }
+            Some('#') => {
+                let parameter = self.parameter()?;
+                self.read(&parameter)
+            }
+            Some('[') => self.expression(),
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.function(),
+            _ => Err(self.invalid("expected a value")),
+        }
+    }
+
+    fn function(&mut self) -> Result<f64, ParseError> {
+        if self.keyword("exists") {
+            self.expect('[')?;
+            let parameter = self.parameter()?;
+            self.expect(']')?;
+            return Ok(bool_value(self.parameters.get(&parameter).is_some()));
+        }
+        if self.keyword("atan") {
+            let y = self.expression()?;
+            self.expect('/')?;
+            let x = self.expression()?;
+            return Ok(y.atan2(x).to_degrees());
+        }
+        type Function = fn(f64) -> f64;
+        let functions: [(&str, Function); 12] = [
+            ("abs", f64::abs),
+            ("acos", |x| x.acos().to_degrees()),
+            ("asin", |x| x.asin().to_degrees()),
+            ("cos", |x| x.to_radians().cos()),
+            ("exp", f64::exp),
+            ("fix", f64::floor),
+            ("fup", f64::ceil),
+            ("round", f64::round),
+            ("ln", f64::ln),
+            ("sin", |x| x.to_radians().sin()),
+            ("sqrt", f64::sqrt),
+            ("tan", |x| x.to_radians().tan()),
+        ];
+        for (word, function) in functions {
+            if self.keyword(word) {
+                return Ok(function(self.expression()?));
+            }
+        }
+        Err(self.invalid("unknown function"))
+    }
+
+    /// `[ … ]`
+    fn expression(&mut self) -> Result<f64, ParseError> {
+        self.expect('[')?;
+        let value = self.binary(0)?;
+        self.expect(']')?;
+        Ok(value)
+    }
+
+    /// Binary operators from the loosest binding `level` up, with `**` binding tightest.
+    fn binary(&mut self, level: usize) -> Result<f64, ParseError> {
+        const LEVELS: [&[&str]; 4] = [
+            &["and", "or", "xor"],
+            &["eq", "ne", "gt", "ge", "lt", "le"],
+            &["+", "-"],
+            &["*", "/", "mod"],
+        ];
+        let next = |reader: &mut Self| {
+            if level + 1 < LEVELS.len() {
+                reader.binary(level + 1)
+            } else {
+                reader.power()
+            }
+        };
+        let mut left = next(self)?;
+        'operators: loop {
+            for operator in LEVELS[level] {
+                if self.keyword(operator) {
+                    let right = next(self)?;
+                    left = match *operator {
+                        "and" => bool_value(left != 0.0 && right != 0.0),
+                        "or" => bool_value(left != 0.0 || right != 0.0),
+                        "xor" => bool_value((left != 0.0) != (right != 0.0)),
+                        "eq" => bool_value(left == right),
+                        "ne" => bool_value(left != right),
+                        "gt" => bool_value(left > right),
+                        "ge" => bool_value(left >= right),
+                        "lt" => bool_value(left < right),
+                        "le" => bool_value(left <= right),
+                        "+" => left + right,
+                        "-" => left - right,
+                        "*" => left * right,
+                        "/" if right == 0.0 => return Err(self.invalid("division by zero")),
+                        "/" => left / right,
+                        _ => left.rem_euclid(right),
+                    };
+                    continue 'operators;
+                }
+            }
+            break Ok(left);
+        }
+    }
+
+    fn power(&mut self) -> Result<f64, ParseError> {
+        let mut value = self.value()?;
+        while self.keyword("**") {
+            value = value.powf(self.value()?);
+        }
+        Ok(value)
+    }
+}
+
+fn bool_value(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Writes a value the way the parser reads it, so `X#1` with `#1 = 2.5` becomes `X2.5`.
+fn push_value(out: &mut String, value: f64) {
+    let mut value = value;
+    if value < 0.0 && out.ends_with('-') {
+        out.pop();
+        value = -value;
+    } else if value < 0.0 && out.ends_with('+') {
+        out.pop();
+    }
+    let text = format!("{:.6}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    out.push_str(if text == "-0" { "0" } else { text });
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum LoopKind {
+    While,
+    Do,
+    Repeat,
+}
+
+struct Loop {
+    label: String,
+    kind: LoopKind,
+
+    /// Line of the `while`, `do` or `repeat`.
+    start: usize,
+
+    remaining: u32,
+}
+
+/// A subprogram being run.
+#[derive(Default)]
+struct Frame {
+    /// Line to carry on from afterwards.
+    return_to: usize,
+
+    /// First line of the subprogram and the runs left, for `M98 L`.
+    start: usize,
+    repeats: u32,
+
+    /// `#1` to `#30` of the caller, restored when an `o call` returns.
+    saved: Option<Vec<Option<f64>>>,
+
+    loops: Vec<Loop>,
+}
+
+struct Expander<'a> {
+    lines: Vec<&'a str>,
+    statements: Vec<Statement>,
+    subs: HashMap<String, usize>,
+    parameters: Parameters,
+    frames: Vec<Frame>,
+    parser: Parser,
+    blocks: Vec<Block>,
+}
+
+impl<'a> Expander<'a> {
+    fn run(&mut self) -> Result<(), ParseError> {
+        let mut pc = 0;
+        let mut steps = 0;
+        while pc < self.lines.len() {
+            steps += 1;
+            if steps > MAX_STEPS {
+                return Err(self.error(pc, ParseErrorKind::RunawayProgram));
+            }
+            pc = self.step(pc)?;
+        }
+        Ok(())
+    }
+
+    fn error(&self, pc: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: pc + 1,
+            column: 1,
+            kind,
+        }
+    }
+
+    fn reader(&self, pc: usize, pos: usize) -> Reader<'_> {
+        Reader {
+            text: self.lines[pc],
+            pos,
+            line: pc + 1,
+            parameters: &self.parameters,
+        }
+    }
+
+    /// Evaluates the `[condition]` after an O-word keyword.
+    fn condition(&self, pc: usize) -> Result<f64, ParseError> {
+        let Statement::Control { rest, .. } = self.statements[pc] else {
+            return Ok(0.0);
+        };
+        self.reader(pc, rest).expression()
+    }
+
+    /// The next line after `from` with the same O-word label and one of the keywords.
+    fn find(&self, from: usize, label: &str, keywords: &[Keyword]) -> Result<usize, ParseError> {
+        (from + 1..self.statements.len())
+            .find(|&index| {
+                matches!(&self.statements[index], Statement::Control { label: other, keyword, .. }
+                    if other == label && keywords.contains(keyword))
+            })
+            .ok_or_else(|| self.error(from, ParseErrorKind::UnmatchedControl(o_word(label))))
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("the main program frame")
+    }
+
+    /// Runs line `pc` and returns the next one.
+    fn step(&mut self, pc: usize) -> Result<usize, ParseError> {
+        match &self.statements[pc] {
+            Statement::Code | Statement::Program(_) => self.code(pc),
+            Statement::Control { label, keyword, .. } => {
+                let label = label.clone();
+                self.control(pc, &label, *keyword)
+            }
+        }
+    }
+
+    fn code(&mut self, pc: usize) -> Result<usize, ParseError> {
+        let (text, assignments) = self.substitute(pc)?;
+        let mut block = self.parser.parse_line(pc + 1, &text)?;
+        let assigned = !assignments.is_empty();
+        for (parameter, value) in assignments {
+            self.parameters.set(parameter, value);
+        }
+
+        let mut next = pc + 1;
+        let codes = std::mem::take(&mut block.codes);
+        let count = codes.len();
+        for code in codes {
+            match &code {
+                GCode::M98 { p, l } => {
+                    let label = p.trim_start_matches(['O', 'o']);
+                    let label = label
+                        .parse::<u32>()
+                        .map_or_else(|_| name(label), |number| number.to_string());
+                    match self.subs.get(&label) {
+                        Some(&start) => {
+                            let repeats = l.unwrap_or(1);
+                            if repeats > 0 {
+                                self.call(pc, start + 1, repeats, None)?;
+                                next = start + 1;
+                            }
+                        }
+                        // Not ours: a file on the controller's SD card.
+                        None => block.codes.push(code),
+                    }
+                }
+                GCode::M99 => {
+                    next = if self.frames.len() > 1 {
+                        self.end_subprogram()
+                    } else {
+                        self.lines.len()
+                    };
+                }
+                GCode::M2 {} => {
+                    block.codes.push(code);
+                    next = self.lines.len();
+                }
+                GCode::M30 { filename } if filename.is_empty() => {
+                    block.codes.push(code);
+                    next = self.lines.len();
+                }
+                _ => block.codes.push(code),
+            }
+        }
+
+        // Lines that only called, returned or assigned leave nothing behind.
+        let emptied = block.codes.is_empty() && (count > 0 || assigned);
+        if !emptied || !block.comments.is_empty() || block.program_number.is_some() {
+            self.blocks.push(block);
+        }
+        Ok(next)
+    }
+
+    fn call(
+        &mut self,
+        pc: usize,
+        start: usize,
+        repeats: u32,
+        arguments: Option<Vec<f64>>,
+    ) -> Result<(), ParseError> {
+        if self.frames.len() > MAX_DEPTH {
+            return Err(self.error(pc, ParseErrorKind::RunawayProgram));
+        }
+        let saved = arguments.map(|arguments| {
+            (1..=30)
+                .map(|number| {
+                    let saved = self.parameters.numbered.get(&number).copied();
+                    let value = arguments.get(number as usize - 1).copied().unwrap_or(0.0);
+                    self.parameters.numbered.insert(number, value);
+                    saved
+                })
+                .collect()
+        });
+        if saved.is_some() {
+            self.parameters.locals.push(HashMap::new());
+        }
+        self.frames.push(Frame {
+            return_to: pc + 1,
+            start,
+            repeats,
+            saved,
+            loops: vec![],
+        });
+        Ok(())
+    }
+
+    /// Runs an `M98` subprogram again or returns from it.
+    fn end_subprogram(&mut self) -> usize {
+        let frame = self.frame();
+        frame.repeats = frame.repeats.saturating_sub(1);
+        if frame.repeats > 0 {
+            frame.loops.clear();
+            return frame.start;
+        }
+        self.return_from()
+    }
+
+    fn return_from(&mut self) -> usize {
+        let frame = self.frames.pop().expect("a subprogram frame");
+        if let Some(saved) = frame.saved {
+            self.parameters.locals.pop();
+            for (number, value) in (1..=30).zip(saved) {
+                match value {
+                    Some(value) => self.parameters.numbered.insert(number, value),
+                    None => self.parameters.numbered.remove(&number),
+                };
+            }
+        }
+        frame.return_to
+    }
+
+    fn control(&mut self, pc: usize, label: &str, keyword: Keyword) -> Result<usize, ParseError> {
+        let next = pc + 1;
+        Ok(match keyword {
+            Keyword::Sub => self.find(pc, label, &[Keyword::EndSub])? + 1,
+            Keyword::EndSub | Keyword::Return => {
+                if self.frames.len() == 1 {
+                    return Err(self.error(pc, ParseErrorKind::UnmatchedControl(o_word(label))));
+                }
+                let Statement::Control { rest, .. } = self.statements[pc] else {
+                    unreachable!()
+                };
+                let mut reader = self.reader(pc, rest);
+                if reader.peek() == Some('[') {
+                    let value = reader.expression()?;
+                    self.parameters
+                        .set(Parameter::Named("_value".to_owned()), value);
+                }
+                self.return_from()
+            }
+            Keyword::Call => {
+                let start = *self.subs.get(label).ok_or_else(|| {
+                    self.error(pc, ParseErrorKind::UnknownSubroutine(o_word(label)))
+                })?;
+                let Statement::Control { rest, .. } = self.statements[pc] else {
+                    unreachable!()
+                };
+                let mut reader = self.reader(pc, rest);
+                let mut arguments = vec![];
+                while reader.peek() == Some('[') {
+                    arguments.push(reader.expression()?);
+                }
+                self.call(pc, start + 1, 1, Some(arguments))?;
+                start + 1
+            }
+            Keyword::If => {
+                if self.condition(pc)? != 0.0 {
+                    return Ok(next);
+                }
+                let mut from = pc;
+                loop {
+                    let index = self.find(
+                        from,
+                        label,
+                        &[Keyword::ElseIf, Keyword::Else, Keyword::EndIf],
+                    )?;
+                    match &self.statements[index] {
+                        Statement::Control {
+                            keyword: Keyword::ElseIf,
+                            ..
+                        } if self.condition(index)? == 0.0 => from = index,
+                        _ => break index + 1,
+                    }
+                }
+            }
+            Keyword::ElseIf | Keyword::Else => self.find(pc, label, &[Keyword::EndIf])? + 1,
+            Keyword::EndIf => next,
+            Keyword::While => {
+                let closes_do = self.frame().loops.last().map_or(false, |open| {
+                    open.label == label && open.kind == LoopKind::Do
+                });
+                let repeat = self.condition(pc)? != 0.0;
+                if closes_do {
+                    if repeat {
+                        self.frame()
+                            .loops
+                            .last()
+                            .map_or(next, |open| open.start + 1)
+                    } else {
+                        self.frame().loops.pop();
+                        next
+                    }
+                } else if repeat {
+                    self.open_loop(label, LoopKind::While, pc, 0);
+                    next
+                } else {
+                    self.find(pc, label, &[Keyword::EndWhile])? + 1
+                }
+            }
+            Keyword::EndWhile => self.close_loop(pc, label)?.start,
+            Keyword::Do => {
+                self.open_loop(label, LoopKind::Do, pc, 0);
+                next
+            }
+            Keyword::Repeat => {
+                let count = self.condition(pc)?.round();
+                if count >= 1.0 {
+                    self.open_loop(label, LoopKind::Repeat, pc, count as u32);
+                    next
+                } else {
+                    self.find(pc, label, &[Keyword::EndRepeat])? + 1
+                }
+            }
+            Keyword::EndRepeat => {
+                let open = self
+                    .frame()
+                    .loops
+                    .last_mut()
+                    .filter(|open| open.label == label);
+                match open {
+                    Some(open) if open.remaining > 1 => {
+                        open.remaining -= 1;
+                        open.start + 1
+                    }
+                    _ => {
+                        self.close_loop(pc, label)?;
+                        next
+                    }
+                }
+            }
+            Keyword::Break => {
+                let open = self.close_loop(pc, label)?;
+                let end = match open.kind {
+                    LoopKind::While => Keyword::EndWhile,
+                    LoopKind::Do => Keyword::While,
+                    LoopKind::Repeat => Keyword::EndRepeat,
+                };
+                self.find(open.start, label, &[end])? + 1
+            }
+            Keyword::Continue => {
+                let kind = self
+                    .frame()
+                    .loops
+                    .iter()
+                    .rev()
+                    .find(|open| open.label == label)
+                    .map(|open| (open.kind, open.start));
+                match kind {
+                    Some((LoopKind::While, _)) => self.close_loop(pc, label)?.start,
+                    Some((LoopKind::Do, start)) => self.find(start, label, &[Keyword::While])?,
+                    Some((LoopKind::Repeat, start)) => {
+                        self.find(start, label, &[Keyword::EndRepeat])?
+                    }
+                    None => {
+                        return Err(self.error(pc, ParseErrorKind::UnmatchedControl(o_word(label))))
+                    }
+                }
+            }
+        })
+    }
+
+    fn open_loop(&mut self, label: &str, kind: LoopKind, start: usize, remaining: u32) {
+        self.frame().loops.push(Loop {
+            label: label.to_owned(),
+            kind,
+            start,
+            remaining,
+        });
+    }
+
+    /// Closes the loop with this label and any left open inside it.
+    fn close_loop(&mut self, pc: usize, label: &str) -> Result<Loop, ParseError> {
+        let loops = &mut self.frame().loops;
+        match loops.iter().rposition(|open| open.label == label) {
+            Some(index) => {
+                let open = loops.remove(index);
+                loops.truncate(index);
+                Ok(open)
+            }
+            None => Err(self.error(pc, ParseErrorKind::UnmatchedControl(o_word(label)))),
+        }
+    }
+
+    /// Replaces parameters and expressions on a code line with their values, and collects the
+    /// `#n = value` assignments, which take effect after the line.
+    fn substitute(&self, pc: usize) -> Result<(String, Vec<(Parameter, f64)>), ParseError> {
+        let text = self.lines[pc];
+        if !text.contains(['#', '[']) {
+            return Ok((text.to_owned(), vec![]));
+        }
+
+        let mut out = String::with_capacity(text.len());
+        let mut assignments = vec![];
+        let mut reader = self.reader(pc, 0);
+        while let Some(c) = reader.text[reader.pos..].chars().next() {
+            let rest = &text[reader.pos..];
+            match c {
+                ';' => {
+                    out.push_str(rest);
+                    break;
+                }
+                '(' | '"' => {
+                    // Comments and strings are copied as they are.
+                    let close = if c == '(' { ')' } else { '"' };
+                    let len = rest[1..].find(close).map_or(rest.len(), |end| end + 2);
+                    out.push_str(&rest[..len]);
+                    reader.pos += len;
+                }
+                '#' => {
+                    let parameter = reader.parameter()?;
+                    if reader.peek() == Some('=') {
+                        reader.pos += 1;
+                        assignments.push((parameter, reader.value()?));
+                    } else {
+                        push_value(&mut out, reader.read(&parameter)?);
+                    }
+                }
+                '[' => push_value(&mut out, reader.expression()?),
+                'M' | 'm' => {
+                    // The free text of `M117 message` and friends is not ours to touch.
+                    let digits = rest[1..]
+                        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                        .unwrap_or(rest.len() - 1);
+                    let free_text = parse_code('M', &rest[1..1 + digits]).map_or(false, takes_text);
+                    if free_text {
+                        out.push_str(rest);
+                        break;
+                    }
+                    out.push_str(&rest[..1 + digits]);
+                    reader.pos += 1 + digits;
+                }
+                _ => {
+                    out.push(c);
+                    reader.pos += c.len_utf8();
+                }
+            }
+        }
+        Ok((out, assignments))
+    }
+}
+
+#[test]
+fn test_gcode_macro() {
+    use super::gcode_emitter::{emit_program, EmitOptions};
+
+    let program = |src: &str| emit_program(&expand(src).unwrap(), &EmitOptions::default());
+
+    // Plain programs come through untouched.
+    let plain = "G0 X1 (start)\n\nM117 Hello [world] #1\n";
+    assert_eq!(program(plain), plain);
+
+    assert_eq!(
+        program(
+            "#<depth> = 2.5\n\
+             #1 = [1 + 2 * 3 ** 2 - #<depth> / 5]\n\
+             G1 X#1 Y-#1 Z[-#<depth>] F[100 * [1 EQ 1]] #1 = 0\n\
+             G1 X#1 Y[sqrt[16] + atan[1]/[1] + fix[-1.5]]"
+        ),
+        "G1 X18.5 Y-18.5 Z-2.5 F100\nG1 X0 Y47\n"
+    );
+
+    // An O-word subroutine called in nested loops, returning a value.
+    assert_eq!(
+        program(
+            "o<hole> sub\n\
+               G0 X#1\n\
+               G1 Z-#2\n\
+             o<hole> endsub [#1 * 10]\n\
+             #<_i> = 0\n\
+             o1 while [#<_i> LT 3]\n\
+               o2 if [#<_i> EQ 1]\n\
+                 #<_i> = [#<_i> + 1]\n\
+                 o1 continue\n\
+               o2 else\n\
+                 o<hole> call [#<_i>] [2]\n\
+               o2 endif\n\
+               #<_i> = [#<_i> + 1]\n\
+             o1 endwhile\n\
+             o3 repeat [2]\n\
+               G0 Z#<_value>\n\
+             o3 endrepeat\n\
+             M2\n\
+             G0 X99"
+        ),
+        "G0 X0\nG1 Z-2\nG0 X2\nG1 Z-2\nG0 Z20\nG0 Z20\nM2\n"
+    );
+
+    // Fanuc subprograms after the end of the main program.
+    assert_eq!(
+        program(
+            "O0001\n\
+             G91\n\
+             M98 P1000 L3\n\
+             M30\n\
+             O1000\n\
+             G1 X10\n\
+             M99"
+        ),
+        "O1\nG91\nG1 X10\nG1 X10\nG1 X10\nM30\n"
+    );
+
+    let error = expand("G0\no<missing> call").unwrap_err();
+    assert_eq!(error.line, 2);
+    assert_eq!(
+        error.kind,
+        ParseErrorKind::UnknownSubroutine("o<missing>".to_owned())
+    );
+    let error = expand("G0 X#<nope>").unwrap_err();
+    assert_eq!(
+        error.kind,
+        ParseErrorKind::UnknownParameter("nope".to_owned())
+    );
+    let error = expand("o1 while [1]\no1 endwhile").unwrap_err();
+    assert_eq!(error.kind, ParseErrorKind::RunawayProgram);
+}
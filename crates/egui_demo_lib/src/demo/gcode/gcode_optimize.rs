@@ -0,0 +1,921 @@
+//! Shrinks G-code programs for controllers that choke on dense CAM output.
+//!
+//! [`optimize`] works on parsed blocks and follows the modal state with the [`Interpreter`], so it
+//! only rewrites what it fully understands and leaves every other block as it was:
+//!
+//! - contours that start with rapids setting `X`, `Y` and `Z` do not depend on each other, and are
+//!   cut in the order that shortens the rapids between them: nearest neighbour, then 2-opt;
+//! - modal codes, axis words and `F` words that change nothing are dropped, and with them moves
+//!   that go nowhere;
+//! - runs of `G1` moves in the `G17` plane may become `G2`/`G3` arcs, see
+//!   [`gcode_arc::fit_arcs`];
+//! - runs of `G1` moves that stay on one straight line become a single move.
+//!
+//! Nothing moves further than the tolerance from the original path.
+
+use std::ops::Range;
+
+use super::gcode_arc::{self, Piece};
+use super::gcode_interpreter::{
+    Compensation, Distance, FeedMode, Interpreter, ModalState, Move, MoveKind, Plane, Position,
+    Units, E, X, Y, Z,
+};
+use super::gcode_parser::Block;
+use super::GCode;
+
+/// Modal groups tracked for redundant codes, see [`modal_group`].
+const MODAL_GROUPS: usize = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct OptimizeOptions {
+    /// How far in mm the optimized path may stray from the original.
+    pub tolerance: f32,
+
+    /// Cut independent contours in the order with the shortest rapids.
+    pub reorder: bool,
+
+    /// Turn runs of short segments into arcs.
+    pub fit_arcs: bool,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: 0.01,
+            reorder: true,
+            fit_arcs: false,
+        }
+    }
+}
+
+/// The program with the same motion in fewer, shorter blocks.
+///
+/// Expects a program without parameters or program flow, such as the output of
+/// [`super::gcode_macro::expand`].
+pub fn optimize(blocks: &[Block], options: &OptimizeOptions) -> Vec<Block> {
+    let mut blocks = blocks.to_vec();
+    if options.reorder {
+        blocks = reorder(&blocks);
+    }
+    blocks = drop_redundant(&blocks);
+    if options.fit_arcs {
+        blocks = fit_arcs(&blocks, options.tolerance);
+    }
+    merge_collinear(&blocks, options.tolerance)
+}
+
+/// What running a block did.
+struct Step {
+    /// Modal state before and after the block.
+    state: ModalState,
+    after: ModalState,
+
+    /// Program coordinates before and after the block.
+    start: Position,
+    end: Position,
+
+    /// Axes the program had put somewhere before the block, so that `start` is where the
+    /// machine really is and not just where the interpreter starts out.
+    placed: [bool; 4],
+
+    moves: Vec<Move>,
+}
+
+impl Step {
+    /// Whether every axis the code moves in absolute coordinates starts where the interpreter
+    /// says it does.
+    fn starts_placed(&self, code: &GCode) -> bool {
+        let (axes, _) = axis_words(code).unwrap_or_default();
+        (0..4).all(|axis| {
+            let distance = if axis == E {
+                self.after.extruder_distance
+            } else {
+                self.after.distance
+            };
+            axes[axis].is_none() || self.placed[axis] || distance == Distance::Relative
+        })
+    }
+}
+
+fn trace(blocks: &[Block]) -> Vec<Step> {
+    let mut interpreter = Interpreter::default();
+    let mut placed = [false; 4];
+    blocks
+        .iter()
+        .map(|block| {
+            let state = interpreter.state.clone();
+            let start = interpreter.program_position();
+            let mut moves = vec![];
+            interpreter.run_block(block, &mut moves);
+            let step = Step {
+                state,
+                after: interpreter.state.clone(),
+                start,
+                end: interpreter.program_position(),
+                placed,
+                moves,
+            };
+            for code in &block.codes {
+                let (axes, absolute) = match *code {
+                    GCode::G92 { x, y, z, e } => ([x, y, z, e], [true; 4]),
+                    _ => match axis_words(code) {
+                        Some((axes, _)) => (
+                            axes,
+                            [
+                                step.after.distance,
+                                step.after.distance,
+                                step.after.distance,
+                                step.after.extruder_distance,
+                            ]
+                            .map(|distance| distance == Distance::Absolute),
+                        ),
+                        None => continue,
+                    },
+                };
+                for axis in 0..4 {
+                    placed[axis] |= axes[axis].is_some() && absolute[axis];
+                }
+            }
+            step
+        })
+        .collect()
+}
+
+/// The only code of a block that holds a single move and nothing that would be lost by rewriting
+/// it, apart from comments.
+fn plain_motion(block: &Block) -> Option<&GCode> {
+    match block.codes.as_slice() {
+        [code @ (GCode::G0 { .. } | GCode::G1 { .. } | GCode::G2 { .. } | GCode::G3 { .. })]
+            if !block.block_delete && !block.percent && block.program_number.is_none() =>
+        {
+            Some(code)
+        }
+        _ => None,
+    }
+}
+
+/// The straight move made by a block holding only a `G1`, when its start is known.
+fn line_move<'a>(block: &Block, step: &'a Step) -> Option<&'a Move> {
+    match (plain_motion(block), step.moves.as_slice()) {
+        (Some(code @ GCode::G1 { .. }), [motion])
+            if motion.kind == MoveKind::Linear && step.starts_placed(code) =>
+        {
+            Some(motion)
+        }
+        _ => None,
+    }
+}
+
+/// Whether two moves run with the same feedrate, tool and spindle.
+fn same_settings(a: &Move, b: &Move) -> bool {
+    a.feedrate == b.feedrate
+        && a.tool == b.tool
+        && a.spindle == b.spindle
+        && a.spindle_speed == b.spindle_speed
+        && a.compensation == b.compensation
+}
+
+/// `X`, `Y`, `Z` and `E` words and the `F` word of a motion code.
+fn axis_words(code: &GCode) -> Option<([Option<f32>; 4], Option<f32>)> {
+    match *code {
+        GCode::G0 { x, y, z, e, f } | GCode::G1 { x, y, z, e, f } => Some(([x, y, z, e], f)),
+        GCode::G2 { x, y, z, e, f, .. } | GCode::G3 { x, y, z, e, f, .. } => {
+            Some(([x, y, z, e], f))
+        }
+        _ => None,
+    }
+}
+
+fn with_axis_words(code: &GCode, [x, y, z, e]: [Option<f32>; 4], f: Option<f32>) -> GCode {
+    match *code {
+        GCode::G0 { .. } => GCode::G0 { x, y, z, e, f },
+        GCode::G1 { .. } => GCode::G1 { x, y, z, e, f },
+        GCode::G2 { i, j, k, r, p, .. } => GCode::G2 {
+            x,
+            y,
+            z,
+            i,
+            j,
+            k,
+            r,
+            p,
+            e,
+            f,
+        },
+        GCode::G3 { i, j, k, r, p, .. } => GCode::G3 {
+            x,
+            y,
+            z,
+            i,
+            j,
+            k,
+            r,
+            p,
+            e,
+            f,
+        },
+        _ => code.clone(),
+    }
+}
+
+/// The words of one move doing what the moves of `range` do together, and the first `F` word.
+fn combined_words(
+    blocks: &[Block],
+    steps: &[Step],
+    range: Range<usize>,
+) -> ([Option<f32>; 4], Option<f32>) {
+    let mut words = [None; 4];
+    let mut feed = None;
+    for index in range {
+        let Some((axes, f)) = plain_motion(&blocks[index]).and_then(axis_words) else {
+            continue;
+        };
+        let state = &steps[index].after;
+        feed = feed.or(f);
+        for (axis, value) in axes.into_iter().enumerate() {
+            let Some(value) = value else { continue };
+            let distance = if axis == E {
+                state.extruder_distance
+            } else {
+                state.distance
+            };
+            words[axis] = Some(match distance {
+                Distance::Absolute => value,
+                Distance::Relative => words[axis].unwrap_or(0.0) + value,
+            });
+        }
+    }
+    (words, feed)
+}
+
+/// Index into the modal groups the optimizer tracks, for codes that only set a mode.
+fn modal_group(code: &GCode) -> Option<usize> {
+    Some(match code {
+        GCode::G17 | GCode::G18 | GCode::G19 => 0,
+        GCode::G20 | GCode::G21 => 1,
+        GCode::G90 | GCode::G91 => 2,
+        GCode::M82 | GCode::M83 => 3,
+        GCode::G93 | GCode::G94 => 4,
+        _ => return None,
+    })
+}
+
+/// Whether a modal code sets what is already set.
+fn is_current(code: &GCode, state: &ModalState) -> bool {
+    match code {
+        GCode::G17 => state.plane == Plane::XY,
+        GCode::G18 => state.plane == Plane::ZX,
+        GCode::G19 => state.plane == Plane::YZ,
+        GCode::G20 => state.units == Units::Inches,
+        GCode::G21 => state.units == Units::Millimeters,
+        GCode::G90 => {
+            state.distance == Distance::Absolute && state.extruder_distance == Distance::Absolute
+        }
+        GCode::G91 => {
+            state.distance == Distance::Relative && state.extruder_distance == Distance::Relative
+        }
+        GCode::M82 => state.extruder_distance == Distance::Absolute,
+        GCode::M83 => state.extruder_distance == Distance::Relative,
+        GCode::G93 => state.feed_mode == FeedMode::InverseTime,
+        GCode::G94 => state.feed_mode == FeedMode::UnitsPerMinute,
+        _ => false,
+    }
+}
+
+/// Codes after which the program position is still what the words so far say it is.
+fn keeps_position(code: &GCode) -> bool {
+    matches!(
+        code,
+        GCode::G0 { .. }
+            | GCode::G1 { .. }
+            | GCode::G2 { .. }
+            | GCode::G3 { .. }
+            | GCode::G4 { .. }
+            | GCode::G17
+            | GCode::G18
+            | GCode::G19
+            | GCode::G90
+            | GCode::G91
+            | GCode::G93
+            | GCode::G94
+            | GCode::M3 { .. }
+            | GCode::M4 { .. }
+            | GCode::M5 {}
+            | GCode::M7 {}
+            | GCode::M8 {}
+            | GCode::M9 {}
+            | GCode::M82
+            | GCode::M83
+    )
+}
+
+/// Drops modal codes, axis words and `F` words that change nothing, and the blocks left empty.
+///
+/// The controller may start in any state, so nothing counts as set until the program sets it.
+fn drop_redundant(blocks: &[Block]) -> Vec<Block> {
+    let steps = trace(blocks);
+    let mut out = Vec::with_capacity(blocks.len());
+    // Program coordinates the words so far have put each axis at.
+    let mut known = [None; 4];
+    let mut modes_set = [false; MODAL_GROUPS];
+    let mut feed_set = false;
+
+    for (block, step) in blocks.iter().zip(&steps) {
+        // Words next to `G53`, `G92`, homing and the like are left alone.
+        let settled = block
+            .codes
+            .iter()
+            .all(|code| modal_group(code).is_some() || keeps_position(code));
+        let mut codes = Vec::with_capacity(block.codes.len());
+        for code in &block.codes {
+            if let Some(group) = modal_group(code) {
+                let redundant = modes_set[group] && is_current(code, &step.state);
+                modes_set[group] = true;
+                if !redundant {
+                    if matches!(code, GCode::G20 | GCode::G21) {
+                        known = [None; 4];
+                    }
+                    codes.push(code.clone());
+                }
+                continue;
+            }
+            let Some((mut axes, mut f)) = axis_words(code).filter(|_| settled) else {
+                feed_set |= axis_words(code).map_or(false, |(_, f)| f.is_some());
+                codes.push(code.clone());
+                continue;
+            };
+
+            let line = matches!(code, GCode::G0 { .. } | GCode::G1 { .. });
+            for (axis, word) in axes.iter_mut().enumerate() {
+                let Some(value) = *word else { continue };
+                let distance = if axis == E {
+                    step.after.extruder_distance
+                } else {
+                    step.after.distance
+                };
+                let redundant = match distance {
+                    Distance::Absolute => {
+                        let redundant = known[axis] == Some(value);
+                        known[axis] = Some(value);
+                        redundant
+                    }
+                    Distance::Relative => {
+                        known[axis] = known[axis].map(|known| known + value);
+                        value == 0.0
+                    }
+                };
+                if redundant && line {
+                    *word = None;
+                }
+            }
+
+            if let Some(value) = f {
+                let redundant = feed_set
+                    && step.after.feed_mode == FeedMode::UnitsPerMinute
+                    && step.state.feedrate == value * step.after.units.scale();
+                feed_set = true;
+                if redundant {
+                    f = None;
+                }
+            }
+
+            if !line || axes.iter().any(Option::is_some) || f.is_some() {
+                codes.push(with_axis_words(code, axes, f));
+            }
+        }
+
+        if !settled {
+            known = [None; 4];
+        }
+
+        let emptied = !block.codes.is_empty() && codes.is_empty();
+        if emptied && block.comments.is_empty() && block.program_number.is_none() {
+            continue;
+        }
+        out.push(Block {
+            codes,
+            ..block.clone()
+        });
+    }
+    out
+}
+
+/// Joins runs of `G1` moves that stay within `tolerance` of the line from the start of the run
+/// to its end, with the extruder moving evenly along it.
+fn merge_collinear(blocks: &[Block], tolerance: f32) -> Vec<Block> {
+    let steps = trace(blocks);
+    let mut out = Vec::with_capacity(blocks.len());
+    let mut start = 0;
+    while start < blocks.len() {
+        let end = collinear_run(blocks, &steps, start, tolerance);
+        if end - start > 1 {
+            let (words, f) = combined_words(blocks, &steps, start..end);
+            out.push(Block {
+                codes: vec![GCode::G1 {
+                    x: words[X],
+                    y: words[Y],
+                    z: words[Z],
+                    e: words[E],
+                    f,
+                }],
+                ..blocks[start].clone()
+            });
+        } else {
+            out.push(blocks[start].clone());
+        }
+        start = end;
+    }
+    out
+}
+
+/// End of the longest run of collinear moves from `start`, exclusive.
+fn collinear_run(blocks: &[Block], steps: &[Step], start: usize, tolerance: f32) -> usize {
+    let Some(first) = line_move(&blocks[start], &steps[start]) else {
+        return start + 1;
+    };
+    let mut end = start + 1;
+    while end < blocks.len() {
+        let Some(next) = line_move(&blocks[end], &steps[end]) else {
+            break;
+        };
+        let (state, first_state) = (&steps[end].after, &steps[start].after);
+        let fits = blocks[end].comments.is_empty()
+            && same_settings(first, next)
+            && state.distance == first_state.distance
+            && state.extruder_distance == first_state.extruder_distance
+            && on_line(
+                first.start,
+                next.end,
+                steps[start..end].iter().map(|step| step.moves[0].end),
+                tolerance,
+            );
+        if !fits {
+            break;
+        }
+        end += 1;
+    }
+    end
+}
+
+/// Whether `points` lie in order along the line from `from` to `to`, within `tolerance` of it,
+/// with the extruder where it would be moving evenly along the line.
+fn on_line(
+    from: Position,
+    to: Position,
+    points: impl Iterator<Item = Position>,
+    tolerance: f32,
+) -> bool {
+    let direction = [to[X] - from[X], to[Y] - from[Y], to[Z] - from[Z]];
+    let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+    if length <= 0.0 {
+        return false;
+    }
+    let mut previous = 0.0;
+    for point in points {
+        let offset = [point[X] - from[X], point[Y] - from[Y], point[Z] - from[Z]];
+        let along = (0..3).map(|i| offset[i] * direction[i]).sum::<f32>() / length;
+        let across = (0..3)
+            .map(|i| (offset[i] - direction[i] * along / length).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let extruded = from[E] + (to[E] - from[E]) * along / length;
+        if along < previous - tolerance
+            || along > length + tolerance
+            || across > tolerance
+            || (point[E] - extruded).abs() > tolerance
+        {
+            return false;
+        }
+        previous = along;
+    }
+    true
+}
+
+/// Replaces runs of flat `G1` moves in the `G17` plane with arcs where they fit.
+fn fit_arcs(blocks: &[Block], tolerance: f32) -> Vec<Block> {
+    let steps = trace(blocks);
+    let mut out = Vec::with_capacity(blocks.len());
+    let mut start = 0;
+    while start < blocks.len() {
+        let end = flat_run(blocks, &steps, start);
+        if end == start {
+            out.push(blocks[start].clone());
+            start += 1;
+            continue;
+        }
+        let mut points = vec![[steps[start].start[X], steps[start].start[Y]]];
+        points.extend(
+            steps[start..end]
+                .iter()
+                .map(|step| [step.end[X], step.end[Y]]),
+        );
+        let tolerance = tolerance / steps[start].after.units.scale();
+
+        let mut from = 0;
+        for piece in gcode_arc::fit_arcs(&points, tolerance) {
+            let range = start + from..start + piece.end();
+            match piece {
+                Piece::Line { .. } => out.extend_from_slice(&blocks[range]),
+                Piece::Arc {
+                    center, clockwise, ..
+                } => {
+                    let (words, f) = combined_words(blocks, &steps, range.clone());
+                    let [i, j] = [center[0] - points[from][0], center[1] - points[from][1]];
+                    let (x, y, z, e) = (words[X], words[Y], words[Z], words[E]);
+                    let (i, j, k, r, p) = (Some(i), Some(j), None, None, None);
+                    let code = if clockwise {
+                        GCode::G2 {
+                            x,
+                            y,
+                            z,
+                            i,
+                            j,
+                            k,
+                            r,
+                            p,
+                            e,
+                            f,
+                        }
+                    } else {
+                        GCode::G3 {
+                            x,
+                            y,
+                            z,
+                            i,
+                            j,
+                            k,
+                            r,
+                            p,
+                            e,
+                            f,
+                        }
+                    };
+                    out.push(Block {
+                        codes: vec![code],
+                        ..blocks[range.start].clone()
+                    });
+                }
+            }
+            from = piece.end();
+        }
+        start = end;
+    }
+    out
+}
+
+/// End of the run of `G1` moves from `start` that stay at one height in the `G17` plane without
+/// extruding, exclusive; `start` itself when the block at `start` is not such a move.
+fn flat_run(blocks: &[Block], steps: &[Step], start: usize) -> usize {
+    let flat = |index: usize| {
+        let motion = line_move(&blocks[index], &steps[index])?;
+        let state = &steps[index].after;
+        (state.plane == Plane::XY
+            && state.compensation == Compensation::Off
+            && motion.start[Z] == motion.end[Z]
+            && motion.start[E] == motion.end[E])
+            .then_some(motion)
+    };
+    let Some(first) = flat(start) else {
+        return start;
+    };
+    let mut end = start + 1;
+    while let Some(next) = (end < blocks.len()).then(|| flat(end)).flatten() {
+        let fits = blocks[end].comments.is_empty()
+            && same_settings(first, next)
+            && next.start[Z] == first.start[Z]
+            && steps[end].after.distance == steps[start].after.distance;
+        if !fits {
+            break;
+        }
+        end += 1;
+    }
+    end
+}
+
+/// A stretch of the program starting with rapids and going on to cut.
+struct Contour {
+    blocks: Range<usize>,
+
+    /// Whether it is only plain moves in absolute coordinates, with no extrusion.
+    movable: bool,
+
+    /// Whether its rapids set `X`, `Y` and `Z` before it starts cutting.
+    independent: bool,
+
+    cuts: bool,
+
+    /// Program `X` and `Y` where it starts cutting and where it ends.
+    entry: [f32; 2],
+    exit: [f32; 2],
+}
+
+fn contours(blocks: &[Block], steps: &[Step]) -> Vec<Contour> {
+    let mut starts = vec![0];
+    let mut cut = false;
+    for (index, block) in blocks.iter().enumerate() {
+        let rapid = block
+            .codes
+            .iter()
+            .any(|code| matches!(code, GCode::G0 { .. }));
+        if rapid && cut {
+            starts.push(index);
+            cut = false;
+        }
+        cut |= block
+            .codes
+            .iter()
+            .any(|code| matches!(code, GCode::G1 { .. } | GCode::G2 { .. } | GCode::G3 { .. }));
+    }
+    starts.push(blocks.len());
+
+    let xy = |position: Position| [position[X], position[Y]];
+    starts
+        .windows(2)
+        .map(|pair| {
+            let blocks_range = pair[0]..pair[1];
+            let mut contour = Contour {
+                blocks: blocks_range.clone(),
+                movable: true,
+                independent: false,
+                cuts: false,
+                entry: [0.0; 2],
+                exit: xy(steps[blocks_range.end - 1].end),
+            };
+            let mut set = [false; 3];
+            for index in blocks_range {
+                let (block, step) = (&blocks[index], &steps[index]);
+                if block.codes.is_empty() {
+                    continue;
+                }
+                let Some(code) = plain_motion(block) else {
+                    contour.movable = false;
+                    continue;
+                };
+                let (axes, _) = axis_words(code).unwrap_or_default();
+                contour.movable &= axes[E].is_none()
+                    && [&step.state, &step.after].iter().all(|state| {
+                        state.distance == Distance::Absolute
+                            && state.feed_mode == FeedMode::UnitsPerMinute
+                            && state.compensation == Compensation::Off
+                    });
+                if matches!(code, GCode::G0 { .. }) {
+                    if !contour.cuts {
+                        for (set, word) in set.iter_mut().zip(axes) {
+                            *set |= word.is_some();
+                        }
+                    }
+                } else if !contour.cuts {
+                    contour.cuts = true;
+                    contour.independent = set == [true; 3];
+                    contour.entry = xy(step.start);
+                }
+            }
+            contour
+        })
+        .collect()
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f64 {
+    f64::from((a[0] - b[0]).hypot(a[1] - b[1]))
+}
+
+/// Cuts each run of independent contours in the order with the shortest rapids between them.
+fn reorder(blocks: &[Block]) -> Vec<Block> {
+    if blocks.is_empty() {
+        return vec![];
+    }
+    let steps = trace(blocks);
+    let contours = contours(blocks, &steps);
+    let mut order = Vec::with_capacity(blocks.len());
+    let mut index = 0;
+    while index < contours.len() {
+        let free = |contour: &Contour| contour.movable && contour.independent && contour.cuts;
+        let mut end = index;
+        while end < contours.len() && free(&contours[end]) {
+            end += 1;
+        }
+        // A contour that starts cutting wherever the last one ended has to stay after it.
+        if let Some(next) = contours.get(end) {
+            if next.cuts && !next.independent && end > index {
+                end -= 1;
+            }
+        }
+        if end - index < 2 {
+            order.extend(contours[index].blocks.clone());
+            index += 1;
+            continue;
+        }
+
+        let run = &contours[index..end];
+        let start = steps[run[0].blocks.start].start;
+        for contour in shortest_order([start[X], start[Y]], run) {
+            order.extend(run[contour].blocks.clone());
+        }
+        index = end;
+    }
+
+    // A cutting move that relied on the feedrate left by the block before it in the program
+    // gets it back wherever the new order leaves a different one.
+    let mut feedrate = steps[0].state.feedrate;
+    let mut out = Vec::with_capacity(blocks.len());
+    for index in order {
+        let (mut block, step) = (blocks[index].clone(), &steps[index]);
+        if let Some(code) = block.codes.first_mut() {
+            let (axes, f) = axis_words(code).unwrap_or_default();
+            let cutting = axis_words(code).is_some() && !matches!(code, GCode::G0 { .. });
+            if cutting
+                && f.is_none()
+                && step.state.feedrate > 0.0
+                && feedrate != step.state.feedrate
+            {
+                let f = step.state.feedrate / step.state.units.scale();
+                *code = with_axis_words(code, axes, Some(f));
+                feedrate = step.state.feedrate;
+            }
+        }
+        let sets_feedrate = block
+            .codes
+            .iter()
+            .any(|code| matches!(axis_words(code), Some((_, Some(_)))));
+        if sets_feedrate || feedrate == step.state.feedrate {
+            feedrate = step.after.feedrate;
+        }
+        out.push(block);
+    }
+    out
+}
+
+/// Nearest neighbour from `start`, improved by reversing stretches of the order while that
+/// shortens the rapids.
+fn shortest_order(start: [f32; 2], contours: &[Contour]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(contours.len());
+    let mut remaining: Vec<usize> = (0..contours.len()).collect();
+    let mut at = start;
+    while !remaining.is_empty() {
+        let nearest = (0..remaining.len())
+            .min_by(|&a, &b| {
+                let entry = |i: usize| contours[remaining[i]].entry;
+                distance(at, entry(a)).total_cmp(&distance(at, entry(b)))
+            })
+            .unwrap_or_default();
+        let contour = remaining.remove(nearest);
+        at = contours[contour].exit;
+        order.push(contour);
+    }
+
+    // Reversing `order[i..=j]` turns around every rapid inside the stretch, and the rapids
+    // between contours are not the same both ways, so both directions are summed up front.
+    loop {
+        let hop = |from: usize, to: usize| distance(contours[from].exit, contours[to].entry);
+        let mut forward = vec![0.0];
+        let mut backward = vec![0.0];
+        for pair in order.windows(2) {
+            forward.push(forward.last().unwrap_or(&0.0) + hop(pair[0], pair[1]));
+            backward.push(backward.last().unwrap_or(&0.0) + hop(pair[1], pair[0]));
+        }
+
+        let mut best = None;
+        for i in 0..order.len() {
+            let before = if i == 0 {
+                start
+            } else {
+                contours[order[i - 1]].exit
+            };
+            for j in i + 1..order.len() {
+                let mut old = distance(before, contours[order[i]].entry) + forward[j] - forward[i];
+                let mut new =
+                    distance(before, contours[order[j]].entry) + backward[j] - backward[i];
+                if let Some(&after) = order.get(j + 1) {
+                    old += hop(order[j], after);
+                    new += hop(order[i], after);
+                }
+                let gain = old - new;
+                if gain > 1e-3 && best.map_or(true, |(_, _, best)| gain > best) {
+                    best = Some((i, j, gain));
+                }
+            }
+        }
+        match best {
+            Some((i, j, _)) => order[i..=j].reverse(),
+            None => break,
+        }
+    }
+    order
+}
+
+#[test]
+fn test_gcode_optimize() {
+    use super::gcode_emitter::{emit_program, EmitOptions};
+    use super::gcode_parser::parse;
+
+    let run = |program: &str, options: &OptimizeOptions| {
+        let blocks = optimize(&parse(program).unwrap(), options);
+        emit_program(&blocks, &EmitOptions::default())
+    };
+    let options = OptimizeOptions::default();
+
+    // Redundant modes, words and feedrates go, and so does the move that goes nowhere.
+    assert_eq!(
+        run(
+            "G21 G90\nG21\nG0 X0 Y0\nG1 X10 Y0 F300\nG1 X10 Y5 F300\nG1 X10 Y5\nG90 G1 X0 F200",
+            &options
+        ),
+        "G21 G90\nG0 X0 Y0\nG1 X10 F300\nG1 Y5\nG1 X0 F200\n"
+    );
+
+    // Collinear moves merge, unless they bend too far or turn back: Y1 to Y0 goes on as one.
+    assert_eq!(
+        run(
+            "G1 X0 Y0 F100\nG1 X1 Y0.001\nG1 X2 Y0\nG1 X3 Y0\nG1 X3 Y1\nG1 X3 Y0.5\nG1 X3 Y0",
+            &options
+        ),
+        "G1 X0 Y0 F100\nG1 X3 Y0\nG1 Y1\nG1 Y0\n"
+    );
+    // Relative moves add up, extrusion included.
+    assert_eq!(
+        run("G91\nG1 X1 E1 F100\nG1 X1 E1\nG1 X2 E2", &options),
+        "G91\nG1 X4 E4 F100\n"
+    );
+
+    // Squares around (0, 0), (100, 0) and (10, 0) come out nearest first, all still at the
+    // feedrate set before them.
+    let square = |x: f32| {
+        format!(
+            "G0 Z5\nG0 X{} Y0\nG1 Z-1\nG1 Y10\nG1 X{}\nG1 Y0\nG1 X{}\n",
+            x,
+            x + 5.0,
+            x
+        )
+    };
+    let program = format!(
+        "G1 F300\n{}{}{}G0 Z5\n",
+        square(0.0),
+        square(100.0),
+        square(10.0)
+    );
+    let blocks = optimize(&parse(&program).unwrap(), &options);
+    let rapids: Vec<f32> = blocks
+        .iter()
+        .filter_map(|block| match block.codes.as_slice() {
+            [GCode::G0 { x: Some(x), .. }] => Some(*x),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(rapids, [0.0, 10.0, 100.0]);
+    let original = Interpreter::default().run(&parse(&program).unwrap());
+    let optimized = Interpreter::default().run(&blocks);
+    let rapid_distance = |moves: &[Move]| -> f32 {
+        moves
+            .iter()
+            .filter(|motion| motion.kind == MoveKind::Rapid)
+            .map(Move::length)
+            .sum()
+    };
+    assert!(rapid_distance(&optimized) < rapid_distance(&original) - 50.0);
+    assert!(optimized
+        .iter()
+        .all(|motion| motion.kind == MoveKind::Rapid || motion.feedrate == Some(300.0)));
+
+    // Cut nearest first, the two squares swap their feedrates, and the contour after them, which
+    // cannot move for its dwell, gets back the feedrate it was left with.
+    let program = "G0 Z5\nG0 X100 Y0\nG1 Z-1 F100\nG1 X105\n\
+                   G0 Z5\nG0 X0 Y0\nG1 Z-1 F500\nG1 X5\n\
+                   G0 Z5\nG0 X50 Y0\nG1 Z-1\nG4 P0\nG1 X55\nG0 Z5\n";
+    let blocks = optimize(&parse(program).unwrap(), &options);
+    let feeds: Vec<(f32, Option<f32>)> = Interpreter::default()
+        .run(&blocks)
+        .iter()
+        .filter(|motion| motion.kind == MoveKind::Linear && motion.start[Z] == motion.end[Z])
+        .map(|motion| (motion.end[X], motion.feedrate))
+        .collect();
+    assert_eq!(
+        feeds,
+        [
+            (5.0, Some(500.0)),
+            (105.0, Some(100.0)),
+            (55.0, Some(500.0))
+        ]
+    );
+
+    // A polygon with 36 sides is a circle within half a millimetre.
+    let mut program = "G0 X10 Y0\nG1 F100\n".to_owned();
+    for step in 1..=18 {
+        let angle = step as f32 * std::f32::consts::TAU / 36.0;
+        program += &format!("G1 X{} Y{}\n", 10.0 * angle.cos(), 10.0 * angle.sin());
+    }
+    let fitted = OptimizeOptions {
+        tolerance: 0.5,
+        fit_arcs: true,
+        ..options
+    };
+    let blocks = optimize(&parse(&program).unwrap(), &fitted);
+    // The feedrate goes onto the arc.
+    assert_eq!(blocks.len(), 2);
+    let GCode::G3 { x, y, i, j, f, .. } = blocks[1].codes[0] else {
+        panic!("{:?}", blocks[1]);
+    };
+    assert_eq!(f, Some(100.0));
+    assert!((x.unwrap() + 10.0).abs() < 1e-3 && y.unwrap().abs() < 1e-3);
+    assert!((i.unwrap() + 10.0).abs() < 0.1 && j.unwrap().abs() < 0.1);
+}
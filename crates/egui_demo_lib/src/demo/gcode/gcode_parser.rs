@@ -0,0 +1,2607 @@
+//! A parser for G-code programs (`.gcode`, `.nc`, `.ngc`).
+//!
+//! Every source line becomes a [`Block`] holding the [`GCode`] values on it, so a program can be
+//! parsed, transformed and written back without losing comments or line structure.
+//!
+//! Handles `;` and `( )` comments, `N` line numbers, `*` checksums, `/` block delete, `%` program
+//! delimiters and lower case words. Several codes may share a line (`G0 G90 G54 X0 Y0 S1000 M3`):
+//! each parameter goes to the nearest command on the line that accepts it. Axis words on a line
+//! with no motion code reuse the last motion mode (`G0`, `G1`, `G2`, `G3`, `G38.x` or a canned
+//! cycle `G81` to `G89`).
+//!
+//! Text arguments are either quoted RepRapFirmware style (`M32 "job.g"`, `""` for a literal quote)
+//! or, for the file and message codes such as `M23` and `M117`, the rest of the line.
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::{Cycle, GCode};
+
+/// The command part of a word, e.g. `G38.2` is `Code { letter: 'G', number: 38, sub: Some(2) }`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Code {
+    pub letter: char,
+    pub number: u32,
+    pub sub: Option<u32>,
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.letter, self.number)?;
+        if let Some(sub) = self.sub {
+            write!(f, ".{}", sub)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Comment {
+    /// `; until the end of the line`
+    Semicolon(String),
+
+    /// `(inline)`, after the first `word` code and parameter words of the line.
+    Parenthesis { text: String, word: usize },
+}
+
+/// One line of a G-code program.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Block {
+    /// 1-based line in the source text.
+    pub line: usize,
+
+    /// Leading `/`: the controller may skip this block.
+    pub block_delete: bool,
+
+    /// `N` word.
+    pub line_number: Option<u32>,
+
+    /// `O` word.
+    pub program_number: Option<u32>,
+
+    /// A `%` program delimiter line.
+    pub percent: bool,
+
+    /// Codes in the order they appear on the line.
+    pub codes: Vec<GCode>,
+
+    pub comments: Vec<Comment>,
+
+    /// `*` checksum, already verified against the line.
+    pub checksum: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// 1-based line.
+    pub line: usize,
+
+    /// 1-based column.
+    pub column: usize,
+
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    UnexpectedCharacter(char),
+    UnknownCode(String),
+    InvalidValue {
+        letter: char,
+        value: String,
+    },
+
+    /// No command on the line accepts this parameter.
+    UnexpectedParameter {
+        code: Option<String>,
+        letter: char,
+    },
+
+    DuplicateParameter {
+        code: String,
+        letter: char,
+    },
+    MissingParameter {
+        code: String,
+        letter: char,
+    },
+    UnterminatedComment,
+    UnterminatedString,
+    ChecksumMismatch {
+        computed: u8,
+        found: u8,
+    },
+
+    /// A `#<name>` read before anything was assigned to it.
+    UnknownParameter(String),
+
+    /// A call to an O-word or `M98` subprogram that is not in the program.
+    UnknownSubroutine(String),
+
+    /// A malformed `[expression]` or `#` parameter.
+    InvalidExpression(String),
+
+    /// An O-word with no matching `sub`, `if`, loop or end.
+    UnmatchedControl(String),
+
+    /// A loop or recursion that does not finish.
+    RunawayProgram,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character {:?}", c),
+            ParseErrorKind::UnknownCode(code) => write!(f, "unknown code {}", code),
+            ParseErrorKind::InvalidValue { letter, value } => {
+                write!(f, "invalid value {:?} for {}", value, letter)
+            }
+            ParseErrorKind::UnexpectedParameter {
+                code: Some(code),
+                letter,
+            } => write!(f, "{} does not take a {} parameter", code, letter),
+            ParseErrorKind::UnexpectedParameter { code: None, letter } => {
+                write!(f, "{} parameter with no command or motion mode", letter)
+            }
+            ParseErrorKind::DuplicateParameter { code, letter } => {
+                write!(f, "{} parameter given twice to {}", letter, code)
+            }
+            ParseErrorKind::MissingParameter { code, letter } => {
+                write!(f, "{} requires a {} parameter", code, letter)
+            }
+            ParseErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            ParseErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            ParseErrorKind::ChecksumMismatch { computed, found } => {
+                write!(f, "checksum is {} but the line says {}", computed, found)
+            }
+            ParseErrorKind::UnknownParameter(name) => write!(f, "parameter #{} is not set", name),
+            ParseErrorKind::UnknownSubroutine(name) => write!(f, "no subroutine {}", name),
+            ParseErrorKind::InvalidExpression(message) => write!(f, "{}", message),
+            ParseErrorKind::UnmatchedControl(label) => {
+                write!(f, "{} has no matching start or end", label)
+            }
+            ParseErrorKind::RunawayProgram => write!(f, "the program does not end"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse a whole program, stopping at the first error.
+pub fn parse(src: &str) -> Result<Vec<Block>, ParseError> {
+    let mut parser = Parser::default();
+    src.lines()
+        .enumerate()
+        .map(|(index, line)| parser.parse_line(index + 1, line))
+        .collect()
+}
+
+/// Parses one line at a time, remembering the motion mode between lines.
+///
+/// Use this directly to report every bad line of a program instead of only the first.
+#[derive(Clone, Debug, Default)]
+pub struct Parser {
+    motion: Option<Code>,
+}
+
+/// A parameter word such as `X10`, `P"file.g"` or a bare flag `X`.
+#[derive(Clone, Debug)]
+struct Word {
+    letter: char,
+    value: String,
+    column: usize,
+}
+
+/// A command word with the text that follows it on the line, if it takes free text.
+struct Command {
+    code: Code,
+    column: usize,
+    tail: Option<String>,
+    words: Vec<Word>,
+}
+
+impl Parser {
+    /// `line` is the 1-based line number used in the [`Block`] and in errors.
+    pub fn parse_line(&mut self, line: usize, text: &str) -> Result<Block, ParseError> {
+        let mut block = Block {
+            line,
+            ..Default::default()
+        };
+        let error = |column: usize, kind| ParseError { line, column, kind };
+
+        let bytes = text.as_bytes();
+        let mut commands: Vec<Command> = vec![];
+        // Parameter words, and the index of the command they follow.
+        let mut words: Vec<(Word, Option<usize>)> = vec![];
+        let mut pos = 0;
+        let mut first_word = true;
+        // Code and parameter words so far, for placing inline comments.
+        let mut word_count = 0;
+
+        while pos < bytes.len() {
+            let c = bytes[pos] as char;
+            let column = pos + 1;
+            if c.is_ascii_whitespace() {
+                pos += 1;
+            } else if c == ';' {
+                block
+                    .comments
+                    .push(Comment::Semicolon(text[pos + 1..].trim().to_owned()));
+                break;
+            } else if c == '(' {
+                let end = text[pos..]
+                    .find(')')
+                    .ok_or_else(|| error(column, ParseErrorKind::UnterminatedComment))?;
+                block.comments.push(Comment::Parenthesis {
+                    text: text[pos + 1..pos + end].trim().to_owned(),
+                    word: word_count,
+                });
+                pos += end + 1;
+            } else if c == '*' {
+                let computed = bytes[..pos].iter().fold(0, |sum, b| sum ^ b);
+                let digits = &text[pos + 1..];
+                let digits_len = digits
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(digits.len());
+                let found = digits[..digits_len].parse().map_err(|_err| {
+                    error(
+                        column,
+                        ParseErrorKind::InvalidValue {
+                            letter: '*',
+                            value: digits[..digits_len].to_owned(),
+                        },
+                    )
+                })?;
+                if found != computed {
+                    return Err(error(
+                        column,
+                        ParseErrorKind::ChecksumMismatch { computed, found },
+                    ));
+                }
+                block.checksum = Some(found);
+                pos += 1 + digits_len;
+                let rest = text[pos..].trim_start();
+                if !rest.is_empty() && !rest.starts_with(';') {
+                    let column = text.len() - rest.len() + 1;
+                    let c = rest.chars().next().unwrap_or_default();
+                    return Err(error(column, ParseErrorKind::UnexpectedCharacter(c)));
+                }
+            } else if c == '/' && first_word {
+                block.block_delete = true;
+                pos += 1;
+            } else if c == '%' && text.trim() == "%" {
+                block.percent = true;
+                pos += 1;
+            } else if c.is_ascii_alphabetic() {
+                let letter = c.to_ascii_uppercase();
+                let start = pos;
+                pos += 1;
+                let value_start = pos;
+                let quoted = bytes.get(pos) == Some(&b'"');
+                let text_parameter = commands.last().map_or(false, |command| {
+                    text_parameters(command.code).contains(letter)
+                });
+                let value = if quoted {
+                    let (value, len) = quoted_string(&text[pos..])
+                        .ok_or_else(|| error(pos + 1, ParseErrorKind::UnterminatedString))?;
+                    pos += len;
+                    value
+                } else if text_parameter {
+                    pos += text[pos..]
+                        .find(|c: char| c.is_ascii_whitespace() || c == ';' || c == '(')
+                        .unwrap_or(text.len() - pos);
+                    text[value_start..pos].to_owned()
+                } else {
+                    pos += text[pos..]
+                        .find(|c: char| !(c.is_ascii_digit() || "+-.:".contains(c)))
+                        .unwrap_or(text.len() - pos);
+                    text[value_start..pos].to_owned()
+                };
+
+                match letter {
+                    'N' if first_word => {
+                        block.line_number = Some(parse_number(&value).ok_or_else(|| {
+                            error(column, ParseErrorKind::InvalidValue { letter, value })
+                        })?);
+                    }
+                    'O' if commands.is_empty() => {
+                        block.program_number = Some(parse_number(&value).ok_or_else(|| {
+                            error(column, ParseErrorKind::InvalidValue { letter, value })
+                        })?);
+                    }
+                    // `T` selects a tool unless the command before it takes a `T` parameter.
+                    'G' | 'M' | 'T'
+                        if letter != 'T'
+                            || !commands
+                                .last()
+                                .map_or(false, |command| accepts(command.code, 'T')) =>
+                    {
+                        let code = parse_code(letter, &value).ok_or_else(|| {
+                            error(column, ParseErrorKind::InvalidValue { letter, value })
+                        })?;
+                        if parameters(code).is_none() {
+                            return Err(error(
+                                column,
+                                ParseErrorKind::UnknownCode(text[start..pos].to_owned()),
+                            ));
+                        }
+                        let tail = if takes_text(code) {
+                            let rest = &text[pos..];
+                            let rest_start = rest.len() - rest.trim_start().len();
+                            pos += rest_start;
+                            if bytes.get(pos) == Some(&b'"') {
+                                let (value, len) =
+                                    quoted_string(&text[pos..]).ok_or_else(|| {
+                                        error(pos + 1, ParseErrorKind::UnterminatedString)
+                                    })?;
+                                pos += len;
+                                Some(value)
+                            } else {
+                                let len = text[pos..]
+                                    .find([';', '(', '*'])
+                                    .unwrap_or(text.len() - pos);
+                                let value = text[pos..pos + len].trim_end().to_owned();
+                                pos += len;
+                                Some(value)
+                            }
+                        } else {
+                            None
+                        };
+                        commands.push(Command {
+                            code,
+                            column,
+                            tail,
+                            words: vec![],
+                        });
+                        word_count += 1;
+                    }
+                    _ => {
+                        let word = Word {
+                            letter,
+                            value,
+                            column,
+                        };
+                        words.push((word, commands.len().checked_sub(1)));
+                        word_count += 1;
+                    }
+                }
+                first_word = false;
+            } else {
+                return Err(error(column, ParseErrorKind::UnexpectedCharacter(c)));
+            }
+        }
+
+        // Hand each parameter to the nearest command before it that accepts it, else the nearest
+        // one after it, else the motion mode carried over from earlier lines.
+        let mut modal: Option<usize> = None;
+        for (word, follows) in words {
+            let letter = word.letter;
+            let accepted = |command: &Command| accepts(command.code, letter);
+            let before =
+                follows.and_then(|follows| commands[..=follows].iter().rposition(accepted));
+            let after = || {
+                let from = follows.map_or(0, |follows| follows + 1);
+                commands[from..]
+                    .iter()
+                    .position(accepted)
+                    .map(|index| index + from)
+            };
+            let owner = match (before.or_else(after), self.motion) {
+                (Some(owner), _) => owner,
+                (None, Some(motion))
+                    if accepts(motion, letter)
+                        && (modal.is_some()
+                            || !commands.iter().any(|command| is_motion(command.code))) =>
+                {
+                    *modal.get_or_insert_with(|| {
+                        commands.push(Command {
+                            code: motion,
+                            column: word.column,
+                            tail: None,
+                            words: vec![],
+                        });
+                        commands.len() - 1
+                    })
+                }
+                (None, _) => {
+                    let code = follows
+                        .or_else(|| (!commands.is_empty()).then_some(0))
+                        .map(|index| commands[index].code.to_string());
+                    return Err(error(
+                        word.column,
+                        ParseErrorKind::UnexpectedParameter { code, letter },
+                    ));
+                }
+            };
+            commands[owner].words.push(word);
+        }
+
+        for command in commands {
+            if is_motion(command.code) {
+                self.motion = Some(command.code);
+            } else if command.code == Code::new('G', 80) {
+                self.motion = None;
+            }
+            let mut args = Args {
+                line,
+                code: command.code,
+                column: command.column,
+                tail: command.tail,
+                words: command.words,
+            };
+            let code = build(&mut args)?;
+            args.finish()?;
+            block.codes.push(code);
+        }
+
+        Ok(block)
+    }
+}
+
+impl Code {
+    pub fn new(letter: char, number: u32) -> Self {
+        Self {
+            letter,
+            number,
+            sub: None,
+        }
+    }
+}
+
+pub(super) fn parse_code(letter: char, value: &str) -> Option<Code> {
+    let (number, sub) = match value.split_once('.') {
+        Some((number, sub)) => (number, Some(sub.parse().ok()?)),
+        None => (value, None),
+    };
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(Code {
+        letter,
+        number: number.parse().ok()?,
+        sub,
+    })
+}
+
+fn accepts(code: Code, letter: char) -> bool {
+    parameters(code).map_or(false, |letters| letters.contains(letter))
+}
+
+fn is_motion(code: Code) -> bool {
+    code.letter == 'G'
+        && (code.number <= 3 || code.number == 38 || (81..=89).contains(&code.number))
+}
+
+/// Parses a `"quoted"` string at the start of `s`, returning it and the length consumed.
+fn quoted_string(s: &str) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices().skip(1).peekable();
+    while let Some((index, c)) = chars.next() {
+        if c == '"' {
+            if chars.peek().map(|&(_, c)| c) == Some('"') {
+                chars.next();
+            } else {
+                return Some((value, index + 1));
+            }
+        }
+        value.push(c);
+    }
+    None
+}
+
+/// Like [`str::parse`], but also accepts integers written with a fraction of zero (`P1.000`).
+fn parse_number<T: FromStr>(s: &str) -> Option<T> {
+    s.parse().ok().or_else(|| {
+        let (whole, fraction) = s.split_once('.')?;
+        if fraction.bytes().all(|b| b == b'0') {
+            whole.parse().ok()
+        } else {
+            None
+        }
+    })
+}
+
+fn parse_list<T: FromStr>(s: &str) -> Option<Vec<T>> {
+    s.split(':').map(parse_number).collect()
+}
+
+/// The parameter words given to one command, consumed as the [`GCode`] is built.
+struct Args {
+    line: usize,
+    code: Code,
+    column: usize,
+    tail: Option<String>,
+    words: Vec<Word>,
+}
+
+impl Args {
+    fn error(&self, column: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column,
+            kind,
+        }
+    }
+
+    fn invalid(&self, word: &Word) -> ParseError {
+        self.error(
+            word.column,
+            ParseErrorKind::InvalidValue {
+                letter: word.letter,
+                value: word.value.clone(),
+            },
+        )
+    }
+
+    fn missing(&self, letter: char) -> ParseError {
+        self.error(
+            self.column,
+            ParseErrorKind::MissingParameter {
+                code: self.code.to_string(),
+                letter,
+            },
+        )
+    }
+
+    fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.tail.as_deref().map_or(true, str::is_empty)
+    }
+
+    fn has(&self, letter: char) -> bool {
+        self.words.iter().any(|word| word.letter == letter)
+    }
+
+    fn value(&self, letter: char) -> Option<&str> {
+        self.words
+            .iter()
+            .find(|word| word.letter == letter)
+            .map(|word| word.value.as_str())
+    }
+
+    fn take(&mut self, letter: char) -> Option<Word> {
+        let index = self.words.iter().position(|word| word.letter == letter)?;
+        Some(self.words.remove(index))
+    }
+
+    fn number<T: FromStr>(&mut self, letter: char) -> Result<Option<T>, ParseError> {
+        match self.take(letter) {
+            Some(word) => parse_number(&word.value)
+                .map(Some)
+                .ok_or_else(|| self.invalid(&word)),
+            None => Ok(None),
+        }
+    }
+
+    fn required<T: FromStr>(&mut self, letter: char) -> Result<T, ParseError> {
+        self.number(letter)?.ok_or_else(|| self.missing(letter))
+    }
+
+    /// A flag such as `G28 X`: present and not `0`.
+    fn flag(&mut self, letter: char) -> Result<bool, ParseError> {
+        Ok(self.switch(letter)?.unwrap_or(false))
+    }
+
+    /// `P1`, `P0` or a bare `P` for on.
+    fn switch(&mut self, letter: char) -> Result<Option<bool>, ParseError> {
+        match self.take(letter) {
+            Some(word) if word.value.is_empty() => Ok(Some(true)),
+            Some(word) => parse_number::<f32>(&word.value)
+                .map(|value| Some(value != 0.0))
+                .ok_or_else(|| self.invalid(&word)),
+            None => Ok(None),
+        }
+    }
+
+    /// `X1.5` is a position, a bare `X` means "use the measured value".
+    fn measured(&mut self, letter: char) -> Result<(Option<f32>, bool), ParseError> {
+        if self.value(letter) == Some("") {
+            self.take(letter);
+            Ok((None, true))
+        } else {
+            Ok((self.number(letter)?, false))
+        }
+    }
+
+    fn text(&mut self, letter: char) -> Option<String> {
+        self.take(letter).map(|word| word.value)
+    }
+
+    fn required_text(&mut self, letter: char) -> Result<String, ParseError> {
+        self.text(letter).ok_or_else(|| self.missing(letter))
+    }
+
+    /// Colon separated values, e.g. `X0:1:2`.
+    fn list<T: FromStr>(&mut self, letter: char) -> Result<Option<Vec<T>>, ParseError> {
+        match self.take(letter) {
+            Some(word) => parse_list(&word.value)
+                .map(Some)
+                .ok_or_else(|| self.invalid(&word)),
+            None => Ok(None),
+        }
+    }
+
+    fn required_list<T: FromStr>(&mut self, letter: char) -> Result<Vec<T>, ParseError> {
+        self.list(letter)?.ok_or_else(|| self.missing(letter))
+    }
+
+    /// Exactly `N` colon separated values.
+    fn array<T: FromStr, const N: usize>(
+        &mut self,
+        letter: char,
+    ) -> Result<Option<[T; N]>, ParseError> {
+        match self.take(letter) {
+            Some(word) => parse_list(&word.value)
+                .and_then(|values| <[T; N]>::try_from(values).ok())
+                .map(Some)
+                .ok_or_else(|| self.invalid(&word)),
+            None => Ok(None),
+        }
+    }
+
+    /// The free text after a file or message code.
+    fn tail(&mut self) -> String {
+        self.tail.take().unwrap_or_default()
+    }
+
+    fn optional_tail(&mut self) -> Option<String> {
+        Some(self.tail()).filter(|tail| !tail.is_empty())
+    }
+
+    /// Anything not consumed by [`build`] was given twice.
+    fn finish(self) -> Result<(), ParseError> {
+        match self.words.first() {
+            Some(word) => Err(self.error(
+                word.column,
+                ParseErrorKind::DuplicateParameter {
+                    code: self.code.to_string(),
+                    letter: word.letter,
+                },
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Codes whose argument is the rest of the line, like `M23 file.gco` or `M117 Hello world`.
+pub(super) fn takes_text(code: Code) -> bool {
+    code.letter == 'M'
+        && code.sub.is_none()
+        && matches!(
+            code.number,
+            1 | 16 | 23 | 28 | 29 | 30 | 32 | 33 | 36 | 38 | 117 | 531
+        )
+}
+
+#[allow(clippy::too_many_lines)]
+/// The words of a canned cycle.
+fn cycle(args: &mut Args) -> Result<Cycle, ParseError> {
+    Ok(Cycle {
+        x: args.number('X')?,
+        y: args.number('Y')?,
+        z: args.number('Z')?,
+        r: args.number('R')?,
+        q: args.number('Q')?,
+        p: args.number('P')?,
+        l: args.number('L')?,
+        f: args.number('F')?,
+    })
+}
+
+fn build(args: &mut Args) -> Result<GCode, ParseError> {
+    let code = args.code;
+    Ok(match (code.letter, code.number, code.sub) {
+        ('G', 0, None) => GCode::G0 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+            f: args.number('F')?,
+        },
+        ('G', 1, None) => GCode::G1 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+            f: args.number('F')?,
+        },
+        ('G', 2, None) => GCode::G2 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            i: args.number('I')?,
+            j: args.number('J')?,
+            k: args.number('K')?,
+            r: args.number('R')?,
+            p: args.number('P')?,
+            e: args.number('E')?,
+            f: args.number('F')?,
+        },
+        ('G', 3, None) => GCode::G3 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            i: args.number('I')?,
+            j: args.number('J')?,
+            k: args.number('K')?,
+            r: args.number('R')?,
+            p: args.number('P')?,
+            e: args.number('E')?,
+            f: args.number('F')?,
+        },
+        ('G', 4, None) => GCode::G4 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('G', 6, None) => GCode::G6 {
+            a: args.number('A')?,
+            b: args.number('B')?,
+            c: args.number('C')?,
+            r: args.switch('R')?,
+        },
+        ('G', 10, None) => {
+            let uvwabc = [
+                args.number('U')?,
+                args.number('V')?,
+                args.number('W')?,
+                args.number('A')?,
+                args.number('B')?,
+                args.number('C')?,
+            ];
+            GCode::G10 {
+                l: args.number('L')?,
+                p: args.number('P')?,
+                x: args.number('X')?,
+                y: args.number('Y')?,
+                z: args.number('Z')?,
+                uvwabc: uvwabc.iter().any(Option::is_some).then_some(uvwabc),
+                r: args.number('R')?,
+                s: args.number('S')?,
+            }
+        }
+        ('G', 11, None) => GCode::G11 {
+            s: args.number('S')?,
+        },
+        ('G', 12, None) => GCode::G12 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+            t: args.number('T')?,
+        },
+        ('G', 17, None) => GCode::G17,
+        ('G', 18, None) => GCode::G18,
+        ('G', 19, None) => GCode::G19,
+        ('G', 20, None) => GCode::G20,
+        ('G', 21, None) => GCode::G21,
+        ('G', 22, None) => GCode::G22,
+        ('G', 23, None) => GCode::G23,
+        ('G', 26, None) => GCode::G26 {
+            c: args.switch('C')?,
+            p: args.switch('P')?,
+            o: args.number('O')?,
+        },
+        ('G', 27, None) => GCode::G27 {
+            p: args.number('P')?,
+        },
+        ('G', 28, None) => GCode::G28 {
+            x: args.flag('X')?,
+            y: args.flag('Y')?,
+            z: args.flag('Z')?,
+            p: args.switch('P')?,
+            i: args.switch('I')?,
+        },
+        ('G', 29, None) => GCode::G29 {
+            s: args.number('S')?,
+            p: args.text('P'),
+        },
+        ('G', 30, None) => GCode::G30 {
+            p: args.number('P')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            h: args.number('H')?,
+            s: args.number('S')?,
+        },
+        ('G', 31, None) => GCode::G31 {
+            p: args.number('P')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            c: args.number('C')?,
+            s: args.number('S')?,
+            t: args.number('T')?,
+        },
+        ('G', 32, None) => GCode::G32 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+        },
+        ('G', 33, None) => GCode::G33 {
+            l: args.number('L')?,
+            r: args.number('R')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('G', 34, None) => GCode::G34 {
+            i: args.number('I')?,
+            t: args.number('T')?,
+            a: args.number('A')?,
+        },
+        ('G', 38, Some(2)) => GCode::G38_2 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            f: args.number('F')?,
+        },
+        ('G', 38, Some(3)) => GCode::G38_3 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            f: args.number('F')?,
+        },
+        ('G', 38, Some(4)) => GCode::G38_4 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            f: args.number('F')?,
+        },
+        ('G', 38, Some(5)) => GCode::G38_5 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            f: args.number('F')?,
+        },
+        ('G', 40, None) => GCode::G40,
+        ('G', 41, None) => GCode::G41 {
+            d: args.number('D')?,
+        },
+        ('G', 41, Some(1)) => GCode::G41_1 {
+            d: args.required('D')?,
+        },
+        ('G', 42, None) if "IJPF".chars().any(|letter| args.has(letter)) => GCode::G42MeshMove {
+            i: args.number('I')?,
+            j: args.number('J')?,
+            p: args.switch('P')?,
+            f: args.number('F')?,
+        },
+        ('G', 42, None) => GCode::G42 {
+            d: args.number('D')?,
+        },
+        ('G', 42, Some(1)) => GCode::G42_1 {
+            d: args.required('D')?,
+        },
+        ('G', 53, None) => GCode::G53,
+        ('G', 54, None) => GCode::G54,
+        ('G', 55, None) => GCode::G55,
+        ('G', 56, None) => GCode::G56,
+        ('G', 57, None) => GCode::G57,
+        ('G', 58, None) => GCode::G58,
+        ('G', 59, None) => GCode::G59,
+        ('G', 60, None) => GCode::G60 {
+            s: args.number('S')?,
+        },
+        ('G', 68, None) => GCode::G68 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            r: args.number('R')?,
+            i: args.switch('I')?,
+        },
+        ('G', 69, None) => GCode::G69,
+        ('G', 75, None) => GCode::G75,
+        ('G', 76, None) => GCode::G76 {
+            b: args.switch('B')?,
+            p: args.switch('P')?,
+        },
+        ('G', 80, None) => GCode::G80,
+        ('G', 81, None) => GCode::G81(cycle(args)?),
+        ('G', 82, None) => GCode::G82(cycle(args)?),
+        ('G', 83, None) => GCode::G83(cycle(args)?),
+        ('G', 84, None) => GCode::G84(cycle(args)?),
+        ('G', 85, None) => GCode::G85(cycle(args)?),
+        ('G', 86, None) => GCode::G86(cycle(args)?),
+        ('G', 87, None) => GCode::G87(cycle(args)?),
+        ('G', 88, None) => GCode::G88(cycle(args)?),
+        ('G', 89, None) => GCode::G89(cycle(args)?),
+        ('G', 90, None) => GCode::G90,
+        ('G', 91, None) => GCode::G91,
+        ('G', 92, None) => GCode::G92 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+        },
+        ('G', 93, None) => GCode::G93,
+        ('G', 94, None) => GCode::G94,
+        ('G', 98, None) => GCode::G98,
+        ('G', 99, None) => GCode::G99,
+        ('G', 100, None) => GCode::G100 {
+            x: args.switch('X')?,
+            y: args.switch('Y')?,
+            z: args.switch('Z')?,
+            r: args.number('R')?,
+        },
+        ('G', 130, None) => GCode::G130 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            a: args.number('A')?,
+            b: args.number('B')?,
+        },
+        ('G', 161, None) => GCode::G161 {
+            x: args.switch('X')?,
+            y: args.switch('Y')?,
+            z: args.switch('Z')?,
+            f: args.number('F')?,
+        },
+        ('G', 162, None) => GCode::G162 {
+            x: args.switch('X')?,
+            y: args.switch('Y')?,
+            z: args.switch('Z')?,
+            f: args.number('F')?,
+        },
+        ('G', 425, None) => GCode::G425 {
+            b: args.switch('B')?,
+            t: args.number('T')?,
+            v: args.switch('V')?,
+            u: args.number('U')?,
+        },
+        ('M', 0, None) => GCode::M0 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 1, None) => GCode::M1 {
+            message: args.optional_tail(),
+        },
+        ('M', 2, None) => GCode::M2 {},
+        ('M', 3, None) => GCode::M3 {
+            s: args.number('S')?,
+        },
+        ('M', 4, None) => GCode::M4 {
+            s: args.number('S')?,
+        },
+        ('M', 5, None) => GCode::M5 {},
+        ('M', 6, None) => GCode::M6 {},
+        ('M', 7, None) => GCode::M7 {},
+        ('M', 8, None) => GCode::M8 {},
+        ('M', 9, None) => GCode::M9 {},
+        ('M', 10, None) => GCode::M10 {},
+        ('M', 11, None) => GCode::M11 {},
+        ('M', 13, None) => GCode::M13 {},
+        ('M', 16, None) => GCode::M16 {
+            machine_name: args.tail(),
+        },
+        ('M', 17, None) => GCode::M17 {
+            x: args.switch('X')?,
+            y: args.switch('Y')?,
+            z: args.switch('Z')?,
+            e: args.switch('E')?,
+        },
+        ('M', 18, None) => GCode::M18 {
+            x: args.switch('X')?,
+            y: args.switch('Y')?,
+            z: args.switch('Z')?,
+            e: args.switch('E')?,
+            s: args.number('S')?,
+        },
+        ('M', 20, None) => GCode::M20 {
+            s: args.number('S')?,
+            r: args.number('R')?,
+            p: args.text('P'),
+            l: args.flag('L')?.then_some(()),
+            t: args.flag('T')?.then_some(()),
+        },
+        ('M', 21, None) => GCode::M21 {
+            p: args.number('P')?,
+        },
+        ('M', 22, None) => GCode::M22 {
+            p: args.number('P')?,
+        },
+        ('M', 23, None) => GCode::M23 {
+            filename: args.tail(),
+        },
+        ('M', 24, None) => GCode::M24,
+        ('M', 25, None) => GCode::M25,
+        ('M', 26, None) => GCode::M26 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+        },
+        ('M', 27, None) => GCode::M27 {
+            c: args.switch('C')?,
+            s: args.number('S')?,
+        },
+        ('M', 28, None) => GCode::M28 {
+            filename: args.tail(),
+        },
+        ('M', 29, None) => GCode::M29 {
+            filename: args.tail(),
+        },
+        ('M', 30, None) => GCode::M30 {
+            filename: args.tail(),
+        },
+        ('M', 31, None) => GCode::M31,
+        ('M', 32, None) => GCode::M32 {
+            filename: args.tail(),
+        },
+        ('M', 33, None) => GCode::M33 { path: args.tail() },
+        ('M', 34, None) => GCode::M34 {},
+        ('M', 35, None) => GCode::M35 {},
+        ('M', 36, None) => GCode::M36 {
+            filename: args.optional_tail(),
+        },
+        ('M', 36, Some(1)) => GCode::M36_1 {
+            p: args.text('P'),
+            s: args.number('S')?,
+        },
+        ('M', 37, None) => GCode::M37 {
+            s: args.number('S')?,
+            p: args.text('P'),
+        },
+        ('M', 38, None) => GCode::M38 {
+            target_file: args.tail(),
+        },
+        ('M', 39, None) => GCode::M39 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 40, None) => GCode::M40 {},
+        ('M', 41, None) => GCode::M41 {},
+        ('M', 42, None) => GCode::M42 {
+            p: args.required('P')?,
+            s: args.required('S')?,
+        },
+        ('M', 43, None) if args.is_empty() => GCode::M43MaterialExhausted,
+        ('M', 43, None) => GCode::M43PinReport {
+            e: args.switch('E')?,
+            p: args.number('P')?,
+            w: args.switch('W')?,
+            i: args.switch('I')?,
+        },
+        ('M', 44, None) if args.is_empty() => GCode::M44ResetBedCalibration,
+        ('M', 44, None) => GCode::M44CodesDebug {
+            i: args.text('I'),
+            j: args.text('J'),
+        },
+        ('M', 45, None) => GCode::M45 {
+            v: args.number('V')?,
+        },
+        ('M', 46, None) => GCode::M46,
+        ('M', 47, None) => GCode::M47,
+        ('M', 48, None) => GCode::M48 {
+            p: args.number('P')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            v: args.number('V')?,
+            e: args.switch('E')?,
+            l: args.number('L')?,
+            s: args.switch('S')?,
+        },
+        ('M', 49, None) => GCode::M49 {
+            s: args.switch('S')?,
+        },
+        ('M', 70, None) => GCode::M70 {
+            p: args.number('P')?,
+            message: args.text('S'),
+        },
+        ('M', 72, None) => GCode::M72 {
+            p: args.number('P')?,
+        },
+        ('M', 73, None) => GCode::M73B {
+            p: args.number('P')?,
+            r: args.number('R')?,
+            q: args.number('Q')?,
+            s: args.number('S')?,
+            c: args.number('C')?,
+            d: args.number('D')?,
+        },
+        ('M', 75, None) => GCode::M75,
+        ('M', 76, None) => GCode::M76,
+        ('M', 80, None) => GCode::M80 { c: args.text('C') },
+        ('M', 81, None) => GCode::M81 {
+            p: args.switch('P')?,
+            r: args.switch('R')?,
+            s: args.number('S')?,
+        },
+        ('M', 82, None) => GCode::M82,
+        ('M', 83, None) => GCode::M83,
+        ('M', 84, None) => GCode::M84 {
+            i: args.number('I')?,
+        },
+        ('M', 85, None) => GCode::M85 {
+            s: args.number('S')?,
+        },
+        ('M', 86, None) => GCode::M86 {
+            s: args.number('S')?,
+        },
+        ('M', 87, None) => GCode::M87,
+        ('M', 92, None) => GCode::M92 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+            s: args.number('S')?,
+        },
+        ('M', 93, None) => GCode::M93,
+        ('M', 98, None) => GCode::M98 {
+            p: args.required_text('P')?,
+            l: args.number('L')?,
+        },
+        ('M', 99, None) => GCode::M99,
+        ('M', 101, None) => GCode::M101,
+        ('M', 102, None) => GCode::M102 {
+            s: args.number('S')?,
+        },
+        ('M', 103, None) => GCode::M103,
+        ('M', 104, None) => GCode::M104 {
+            c: args.number('C')?,
+            d: args.number('D')?,
+            s: args.number('S')?,
+            r: args.number('R')?,
+        },
+        ('M', 105, None) => GCode::M105,
+        ('M', 106, None) => GCode::M106 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+            i: args.number('I')?,
+            f: args.number('F')?,
+            l: args.number('L')?,
+            x: args.number('X')?,
+            b: args.number('B')?,
+            h: args.text('H'),
+            r: args.number('R')?,
+            t: args.number('T')?,
+            c: args.text('C'),
+        },
+        ('M', 107, None) => GCode::M107,
+        ('M', 108, None) => GCode::M108,
+        ('M', 109, None) => GCode::M109 {
+            c: args.switch('C')?,
+            s: args.number('S')?,
+            r: args.number('R')?,
+            t: args.number('T')?,
+            f: args.switch('F')?,
+        },
+        ('M', 110, None) => GCode::M110 {
+            n: args.number('N')?,
+        },
+        ('M', 111, None) => GCode::M111 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 112, None) => GCode::M112,
+        ('M', 113, None) => GCode::M113 {
+            s: args.number('S')?,
+        },
+        ('M', 114, None) => GCode::M114,
+        ('M', 115, None) => GCode::M115 {
+            b: args.number('B')?,
+            p: args.number('P')?,
+            v: args.switch('V')?,
+            u: args.text('U'),
+        },
+        ('M', 116, None) => GCode::M116 {
+            p: args.number('P')?,
+            h: args.number('H')?,
+            c: args.number('C')?,
+        },
+        ('M', 117, None) if args.is_empty() => GCode::M117_GetZeroPosition,
+        ('M', 117, None) => GCode::M117_DisplayMessage {
+            message: args.tail(),
+        },
+        ('M', 118, None) => GCode::M118 {
+            p: args.number('P')?,
+            s: args.text('S'),
+        },
+        ('M', 119, None) => GCode::M119,
+        ('M', 120, None) => GCode::M120,
+        ('M', 121, None) => GCode::M121,
+        ('M', 122, None) => GCode::M122 {
+            b: args.number('B')?,
+            p: args.number('P')?,
+        },
+        ('M', 124, None) if args.is_empty() => GCode::M124_ImmediateMotorStop,
+        ('M', 124, None) => GCode::M124_SetEndstopPullup {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            i: args.number('I')?,
+            j: args.number('J')?,
+            k: args.number('K')?,
+            p: args.number('P')?,
+            d: args.number('D')?,
+        },
+        ('M', 126, None) if args.has('T') => GCode::M126_MakerBot {
+            t: args.number('T')?,
+        },
+        ('M', 126, None) => GCode::M126_OpenValve {
+            p: args.number('P')?,
+        },
+        ('M', 127, None) if args.has('T') => GCode::M127_MakerBot {
+            t: args.number('T')?,
+        },
+        ('M', 127, None) => GCode::M127_CloseValve {
+            p: args.number('P')?,
+        },
+        ('M', 128, None) => GCode::M128_ExtruderPressurePWM {
+            s: args.number('S')?,
+        },
+        ('M', 129, None) => GCode::M129_ExtruderPressureOff {
+            p: args.number('P')?,
+        },
+        ('M', 130, None) => GCode::M130_SetPID_PValue {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 131, None) => GCode::M131_SetPID_IValue {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 132, None) if "XYZAB".chars().any(|letter| args.has(letter)) => {
+            GCode::M132_MakerBot {
+                x: args.number('X')?,
+                y: args.number('Y')?,
+                z: args.number('Z')?,
+                a: args.number('A')?,
+                b: args.number('B')?,
+            }
+        }
+        ('M', 132, None) => GCode::M132_SetPID_DValue {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 133, None) if args.has('S') => GCode::M133_SetPID_ILimitValue {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 133, None) => GCode::M133 {
+            t: args.number('T')?,
+            p: args.number('P')?,
+        },
+        ('M', 134, None) if args.is_empty() => GCode::M134,
+        ('M', 134, None) => GCode::M134_MakerBot {
+            t: args.number('T')?,
+            p: args.number('P')?,
+        },
+        ('M', 135, None) if args.has('T') => GCode::M135_MakerBot {
+            t: args.number('T')?,
+        },
+        ('M', 135, None) => GCode::M135 {
+            s: args.number('S')?,
+        },
+        ('M', 136, None) => GCode::M136,
+        ('M', 140, None) => GCode::M140 {
+            p: args.number('P')?,
+            h: args.number('H')?,
+            t: args.number('T')?,
+            s: args.number('S')?,
+            r: args.number('R')?,
+        },
+        ('M', 141, None) => GCode::M141 {
+            p: args.number('P')?,
+            h: args.number('H')?,
+            t: args.number('T')?,
+            s: args.number('S')?,
+            r: args.number('R')?,
+        },
+        ('M', 142, None) => GCode::M142,
+        ('M', 143, None) => GCode::M143 {
+            h: args.number('H')?,
+            s: args.number('S')?,
+        },
+        ('M', 144, None) => GCode::M144 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 146, None) => GCode::M146 {
+            r: args.number('R')?,
+        },
+        ('M', 149, None) => GCode::M149 {
+            c: args.flag('C')?,
+            k: args.flag('K')?,
+        },
+        ('M', 150, None) => GCode::M150 {
+            r: args.number('R')?,
+            u: args.number('U')?,
+            b: args.number('B')?,
+            w: args.number('W')?,
+            p: args.number('P')?,
+        },
+        ('M', 155, None) => GCode::M155 {
+            s: args.number('S')?,
+            c: args.number('C')?,
+        },
+        ('M', 160, None) => GCode::M160 {
+            s: args.number('S')?,
+        },
+        ('M', 163, None) => GCode::M163 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+        },
+        ('M', 164, None) => GCode::M164 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+        },
+        ('M', 165, None) => GCode::M165 {
+            a: args.number('A')?,
+            b: args.number('B')?,
+            c: args.number('C')?,
+            d: args.number('D')?,
+            h: args.number('H')?,
+            i: args.number('I')?,
+        },
+        ('M', 190, None) => GCode::M190 {
+            s: args.number('S')?,
+            r: args.number('R')?,
+        },
+        ('M', 191, None) => GCode::M191 {
+            s: args.number('S')?,
+            r: args.number('R')?,
+        },
+        ('M', 200, None) => GCode::M200 {
+            d: args.number('D')?,
+            t: args.number('T')?,
+            s: args.number('S')?,
+            l: args.number('L')?,
+        },
+        ('M', 201, None) => GCode::M201 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+        },
+        ('M', 202, None) => GCode::M202 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+        },
+        ('M', 203, None) => GCode::M203 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+            i: args.number('I')?,
+        },
+        ('M', 204, None) => GCode::M204 {
+            p: args.number('P')?,
+            t: args.number('T')?,
+        },
+        ('M', 205, None) => GCode::M205 {
+            s: args.number('S')?,
+            t: args.number('T')?,
+            b: args.number('B')?,
+            x: args.number('X')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+        },
+        ('M', 206, None) => GCode::M206 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('M', 207, None) => GCode::M207 {
+            s: args.number('S')?,
+            r: args.number('R')?,
+            f: args.number('F')?,
+            t: args.number('T')?,
+            z: args.number('Z')?,
+        },
+        ('M', 208, None) => GCode::M208 {
+            s: args.number('S')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('M', 209, None) => GCode::M209 {
+            s: args.number('S')?,
+        },
+        ('M', 210, None) => GCode::M210 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+        },
+        ('M', 211, None) => GCode::M211 {
+            s: args.number('S')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('M', 212, None) => GCode::M212 {
+            z: args.number('Z')?,
+        },
+        ('M', 214, None) => GCode::M214 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+            n: args.number('N')?,
+            r: args.number('R')?,
+            f: args.number('F')?,
+        },
+        ('M', 217, None) => GCode::M217 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+            r: args.number('R')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('M', 218, None) => GCode::M218 {
+            t: args.number('T')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+        },
+        ('M', 220, None) => GCode::M220 {
+            s: args.number('S')?,
+        },
+        ('M', 221, None) => GCode::M221 {
+            s: args.number('S')?,
+            d: args.number('D')?,
+        },
+        ('M', 222, None) => GCode::M222 {
+            speed: args.required('S')?,
+        },
+        ('M', 223, None) => GCode::M223 {
+            speed: args.required('S')?,
+        },
+        ('M', 224, None) => GCode::M224 {
+            enable: args.flag('S')?,
+        },
+        ('M', 225, None) => GCode::M225 {
+            disable: args.flag('S')?,
+        },
+        ('M', 226, None) if args.has('S') => GCode::M226Pin {
+            pin_number: args.required('P')?,
+            pin_state: args.required('S')?,
+        },
+        ('M', 226, None) => GCode::M226 {
+            message: args.text('P'),
+        },
+        ('M', 227, None) => GCode::M227 {
+            steps: (args.required('P')?, args.required('S')?),
+        },
+        ('M', 228, None) => GCode::M228 {
+            disabled: args.flag('S')?,
+        },
+        ('M', 229, None) => GCode::M229 {
+            extruder_params: (args.required('P')?, args.required('S')?),
+        },
+        ('M', 230, None) => GCode::M230 {
+            enable: args.flag('S')?,
+        },
+        ('M', 231, None) => GCode::M231 {
+            ops_mode: args.required('S')?,
+            min_distance: args.required('X')?,
+            retract: args.required('Y')?,
+            backslash: args.required('Z')?,
+            retract_move: args.required('F')?,
+        },
+        ('M', 232, None) => GCode::M232,
+        ('M', 240, None) if args.has('S') => GCode::M240Belt {
+            disabled: args.flag('S')?,
+        },
+        ('M', 240, None) => GCode::M240 {
+            message: args.text('P'),
+        },
+        ('M', 241, None) => GCode::M241 {
+            enabled: args.flag('S')?,
+        },
+        ('M', 245, None) => GCode::M245,
+        ('M', 246, None) => GCode::M246,
+        ('M', 250, None) => GCode::M250 {
+            c: args.required('C')?,
+        },
+        ('M', 256, None) => GCode::M256 {
+            b: args.required('B')?,
+        },
+        ('M', 251, None) => GCode::M251 {
+            s: args.required('S')?,
+        },
+        ('M', 260, None) => GCode::M260 {
+            a: args.required('A')?,
+            bytes: args.required_list('B')?,
+        },
+        ('M', 261, None) => GCode::M261 {
+            a: args.required('A')?,
+            b: args.required('B')?,
+            s: args.required('S')?,
+        },
+        ('M', 280, None) => GCode::M280 {
+            p: args.required('P')?,
+            s: args.required('S')?,
+            i: args.switch('I')?,
+        },
+        ('M', 281, None) => {
+            let p = args.required('P')?;
+            let column = args.column;
+            let angles: Vec<f32> = args.required_list('S')?;
+            if angles.len() > 8 {
+                return Err(args.error(
+                    column,
+                    ParseErrorKind::InvalidValue {
+                        letter: 'S',
+                        value: format!("{} angles", angles.len()),
+                    },
+                ));
+            }
+            let angle = |index: usize| angles.get(index).copied();
+            GCode::M281 {
+                p,
+                angle_1: angles[0],
+                angle_2: angle(1),
+                angle_3: angle(2),
+                angle_4: angle(3),
+                angle_5: angle(4),
+                angle_6: angle(5),
+                angle_7: angle(6),
+                angle_8: angle(7),
+            }
+        }
+        ('M', 292, None) => GCode::M292 {
+            p: args.number('P')?,
+        },
+        ('M', 300, None) => GCode::M300 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+            v: args.number('V')?,
+        },
+        ('M', 301, None) => GCode::M301 {
+            h: args.required('H')?,
+            p: args.number('P')?,
+            i: args.number('I')?,
+            d: args.number('D')?,
+        },
+        ('M', 302, None) => GCode::M302 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+            r: args.number('R')?,
+        },
+        ('M', 303, None) => GCode::M303 {
+            s: args.number('S')?,
+            c: args.number('C')?,
+            h: args.number('H')?,
+            p: args.number('P')?,
+        },
+        ('M', 304, None) => GCode::M304 {
+            p: args.number('P')?,
+            i: args.number('I')?,
+            d: args.number('D')?,
+        },
+        ('M', 305, None) => GCode::M305 {
+            p: args.required('P')?,
+            s: args.text('S'),
+            t: args.number('T')?,
+            c: args.number('C')?,
+            b: args.number('B')?,
+            r: args.number('R')?,
+            l: args.number('L')?,
+            h: args.number('H')?,
+            x: args.number('X')?,
+            f: args.number('F')?,
+        },
+        ('M', 306, None) => GCode::M306 {
+            z: args.number('Z')?,
+        },
+        ('M', 307, None) => GCode::M307 {
+            h: args.required('H')?,
+            a: args.number('A')?,
+            c: args.number('C')?,
+            d: args.number('D')?,
+            f: args.number('F')?,
+            b: args.number('B')?,
+            s: args.number('S')?,
+            v: args.number('V')?,
+        },
+        ('M', 308, None) => GCode::M308 {
+            s: args.required('S')?,
+            p: args.text('P'),
+            y: args.text('Y'),
+            a: args.text('A'),
+        },
+        ('M', 309, None) => GCode::M309 {
+            p: args.number('P')?,
+            s: args.list('S')?,
+        },
+        ('M', 310, None) => GCode::M310 {
+            a: args.flag('A')?,
+            f: args.flag('F')?,
+            s: args.number('S')?,
+            i: args.number('I')?,
+            r: args.number('R')?,
+            p: args.number('P')?,
+            c: args.number('C')?,
+            b: args.number('B')?,
+            e: args.number('E')?,
+            w: args.number('W')?,
+            t: args.number('T')?,
+        },
+        ('M', 320, None) => GCode::M320 {
+            s: args.number('S')?,
+        },
+        ('M', 321, None) => GCode::M321 {
+            s: args.number('S')?,
+        },
+        ('M', 322, None) => GCode::M322 {
+            s: args.number('S')?,
+        },
+        ('M', 323, None) => GCode::M323 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+        },
+        ('M', 340, None) => GCode::M340 {
+            p: args.required('P')?,
+            s: args.required('S')?,
+        },
+        ('M', 350, None) => GCode::M350 {
+            s: args.number('S')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.text('E'),
+            b: args.number('B')?,
+            i: args.number('I')?,
+        },
+        ('M', 351, None) => GCode::M351,
+        ('M', 355, None) => GCode::M355 {
+            s: args.number('S')?,
+            p: args.number('P')?,
+        },
+        ('M', 360, None) => GCode::M360 {
+            p: args.number('P')?,
+        },
+        ('M', 361, None) => GCode::M361 {
+            p: args.number('P')?,
+        },
+        ('M', 362, None) => GCode::M362,
+        ('M', 363, None) => GCode::M363,
+        ('M', 364, None) => GCode::M364 {
+            p: args.number('P')?,
+        },
+        ('M', 365, None) => GCode::M365 {
+            s: args.number('S')?,
+        },
+        ('M', 366, None) => GCode::M366,
+        ('M', 370, None) => GCode::M370 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+        },
+        ('M', 371, None) => GCode::M371,
+        ('M', 372, None) => GCode::M372,
+        ('M', 373, None) => GCode::M373,
+        ('M', 374, None) => GCode::M374 {
+            extension: args.text('E'),
+            filename: args.text('P'),
+            z: args.flag('Z')?,
+        },
+        ('M', 375, None) => GCode::M375 {
+            extension: args.text('E'),
+            filename: args.text('P'),
+        },
+        ('M', 376, None) => GCode::M376 {
+            h: args.number('H')?,
+        },
+        ('M', 380, None) => GCode::M380,
+        ('M', 381, None) => GCode::M381,
+        ('M', 400, None) => GCode::M400,
+        ('M', 401, None) => GCode::M401 {
+            p: args.number('P')?,
+            s: args.switch('S')?,
+            h: args.flag('H')?,
+        },
+        ('M', 402, None) => GCode::M402 {
+            p: args.number('P')?,
+        },
+        ('M', 403, None) => GCode::M403 {
+            e: args.required('E')?,
+            f: args.required_text('F')?,
+        },
+        ('M', 404, None) => GCode::M404 {
+            n: args.number('N')?,
+            w: args.number('W')?,
+        },
+        ('M', 405, None) => GCode::M405 {
+            delay: args.number('D')?,
+        },
+        ('M', 406, None) => GCode::M406,
+        ('M', 407, None) => GCode::M407,
+        ('M', 408, None) => GCode::M408 {
+            response_type: args.required('S')?,
+            sequence_num: args.number('R')?,
+        },
+        ('M', 409, None) => GCode::M409 {
+            key: args.text('K'),
+            flags: args.text('F'),
+        },
+        ('M', 410, None) => GCode::M410,
+        ('M', 412, None) => GCode::M412 {
+            s: args.switch('S')?,
+        },
+        ('M', 413, None) => GCode::M413 {
+            s: args.switch('S')?,
+        },
+        ('M', 415, None) => GCode::M415 {
+            s: args.switch('S')?,
+            z: args.number('Z')?,
+        },
+        ('M', 416, None) => GCode::M416,
+        ('M', 420, None) => GCode::M420 {
+            r: args.required('R')?,
+            e: args.required('E')?,
+            b: args.required('B')?,
+        },
+        ('M', 421, None) => GCode::M421 {
+            i: args.required('I')?,
+            j: args.required('J')?,
+            z: args.required('Z')?,
+            q: args.number('Q')?,
+        },
+        ('M', 422, None) => GCode::M422 {
+            s: args.required('S')?,
+            x: args.required('X')?,
+            y: args.required('Y')?,
+        },
+        ('M', 423, None) => GCode::M423 {
+            r: args.flag('R')?,
+            x: args.number('X')?,
+            z: args.number('Z')?,
+            a: args.number('A')?,
+            i: args.number('I')?,
+        },
+        ('M', 424, None) => GCode::M424 {
+            z: args.number('Z')?,
+        },
+        ('M', 425, None) => {
+            let (x, x_meas) = args.measured('X')?;
+            let (y, y_meas) = args.measured('Y')?;
+            let (z, z_meas) = args.measured('Z')?;
+            GCode::M425 {
+                f: args.number('F')?,
+                s: args.number('S')?,
+                x,
+                y,
+                z,
+                x_meas,
+                y_meas,
+                z_meas,
+            }
+        }
+        ('M', 450, None) => GCode::M450,
+        ('M', 451, None) => GCode::M451,
+        ('M', 452, None) => GCode::M452,
+        ('M', 453, None) => GCode::M453 {
+            spindle_index: args.number('S')?,
+            spindle_pin_cw: args.number('C')?,
+            spindle_pin_ccw: args.number('D')?,
+            invert: args.flag('I')?,
+            spindle_rpm: args.number('R')?,
+            pwm_freq: args.number('Q')?,
+            assign_spindle: args.number('T')?,
+        },
+        ('M', 460, None) => GCode::M460 {
+            min_temp: args.required('X')?,
+            max_temp: args.required('Y')?,
+        },
+        ('M', 470, None) => GCode::M470 {
+            name: args.required_text('P')?,
+        },
+        ('M', 471, None) => GCode::M471 {
+            source: args.required_text('S')?,
+            dest: args.required_text('T')?,
+            delete: args.flag('D')?,
+        },
+        ('M', 472, None) => GCode::M472 {
+            name: args.required_text('P')?,
+            recursive: args.flag('R')?,
+        },
+        ('M', 486, None) => GCode::M486 {
+            t: args.number('T')?,
+            s: args.number('S')?,
+            a: args.text('A'),
+            p: args.number('P')?,
+            u: args.number('U')?,
+            c: args.flag('C')?,
+        },
+        ('M', 493, None) => GCode::M493 {
+            s: args.number('S')?,
+            p: args.switch('P')?,
+            k: args.number('K')?,
+            d: args.number('D')?,
+            a: args.number('A')?,
+            f: args.number('F')?,
+            b: args.number('B')?,
+            h: args.number('H')?,
+        },
+        ('M', 500, None) => GCode::M500,
+        ('M', 501, None) => GCode::M501 {
+            s: args.number('S')?,
+        },
+        ('M', 502, None) => GCode::M502,
+        ('M', 503, None) => GCode::M503 { s: args.flag('S')? },
+        ('M', 504, None) => GCode::M504,
+        ('M', 505, None) if args.has('P') => GCode::M505_SetConfigFolder {
+            name: args.required_text('P')?,
+        },
+        ('M', 505, None) if args.is_empty() => GCode::M505_ClearEepromReset,
+        ('M', 505, None) => GCode::M505_SetEepromValue {
+            varname: args.required_text('S')?,
+            value: args.required_text('V')?,
+        },
+        ('M', 509, None) => GCode::M509,
+        ('M', 510, None) => GCode::M510,
+        ('M', 511, None) => GCode::M511 {
+            passcode: args.text('P'),
+        },
+        ('M', 512, None) => GCode::M512 {
+            oldpass: args.text('P'),
+            newpass: args.text('S'),
+        },
+        ('M', 513, None) => GCode::M513 {
+            currpass: args.text('P'),
+            removal_hash: args.text('S'),
+        },
+        ('M', 524, None) => GCode::M524,
+        ('M', 530, None) => GCode::M530 {
+            state: args.flag('S')?,
+            layers: args.required('L')?,
+        },
+        ('M', 531, None) => GCode::M531(args.tail()),
+        ('M', 532, None) => GCode::M532 {
+            progress: args.required('X')?,
+            layer: args.required('L')?,
+        },
+        ('M', 540, None) => GCode::M540(args.required_text('P')?),
+        ('M', 544, None) => GCode::M544 {
+            case_insensitive: args.flag('S')?,
+        },
+        ('M', 550, None) => GCode::M550(args.required_text('P')?),
+        ('M', 551, None) => GCode::M551(args.required_text('P')?),
+        ('M', 552, None) => GCode::M552 {
+            net_interface: args.number('I')?,
+            ip_address: args.text('P'),
+            enable_state: args.number('S')?,
+        },
+        ('M', 553, None) => GCode::M553 {
+            net_interface: args.number('I')?,
+            net_mask: args.text('P'),
+        },
+        ('M', 554, None) => GCode::M554 {
+            net_interface: args.number('I')?,
+            gateway: args.text('P'),
+            dns: args.text('S'),
+        },
+        ('M', 555, None) => GCode::M555 {
+            p: args.required('P')?,
+        },
+        ('M', 556, None) => GCode::M556 {
+            s: args.required('S')?,
+            x: args.required('X')?,
+            y: args.required('Y')?,
+            z: args.required('Z')?,
+            p: args.required('P')?,
+        },
+        ('M', 557, None) if args.has('R') || args.has('S') => GCode::M557ProbeGrid {
+            x: args.required('X')?,
+            y: args.required('Y')?,
+            r: args.required('R')?,
+            s: args.required('S')?,
+            p: args.required('P')?,
+        },
+        ('M', 557, None) => GCode::M557ProbePoint {
+            p: args.required('P')?,
+            x: args.required('X')?,
+            y: args.required('Y')?,
+        },
+        ('M', 558, None) => GCode::M558 {
+            p: args.required('P')?,
+            f: args.required('F')?,
+            h: args.required('H')?,
+            i: args.required('I')?,
+            r: args.required('R')?,
+            t: args.required('T')?,
+            a: args.required('A')?,
+            s: args.required('S')?,
+            b: args.required('B')?,
+        },
+        ('M', 559, None) => GCode::M559,
+        ('M', 560, None) => GCode::M560,
+        ('M', 561, None) => GCode::M561,
+        ('M', 562, None) => GCode::M562 {
+            p: args.required('P')?,
+        },
+        ('M', 563, None) => GCode::M563 {
+            p: args.required('P')?,
+            s: args.text('S'),
+            d: args.number('D')?,
+            h: args.number('H')?,
+            f: args.number('F')?,
+            x: args.text('X'),
+            y: args.text('Y'),
+            z: args.text('Z'),
+            u: args.text('U'),
+        },
+        ('M', 564, None) => GCode::M564 {
+            h: args.number('H')?,
+            s: args.number('S')?,
+        },
+        ('M', 565, None) => GCode::M565 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('M', 566, None) => GCode::M566 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+        },
+        ('M', 567, None) => GCode::M567 {
+            p: args.required('P')?,
+            e: args.required_list('E')?,
+        },
+        ('M', 568, None) => GCode::M568 {
+            p: args.number('P')?,
+            r: args.number('R')?,
+            s: args.number('S')?,
+            f: args.number('F')?,
+            a: args.number('A')?,
+        },
+        ('M', 569, None) => {
+            // `T` is either one pulse width or four timings, `Y` two or three hysteresis values.
+            let ta = if args.value('T').map_or(false, |value| value.contains(':')) {
+                args.array('T')?.map(|[a, b, c, d]| (a, b, c, d))
+            } else {
+                None
+            };
+            let y = if args
+                .value('Y')
+                .map_or(0, |value| value.matches(':').count())
+                == 2
+            {
+                args.array('Y')?
+                    .map(|[start, end, decrement]| (start, end, Some(decrement)))
+            } else {
+                args.array('Y')?.map(|[start, end]| (start, end, None))
+            };
+            GCode::M569 {
+                p: args.required('P')?,
+                s: args.number('S')?,
+                r: args.number('R')?,
+                t: args.number('T')?,
+                ta,
+                d: args.number('D')?,
+                f_n: args.number('F')?,
+                b_n: args.number('B')?,
+                y,
+                c: args.number('C')?,
+                h: args.number('H')?,
+                v: args.number('V')?,
+            }
+        }
+        ('M', 570, None) => GCode::M570 {
+            s: args.number('S')?,
+            h: args.number('H')?,
+            p: args.number('P')?,
+            t: args.number('T')?,
+        },
+        ('M', 571, None) => GCode::M571 {
+            s: args.number('S')?,
+            f: args.number('F')?,
+            p: args.number('P')?,
+        },
+        ('M', 572, None) => GCode::M572 {
+            d: args.number('D')?,
+            s: args.number('S')?,
+        },
+        ('M', 573, None) => GCode::M573 {
+            p: args.number('P')?,
+        },
+        ('M', 574, None) => GCode::M574 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            s: args.number('S')?,
+        },
+        ('M', 575, None) => GCode::M575 {
+            p: args.number('P')?,
+            b: args.number('B')?,
+            s: args.number('S')?,
+        },
+        ('M', 576, None) => GCode::M576 {
+            s: args.number('S')?,
+            f: args.number('F')?,
+            p: args.number('P')?,
+        },
+        ('M', 577, None) => GCode::M577 {
+            s: args.number('S')?,
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+            e: args.number('E')?,
+        },
+        ('M', 578, None) => GCode::M578 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 579, None) => GCode::M579 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('M', 580, None) => GCode::M580 {
+            r: args.number('R')?,
+            p: args.text('P'),
+        },
+        ('M', 581, None) => GCode::M581 {
+            t: args.number('T')?,
+            x: args.switch('X')?,
+            y: args.switch('Y')?,
+            z: args.switch('Z')?,
+            e: args.switch('E')?,
+            p: args.switch('P')?,
+            s: args.number('S')?,
+            c: args.number('C')?,
+        },
+        ('M', 582, None) => GCode::M582 {
+            t: args.number('T')?,
+        },
+        ('M', 584, None) => GCode::M584 {
+            x: args.list('X')?,
+            y: args.list('Y')?,
+            z: args.list('Z')?,
+            u: args.list('U')?,
+            v: args.list('V')?,
+            w: args.list('W')?,
+            a: args.list('A')?,
+            b: args.list('B')?,
+            c: args.list('C')?,
+            e: args.list('E')?,
+            p: args.number('P')?,
+        },
+        ('M', 585, None) => GCode::M585 {
+            x: args.number('X')?,
+            y: args.number('Y')?,
+            z: args.number('Z')?,
+        },
+        ('M', 586, None) => GCode::M586 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+            r: args.number('R')?,
+            t: args.number('T')?,
+            c: args.text('C'),
+        },
+        ('M', 587, None) => GCode::M587 {
+            s: args.text('S'),
+            p: args.text('P'),
+            i: args.text('I'),
+            j: args.text('J'),
+            k: args.text('K'),
+            l: args.text('L'),
+            c: args.text('C'),
+        },
+        ('M', 588, None) => GCode::M588 {
+            s: args.required_text('S')?,
+        },
+        ('M', 589, None) => GCode::M589 {
+            s: args.required_text('S')?,
+            p: args.required_text('P')?,
+            i: args.required_text('I')?,
+        },
+        ('M', 590, None) => GCode::M590 {},
+        ('M', 591, None) => GCode::M591 {
+            c: args.number('C')?,
+            d: args.number('D')?,
+            p: args.number('P')?,
+            s: args.number('S')?,
+            r: args.array('R')?.map(|[min, max]| (min, max)),
+            e: args.number('E')?,
+            l: args.number('L')?,
+        },
+        ('M', 592, None) => GCode::M592 {
+            d: args.number('D')?,
+            a: args.number('A')?,
+            b: args.number('B')?,
+            l: args.number('L')?,
+            t: args.number('T')?,
+        },
+        ('M', 593, None) => GCode::M593 {
+            p: args.text('P'),
+            f: args.number('F')?,
+            s: args.number('S')?,
+            l: args.number('L')?,
+            h: args.list('H')?,
+            t: args.list('T')?,
+        },
+        ('M', 594, None) => GCode::M594 {
+            p: args.number('P')?,
+        },
+        ('M', 595, None) => GCode::M595 {
+            p: args.number('P')?,
+            s: args.number('S')?,
+        },
+        ('M', 596, None) => GCode::M596 {
+            p: args.number('P')?,
+        },
+        ('T', number, None) => GCode::T(number),
+        _ => return Err(args.error(args.column, ParseErrorKind::UnknownCode(code.to_string()))),
+    })
+}
+
+/// The parameter letters each code accepts, or `None` for codes the parser does not know.
+#[allow(clippy::too_many_lines)]
+fn parameters(code: Code) -> Option<&'static str> {
+    Some(match (code.letter, code.number, code.sub) {
+        ('G', 0, None) => "XYZEF",
+        ('G', 1, None) => "XYZEF",
+        ('G', 2, None) => "XYZIJKRPEF",
+        ('G', 3, None) => "XYZIJKRPEF",
+        ('G', 4, None) => "PS",
+        ('G', 6, None) => "ABCR",
+        ('G', 10, None) => "LPXYZUVWABCRS",
+        ('G', 11, None) => "S",
+        ('G', 12, None) => "PST",
+        ('G', 17, None) => "",
+        ('G', 18, None) => "",
+        ('G', 19, None) => "",
+        ('G', 20, None) => "",
+        ('G', 21, None) => "",
+        ('G', 22, None) => "",
+        ('G', 23, None) => "",
+        ('G', 26, None) => "CPO",
+        ('G', 27, None) => "P",
+        ('G', 28, None) => "XYZPI",
+        ('G', 29, None) => "SP",
+        ('G', 30, None) => "PXYZHS",
+        ('G', 31, None) => "PXYZCST",
+        ('G', 32, None) => "SP",
+        ('G', 33, None) => "LRXYZ",
+        ('G', 34, None) => "ITA",
+        ('G', 38, Some(2)) => "XYZF",
+        ('G', 38, Some(3)) => "XYZF",
+        ('G', 38, Some(4)) => "XYZF",
+        ('G', 38, Some(5)) => "XYZF",
+        ('G', 40, None) => "",
+        ('G', 41, None) => "D",
+        ('G', 41, Some(1)) => "D",
+        ('G', 42, None) => "IJPFD",
+        ('G', 42, Some(1)) => "D",
+        ('G', 53, None) => "",
+        ('G', 54, None) => "",
+        ('G', 55, None) => "",
+        ('G', 56, None) => "",
+        ('G', 57, None) => "",
+        ('G', 58, None) => "",
+        ('G', 59, None) => "",
+        ('G', 60, None) => "S",
+        ('G', 68, None) => "XYRI",
+        ('G', 69, None) => "",
+        ('G', 75, None) => "",
+        ('G', 76, None) => "BP",
+        ('G', 80, None) => "",
+        ('G', 81, None) => "XYZRQPLF",
+        ('G', 82, None) => "XYZRQPLF",
+        ('G', 83, None) => "XYZRQPLF",
+        ('G', 84, None) => "XYZRQPLF",
+        ('G', 85, None) => "XYZRQPLF",
+        ('G', 86, None) => "XYZRQPLF",
+        ('G', 87, None) => "XYZRQPLF",
+        ('G', 88, None) => "XYZRQPLF",
+        ('G', 89, None) => "XYZRQPLF",
+        ('G', 90, None) => "",
+        ('G', 91, None) => "",
+        ('G', 92, None) => "XYZE",
+        ('G', 93, None) => "",
+        ('G', 94, None) => "",
+        ('G', 98, None) => "",
+        ('G', 99, None) => "",
+        ('G', 100, None) => "XYZR",
+        ('G', 130, None) => "XYZAB",
+        ('G', 161, None) => "XYZF",
+        ('G', 162, None) => "XYZF",
+        ('G', 425, None) => "BTVU",
+        ('M', 0, None) => "PS",
+        ('M', 1, None) => "",
+        ('M', 2, None) => "",
+        ('M', 3, None) => "S",
+        ('M', 4, None) => "S",
+        ('M', 5, None) => "",
+        ('M', 6, None) => "",
+        ('M', 7, None) => "",
+        ('M', 8, None) => "",
+        ('M', 9, None) => "",
+        ('M', 10, None) => "",
+        ('M', 11, None) => "",
+        ('M', 13, None) => "",
+        ('M', 16, None) => "",
+        ('M', 17, None) => "XYZE",
+        ('M', 18, None) => "XYZES",
+        ('M', 20, None) => "SRPLT",
+        ('M', 21, None) => "P",
+        ('M', 22, None) => "P",
+        ('M', 23, None) => "",
+        ('M', 24, None) => "",
+        ('M', 25, None) => "",
+        ('M', 26, None) => "SP",
+        ('M', 27, None) => "CS",
+        ('M', 28, None) => "",
+        ('M', 29, None) => "",
+        ('M', 30, None) => "",
+        ('M', 31, None) => "",
+        ('M', 32, None) => "",
+        ('M', 33, None) => "",
+        ('M', 34, None) => "",
+        ('M', 35, None) => "",
+        ('M', 36, None) => "",
+        ('M', 36, Some(1)) => "PS",
+        ('M', 37, None) => "SP",
+        ('M', 38, None) => "",
+        ('M', 39, None) => "PS",
+        ('M', 40, None) => "",
+        ('M', 41, None) => "",
+        ('M', 42, None) => "PS",
+        ('M', 43, None) => "EPWI",
+        ('M', 44, None) => "IJ",
+        ('M', 45, None) => "V",
+        ('M', 46, None) => "",
+        ('M', 47, None) => "",
+        ('M', 48, None) => "PXYVELS",
+        ('M', 49, None) => "S",
+        ('M', 70, None) => "PS",
+        ('M', 72, None) => "P",
+        ('M', 73, None) => "PRQSCD",
+        ('M', 75, None) => "",
+        ('M', 76, None) => "",
+        ('M', 80, None) => "C",
+        ('M', 81, None) => "PRS",
+        ('M', 82, None) => "",
+        ('M', 83, None) => "",
+        ('M', 84, None) => "I",
+        ('M', 85, None) => "S",
+        ('M', 86, None) => "S",
+        ('M', 87, None) => "",
+        ('M', 92, None) => "XYZES",
+        ('M', 93, None) => "",
+        ('M', 98, None) => "PL",
+        ('M', 99, None) => "",
+        ('M', 101, None) => "",
+        ('M', 102, None) => "S",
+        ('M', 103, None) => "",
+        ('M', 104, None) => "CDSR",
+        ('M', 105, None) => "",
+        ('M', 106, None) => "PSIFLXBHRTC",
+        ('M', 107, None) => "",
+        ('M', 108, None) => "",
+        ('M', 109, None) => "CSRTF",
+        ('M', 110, None) => "N",
+        ('M', 111, None) => "PS",
+        ('M', 112, None) => "",
+        ('M', 113, None) => "S",
+        ('M', 114, None) => "",
+        ('M', 115, None) => "BPVU",
+        ('M', 116, None) => "PHC",
+        ('M', 117, None) => "",
+        ('M', 118, None) => "PS",
+        ('M', 119, None) => "",
+        ('M', 120, None) => "",
+        ('M', 121, None) => "",
+        ('M', 122, None) => "BP",
+        ('M', 124, None) => "XYZIJKPD",
+        ('M', 126, None) => "PT",
+        ('M', 127, None) => "PT",
+        ('M', 128, None) => "S",
+        ('M', 129, None) => "P",
+        ('M', 130, None) => "PS",
+        ('M', 131, None) => "PS",
+        ('M', 132, None) => "PSXYZAB",
+        ('M', 133, None) => "PST",
+        ('M', 134, None) => "TP",
+        ('M', 135, None) => "ST",
+        ('M', 136, None) => "",
+        ('M', 140, None) => "PHTSR",
+        ('M', 141, None) => "PHTSR",
+        ('M', 142, None) => "",
+        ('M', 143, None) => "HS",
+        ('M', 144, None) => "PS",
+        ('M', 146, None) => "R",
+        ('M', 149, None) => "CK",
+        ('M', 150, None) => "RUBWP",
+        ('M', 155, None) => "SC",
+        ('M', 160, None) => "S",
+        ('M', 163, None) => "SP",
+        ('M', 164, None) => "SP",
+        ('M', 165, None) => "ABCDHI",
+        ('M', 190, None) => "SR",
+        ('M', 191, None) => "SR",
+        ('M', 200, None) => "DTSL",
+        ('M', 201, None) => "XYZE",
+        ('M', 202, None) => "XY",
+        ('M', 203, None) => "XYZEI",
+        ('M', 204, None) => "PT",
+        ('M', 205, None) => "STBXZE",
+        ('M', 206, None) => "XYZ",
+        ('M', 207, None) => "SRFTZ",
+        ('M', 208, None) => "SXYZ",
+        ('M', 209, None) => "S",
+        ('M', 210, None) => "XY",
+        ('M', 211, None) => "SXYZ",
+        ('M', 212, None) => "Z",
+        ('M', 214, None) => "PSNRF",
+        ('M', 217, None) => "SPRXYZ",
+        ('M', 218, None) => "TXY",
+        ('M', 220, None) => "S",
+        ('M', 221, None) => "SD",
+        ('M', 222, None) => "S",
+        ('M', 223, None) => "S",
+        ('M', 224, None) => "S",
+        ('M', 225, None) => "S",
+        ('M', 226, None) => "PS",
+        ('M', 227, None) => "PS",
+        ('M', 228, None) => "S",
+        ('M', 229, None) => "PS",
+        ('M', 230, None) => "S",
+        ('M', 231, None) => "SXYZF",
+        ('M', 232, None) => "",
+        ('M', 240, None) => "PS",
+        ('M', 241, None) => "S",
+        ('M', 245, None) => "",
+        ('M', 246, None) => "",
+        ('M', 250, None) => "C",
+        ('M', 256, None) => "B",
+        ('M', 251, None) => "S",
+        ('M', 260, None) => "AB",
+        ('M', 261, None) => "ABS",
+        ('M', 280, None) => "PSI",
+        ('M', 281, None) => "PS",
+        ('M', 292, None) => "P",
+        ('M', 300, None) => "SPV",
+        ('M', 301, None) => "HPID",
+        ('M', 302, None) => "SPR",
+        ('M', 303, None) => "SCHP",
+        ('M', 304, None) => "PID",
+        ('M', 305, None) => "PSTCBRLHXF",
+        ('M', 306, None) => "Z",
+        ('M', 307, None) => "HACDFBSV",
+        ('M', 308, None) => "SPYA",
+        ('M', 309, None) => "PS",
+        ('M', 310, None) => "AFSIRPCBEWT",
+        ('M', 320, None) => "S",
+        ('M', 321, None) => "S",
+        ('M', 322, None) => "S",
+        ('M', 323, None) => "SP",
+        ('M', 340, None) => "PS",
+        ('M', 350, None) => "SXYZEBI",
+        ('M', 351, None) => "",
+        ('M', 355, None) => "SP",
+        ('M', 360, None) => "P",
+        ('M', 361, None) => "P",
+        ('M', 362, None) => "",
+        ('M', 363, None) => "",
+        ('M', 364, None) => "P",
+        ('M', 365, None) => "S",
+        ('M', 366, None) => "",
+        ('M', 370, None) => "XY",
+        ('M', 371, None) => "",
+        ('M', 372, None) => "",
+        ('M', 373, None) => "",
+        ('M', 374, None) => "EPZ",
+        ('M', 375, None) => "EP",
+        ('M', 376, None) => "H",
+        ('M', 380, None) => "",
+        ('M', 381, None) => "",
+        ('M', 400, None) => "",
+        ('M', 401, None) => "PSH",
+        ('M', 402, None) => "P",
+        ('M', 403, None) => "EF",
+        ('M', 404, None) => "NW",
+        ('M', 405, None) => "D",
+        ('M', 406, None) => "",
+        ('M', 407, None) => "",
+        ('M', 408, None) => "SR",
+        ('M', 409, None) => "KF",
+        ('M', 410, None) => "",
+        ('M', 412, None) => "S",
+        ('M', 413, None) => "S",
+        ('M', 415, None) => "SZ",
+        ('M', 416, None) => "",
+        ('M', 420, None) => "REB",
+        ('M', 421, None) => "IJZQ",
+        ('M', 422, None) => "SXY",
+        ('M', 423, None) => "RXZAI",
+        ('M', 424, None) => "Z",
+        ('M', 425, None) => "FSXYZ",
+        ('M', 450, None) => "",
+        ('M', 451, None) => "",
+        ('M', 452, None) => "",
+        ('M', 453, None) => "SCDIRQT",
+        ('M', 460, None) => "XY",
+        ('M', 470, None) => "P",
+        ('M', 471, None) => "STD",
+        ('M', 472, None) => "PR",
+        ('M', 486, None) => "TSAPUC",
+        ('M', 493, None) => "SPKDAFBH",
+        ('M', 500, None) => "",
+        ('M', 501, None) => "S",
+        ('M', 502, None) => "",
+        ('M', 503, None) => "S",
+        ('M', 504, None) => "",
+        ('M', 505, None) => "PSV",
+        ('M', 509, None) => "",
+        ('M', 510, None) => "",
+        ('M', 511, None) => "P",
+        ('M', 512, None) => "PS",
+        ('M', 513, None) => "PS",
+        ('M', 524, None) => "",
+        ('M', 530, None) => "SL",
+        ('M', 531, None) => "",
+        ('M', 532, None) => "XL",
+        ('M', 540, None) => "P",
+        ('M', 544, None) => "S",
+        ('M', 550, None) => "P",
+        ('M', 551, None) => "P",
+        ('M', 552, None) => "IPS",
+        ('M', 553, None) => "IP",
+        ('M', 554, None) => "IPS",
+        ('M', 555, None) => "P",
+        ('M', 556, None) => "SXYZP",
+        ('M', 557, None) => "PXYRS",
+        ('M', 558, None) => "PFHIRTASB",
+        ('M', 559, None) => "",
+        ('M', 560, None) => "",
+        ('M', 561, None) => "",
+        ('M', 562, None) => "P",
+        ('M', 563, None) => "PSDHFXYZU",
+        ('M', 564, None) => "HS",
+        ('M', 565, None) => "XYZ",
+        ('M', 566, None) => "XYZE",
+        ('M', 567, None) => "PE",
+        ('M', 568, None) => "PRSFA",
+        ('M', 569, None) => "PSRTDFBYCHV",
+        ('M', 570, None) => "SHPT",
+        ('M', 571, None) => "SFP",
+        ('M', 572, None) => "DS",
+        ('M', 573, None) => "P",
+        ('M', 574, None) => "XYZS",
+        ('M', 575, None) => "PBS",
+        ('M', 576, None) => "SFP",
+        ('M', 577, None) => "SXYZE",
+        ('M', 578, None) => "PS",
+        ('M', 579, None) => "XYZ",
+        ('M', 580, None) => "RP",
+        ('M', 581, None) => "TXYZEPSC",
+        ('M', 582, None) => "T",
+        ('M', 584, None) => "XYZUVWABCEP",
+        ('M', 585, None) => "XYZ",
+        ('M', 586, None) => "PSRTC",
+        ('M', 587, None) => "SPIJKLC",
+        ('M', 588, None) => "S",
+        ('M', 589, None) => "SPI",
+        ('M', 590, None) => "",
+        ('M', 591, None) => "CDPSREL",
+        ('M', 592, None) => "DABLT",
+        ('M', 593, None) => "PFSLHT",
+        ('M', 594, None) => "P",
+        ('M', 595, None) => "PS",
+        ('M', 596, None) => "P",
+        ('T', _, None) => "",
+        _ => return None,
+    })
+}
+
+/// Parameters whose value is text rather than a number, e.g. `M32 P"job.g"`.
+fn text_parameters(code: Code) -> &'static str {
+    match (code.letter, code.number, code.sub) {
+        ('G', 29, None) => "P",
+        ('M', 20, None) => "P",
+        ('M', 36, Some(1)) => "P",
+        ('M', 37, None) => "P",
+        ('M', 44, None) => "IJ",
+        ('M', 70, None) => "S",
+        ('M', 80, None) => "C",
+        ('M', 98, None) => "P",
+        ('M', 106, None) => "HC",
+        ('M', 115, None) => "U",
+        ('M', 118, None) => "S",
+        ('M', 226, None) => "P",
+        ('M', 240, None) => "P",
+        ('M', 305, None) => "S",
+        ('M', 308, None) => "PYA",
+        ('M', 350, None) => "E",
+        ('M', 374, None) => "EP",
+        ('M', 375, None) => "EP",
+        ('M', 403, None) => "F",
+        ('M', 409, None) => "KF",
+        ('M', 470, None) => "P",
+        ('M', 471, None) => "ST",
+        ('M', 472, None) => "P",
+        ('M', 486, None) => "A",
+        ('M', 505, None) => "PSV",
+        ('M', 511, None) => "P",
+        ('M', 512, None) => "PS",
+        ('M', 513, None) => "PS",
+        ('M', 540, None) => "P",
+        ('M', 550, None) => "P",
+        ('M', 551, None) => "P",
+        ('M', 552, None) => "P",
+        ('M', 553, None) => "P",
+        ('M', 554, None) => "PS",
+        ('M', 563, None) => "SXYZU",
+        ('M', 580, None) => "P",
+        ('M', 586, None) => "C",
+        ('M', 587, None) => "SPIJKLC",
+        ('M', 588, None) => "S",
+        ('M', 589, None) => "SPI",
+        ('M', 593, None) => "P",
+        _ => "",
+    }
+}
+
+#[test]
+fn test_gcode_parser() {
+    let blocks = parse(
+        "%\n\
+         N10 g0 G90 G54 X0 Y-1.5 S12000 M3 (rapid to start)\n\
+         X10 Y.5 ; modal\n\
+         N3 T1 M6*99\n\
+         M117 Hello world\n\
+         G1X1Y2F300",
+    )
+    .unwrap();
+    assert!(blocks[0].percent);
+    assert_eq!(blocks[1].line_number, Some(10));
+    assert_eq!(
+        blocks[1].codes,
+        vec![
+            GCode::G0 {
+                x: Some(0.0),
+                y: Some(-1.5),
+                z: None,
+                e: None,
+                f: None,
+            },
+            GCode::G90,
+            GCode::G54,
+            GCode::M3 { s: Some(12000) },
+        ]
+    );
+    assert_eq!(
+        blocks[1].comments,
+        vec![Comment::Parenthesis {
+            text: "rapid to start".to_owned(),
+            word: 7,
+        }]
+    );
+    assert_eq!(
+        blocks[2].codes,
+        vec![GCode::G0 {
+            x: Some(10.0),
+            y: Some(0.5),
+            z: None,
+            e: None,
+            f: None,
+        }]
+    );
+    assert_eq!(
+        blocks[2].comments,
+        vec![Comment::Semicolon("modal".to_owned())]
+    );
+    assert_eq!(blocks[3].codes, vec![GCode::T(1), GCode::M6 {}]);
+    assert_eq!(blocks[3].checksum, Some(99));
+    assert_eq!(
+        blocks[4].codes,
+        vec![GCode::M117_DisplayMessage {
+            message: "Hello world".to_owned()
+        }]
+    );
+    assert_eq!(
+        blocks[5].codes,
+        vec![GCode::G1 {
+            x: Some(1.0),
+            y: Some(2.0),
+            z: None,
+            e: None,
+            f: Some(300.0),
+        }]
+    );
+
+    let error = parse("G1 X1\nG1 Q2").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 4));
+    assert_eq!(
+        error.kind,
+        ParseErrorKind::UnexpectedParameter {
+            code: Some("G1".to_owned()),
+            letter: 'Q',
+        }
+    );
+}
@@ -0,0 +1,529 @@
+//! Post-processes a program for a machine carrying several tools side by side.
+//!
+//! Each [`ToolSetup`] says how wide a tool cuts and where it sits relative to the machine's
+//! reference point. [`postprocess`] first bakes cutter compensation into the path, using the
+//! width of the tool in the spindle as its diameter, so `G41`/`G42` programs run on firmware
+//! without them. It then swaps every `T` word for the machine's tool-change sequence and shifts
+//! all positions from there on by the new tool's offset, so each tool cuts where the program says.
+//!
+//! Canned cycles are expanded first so their holes move with the tools too. `G53` moves are in
+//! machine coordinates and stay where they are.
+
+use std::fmt;
+
+use super::gcode_compensation::{self, CompensationError};
+use super::gcode_dialect::{self, Dialect};
+use super::gcode_interpreter::{
+    Compensation, Distance, Interpreter, Move, MoveKind, Position, E, X, Y, Z,
+};
+use super::gcode_macro;
+use super::gcode_parser::{Block, ParseError};
+use super::GCode;
+
+/// One tool on the machine.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct ToolSetup {
+    /// `T` number.
+    pub number: u32,
+
+    /// mm the tool takes away across the path: bit diameter, kerf or line width.
+    pub width: f32,
+
+    /// mm from the reference point in `X`, `Y` and `Z`, added to every position the tool goes to.
+    pub offset: [f32; 3],
+}
+
+/// A machine to post-process for.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Machine {
+    pub name: String,
+    pub dialect: Dialect,
+    pub tools: Vec<ToolSetup>,
+
+    /// G-code run in place of each `T` word. `{tool}`, `{width}` and `{previous}` stand for the
+    /// new tool's number and width and the number of the tool it replaces.
+    pub tool_change: String,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        let tool = |number, width, x| ToolSetup {
+            number,
+            width,
+            offset: [x, 0.0, 0.0],
+        };
+        Self {
+            name: "Machine 1".to_owned(),
+            dialect: Dialect::default(),
+            tools: vec![
+                tool(1, 60.0, 100.0),
+                tool(2, 60.0, 200.0),
+                tool(3, 60.0, 300.0),
+                tool(4, 30.0, 400.0),
+            ],
+            tool_change: "; tool {previous} to {tool}\nT{tool}".to_owned(),
+        }
+    }
+}
+
+impl Machine {
+    /// The setup of tool `number`, if the machine has one.
+    pub fn tool(&self, number: Option<u32>) -> Option<&ToolSetup> {
+        let number = number?;
+        self.tools.iter().find(|tool| tool.number == number)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PostprocessError {
+    /// A tool does not fit the contour it is compensated for.
+    Compensation(CompensationError),
+
+    /// The machine's tool-change sequence does not parse.
+    ToolChange(ParseError),
+}
+
+impl fmt::Display for PostprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostprocessError::Compensation(err) => err.fmt(f),
+            PostprocessError::ToolChange(err) => write!(f, "tool change sequence: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PostprocessError {}
+
+/// The program as it has to run on `machine`.
+pub fn postprocess(blocks: &[Block], machine: &Machine) -> Result<Vec<Block>, PostprocessError> {
+    let blocks = gcode_dialect::expand_cycles(blocks);
+    let blocks = bake_compensation(&blocks, machine)?;
+    change_tools(&blocks, machine)
+}
+
+fn is_compensation(code: &GCode) -> bool {
+    matches!(
+        code,
+        GCode::G40
+            | GCode::G41 { .. }
+            | GCode::G42 { .. }
+            | GCode::G41_1 { .. }
+            | GCode::G42_1 { .. }
+    )
+}
+
+fn is_path(code: &GCode) -> bool {
+    matches!(
+        code,
+        GCode::G0 { .. } | GCode::G1 { .. } | GCode::G2 { .. } | GCode::G3 { .. }
+    )
+}
+
+/// Where the program's zero is in machine mm, and how it measures, after a block.
+struct Frame {
+    origin: Position,
+    scale: f32,
+    distance: Distance,
+    extruder_distance: Distance,
+
+    /// `F` in program units.
+    feedrate: f32,
+}
+
+impl Frame {
+    fn new(interpreter: &Interpreter) -> Self {
+        let scale = interpreter.state.units.scale();
+        let program = interpreter.program_position();
+        Self {
+            origin: [0, 1, 2, 3].map(|axis| interpreter.position[axis] - program[axis] * scale),
+            scale,
+            distance: interpreter.state.distance,
+            extruder_distance: interpreter.state.extruder_distance,
+            feedrate: interpreter.state.feedrate / scale,
+        }
+    }
+
+    /// A path move back in the program's coordinates and units, absolute in the axes it moves.
+    fn code(&self, motion: &Move, f: Option<f32>) -> GCode {
+        let at = |axis: usize| {
+            (motion.end[axis] != motion.start[axis])
+                .then(|| (motion.end[axis] - self.origin[axis]) / self.scale)
+        };
+        let (x, y, z) = (at(X), at(Y), at(Z));
+        let e = (motion.end[E] != motion.start[E]).then(|| {
+            if self.extruder_distance == Distance::Relative && self.distance == Distance::Absolute {
+                (motion.end[E] - motion.start[E]) / self.scale
+            } else {
+                (motion.end[E] - self.origin[E]) / self.scale
+            }
+        });
+        match motion.kind {
+            MoveKind::Rapid => GCode::G0 {
+                x,
+                y,
+                z,
+                e,
+                f: None,
+            },
+            MoveKind::Arc {
+                center, clockwise, ..
+            } => {
+                let i = Some((center[X] - motion.start[X]) / self.scale);
+                let j = Some((center[Y] - motion.start[Y]) / self.scale);
+                if clockwise {
+                    GCode::G2 {
+                        x,
+                        y,
+                        z,
+                        i,
+                        j,
+                        k: None,
+                        r: None,
+                        p: None,
+                        e,
+                        f,
+                    }
+                } else {
+                    GCode::G3 {
+                        x,
+                        y,
+                        z,
+                        i,
+                        j,
+                        k: None,
+                        r: None,
+                        p: None,
+                        e,
+                        f,
+                    }
+                }
+            }
+            _ => GCode::G1 { x, y, z, e, f },
+        }
+    }
+}
+
+/// Replaces the path moves of blocks running under `G41`/`G42` with the offset path, and drops
+/// the compensation codes.
+fn bake_compensation(blocks: &[Block], machine: &Machine) -> Result<Vec<Block>, PostprocessError> {
+    let mut interpreter = Interpreter::default();
+    for tool in &machine.tools {
+        interpreter.tool_radii.insert(tool.number, tool.width / 2.0);
+    }
+
+    // Moves carry the index of their block instead of the source line, so the offset path can
+    // go back where it came from.
+    let mut moves = vec![];
+    let mut frames = Vec::with_capacity(blocks.len());
+    let mut compensated = vec![false; blocks.len()];
+    for (index, block) in blocks.iter().enumerate() {
+        // As in `change_tools`, the tool changes at the `T` word.
+        if let Some(tool) = tool_word(block) {
+            interpreter.state.tool = Some(tool);
+        }
+        let first = moves.len();
+        interpreter.run_block(block, &mut moves);
+        for motion in &mut moves[first..] {
+            compensated[index] |= motion.compensation != Compensation::Off;
+            motion.line = index;
+        }
+        frames.push(Frame::new(&interpreter));
+    }
+
+    let mut by_block = vec![vec![]; blocks.len()];
+    if compensated.contains(&true) {
+        let baked = gcode_compensation::compensate(&moves).map_err(|mut err| {
+            err.line = blocks[err.line].line;
+            PostprocessError::Compensation(err)
+        })?;
+        // The offset path borrows blocks from the contour beside it, which can go backwards
+        // round a corner.
+        let mut index = 0;
+        for motion in baked {
+            index = index.max(motion.line);
+            by_block[index].push(motion);
+        }
+    }
+
+    let mut out = Vec::with_capacity(blocks.len());
+    // `F` as the controller has it.
+    let mut feedrate = None;
+    for (index, block) in blocks.iter().enumerate() {
+        let kept = Block {
+            codes: block
+                .codes
+                .iter()
+                .filter(|code| !(is_compensation(code) || compensated[index] && is_path(code)))
+                .cloned()
+                .collect(),
+            ..block.clone()
+        };
+        if !kept.codes.is_empty() || !kept.comments.is_empty() {
+            out.push(kept);
+        }
+        if !compensated[index] {
+            feedrate = Some(frames[index].feedrate);
+            continue;
+        }
+
+        let frame = &frames[index];
+        let line = |code| Block {
+            line: block.line,
+            codes: vec![code],
+            ..Default::default()
+        };
+        let relative = frame.distance == Distance::Relative;
+        if relative {
+            out.push(line(GCode::G90));
+        }
+        for motion in &by_block[index] {
+            let moves = match motion.kind {
+                MoveKind::Rapid | MoveKind::Linear => motion.start != motion.end,
+                MoveKind::Arc { .. } => true,
+                _ => false,
+            };
+            if !moves {
+                continue;
+            }
+            let f = motion.feedrate.map(|f| f / frame.scale);
+            out.push(line(
+                frame.code(motion, if f == feedrate { None } else { f }),
+            ));
+            if motion.kind != MoveKind::Rapid {
+                feedrate = f;
+            }
+        }
+        if relative {
+            out.push(line(GCode::G91));
+            if frame.extruder_distance == Distance::Absolute {
+                out.push(line(GCode::M82));
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn tool_word(block: &Block) -> Option<u32> {
+    block.codes.iter().rev().find_map(|code| match code {
+        GCode::T(number) => Some(*number),
+        _ => None,
+    })
+}
+
+/// The tool-change sequence for going from `previous` to `tool`, on the line of the `T` word.
+fn tool_change_blocks(
+    machine: &Machine,
+    previous: Option<u32>,
+    tool: u32,
+    line: usize,
+) -> Result<Vec<Block>, PostprocessError> {
+    let width = machine.tool(Some(tool)).map_or(0.0, |tool| tool.width);
+    let text = machine
+        .tool_change
+        .replace("{tool}", &tool.to_string())
+        .replace("{width}", &width.to_string())
+        .replace(
+            "{previous}",
+            &previous.map_or_else(String::new, |previous| previous.to_string()),
+        );
+    let mut blocks = gcode_macro::expand(&text).map_err(PostprocessError::ToolChange)?;
+    for block in &mut blocks {
+        block.line = line;
+        block.line_number = None;
+    }
+    Ok(blocks)
+}
+
+/// Swaps `T` words for the tool-change sequence and moves everything after it by the new tool's
+/// offset.
+///
+/// The change happens at the `T` word. `M6` words go, since the sequence does the change.
+fn change_tools(blocks: &[Block], machine: &Machine) -> Result<Vec<Block>, PostprocessError> {
+    let mut interpreter = Interpreter::default();
+    let mut out = Vec::with_capacity(blocks.len());
+    let mut tool = None;
+    // mm added to every position.
+    let mut offset = [0.0; 3];
+    // Axes the program has sent somewhere known, which the tool moves to on a change.
+    let mut placed = [false; 3];
+
+    for block in blocks {
+        let change = tool_word(block);
+        if let Some(number) = change {
+            out.extend(tool_change_blocks(machine, tool, number, block.line)?);
+            tool = Some(number);
+
+            let next = machine.tool(tool).map_or([0.0; 3], |tool| tool.offset);
+            let scale = interpreter.state.units.scale();
+            let program = interpreter.program_position();
+            let mut words = [None; 4];
+            for axis in [X, Y, Z] {
+                if next[axis] == offset[axis] {
+                    continue;
+                }
+                words[axis] = match interpreter.state.distance {
+                    Distance::Relative => Some((next[axis] - offset[axis]) / scale),
+                    Distance::Absolute if placed[axis] => Some(program[axis] + next[axis] / scale),
+                    Distance::Absolute => None,
+                };
+            }
+            if words != [None; 4] {
+                let [x, y, z, _] = words;
+                out.push(Block {
+                    line: block.line,
+                    codes: vec![GCode::G0 {
+                        x,
+                        y,
+                        z,
+                        e: None,
+                        f: None,
+                    }],
+                    ..Default::default()
+                });
+            }
+            offset = next;
+        }
+
+        interpreter.run_block(block, &mut vec![]);
+        let scale = interpreter.state.units.scale();
+        let shift = |word: &mut Option<f32>, axis: usize| {
+            if let Some(value) = word {
+                *value += offset[axis] / scale;
+            }
+        };
+        let machine_coordinates = block.codes.iter().any(|code| matches!(code, GCode::G53));
+        let absolute = interpreter.state.distance == Distance::Absolute;
+
+        let mut codes = Vec::with_capacity(block.codes.len());
+        for code in &block.codes {
+            let mut code = code.clone();
+            match &mut code {
+                GCode::T(_) | GCode::M6 {} => continue,
+                GCode::G0 { x, y, z, .. }
+                | GCode::G1 { x, y, z, .. }
+                | GCode::G2 { x, y, z, .. }
+                | GCode::G3 { x, y, z, .. }
+                    if absolute && !machine_coordinates =>
+                {
+                    for (axis, word) in [(X, x), (Y, y), (Z, z)] {
+                        placed[axis] |= word.is_some();
+                        shift(word, axis);
+                    }
+                }
+                GCode::G92 { x, y, z, .. } => {
+                    for (axis, word) in [(X, x), (Y, y), (Z, z)] {
+                        placed[axis] |= word.is_some();
+                        shift(word, axis);
+                    }
+                }
+                GCode::G28 { x, y, z, .. } => {
+                    let all = !(*x || *y || *z);
+                    for (axis, home) in [(X, *x), (Y, *y), (Z, *z)] {
+                        placed[axis] |= all || home;
+                    }
+                }
+                _ => {}
+            }
+            codes.push(code);
+        }
+        if !codes.is_empty() || !block.comments.is_empty() || change.is_none() {
+            out.push(Block {
+                codes,
+                ..block.clone()
+            });
+        }
+    }
+    Ok(out)
+}
+
+#[test]
+fn test_gcode_postprocess() {
+    use super::gcode_emitter::{emit_program, EmitOptions};
+    use super::gcode_parser::parse;
+
+    let machine = Machine {
+        tools: vec![
+            ToolSetup {
+                number: 1,
+                width: 2.0,
+                offset: [0.0; 3],
+            },
+            ToolSetup {
+                number: 2,
+                width: 4.0,
+                offset: [10.0, 5.0, -1.0],
+            },
+        ],
+        tool_change: "M400 ; {previous} to {tool}, {width} mm".to_owned(),
+        ..Default::default()
+    };
+    let run = |program: &str| {
+        postprocess(&parse(program).unwrap(), &machine)
+            .map(|blocks| emit_program(&blocks, &EmitOptions::default()))
+    };
+
+    // Absolute positions carry the offset, and the tool moves over on the change.
+    assert_eq!(
+        run("G90\nG0 X1 Y2\nT2 M6\nG1 X3 F100\nT1\nG1 Y4").unwrap(),
+        "G90\n\
+         G0 X1 Y2\n\
+         M400 ; to 2, 4 mm\n\
+         G0 X11 Y7\n\
+         G1 X13 F100\n\
+         M400 ; 2 to 1, 2 mm\n\
+         G0 X3 Y2\n\
+         G1 Y4\n"
+    );
+
+    // Relative moves stay as they are; the change moves the tool by the difference.
+    assert_eq!(
+        run("G91\nT2\nG1 X1 Z1 F100\nG92 X0\nG90\nG1 X1").unwrap(),
+        "G91\n\
+         M400 ; to 2, 4 mm\n\
+         G0 X10 Y5 Z-1\n\
+         G1 X1 Z1 F100\n\
+         G92 X10\n\
+         G90\n\
+         G1 X11\n"
+    );
+
+    // A 10 mm square cut with a 2 mm tool on the inside.
+    let square =
+        run("T1 M6\nG0 X0 Y-5\nG41\nG1 X0 Y0 F100\nX10\nY10\nX0\nY0\nG40\nG0 X0 Y-5").unwrap();
+    assert!(
+        !square.contains("G41") && !square.contains("G40"),
+        "{}",
+        square
+    );
+    let corners: Vec<&str> = square
+        .lines()
+        .filter(|line| line.starts_with("G1"))
+        .collect();
+    assert_eq!(
+        corners,
+        ["G1 X1 Y1 F100", "G1 X9", "G1 Y9", "G1 X1", "G1 Y1"],
+        "{}",
+        square
+    );
+
+    // Tool 2 is too wide for the inside of a 1 mm arc.
+    assert_eq!(
+        run("T2\nG0 X0 Y0\nG41\nG1 X1 F100\nG3 X-1 I-1\nG40"),
+        Err(PostprocessError::Compensation(CompensationError {
+            line: 5,
+            kind: gcode_compensation::CompensationErrorKind::Gouge,
+        }))
+    );
+
+    let broken = Machine {
+        tool_change: "G1 X(".to_owned(),
+        ..machine.clone()
+    };
+    assert!(matches!(
+        postprocess(&parse("T1").unwrap(), &broken),
+        Err(PostprocessError::ToolChange(_))
+    ));
+}
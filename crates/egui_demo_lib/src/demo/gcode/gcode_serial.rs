@@ -0,0 +1,305 @@
+//! Drives a USB-attached controller over its serial port, on native only.
+//!
+//! A [`Connection`] owns the port on a thread of its own, writing the lines a
+//! [`SerialSender`] hands out and reading whatever the firmware prints, so the UI only looks at
+//! the shared [`Status`]. Any port `serialport` can open works, pseudo-terminals included.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gcode_stream::SerialSender;
+use serialport::SerialPort;
+
+/// Rates controllers usually run at.
+pub const BAUD_RATES: [u32; 9] = [
+    9600, 19_200, 38_400, 57_600, 115_200, 230_400, 250_000, 500_000, 1_000_000,
+];
+
+/// How long a read waits before the thread looks for commands again.
+const POLL: Duration = Duration::from_millis(50);
+
+/// How long the firmware may stay silent about a line before it is sent again. Marlin prints
+/// `busy:` every few seconds during long moves, which counts as an answer.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Lines kept in [`Status::log`].
+const MAX_LOG: usize = 1000;
+
+/// Names of the serial ports on this machine, such as `/dev/ttyUSB0` or `COM3`.
+pub fn ports() -> Vec<String> {
+    serialport::available_ports()
+        .map(|ports| ports.into_iter().map(|port| port.port_name).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    /// Lines written, starting with `>`, and lines read, oldest first.
+    pub log: VecDeque<String>,
+
+    /// Lines acknowledged and the total for the last program sent.
+    pub progress: Option<(usize, usize)>,
+
+    /// Whether a program is being sent.
+    pub running: bool,
+
+    /// Why the last program stopped early, or why the port closed.
+    pub error: Option<String>,
+
+    /// Whether the port is still open.
+    pub connected: bool,
+}
+
+impl Status {
+    fn log(&mut self, line: String) {
+        if self.log.len() == MAX_LOG {
+            self.log.pop_front();
+        }
+        self.log.push_back(line);
+    }
+}
+
+enum Command {
+    /// Send a program, framed or not.
+    Program(Vec<String>, bool),
+
+    /// Send one line typed by the user, when no program is running.
+    Line(String),
+
+    /// Stop sending the program.
+    Stop,
+}
+
+/// An open port and the thread serving it, which stops when this is dropped.
+#[derive(Debug)]
+pub struct Connection {
+    commands: mpsc::Sender<Command>,
+    status: Arc<Mutex<Status>>,
+}
+
+impl PartialEq for Connection {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.status, &other.status)
+    }
+}
+
+impl Connection {
+    pub fn open(port: &str, baud_rate: u32) -> Result<Self, serialport::Error> {
+        let port = serialport::new(port, baud_rate).timeout(POLL).open()?;
+        Ok(Self::with_port(port))
+    }
+
+    /// Serves a port that is already open, which must time out reads after about [`POLL`].
+    pub fn with_port(port: Box<dyn SerialPort>) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let status = Arc::new(Mutex::new(Status {
+            connected: true,
+            ..Default::default()
+        }));
+        let shared = Arc::clone(&status);
+        thread::spawn(move || {
+            let result = serve(port, &receiver, &shared);
+            if let Ok(mut status) = shared.lock() {
+                status.connected = false;
+                status.running = false;
+                if let Err(err) = result {
+                    status.error = Some(format!("Port closed: {}", err));
+                }
+            }
+        });
+        Self { commands, status }
+    }
+
+    /// Sends a program, with sequence numbers and checksums if `framed`.
+    pub fn send_program(&self, lines: Vec<String>, framed: bool) {
+        self.commands.send(Command::Program(lines, framed)).ok();
+    }
+
+    /// Sends one line, unless a program is running.
+    pub fn send_line(&self, line: String) {
+        self.commands.send(Command::Line(line)).ok();
+    }
+
+    pub fn stop(&self) {
+        self.commands.send(Command::Stop).ok();
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+            .lock()
+            .map(|status| status.clone())
+            .unwrap_or_default()
+    }
+}
+
+fn update(status: &Mutex<Status>, f: impl FnOnce(&mut Status)) {
+    if let Ok(mut status) = status.lock() {
+        f(&mut status);
+    }
+}
+
+fn write_line(port: &mut dyn SerialPort, line: &str, status: &Mutex<Status>) -> io::Result<()> {
+    port.write_all(line.as_bytes())?;
+    port.write_all(b"\n")?;
+    port.flush()?;
+    update(status, |status| status.log(format!("> {}", line)));
+    Ok(())
+}
+
+/// Runs the port until the [`Connection`] goes away or the port fails.
+fn serve(
+    port: Box<dyn SerialPort>,
+    commands: &mpsc::Receiver<Command>,
+    status: &Mutex<Status>,
+) -> io::Result<()> {
+    let mut writer = port.try_clone()?;
+    let mut reader = BufReader::new(port);
+    let mut sender: Option<SerialSender> = None;
+    // What has come in of a line so far, kept across timed out reads.
+    let mut partial = vec![];
+    let mut heard = Instant::now();
+
+    loop {
+        loop {
+            match commands.try_recv() {
+                Ok(Command::Program(lines, framed)) => {
+                    let program = SerialSender::new(lines, framed);
+                    update(status, |status| {
+                        status.progress = Some(program.progress());
+                        status.running = true;
+                        status.error = None;
+                    });
+                    sender = Some(program);
+                }
+                Ok(Command::Line(line)) if sender.is_none() => {
+                    write_line(writer.as_mut(), &line, status)?;
+                }
+                Ok(Command::Line(_)) => {}
+                Ok(Command::Stop) => {
+                    sender = None;
+                    update(status, |status| status.running = false);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if let Some(line) = sender.as_mut().and_then(SerialSender::next_line) {
+            write_line(writer.as_mut(), &line, status)?;
+            heard = Instant::now();
+        }
+
+        match reader.read_until(b'\n', &mut partial) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) if partial.ends_with(b"\n") => {
+                let line = String::from_utf8_lossy(&partial).trim().to_owned();
+                partial.clear();
+                heard = Instant::now();
+                if line.is_empty() {
+                    continue;
+                }
+                if let Some(program) = &mut sender {
+                    if let Err(err) = program.handle_line(&line) {
+                        sender = None;
+                        update(status, |status| {
+                            status.running = false;
+                            status.error = Some(err.to_string());
+                        });
+                    }
+                }
+                update(status, |status| status.log(line));
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                if heard.elapsed() > ANSWER_TIMEOUT {
+                    if let Some(program) = &mut sender {
+                        program.handle_timeout();
+                    }
+                    heard = Instant::now();
+                }
+            }
+            Err(err) => return Err(err),
+        }
+
+        if let Some(program) = &sender {
+            let progress = program.progress();
+            let done = program.is_done();
+            update(status, |status| {
+                status.progress = Some(progress);
+                status.running = !done;
+            });
+            if done {
+                sender = None;
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_gcode_serial() {
+    use std::io::Write as _;
+
+    use gcode_stream::frame;
+    use serialport::TTYPort;
+
+    // The master end of a pseudo-terminal plays Marlin; the host gets the terminal itself.
+    let (mut firmware, mut host) = TTYPort::pair().unwrap();
+    host.set_timeout(POLL).unwrap();
+    firmware.set_timeout(Duration::from_secs(5)).unwrap();
+    let connection = Connection::with_port(Box::new(host));
+    connection.send_program(
+        vec![
+            "G28".to_owned(),
+            "G1 X10 ; cut".to_owned(),
+            "M400".to_owned(),
+        ],
+        true,
+    );
+
+    let mut reader = BufReader::new(firmware.try_clone_native().unwrap());
+    let mut queued = vec![];
+    let mut resent = false;
+    while queued.len() < 3 {
+        let mut frame = String::new();
+        reader.read_line(&mut frame).unwrap();
+        // A terminal left in echo mode hands the firmware its own replies back.
+        let (sequence, line) = match frame::decode(&frame) {
+            Ok(decoded) => decoded,
+            Err(_) => continue,
+        };
+        let reply = if sequence == 2 && !resent {
+            // As if line 2 arrived damaged.
+            resent = true;
+            "Error:checksum mismatch, Last Line: 1\nResend: 2\nok\n"
+        } else {
+            if sequence > 0 {
+                queued.push(line.to_owned());
+            }
+            "echo:busy: processing\nok\n"
+        };
+        firmware.write_all(reply.as_bytes()).unwrap();
+    }
+    assert_eq!(queued, ["G28", "G1 X10", "M400"]);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while connection.status().running && Instant::now() < deadline {
+        thread::sleep(POLL);
+    }
+    let status = connection.status();
+    assert_eq!(status.progress, Some((3, 3)));
+    assert_eq!(status.error, None);
+    assert!(status.log.contains(&"Resend: 2".to_owned()));
+
+    // Lines typed in go out as they are.
+    connection.send_line("M105".to_owned());
+    let mut line = String::new();
+    while line.trim() != "M105" {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+}
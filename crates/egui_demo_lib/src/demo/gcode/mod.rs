@@ -1,9 +1,129 @@
 use egui::*;
 
-#[derive(PartialEq, Default, Debug)]
-pub struct Gcode {}
+pub mod gcode_arc;
+pub mod gcode_compensation;
+pub mod gcode_dialect;
+pub mod gcode_emitter;
+pub mod gcode_estimate;
+pub mod gcode_interpreter;
+pub mod gcode_lint;
+pub mod gcode_macro;
+pub mod gcode_optimize;
+pub mod gcode_parser;
+pub mod gcode_postprocess;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod gcode_serial;
 
-#[derive(Debug, PartialEq)]
+use gcode_estimate::{Dynamics, Estimate};
+use gcode_lint::{Diagnostic, Limits, Severity};
+use gcode_optimize::OptimizeOptions;
+
+#[derive(PartialEq, Debug)]
+pub struct Gcode {
+    /// The program in the editor.
+    code: String,
+
+    /// Machine travel the program is checked against.
+    limits: Limits,
+
+    /// Problems in `code`, found again whenever it changes.
+    diagnostics: Vec<Diagnostic>,
+
+    /// Line to put the cursor on, picked from the problems list.
+    jump_to: Option<usize>,
+
+    /// How the machine accelerates, for the estimate.
+    dynamics: Dynamics,
+
+    /// Time and material for `code`, or `None` if it does not expand.
+    estimate: Option<Estimate>,
+
+    optimize_options: OptimizeOptions,
+
+    /// What the last optimization did, or why it failed.
+    optimize_summary: Option<String>,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    serial: Serial,
+}
+
+impl Default for Gcode {
+    fn default() -> Self {
+        let code = "G21 G90 G54\n\
+                    G0 X10 Y10 Z5\n\
+                    M3 S12000\n\
+                    G1 Z-1 F300\n\
+                    G1 X60\n\
+                    G2 X70 Y20 I0 J10\n\
+                    G1 Y60\n\
+                    G0 Z5\n\
+                    M5\n\
+                    M2\n"
+            .to_owned();
+        let limits = Limits::default();
+        let dynamics = Dynamics::default();
+        Self {
+            diagnostics: gcode_lint::lint(&code, &limits),
+            estimate: estimate_source(&code, &dynamics),
+            code,
+            limits,
+            jump_to: None,
+            dynamics,
+            optimize_options: OptimizeOptions::default(),
+            optimize_summary: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            serial: Serial::default(),
+        }
+    }
+}
+
+/// The link to the machine and the console around it.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(PartialEq, Debug)]
+struct Serial {
+    /// Ports found by the last look.
+    ports: Vec<String>,
+
+    port: String,
+    baud_rate: u32,
+
+    /// Send line numbers and checksums, which grbl does not take.
+    framed: bool,
+
+    /// The line being typed into the console.
+    command: String,
+
+    connection: Option<gcode_serial::Connection>,
+
+    /// Why the port did not open or the program did not expand.
+    error: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for Serial {
+    fn default() -> Self {
+        let ports = gcode_serial::ports();
+        Self {
+            port: ports.first().cloned().unwrap_or_default(),
+            ports,
+            baud_rate: 115_200,
+            framed: true,
+            command: String::new(),
+            connection: None,
+            error: None,
+        }
+    }
+}
+
+/// The estimate for a program, or `None` if it does not expand.
+fn estimate_source(code: &str, dynamics: &Dynamics) -> Option<Estimate> {
+    let blocks = gcode_macro::expand(code).ok()?;
+    let moves = gcode_interpreter::Interpreter::default().run(&blocks);
+    Some(gcode_estimate::estimate(&moves, dynamics))
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum GCode {
     G0 {
         // Rapid move
@@ -35,10 +155,18 @@ pub enum GCode {
         // The position to move to on the X axis
         y: Option<f32>,
         // The position to move to on the Y axis
+        z: Option<f32>,
+        // The position to move to on the Z axis
         i: Option<f32>,
         // The point in X space from the current X position to maintain a constant distance from
         j: Option<f32>,
         // The point in Y space from the current Y position to maintain a constant distance from
+        k: Option<f32>,
+        // The point in Z space from the current Z position to maintain a constant distance from
+        r: Option<f32>,
+        // The radius of the arc, negative for arcs over 180 degrees (instead of I, J and K)
+        p: Option<u32>,
+        // The number of turns (full circles end where they start)
         e: Option<f32>,
         // The amount to extrude between the starting point and ending point
         f: Option<f32>, // The feedrate per minute of the move between the starting point and ending point (if supplied)
@@ -49,19 +177,27 @@ pub enum GCode {
         // The position to move to on the X axis
         y: Option<f32>,
         // The position to move to on the Y axis
+        z: Option<f32>,
+        // The position to move to on the Z axis
         i: Option<f32>,
         // The point in X space from the current X position to maintain a constant distance from
         j: Option<f32>,
         // The point in Y space from the current Y position to maintain a constant distance from
+        k: Option<f32>,
+        // The point in Z space from the current Z position to maintain a constant distance from
+        r: Option<f32>,
+        // The radius of the arc, negative for arcs over 180 degrees (instead of I, J and K)
+        p: Option<u32>,
+        // The number of turns (full circles end where they start)
         e: Option<f32>,
         // The amount to extrude between the starting point and ending point
         f: Option<f32>, // The feedrate per minute of the move between the starting point and ending point (if supplied)
     },
     G4 {
         // Dwell
-        p: Option<u32>,
+        p: Option<f32>,
         // Time to wait, in milliseconds
-        s: Option<u32>, // Time to wait, in seconds
+        s: Option<f32>, // Time to wait, in seconds
     },
     G6 {
         // Set coordinate system offsets
@@ -241,7 +377,24 @@ pub enum GCode {
     },
     G40,
     // turns off cutter compensation
+    G41 {
+        // Cutter compensation, tool left of the path (CNC specific)
+        d: Option<u32>, // Tool whose radius to use, the current tool if omitted
+    },
+    G41_1 {
+        // Cutter compensation left with a given tool (CNC specific)
+        d: f32, // Tool diameter
+    },
     G42 {
+        // Cutter compensation, tool right of the path (CNC specific)
+        d: Option<u32>, // Tool whose radius to use, the current tool if omitted
+    },
+    G42_1 {
+        // Cutter compensation right with a given tool (CNC specific)
+        d: f32, // Tool diameter
+    },
+    G42MeshMove {
+        // G42: Move to mesh coordinate
         i: Option<u32>,
         // grid X index (zero-based)
         j: Option<u32>,
@@ -287,22 +440,25 @@ pub enum GCode {
     },
     G80,
     // Cancel Canned Cycle (CNC specific)
-    G81,
-    // Mesh bed leveling status
-    G82,
-    // Single Z probe at current location
-    G83,
-    // Babystep in Z and store to EEPROM
-    G84,
-    // UNDO Babystep Z (move Z axis back)
-    G85,
-    // Pick best babystep
-    G86,
-    // Disable babystep correction after home
-    G87,
-    // Enable babystep correction after home
-    G88,
-    // Reserved
+    G81(Cycle),
+    // Drilling cycle (CNC specific). Prusa firmware uses G81 to G88 for mesh bed leveling and
+    // babystepping, which are not modeled: on Prusa they parse as these cycles.
+    G82(Cycle),
+    // Drilling cycle with dwell (CNC specific)
+    G83(Cycle),
+    // Peck drilling cycle (CNC specific)
+    G84(Cycle),
+    // Right-hand tapping cycle (CNC specific)
+    G85(Cycle),
+    // Boring cycle, feed out (CNC specific)
+    G86(Cycle),
+    // Boring cycle, spindle stop, rapid out (CNC specific)
+    G87(Cycle),
+    // Back boring cycle (CNC specific)
+    G88(Cycle),
+    // Boring cycle, spindle stop, manual out (CNC specific)
+    G89(Cycle),
+    // Boring cycle, dwell, feed out (CNC specific)
     G90,
     // Set to Absolute Positioning
     G91,
@@ -322,9 +478,9 @@ pub enum GCode {
     G94,
     // Feed Rate Mode (Units per Minute) (CNC specific)
     G98,
-    // Activate farm mode
+    // Canned cycles return to the initial level (CNC specific)
     G99,
-    // Deactivate farm mode
+    // Canned cycles return to the R level (CNC specific)
     G100 {
         // Calibrate floor or rod radius
         x: Option<bool>,
@@ -657,7 +813,9 @@ pub enum GCode {
     // Send axis_steps_per_unit
     M98 {
         // Call Macro/Subprogram
-        p: String, // Macro filename
+        p: String,
+        // Macro filename, or the number of an O-word subprogram
+        l: Option<u32>, // Number of times to run the subprogram
     },
     M99,
     // Return from Macro/Subprogram
@@ -2056,28 +2214,41 @@ pub enum GCode {
     M596 {
         p: Option<u32>, // Movement queue number
     },
-    
+    // T: Select tool
+    T(u32),
+}
+
+/// The words of a `G81` to `G89` canned cycle. Any left out carry over from the block before.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cycle {
+    /// Hole position on the X axis.
+    pub x: Option<f32>,
+    /// Hole position on the Y axis.
+    pub y: Option<f32>,
+    /// Hole bottom on the Z axis.
+    pub z: Option<f32>,
+    /// Retract plane to rapid down to and back up to.
+    pub r: Option<f32>,
+    /// Peck depth for `G83`, sideways shift for `G87`.
+    pub q: Option<f32>,
+    /// Dwell at the bottom, in seconds.
+    pub p: Option<f32>,
+    /// Number of repeats, stepping by X and Y in incremental mode.
+    pub l: Option<u32>,
+    /// Feedrate per minute into the hole.
+    pub f: Option<f32>,
 }
 
 impl GCode {
-    //pub fn from_string(s: &str) -> Option<Self> {
-    //    let mut parts = s.split_whitespace();
-    //    let code = parts.next().unwrap_or("").to_ascii_uppercase();
-        //match code.as_str() {
-        //    "G0" => Some(GCode::G0 {
-        //        x: Self::parse_optional_value(parts.next(), "X"),
-        //        y: Self::parse_optional_value(parts.next(), "Y"),
-        //        z: Self::parse_optional_value(parts.next(), "Z"),
-        //        e: Self::parse_optional_value(parts.next(), "E"),
-        //        f: Self::parse_optional_value(parts.next(), "F"),
-        //    }),
-        //    "M405" => Some(GCode::M405 {
-        //        delay: Self::parse_optional_value(parts.next(), "D"),
-        //    }),
-        //    "M406" => Some(GCode::M406),
-        //    "M407" => Some(GCode::M407),
-        //}
-    //}
+    /// Parse the first code on a single line of G-code, e.g. `"G0 X10 Y20"`.
+    ///
+    /// Use [`gcode_parser::parse`] for whole programs and error details.
+    pub fn from_string(s: &str) -> Option<Self> {
+        gcode_parser::Parser::default()
+            .parse_line(1, s)
+            .ok()
+            .and_then(|block| block.codes.into_iter().next())
+    }
 }
 
 impl super::Demo for Gcode {
@@ -2096,11 +2267,533 @@ impl super::Demo for Gcode {
 }
 
 impl super::View for Gcode {
-    #[allow(clippy::unused_self)]
     fn ui(&mut self, ui: &mut Ui) {
-        let ui_connect = ui
-            .button("Connect")
-            .on_hover_text("Initiate serial communications");
-        if ui_connect.clicked() {}
+        let Self {
+            code,
+            limits,
+            diagnostics,
+            jump_to,
+            dynamics,
+            estimate,
+            optimize_options,
+            optimize_summary,
+            #[cfg(not(target_arch = "wasm32"))]
+            serial,
+        } = self;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.collapsing("Connection", |ui| serial_ui(ui, serial, code));
+        #[cfg(target_arch = "wasm32")]
+        ui.add_enabled(false, Button::new("Connect"))
+            .on_disabled_hover_text("Serial ports need the desktop app");
+
+        ui.collapsing("Machine limits", |ui| {
+            let mut changed = false;
+            Grid::new("gcode_limits").show(ui, |ui| {
+                ui.label("");
+                ui.label("Min");
+                ui.label("Max");
+                ui.end_row();
+                for (axis, name) in ["X", "Y", "Z"].iter().enumerate() {
+                    ui.label(*name);
+                    changed |= ui
+                        .add(DragValue::new(&mut limits.min[axis]).suffix(" mm"))
+                        .changed();
+                    changed |= ui
+                        .add(DragValue::new(&mut limits.max[axis]).suffix(" mm"))
+                        .changed();
+                    ui.end_row();
+                }
+            });
+            if changed {
+                *diagnostics = gcode_lint::lint(code, limits);
+            }
+        });
+
+        ui.collapsing("Machine dynamics", |ui| {
+            if dynamics_ui(ui, dynamics) {
+                *estimate = estimate_source(code, dynamics);
+            }
+        });
+
+        ui.collapsing("Optimize", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Tolerance");
+                ui.add(
+                    DragValue::new(&mut optimize_options.tolerance)
+                        .speed(0.001)
+                        .clamp_range(0.0..=1.0)
+                        .suffix(" mm"),
+                );
+            });
+            ui.checkbox(&mut optimize_options.reorder, "Reorder contours")
+                .on_hover_text("Cut independent contours in the order with the shortest rapids");
+            ui.checkbox(&mut optimize_options.fit_arcs, "Fit arcs")
+                .on_hover_text("Turn runs of short segments into G2/G3 arcs");
+            if ui
+                .button("Optimize")
+                .on_hover_text("Rewrite the program, expanding subroutines and loops")
+                .clicked()
+            {
+                match gcode_macro::expand(code) {
+                    Ok(blocks) => {
+                        let blocks = gcode_optimize::optimize(&blocks, optimize_options);
+                        let optimized = gcode_emitter::emit_program(&blocks, &Default::default());
+                        *optimize_summary = Some(format!(
+                            "{} → {} lines, {} → {} bytes",
+                            code.lines().count(),
+                            optimized.lines().count(),
+                            code.len(),
+                            optimized.len()
+                        ));
+                        *code = optimized;
+                        *diagnostics = gcode_lint::lint(code, limits);
+                        *estimate = estimate_source(code, dynamics);
+                    }
+                    Err(err) => *optimize_summary = Some(err.to_string()),
+                }
+            }
+            if let Some(summary) = optimize_summary {
+                ui.label(summary.as_str());
+            }
+        });
+
+        let editor_id = ui.make_persistent_id("gcode_editor");
+        let jump = jump_to.take();
+        if let Some(line) = jump {
+            let index = code
+                .split('\n')
+                .take(line - 1)
+                .map(|text| text.chars().count() + 1)
+                .sum();
+            let mut state = text_edit::TextEditState::load(ui.ctx(), editor_id).unwrap_or_default();
+            state.set_ccursor_range(Some(text::CCursorRange::one(text::CCursor::new(index))));
+            state.store(ui.ctx(), editor_id);
+            ui.memory_mut(|mem| mem.request_focus(editor_id));
+        }
+
+        let theme = crate::syntax_highlighting::CodeTheme::from_memory(ui.ctx());
+        let font = TextStyle::Monospace.resolve(ui.style());
+        let digits = code.split('\n').count().to_string().len().max(3);
+        let gutter = ui.fonts(|f| f.glyph_width(&font, '0')) * digits as f32 + 20.0;
+
+        let output = ScrollArea::vertical()
+            .id_source("gcode_editor_scroll")
+            .max_height(ui.available_height() * 0.7)
+            .show(ui, |ui| {
+                let marks = &*diagnostics;
+                let mut layouter = |ui: &Ui, string: &str, _wrap_width: f32| {
+                    let mut layout_job =
+                        crate::syntax_highlighting::highlight(ui.ctx(), &theme, string, "gcode");
+                    underline_lines(ui, &mut layout_job, string, marks);
+                    ui.fonts(|f| f.layout_job(layout_job))
+                };
+                let output = TextEdit::multiline(code)
+                    .id(editor_id)
+                    .font(TextStyle::Monospace) // for cursor height
+                    .code_editor()
+                    .desired_rows(20)
+                    .lock_focus(true)
+                    .desired_width(f32::INFINITY)
+                    .margin(vec2(gutter, 2.0))
+                    .layouter(&mut layouter)
+                    .show(ui);
+                paint_gutter(ui, &output, &font, gutter, marks);
+                if let Some(line) = jump {
+                    if let Some(rect) = line_rect(&output, line) {
+                        ui.scroll_to_rect(rect, Some(Align::Center));
+                    }
+                }
+                output
+            })
+            .inner;
+        if output.response.changed() {
+            *diagnostics = gcode_lint::lint(code, limits);
+            *estimate = estimate_source(code, dynamics);
+        }
+
+        if let Some(estimate) = estimate {
+            ui.separator();
+            estimate_ui(ui, estimate);
+        }
+
+        ui.separator();
+        ui.label(format!("Problems: {}", diagnostics.len()));
+        ScrollArea::vertical()
+            .id_source("gcode_problems_scroll")
+            .show(ui, |ui| {
+                for diagnostic in diagnostics.iter() {
+                    ui.horizontal(|ui| {
+                        let color = severity_color(ui, diagnostic.severity);
+                        ui.colored_label(color, "●");
+                        if ui
+                            .link(diagnostic.to_string())
+                            .on_hover_text("Go to the line")
+                            .clicked()
+                        {
+                            *jump_to = Some(diagnostic.line);
+                            ui.ctx().request_repaint();
+                        }
+                    });
+                }
+            });
+    }
+}
+
+/// Opens a serial port, streams `code` to the machine and shows what it answers.
+#[cfg(not(target_arch = "wasm32"))]
+fn serial_ui(ui: &mut Ui, serial: &mut Serial, code: &str) {
+    let status = serial
+        .connection
+        .as_ref()
+        .map(gcode_serial::Connection::status);
+    let connected = status.as_ref().map_or(false, |status| status.connected);
+
+    ui.horizontal(|ui| {
+        ui.add_enabled_ui(!connected, |ui| {
+            ComboBox::from_id_source("gcode_serial_port")
+                .selected_text(serial.port.as_str())
+                .show_ui(ui, |ui| {
+                    for port in &serial.ports {
+                        ui.selectable_value(&mut serial.port, port.clone(), port.as_str());
+                    }
+                });
+            if ui
+                .button("⟳")
+                .on_hover_text("Look for ports again")
+                .clicked()
+            {
+                serial.ports = gcode_serial::ports();
+            }
+            ComboBox::from_id_source("gcode_serial_baud_rate")
+                .selected_text(format!("{} baud", serial.baud_rate))
+                .show_ui(ui, |ui| {
+                    for baud_rate in gcode_serial::BAUD_RATES {
+                        ui.selectable_value(
+                            &mut serial.baud_rate,
+                            baud_rate,
+                            baud_rate.to_string(),
+                        );
+                    }
+                });
+        });
+        if connected {
+            if ui.button("Disconnect").clicked() {
+                serial.connection = None;
+            }
+        } else if ui
+            .add_enabled(!serial.port.is_empty(), Button::new("Connect"))
+            .on_hover_text("Open the serial port")
+            .clicked()
+        {
+            match gcode_serial::Connection::open(&serial.port, serial.baud_rate) {
+                Ok(connection) => {
+                    serial.connection = Some(connection);
+                    serial.error = None;
+                }
+                Err(err) => serial.error = Some(err.to_string()),
+            }
+        }
+    });
+    ui.checkbox(&mut serial.framed, "Line numbers and checksums")
+        .on_hover_text("Marlin and RepRapFirmware check these and ask for damaged lines again; grbl does not take them");
+    if let Some(error) = &serial.error {
+        ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+    }
+
+    let (connection, status) = match (&serial.connection, status) {
+        (Some(connection), Some(status)) => (connection, status),
+        _ => return,
+    };
+    ui.horizontal(|ui| {
+        if status.running {
+            if ui.button("Stop").clicked() {
+                connection.stop();
+            }
+        } else if ui
+            .add_enabled(status.connected, Button::new("Send program"))
+            .on_hover_text("Send the program a line at a time, expanding subroutines and loops")
+            .clicked()
+        {
+            match gcode_macro::expand(code) {
+                Ok(blocks) => {
+                    let options = Default::default();
+                    let lines = blocks
+                        .iter()
+                        .map(|block| gcode_emitter::emit_block(block, &options))
+                        .collect();
+                    connection.send_program(lines, serial.framed);
+                    serial.error = None;
+                }
+                Err(err) => serial.error = Some(err.to_string()),
+            }
+        }
+        if let Some((acked, total)) = status.progress {
+            let fraction = if total == 0 {
+                1.0
+            } else {
+                acked as f32 / total as f32
+            };
+            ui.add(ProgressBar::new(fraction).text(format!("{} / {} lines", acked, total)));
+        }
+    });
+    if let Some(error) = &status.error {
+        ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+    }
+
+    ScrollArea::vertical()
+        .id_source("gcode_serial_log")
+        .max_height(150.0)
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            for line in &status.log {
+                ui.monospace(line.as_str());
+            }
+        });
+    ui.horizontal(|ui| {
+        let idle = status.connected && !status.running;
+        let response = ui.add_enabled(
+            idle,
+            TextEdit::singleline(&mut serial.command)
+                .font(TextStyle::Monospace)
+                .hint_text("M114"),
+        );
+        let entered = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+        let clicked = ui.add_enabled(idle, Button::new("Send")).clicked();
+        if (entered || clicked) && !serial.command.trim().is_empty() {
+            connection.send_line(std::mem::take(&mut serial.command));
+            response.request_focus();
+        }
+    });
+
+    if status.connected {
+        // Answers come in on the port's own thread.
+        ui.ctx()
+            .request_repaint_after(std::time::Duration::from_millis(100));
+    }
+}
+
+/// Edits the machine dynamics an estimate uses, returning whether they changed.
+pub fn dynamics_ui(ui: &mut Ui, dynamics: &mut Dynamics) -> bool {
+    let mut changed = false;
+    Grid::new("gcode_dynamics").show(ui, |ui| {
+        ui.label("Acceleration");
+        changed |= ui
+            .add(
+                DragValue::new(&mut dynamics.acceleration)
+                    .clamp_range(1.0..=100_000.0)
+                    .suffix(" mm/s²"),
+            )
+            .changed();
+        ui.end_row();
+
+        ui.label("Junction deviation");
+        changed |= ui
+            .add(
+                DragValue::new(&mut dynamics.junction_deviation)
+                    .speed(0.001)
+                    .clamp_range(0.0..=10.0)
+                    .suffix(" mm"),
+            )
+            .changed();
+        ui.end_row();
+
+        ui.label("Rapid feedrate");
+        changed |= ui
+            .add(
+                DragValue::new(&mut dynamics.rapid_feedrate)
+                    .clamp_range(1.0..=100_000.0)
+                    .suffix(" mm/min"),
+            )
+            .changed();
+        ui.end_row();
+    });
+    changed
+}
+
+/// Shows how long a program takes and what it uses.
+pub fn estimate_ui(ui: &mut Ui, estimate: &Estimate) {
+    use gcode_estimate::format_duration;
+
+    Grid::new("gcode_estimate").show(ui, |ui| {
+        ui.label("Time");
+        ui.strong(format_duration(estimate.time));
+        ui.end_row();
+
+        ui.label("Feed distance");
+        ui.label(format!("{:.1} mm", estimate.feed_distance));
+        ui.end_row();
+
+        ui.label("Rapid distance");
+        ui.label(format!("{:.1} mm", estimate.rapid_distance));
+        ui.end_row();
+
+        if estimate.extrusion != 0.0 {
+            ui.label("Extrusion");
+            ui.label(format!("{:.1} mm", estimate.extrusion));
+            ui.end_row();
+        }
+
+        if estimate.beam_time > 0.0 {
+            ui.label("Spindle, laser or torch on");
+            ui.label(format_duration(estimate.beam_time));
+            ui.end_row();
+        }
+
+        for (tool, seconds) in &estimate.tool_time {
+            match tool {
+                Some(tool) => ui.label(format!("Tool {}", tool)),
+                None => ui.label("No tool"),
+            };
+            ui.label(format_duration(*seconds));
+            ui.end_row();
+        }
+    });
+    if estimate.unfed_moves > 0 {
+        ui.colored_label(
+            ui.visuals().warn_fg_color,
+            format!(
+                "{} feed moves have no feedrate and are left out",
+                estimate.unfed_moves
+            ),
+        );
+    }
+}
+
+pub fn severity_color(ui: &Ui, severity: Severity) -> Color32 {
+    match severity {
+        Severity::Error => ui.visuals().error_fg_color,
+        Severity::Warning => ui.visuals().warn_fg_color,
+    }
+}
+
+/// The most serious problem on a line.
+fn worst(diagnostics: &[Diagnostic], line: usize) -> Option<Severity> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.line == line)
+        .map(|diagnostic| diagnostic.severity)
+        .min()
+}
+
+/// Underlines the lines that have problems, in the colour of the worst one.
+fn underline_lines(
+    ui: &Ui,
+    layout_job: &mut text::LayoutJob,
+    string: &str,
+    diagnostics: &[Diagnostic],
+) {
+    let mut marks = vec![];
+    let mut offset = 0;
+    for (index, line) in string.split('\n').enumerate() {
+        if let Some(severity) = worst(diagnostics, index + 1) {
+            marks.push((offset..offset + line.len(), severity_color(ui, severity)));
+        }
+        offset += line.len() + 1;
+    }
+    if marks.is_empty() {
+        return;
+    }
+
+    let mut sections = Vec::with_capacity(layout_job.sections.len() + 2 * marks.len());
+    for section in layout_job.sections.drain(..) {
+        let end = section.byte_range.end;
+        let mut start = section.byte_range.start;
+        let mut leading_space = section.leading_space;
+        for (range, color) in &marks {
+            let (from, to) = (range.start.max(start), range.end.min(end));
+            if from >= to {
+                continue;
+            }
+            if from > start {
+                sections.push(text::LayoutSection {
+                    leading_space,
+                    byte_range: start..from,
+                    format: section.format.clone(),
+                });
+                leading_space = 0.0;
+            }
+            let mut format = section.format.clone();
+            format.underline = Stroke::new(1.0, *color);
+            sections.push(text::LayoutSection {
+                leading_space,
+                byte_range: from..to,
+                format,
+            });
+            leading_space = 0.0;
+            start = to;
+        }
+        if start < end {
+            sections.push(text::LayoutSection {
+                leading_space,
+                byte_range: start..end,
+                format: section.format,
+            });
+        }
+    }
+    layout_job.sections = sections;
+}
+
+/// Where a source line starts on screen.
+fn line_rect(output: &text_edit::TextEditOutput, line: usize) -> Option<Rect> {
+    let mut current = 1;
+    let mut starts_line = true;
+    for row in &output.galley.rows {
+        if starts_line && current == line {
+            return Some(row.rect.translate(output.text_draw_pos.to_vec2()));
+        }
+        starts_line = row.ends_with_newline;
+        if row.ends_with_newline {
+            current += 1;
+        }
+    }
+    None
+}
+
+/// Line numbers and problem markers left of the text, with the problems of a line shown when
+/// hovering its marker.
+fn paint_gutter(
+    ui: &Ui,
+    output: &text_edit::TextEditOutput,
+    font: &FontId,
+    gutter: f32,
+    diagnostics: &[Diagnostic],
+) {
+    let painter = ui.painter_at(output.response.rect);
+    let left = output.text_draw_pos.x - gutter;
+    let hover = output.response.hover_pos();
+    let mut line = 1;
+    let mut starts_line = true;
+    for row in &output.galley.rows {
+        if starts_line {
+            let rect = row.rect.translate(output.text_draw_pos.to_vec2());
+            let y = rect.center().y;
+            painter.text(
+                pos2(left + gutter - 18.0, y),
+                Align2::RIGHT_CENTER,
+                line.to_string(),
+                font.clone(),
+                ui.visuals().weak_text_color(),
+            );
+            if let Some(severity) = worst(diagnostics, line) {
+                let center = pos2(left + gutter - 10.0, y);
+                painter.circle_filled(center, 3.5, severity_color(ui, severity));
+                let marker = Rect::from_x_y_ranges(left..=left + gutter, rect.y_range());
+                if hover.map_or(false, |pos| marker.contains(pos)) {
+                    show_tooltip_at_pointer(ui.ctx(), Id::new("gcode_gutter_tooltip"), |ui| {
+                        for diagnostic in diagnostics.iter().filter(|d| d.line == line) {
+                            ui.colored_label(
+                                severity_color(ui, diagnostic.severity),
+                                diagnostic.message.as_str(),
+                            );
+                        }
+                    });
+                }
+            }
+        }
+        starts_line = row.ends_with_newline;
+        if row.ends_with_newline {
+            line += 1;
+        }
     }
 }
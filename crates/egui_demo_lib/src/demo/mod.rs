@@ -13,6 +13,7 @@ pub mod toolpath;
 pub mod configuration;
 pub mod controls;
 pub mod files;
+pub mod gcode;
 pub mod postprocess;
 pub mod about;
 pub mod code_editor;
@@ -3,27 +3,60 @@ use rfd::AsyncFileDialog;
 use std::future::Future;
 use std::sync::{Arc, Mutex};
 
+use super::gcode::gcode_dialect::{self, Dialect};
+use super::gcode::gcode_emitter;
+use super::gcode::gcode_estimate::{self, Dynamics, Estimate};
+use super::gcode::gcode_interpreter::Interpreter;
+use super::gcode::gcode_lint::Diagnostic;
+use super::gcode::gcode_macro;
+use super::gcode::gcode_parser::{Block, ParseError};
+use super::gcode::gcode_postprocess::{self, Machine, PostprocessError, ToolSetup};
+
 #[derive(Debug)]
 pub struct Postprocess {
     file:  Arc<Mutex<Vec<u8>>>,
-    radio: Enum,
-    string: String,
+    program: Option<Result<Vec<Block>, ParseError>>,
+    /// Firmware the loaded program was written for.
+    source: Dialect,
+    /// Loaded from egui's memory on the first frame.
+    machines: Option<Machines>,
+    /// The program for the selected machine and what its firmware makes of it, computed when shown.
+    output: Option<Result<Processed, PostprocessError>>,
+    dynamics: Dynamics,
+    estimate: Option<Estimate>,
 }
 
-#[derive(Debug, PartialEq)]
+/// A program for one machine and the problems its firmware finds in it.
+type Processed = (Vec<Block>, Vec<Diagnostic>);
+
+/// The machines to post-process for, kept across runs with the `persistence` feature.
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
-enum Enum {
-    First,
-    Second,
-    Third,
+#[cfg_attr(feature = "serde", serde(default))]
+struct Machines {
+    list: Vec<Machine>,
+    selected: usize,
+}
+
+impl Default for Machines {
+    fn default() -> Self {
+        Self {
+            list: vec![Machine::default()],
+            selected: 0,
+        }
+    }
 }
 
 impl Postprocess {
     pub fn new() -> Postprocess {
         Postprocess {
             file: Arc::new(Mutex::new(vec![])),
-            radio: Enum::First,
-            string: Default::default(),
+            program: None,
+            source: Dialect::default(),
+            machines: None,
+            output: None,
+            dynamics: Dynamics::default(),
+            estimate: None,
         }
     }
 }
@@ -32,8 +65,12 @@ impl Default for Postprocess {
     fn default() -> Self {
         Self {
             file: Arc::new(Mutex::new(vec![])),
-            radio: Enum::First,
-            string: Default::default(),
+            program: None,
+            source: Dialect::default(),
+            machines: None,
+            output: None,
+            dynamics: Dynamics::default(),
+            estimate: None,
         }
     }
 }
@@ -59,32 +96,32 @@ impl super::View for Postprocess {
         let ui_open_file = ui
             .button("Open file")
             .on_hover_text("Open gcode file");
-        ui.label("Profile");
-        let ui_profile = egui::ComboBox::from_label("")
-            .selected_text(format!("{:?}", self.radio))
+        ui.label("Written for");
+        egui::ComboBox::from_id_source("postprocess_source")
+            .selected_text(self.source.name())
             .show_ui(ui, |ui| {
                 ui.style_mut().wrap = Some(true);
                 ui.set_min_width(60.0);
-                ui.selectable_value(&mut self.radio, Enum::First, "First");
-                ui.selectable_value(&mut self.radio, Enum::Second, "Second");
-                ui.selectable_value(&mut self.radio, Enum::Third, "Third");
+                for dialect in Dialect::ALL {
+                    if ui
+                        .selectable_value(&mut self.source, dialect, dialect.name())
+                        .changed()
+                    {
+                        self.estimate = None;
+                        self.output = None;
+                    }
+                }
             });
-        ui.label("Tool 1 width");
-        let ui_tool_one_width = egui::TextEdit::singleline(&mut self.string).hint_text("60").show(ui);
-        ui.label("Tool 2 width");
-        let ui_tool_two_width = egui::TextEdit::singleline(&mut self.string).hint_text("60").show(ui);
-        ui.label("Tool 3 width");
-        let ui_tool_three_width = egui::TextEdit::singleline(&mut self.string).hint_text("60").show(ui);
-        ui.label("Tool 4 width");
-        let ui_tool_four_width = egui::TextEdit::singleline(&mut self.string).hint_text("30").show(ui);
-        ui.label("Tool 1 offset");
-        let ui_tool_one_offset = egui::TextEdit::singleline(&mut self.string).hint_text("100").show(ui);
-        ui.label("Tool 2 offset");
-        let ui_tool_two_offset = egui::TextEdit::singleline(&mut self.string).hint_text("200").show(ui);
-        ui.label("Tool 3 offset");
-        let ui_tool_three_offset = egui::TextEdit::singleline(&mut self.string).hint_text("300").show(ui);
-        ui.label("Tool 4 offset");
-        let ui_tool_four_offset = egui::TextEdit::singleline(&mut self.string).hint_text("400").show(ui);
+        let machines_id = Id::new("postprocess_machines");
+        let machines = self.machines.get_or_insert_with(|| {
+            ui.data_mut(|d| d.get_persisted(machines_id))
+                .unwrap_or_default()
+        });
+        if machines_ui(ui, machines) {
+            ui.data_mut(|d| d.insert_persisted(machines_id, machines.clone()));
+            self.estimate = None;
+            self.output = None;
+        }
         let ui_process_file = ui.button("Process and save").on_hover_text("Process the gcode file and save the result");
 
         let file_arc = Arc::clone(&self.file);
@@ -107,14 +144,253 @@ impl super::View for Postprocess {
             execute(filepicker_future);
         }
 
-        if let Ok(file_lock) = self.file.lock() {
+        if let Ok(mut file_lock) = self.file.lock() {
             if !file_lock.is_empty() {
-                //let drawing = Drawing::load(&mut file_lock.as_slice());
+                let text = String::from_utf8_lossy(&std::mem::take(&mut *file_lock)).into_owned();
+                self.program = Some(gcode_macro::expand(&text));
+                self.estimate = None;
+                self.output = None;
+            }
+        }
+
+        ui.collapsing("Machine dynamics", |ui| {
+            if super::gcode::dynamics_ui(ui, &mut self.dynamics) {
+                self.estimate = None;
+            }
+        });
+
+        match &self.program {
+            Some(Ok(blocks)) => {
+                let codes: usize = blocks.iter().map(|block| block.codes.len()).sum();
+                ui.label(format!("{} lines, {} codes", blocks.len(), codes));
+                let dynamics = &self.dynamics;
+                let source = self.source;
+                let estimate = self.estimate.get_or_insert_with(|| {
+                    // The interpreter reads dwells the Marlin way
+                    let (blocks, _) = gcode_dialect::convert(blocks, source, Dialect::Marlin);
+                    gcode_estimate::estimate(&Interpreter::default().run(&blocks), dynamics)
+                });
+                super::gcode::estimate_ui(ui, estimate);
+
+                let machine = &machines.list[machines.selected];
+                let output = self
+                    .output
+                    .get_or_insert_with(|| process(blocks, source, machine));
+                match output {
+                    Ok((processed, problems)) => {
+                        if problems.is_empty() {
+                            ui.label(format!("Runs on {}", machine.dialect));
+                        } else {
+                            ui.label(format!(
+                                "{} problems on {}",
+                                problems.len(),
+                                machine.dialect
+                            ));
+                            ScrollArea::vertical()
+                                .id_source("postprocess_problems")
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    for problem in problems.iter() {
+                                        let color =
+                                            super::gcode::severity_color(ui, problem.severity);
+                                        ui.colored_label(color, problem.to_string());
+                                    }
+                                });
+                        }
+                        if ui_process_file.clicked() {
+                            let text = gcode_emitter::emit_program(processed, &Default::default());
+                            save(format!("{}.gcode", machine.name), text);
+                        }
+                    }
+                    Err(err) => {
+                        ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                    }
+                }
+            }
+            Some(Err(err)) => {
+                ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+            }
+            None => {}
+        }
+    }
+}
+
+/// The program for `machine`, with what converting it changed and what the machine's firmware
+/// still does not accept.
+fn process(
+    blocks: &[Block],
+    source: Dialect,
+    machine: &Machine,
+) -> Result<Processed, PostprocessError> {
+    let (converted, mut problems) = gcode_dialect::convert(blocks, source, machine.dialect);
+    let processed = gcode_postprocess::postprocess(&converted, machine)?;
+    problems.extend(gcode_dialect::validate(&processed, machine.dialect));
+    problems.sort();
+    problems.dedup();
+    Ok((processed, problems))
+}
+
+/// Picks and edits a machine, returning whether anything changed.
+fn machines_ui(ui: &mut Ui, machines: &mut Machines) -> bool {
+    let mut changed = false;
+    if machines.list.is_empty() {
+        machines.list.push(Machine::default());
+    }
+    machines.selected = machines.selected.min(machines.list.len() - 1);
 
+    ui.horizontal(|ui| {
+        ui.label("Machine");
+        egui::ComboBox::from_id_source("postprocess_machine")
+            .selected_text(machines.list[machines.selected].name.as_str())
+            .show_ui(ui, |ui| {
+                for (index, machine) in machines.list.iter().enumerate() {
+                    changed |= ui
+                        .selectable_value(&mut machines.selected, index, machine.name.as_str())
+                        .changed();
+                }
+            });
+        if ui
+            .button("New")
+            .on_hover_text("Copy this machine")
+            .clicked()
+        {
+            let machine = Machine {
+                name: format!("Machine {}", machines.list.len() + 1),
+                ..machines.list[machines.selected].clone()
+            };
+            machines.list.push(machine);
+            machines.selected = machines.list.len() - 1;
+            changed = true;
+        }
+        if ui
+            .add_enabled(machines.list.len() > 1, Button::new("Remove"))
+            .clicked()
+        {
+            machines.list.remove(machines.selected);
+            machines.selected = machines.selected.min(machines.list.len() - 1);
+            changed = true;
+        }
+    });
+
+    let machine = &mut machines.list[machines.selected];
+    Grid::new("postprocess_machine_grid").show(ui, |ui| {
+        ui.label("Name");
+        changed |= ui.text_edit_singleline(&mut machine.name).changed();
+        ui.end_row();
+
+        ui.label("Firmware");
+        egui::ComboBox::from_id_source("postprocess_profile")
+            .selected_text(machine.dialect.name())
+            .show_ui(ui, |ui| {
+                for dialect in Dialect::ALL {
+                    changed |= ui
+                        .selectable_value(&mut machine.dialect, dialect, dialect.name())
+                        .changed();
+                }
+            });
+        ui.end_row();
+    });
+
+    ui.label("Tools");
+    let mut remove = None;
+    Grid::new("postprocess_tools").striped(true).show(ui, |ui| {
+        for heading in ["T", "Width", "X offset", "Y offset", "Z offset"] {
+            ui.label(heading);
+        }
+        ui.end_row();
 
+        for (index, tool) in machine.tools.iter_mut().enumerate() {
+            changed |= ui
+                .add(DragValue::new(&mut tool.number).clamp_range(0..=999))
+                .changed();
+            changed |= ui
+                .add(
+                    DragValue::new(&mut tool.width)
+                        .speed(0.01)
+                        .clamp_range(0.0..=1000.0)
+                        .suffix(" mm"),
+                )
+                .changed();
+            for offset in &mut tool.offset {
+                changed |= ui
+                    .add(DragValue::new(offset).speed(0.1).suffix(" mm"))
+                    .changed();
+            }
+            if ui.button("🗑").on_hover_text("Remove this tool").clicked() {
+                remove = Some(index);
             }
+            ui.end_row();
         }
+    });
+    if let Some(index) = remove {
+        machine.tools.remove(index);
+        changed = true;
+    }
+    if ui.button("Add tool").clicked() {
+        let number = machine.tools.iter().map(|tool| tool.number + 1).max();
+        let width = machine.tools.last().map_or(1.0, |tool| tool.width);
+        machine.tools.push(ToolSetup {
+            number: number.unwrap_or(1),
+            width,
+            offset: [0.0; 3],
+        });
+        changed = true;
+    }
+
+    ui.label("Tool change").on_hover_text(
+        "Runs in place of each T word. {tool}, {width} and {previous} stand for the new tool, \
+         its width and the tool it replaces.",
+    );
+    changed |= ui
+        .add(
+            TextEdit::multiline(&mut machine.tool_change)
+                .code_editor()
+                .desired_rows(4),
+        )
+        .changed();
+    changed
+}
+
+/// Hands the processed program to the user through a save dialog.
+#[cfg(not(target_arch = "wasm32"))]
+fn save(file_name: String, text: String) {
+    execute(async move {
+        let Some(file) = AsyncFileDialog::new()
+            .add_filter("Gcode files (gcode, nc, ngc)", &["gcode", "nc", "ngc"])
+            .set_file_name(&file_name)
+            .save_file()
+            .await
+        else {
+            return;
+        };
+        if let Err(err) = std::fs::write(file.path(), text) {
+            log::error!("Failed to save {}: {}", file.path().display(), err);
+        }
+    });
+}
+
+/// Hands the processed program to the user as a download.
+#[cfg(target_arch = "wasm32")]
+fn save(file_name: String, text: String) {
+    use wasm_bindgen::{JsCast as _, JsValue};
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(&text));
+    let Ok(blob) = web_sys::Blob::new_with_str_sequence(&parts) else {
+        return;
+    };
+    let Ok(url) = web_sys::Url::create_object_url_with_blob(&blob) else {
+        return;
+    };
+    let anchor = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.create_element("a").ok())
+        .and_then(|element| element.dyn_into::<web_sys::HtmlAnchorElement>().ok());
+    if let Some(anchor) = anchor {
+        anchor.set_href(&url);
+        anchor.set_download(&file_name);
+        anchor.click();
     }
+    web_sys::Url::revoke_object_url(&url).ok();
 }
 
 #[cfg(not(target_arch = "wasm32"))]
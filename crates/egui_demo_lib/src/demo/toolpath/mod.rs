@@ -0,0 +1,1079 @@
+use dxf::Drawing;
+use egui::*;
+use plot::{Arrows, Legend, LineStyle, MarkerShape, Plot, PlotPoint, Points, Text};
+use rfd::AsyncFileDialog;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use url::{Url, Host, Position};
+use cavalier_contours::polyline::PlineSource;
+
+use super::gcode::gcode_arc;
+use super::gcode::gcode_compensation;
+use super::gcode::gcode_interpreter::{Interpreter, MoveKind};
+use super::gcode::gcode_macro;
+use super::gcode::GCode;
+use gcode_stream::Sender;
+
+pub mod toolpath_dxf;
+pub mod toolpath_gcode;
+pub mod toolpath_kerf;
+pub mod toolpath_lead;
+pub mod toolpath_nest;
+pub mod toolpath_order;
+pub mod toolpath_pocket;
+pub mod toolpath_sketch;
+pub mod toolpath_svg;
+pub mod toolpath_tabs;
+pub mod toolpath_tree;
+
+use toolpath_gcode::{Beam, Feeds, Laser, Machine, Plasma};
+use toolpath_lead::Leads;
+use toolpath_nest::{Grain, Nest, Rotations};
+use toolpath_pocket::{Direction, Pocket, Strategy};
+use toolpath_sketch::{Contour, Segment, Sketch};
+use toolpath_tabs::{Placement, Tabs};
+use toolpath_tree::Tree;
+
+//use crate::demo::Demo;
+
+/// How far apart two ends of a drawing may be and still join, and how far flattened curves may
+/// stray, in drawing units, which are mm for SVG.
+const IMPORT_TOLERANCE: f64 = 0.001;
+
+/// How far the plotted outlines may stray from the arcs they stand for, in mm.
+const PLOT_TOLERANCE: f64 = 0.01;
+
+/// How near the toolpath a click must land to place or remove a tab, in mm.
+const TAB_PICK_DISTANCE: f64 = 5.0;
+
+/// What Plan does with the closed contours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operation {
+    /// Cut around each contour, outside parts and inside holes.
+    Profile,
+    /// Clear the material inside each contour, leaving islands standing.
+    Pocket,
+}
+
+#[derive(Debug)]
+pub struct Toolpath {
+    /// The loaded drawing in mm, or the toolpath made from it.
+    sketch: Sketch,
+    /// The outline of each contour in `sketch`, arcs flattened for plotting.
+    points_to_plot: Vec<Vec<[f64; 2]>>,
+    /// What the last file held, or why it did not load.
+    import_status: String,
+    /// The size of an SVG pixel, as pixels per inch.
+    svg_dpi: f64,
+    /// How far arcs fitted onto runs of short lines may stray from them, in mm.
+    arc_tolerance: f64,
+    /// What the last arc fitting did.
+    arc_status: String,
+    /// Cutter diameter, or kerf width for lasers and plasma, in mm.
+    tool_diameter: f64,
+    machine: Machine,
+    laser: Laser,
+    plasma: Plasma,
+    /// How lasers and plasma cutters run onto and off each contour.
+    leads: Leads,
+    operation: Operation,
+    feeds: Feeds,
+    pocket: Pocket,
+    tabs: Tabs,
+    /// The middle of each tab on each contour of `toolpath`, as distances along it.
+    tab_middles: Vec<Vec<f64>>,
+    /// Whether clicks on the plot place and remove tabs.
+    placing_tabs: bool,
+    /// Whether rapids keep off parts already cut for as long as they can.
+    avoid_cut_parts: bool,
+    show_cut_order: bool,
+    /// Where each profile cut starts, in the order they are cut.
+    cut_starts: Vec<[f64; 2]>,
+    /// The rapid moves between profile cuts, from the origin on.
+    rapids: Vec<[[f64; 2]; 2]>,
+    nest: Nest,
+    /// The width and height of a rectangular sheet to nest on, in mm.
+    sheet_size: [f64; 2],
+    /// The layer whose largest closed contour is the sheet to nest on, or `None` for a
+    /// rectangle.
+    remnant_layer: Option<String>,
+    /// The drawing as loaded, while `sketch` holds copies of it nested on a sheet.
+    nest_source: Option<Sketch>,
+    /// The outline of the sheet nested on.
+    sheet_points: Vec<[f64; 2]>,
+    /// How well the last nesting went.
+    nest_status: String,
+    /// The path the tool follows, once planned.
+    toolpath: Vec<Contour>,
+    /// The moves that cut `toolpath`, once planned.
+    program: Vec<GCode>,
+    /// The outline of each contour in `toolpath`, arcs flattened for plotting.
+    toolpath_points: Vec<Vec<[f64; 2]>>,
+    /// Each contour of `toolpath` with its leads, arcs flattened for plotting.
+    lead_points: Vec<Vec<[f64; 2]>>,
+    /// What the last plan left out.
+    plan_status: String,
+    cad_file:  Arc<Mutex<Vec<u8>>>,
+    cad_file_name: Arc<Mutex<String>>,
+    /// Progress of the last program sent to the machine.
+    stream_status: Arc<Mutex<String>>,
+}
+
+impl Default for Toolpath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Toolpath {
+    pub fn new() -> Toolpath {
+        Toolpath {
+            sketch: Sketch::default(),
+            points_to_plot: vec![],
+            import_status: String::new(),
+            svg_dpi: toolpath_svg::DEFAULT_DPI,
+            arc_tolerance: 0.01,
+            arc_status: String::new(),
+            tool_diameter: 3.0,
+            machine: Machine::Mill,
+            laser: Laser::default(),
+            plasma: Plasma::default(),
+            leads: Leads::default(),
+            operation: Operation::Profile,
+            feeds: Feeds::default(),
+            pocket: Pocket::default(),
+            tabs: Tabs::default(),
+            tab_middles: vec![],
+            placing_tabs: false,
+            avoid_cut_parts: false,
+            show_cut_order: true,
+            cut_starts: vec![],
+            rapids: vec![],
+            nest: Nest::default(),
+            sheet_size: [1000.0, 500.0],
+            remnant_layer: None,
+            nest_source: None,
+            sheet_points: vec![],
+            nest_status: String::new(),
+            toolpath: vec![],
+            program: vec![],
+            toolpath_points: vec![],
+            lead_points: vec![],
+            plan_status: String::new(),
+            cad_file: Arc::new(Mutex::new(vec![])),
+            cad_file_name: Arc::new(Mutex::new(String::new())),
+            stream_status: Arc::new(Mutex::new(String::new())),
+        }
+    }
+
+    fn set_sketch(&mut self, sketch: Sketch) {
+        self.points_to_plot = sketch
+            .contours
+            .iter()
+            .map(|contour| toolpath_sketch::points(&contour.polyline, PLOT_TOLERANCE))
+            .collect();
+        self.sketch = sketch;
+        self.set_toolpath(vec![]);
+        self.program.clear();
+        self.lead_points.clear();
+        self.cut_starts.clear();
+        self.rapids.clear();
+    }
+
+    fn set_toolpath(&mut self, toolpath: Vec<Contour>) {
+        self.toolpath_points = toolpath
+            .iter()
+            .map(|contour| toolpath_sketch::points(&contour.polyline, PLOT_TOLERANCE))
+            .collect();
+        self.tab_middles = vec![vec![]; toolpath.len()];
+        self.toolpath = toolpath;
+    }
+
+    /// Whether beams run onto and off the contours they cut around.
+    fn has_leads(&self) -> bool {
+        self.machine.is_beam() && self.operation == Operation::Profile
+    }
+
+    /// Makes the program that cuts the toolpath, leaving the tabs.
+    fn set_program(&mut self) {
+        let tree = Tree::new(&self.toolpath, PLOT_TOLERANCE);
+        let mut paths = vec![];
+        let mut tabs = vec![];
+        for (index, (contour, middles)) in self.toolpath.iter().zip(&self.tab_middles).enumerate() {
+            let spans = toolpath_tabs::spans(&contour.polyline, middles, self.tabs.width);
+            if self.has_leads() {
+                let led = toolpath_lead::leads(&contour.polyline, tree.is_hole(index), &self.leads, PLOT_TOLERANCE);
+                let shift = led.lead_in_length();
+                paths.push(led.path(&contour.polyline));
+                tabs.push(spans.iter().map(|span| [span[0] + shift, span[1] + shift]).collect());
+            } else {
+                paths.push(contour.polyline.clone());
+                tabs.push(spans);
+            }
+        }
+        self.lead_points = if self.has_leads() {
+            paths.iter().map(|path| toolpath_sketch::points(path, PLOT_TOLERANCE)).collect()
+        } else {
+            vec![]
+        };
+
+        // Pocket passes keep the order they were planned in; profiles go inside out.
+        self.cut_starts.clear();
+        self.rapids.clear();
+        if self.operation == Operation::Profile {
+            let contours: Vec<_> = self.toolpath.iter().map(|contour| contour.polyline.clone()).collect();
+            let jobs = toolpath_order::jobs(&contours, &paths, &tree, PLOT_TOLERANCE);
+            let order = toolpath_order::order(&jobs, [0.0, 0.0], self.avoid_cut_parts);
+            paths = order.iter().map(|&index| paths[index].clone()).collect();
+            tabs = order.iter().map(|&index| tabs[index].clone()).collect();
+            let mut position = [0.0, 0.0];
+            for path in &paths {
+                let points = toolpath_sketch::points(path, PLOT_TOLERANCE);
+                let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+                    continue;
+                };
+                self.cut_starts.push(first);
+                self.rapids.push([position, first]);
+                position = last;
+            }
+        }
+
+        let beam = match self.machine {
+            Machine::Mill => Beam::default(),
+            Machine::Laser => self.laser.into(),
+            Machine::Plasma => self.plasma.into(),
+        };
+        let cuts = toolpath_gcode::cuts(&paths, &tabs, self.tabs.height, &self.feeds, self.machine);
+        self.program = toolpath_gcode::program(&cuts, &self.feeds, self.machine, &beam);
+    }
+
+    /// Spreads tabs around every closed contour of the toolpath that goes around a part. Holes
+    /// fall out as scrap, so they get none.
+    fn place_tabs(&mut self) {
+        let tree = Tree::new(&self.toolpath, PLOT_TOLERANCE);
+        for (index, contour) in self.toolpath.iter().enumerate() {
+            self.tab_middles[index] = if tree.depths[index].is_some() && !tree.is_hole(index) {
+                toolpath_tabs::place(&contour.polyline, self.tabs.placement)
+            } else {
+                vec![]
+            };
+        }
+        self.set_program();
+    }
+
+    /// Lays out copies of the parts of the loaded drawing on the sheet, and puts them in its
+    /// place.
+    fn nest(&mut self) {
+        let source = self.nest_source.take().unwrap_or_else(|| self.sketch.clone());
+        let (sheet, contours) = match &self.remnant_layer {
+            Some(layer) => {
+                let (remnant, contours): (Vec<Contour>, Vec<Contour>) =
+                    source.contours.iter().cloned().partition(|contour| contour.layer == *layer);
+                let sheet = remnant
+                    .iter()
+                    .filter(|contour| contour.polyline.is_closed)
+                    .max_by(|a, b| {
+                        toolpath_sketch::area(&a.polyline).abs().total_cmp(&toolpath_sketch::area(&b.polyline).abs())
+                    })
+                    .map(|contour| toolpath_sketch::points(&contour.polyline, PLOT_TOLERANCE))
+                    .unwrap_or_default();
+                (sheet, contours)
+            }
+            None => (toolpath_nest::rectangle(self.sheet_size[0], self.sheet_size[1]), source.contours.clone()),
+        };
+        let tree = Tree::new(&contours, PLOT_TOLERANCE);
+        let (parts, loose) = toolpath_nest::parts(&contours, &tree, self.nest.spacing, PLOT_TOLERANCE);
+        let nesting = toolpath_nest::nest(&parts, &sheet, &self.nest);
+        self.nest_status = format!(
+            "{} of {} copies placed, {:.1}% of the sheet used",
+            nesting.placements.len(),
+            nesting.placements.len() + nesting.unplaced,
+            nesting.utilization * 100.0,
+        );
+        if loose > 0 {
+            self.nest_status += &format!(", {} open contours outside any part left out", loose);
+        }
+        let contours = toolpath_nest::contours(&parts, &nesting, IMPORT_TOLERANCE);
+        self.set_sketch(Sketch { contours, units: source.units });
+        self.nest_source = Some(source);
+        self.sheet_points = sheet;
+    }
+
+    /// Removes the tab nearest a clicked point, or adds one on the nearest contour.
+    fn toggle_tab(&mut self, point: [f64; 2]) {
+        let nearest = self
+            .toolpath
+            .iter()
+            .enumerate()
+            .filter_map(|(index, contour)| {
+                let (along, off) = toolpath_tabs::nearest(&contour.polyline, point, PLOT_TOLERANCE)?;
+                Some((index, along, off))
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((index, along, off)) = nearest else {
+            return;
+        };
+        if off > TAB_PICK_DISTANCE {
+            return;
+        }
+        let middles = &mut self.tab_middles[index];
+        match middles.iter().position(|middle| (middle - along).abs() <= self.tabs.width / 2.0) {
+            Some(tab) => {
+                middles.remove(tab);
+            }
+            None => middles.push(along),
+        }
+        self.set_program();
+    }
+}
+
+impl super::Demo for Toolpath {
+    fn name(&self) -> &'static str {
+        "🗠 2D vector view"
+    }
+
+    fn show(&mut self, ctx: &Context, open: &mut bool) {
+        use super::View as _;
+        Window::new(self.name())
+            .open(open)
+            .default_size(vec2(1200.0, 800.0))
+            .vscroll(false)
+            .show(ctx, |ui| self.ui(ui));
+    }
+}
+
+impl super::View for Toolpath {
+    #[allow(clippy::unused_self)]
+    fn ui(&mut self, ui: &mut Ui) {
+        let ui_open_file = ui.button("Open file").on_hover_text("SVG, DXF and G-code are supported");
+        ui.horizontal(|ui| {
+            ui.label("SVG pixels per inch:");
+            ui.add(DragValue::new(&mut self.svg_dpi).clamp_range(1.0..=2400.0))
+                .on_hover_text("Inkscape and browsers use 96, older Illustrator files 72");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Tool diameter:");
+            ui.add(
+                DragValue::new(&mut self.tool_diameter)
+                    .clamp_range(0.0..=100.0)
+                    .speed(0.01)
+                    .suffix(" mm"),
+            )
+            .on_hover_text("Cutter diameter, or kerf width for lasers and plasma");
+        });
+        ui.horizontal(|ui| {
+            ComboBox::from_label("Machine")
+                .selected_text(match self.machine {
+                    Machine::Mill => "Mill",
+                    Machine::Laser => "Laser",
+                    Machine::Plasma => "Plasma",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.machine, Machine::Mill, "Mill");
+                    ui.selectable_value(&mut self.machine, Machine::Laser, "Laser");
+                    ui.selectable_value(&mut self.machine, Machine::Plasma, "Plasma");
+                });
+            ComboBox::from_label("Operation")
+                .selected_text(match self.operation {
+                    Operation::Profile => "Profile",
+                    Operation::Pocket => "Pocket",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.operation, Operation::Profile, "Profile");
+                    ui.selectable_value(&mut self.operation, Operation::Pocket, "Pocket");
+                });
+            ui.label("Depth:");
+            ui.add(DragValue::new(&mut self.feeds.depth).clamp_range(0.0..=200.0).speed(0.01).suffix(" mm"));
+            ui.label("Step-down:");
+            ui.add(DragValue::new(&mut self.feeds.step_down).clamp_range(0.01..=200.0).speed(0.01).suffix(" mm"))
+                .on_hover_text("How much deeper each pass cuts");
+            ui.label("Safe Z:");
+            ui.add(DragValue::new(&mut self.feeds.safe_z).clamp_range(0.0..=200.0).speed(0.1).suffix(" mm"));
+            ui.label("Feed:");
+            ui.add(DragValue::new(&mut self.feeds.feed).clamp_range(1.0..=20000.0).suffix(" mm/min"));
+            ui.label("Plunge:");
+            ui.add(DragValue::new(&mut self.feeds.plunge_feed).clamp_range(1.0..=20000.0).suffix(" mm/min"));
+            if self.machine == Machine::Mill {
+                ui.label("Spindle:");
+                ui.add(DragValue::new(&mut self.feeds.spindle_speed).clamp_range(0..=60000).speed(10.0).suffix(" rpm"));
+            }
+        });
+        if self.operation == Operation::Pocket {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.pocket.strategy, Strategy::Offset, "Offset")
+                    .on_hover_text("Loops parallel to the walls");
+                ui.radio_value(&mut self.pocket.strategy, Strategy::Zigzag, "Zigzag")
+                    .on_hover_text("Rows joined end to end");
+                ui.radio_value(&mut self.pocket.strategy, Strategy::Raster, "Raster")
+                    .on_hover_text("Rows all cut the same way");
+                ui.separator();
+                ui.radio_value(&mut self.pocket.direction, Direction::Climb, "Climb");
+                ui.radio_value(&mut self.pocket.direction, Direction::Conventional, "Conventional");
+                ui.separator();
+                ui.label("Stepover:");
+                ui.add(DragValue::new(&mut self.pocket.stepover).clamp_range(0.01..=100.0).speed(0.01).suffix(" mm"))
+                    .on_hover_text("How far apart neighboring passes are");
+            });
+        }
+        match self.machine {
+            Machine::Mill => {}
+            Machine::Laser => {
+                ui.horizontal(|ui| {
+                    ui.label("Laser power:");
+                    ui.add(DragValue::new(&mut self.laser.power).clamp_range(0.0..=100000.0));
+                    ui.label("Min:");
+                    ui.add(DragValue::new(&mut self.laser.min_power).clamp_range(0.0..=100000.0));
+                    ui.label("Max:");
+                    ui.add(DragValue::new(&mut self.laser.max_power).clamp_range(0.0..=100000.0));
+                });
+            }
+            Machine::Plasma => {
+                ui.horizontal(|ui| {
+                    ui.label("Plasma power:");
+                    ui.add(DragValue::new(&mut self.plasma.power).clamp_range(0.0..=100000.0));
+                    ui.label("Min:");
+                    ui.add(DragValue::new(&mut self.plasma.min_power).clamp_range(0.0..=100000.0));
+                    ui.label("Max:");
+                    ui.add(DragValue::new(&mut self.plasma.max_power).clamp_range(0.0..=100000.0));
+                    ui.label("Pierce delay:");
+                    ui.add(DragValue::new(&mut self.plasma.pierce_delay).clamp_range(0.0..=60.0).speed(0.01).suffix(" s"));
+                    ui.label("Cut height:");
+                    ui.add(DragValue::new(&mut self.plasma.cut_height).clamp_range(0.0..=50.0).speed(0.01).suffix(" mm"));
+                    ui.label("Hop:");
+                    ui.add(DragValue::new(&mut self.plasma.hop_distance).clamp_range(0.0..=100.0).speed(0.1).suffix(" mm"))
+                        .on_hover_text("How far the torch rises above the cut height between cuts");
+                });
+            }
+        }
+        if self.has_leads() {
+            ui.horizontal(|ui| {
+                ui.label("Leads:");
+                ui.radio_value(&mut self.leads.style, toolpath_lead::Style::Arc, "Arc");
+                ui.radio_value(&mut self.leads.style, toolpath_lead::Style::Line, "Line");
+                ui.label("In:");
+                ui.add(DragValue::new(&mut self.leads.lead_in).clamp_range(0.0..=100.0).speed(0.1).suffix(" mm"));
+                ui.label("Out:");
+                ui.add(DragValue::new(&mut self.leads.lead_out).clamp_range(0.0..=100.0).speed(0.1).suffix(" mm"));
+                ui.label("Overcut:");
+                ui.add(DragValue::new(&mut self.leads.overcut).clamp_range(0.0..=100.0).speed(0.1).suffix(" mm"));
+                ui.label("Sharp corner:");
+                ui.add(DragValue::new(&mut self.leads.corner_angle).clamp_range(0.0..=180.0).suffix("°"))
+                    .on_hover_text("Pierces land away from corners turning more than this");
+            });
+        }
+        let mut ui_place_tabs = None;
+        let mut ui_clear_tabs = None;
+        if self.operation == Operation::Profile {
+            ui.horizontal(|ui| {
+                ui.label("Tabs:");
+                if ui.radio(matches!(self.tabs.placement, Placement::Count(_)), "Count").clicked() {
+                    self.tabs.placement = Placement::Count(4);
+                }
+                if ui.radio(matches!(self.tabs.placement, Placement::Spacing(_)), "Spacing").clicked() {
+                    self.tabs.placement = Placement::Spacing(100.0);
+                }
+                match &mut self.tabs.placement {
+                    Placement::Count(count) => {
+                        ui.add(DragValue::new(count).clamp_range(1..=100).suffix(" per part"));
+                    }
+                    Placement::Spacing(spacing) => {
+                        ui.add(DragValue::new(spacing).clamp_range(1.0..=10000.0).suffix(" mm"));
+                    }
+                }
+                ui.label("Width:");
+                ui.add(DragValue::new(&mut self.tabs.width).clamp_range(0.1..=100.0).speed(0.1).suffix(" mm"));
+                if !self.machine.is_beam() {
+                    ui.label("Height:");
+                    ui.add(DragValue::new(&mut self.tabs.height).clamp_range(0.0..=200.0).speed(0.01).suffix(" mm"))
+                        .on_hover_text("How much material the mill leaves under each tab");
+                }
+                ui_place_tabs = Some(ui.button("Place tabs").on_hover_text("Spread tabs around every part"));
+                ui_clear_tabs = Some(ui.button("Clear tabs"));
+                ui.checkbox(&mut self.placing_tabs, "Click to place")
+                    .on_hover_text("Click on the toolpath to add a tab, or on a tab to remove it");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Cut order:");
+                let avoid = ui
+                    .checkbox(&mut self.avoid_cut_parts, "Avoid cut parts")
+                    .on_hover_text("Keep rapids off parts already cut, so a plasma torch does not catch on one that has tipped");
+                if avoid.changed() && !self.toolpath.is_empty() {
+                    self.set_program();
+                }
+                ui.checkbox(&mut self.show_cut_order, "Show");
+            });
+        }
+        let mut ui_nest = None;
+        let mut ui_unnest = None;
+        ui.horizontal(|ui| {
+            ui.label("Nest:");
+            let layers = self.nest_source.as_ref().unwrap_or(&self.sketch).layers();
+            ComboBox::from_label("Sheet")
+                .selected_text(self.remnant_layer.as_deref().unwrap_or("Rectangle"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.remnant_layer, None, "Rectangle");
+                    for layer in layers.iter().filter(|layer| !layer.is_empty()) {
+                        ui.selectable_value(&mut self.remnant_layer, Some((*layer).to_owned()), *layer);
+                    }
+                })
+                .response
+                .on_hover_text("A rectangle, or the largest closed contour on a layer for a remnant");
+            if self.remnant_layer.is_none() {
+                ui.add(DragValue::new(&mut self.sheet_size[0]).clamp_range(1.0..=100000.0).prefix("W: ").suffix(" mm"));
+                ui.add(DragValue::new(&mut self.sheet_size[1]).clamp_range(1.0..=100000.0).prefix("H: ").suffix(" mm"));
+            }
+            ui.label("Spacing:");
+            ui.add(DragValue::new(&mut self.nest.spacing).clamp_range(0.0..=100.0).speed(0.1).suffix(" mm"))
+                .on_hover_text("The gap between parts, and between parts and the edge of the sheet");
+            ui.label("Copies:");
+            ui.add(DragValue::new(&mut self.nest.copies).clamp_range(1..=200));
+            ComboBox::from_label("Rotations")
+                .selected_text(match self.nest.rotations {
+                    Rotations::None => "None",
+                    Rotations::Half => "180°",
+                    Rotations::Quarter => "90°",
+                    Rotations::Step(_) => "Step",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.nest.rotations, Rotations::None, "None");
+                    ui.selectable_value(&mut self.nest.rotations, Rotations::Half, "180°");
+                    ui.selectable_value(&mut self.nest.rotations, Rotations::Quarter, "90°");
+                    if ui.selectable_label(matches!(self.nest.rotations, Rotations::Step(_)), "Step").clicked() {
+                        self.nest.rotations = Rotations::Step(15.0);
+                    }
+                });
+            if let Rotations::Step(step) = &mut self.nest.rotations {
+                ui.add(DragValue::new(step).clamp_range(1.0..=180.0).suffix("°"));
+            }
+            ComboBox::from_label("Grain")
+                .selected_text(match self.nest.grain {
+                    Grain::Free => "Free",
+                    Grain::AlongX => "Along X",
+                    Grain::AlongY => "Along Y",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.nest.grain, Grain::Free, "Free");
+                    ui.selectable_value(&mut self.nest.grain, Grain::AlongX, "Along X");
+                    ui.selectable_value(&mut self.nest.grain, Grain::AlongY, "Along Y");
+                })
+                .response
+                .on_hover_text("Which way the sheet's grain runs through the parts, as drawn along X");
+            ui_nest = Some(ui.button("Nest").on_hover_text("Lay out copies of the parts on the sheet"));
+            ui_unnest = Some(ui.add_enabled(self.nest_source.is_some(), Button::new("Original")));
+        });
+        let ui_fit_arcs = ui
+            .horizontal(|ui| {
+                let button = ui.button("Fit arcs").on_hover_text("Turn runs of short lines in the drawing into arcs");
+                ui.label("Tolerance:");
+                ui.add(DragValue::new(&mut self.arc_tolerance).clamp_range(0.001..=1.0).speed(0.001).suffix(" mm"));
+                button
+            })
+            .inner;
+        let ui_toolpath_shrink = ui.button("Shrink").on_hover_text("Shrink the drawing by the tool radius");
+        let ui_toolpath_grow = ui.button("Grow").on_hover_text("Grow the drawing by the tool radius");
+        let ui_toolpath_status_on = ui.button("On").on_hover_text("Turn the status light on");
+        let ui_toolpath_status_off = ui.button("Off").on_hover_text("Turn the status light off");
+        let ui_toolpath_send = ui.button("Send").on_hover_text("Send the planned program, or else the geometry, to the machine");
+        let ui_toolpath_plan = ui.button("Plan").on_hover_text("Plan toolpath and display it");
+        let ui_toolpath_relay_on = ui.button("Relay on").on_hover_text("Turn the relay on");
+        let ui_toolpath_relay_off = ui.button("Relay off").on_hover_text("Turn the relay off");
+
+        let layers = self.sketch.layers();
+        let tab_points: Vec<[f64; 2]> = self
+            .toolpath
+            .iter()
+            .zip(&self.tab_middles)
+            .flat_map(|(contour, middles)| {
+                middles.iter().filter_map(|&along| toolpath_tabs::point_at(&contour.polyline, along))
+            })
+            .collect();
+        let plot = Plot::new("Geometry")
+            .height(700.0)
+            .allow_scroll(false)
+            .allow_drag(!self.placing_tabs)
+            .legend(Legend::default());
+        let clicked = plot.show(ui, |plot_ui| {
+            if !self.sheet_points.is_empty() {
+                plot_ui.line(
+                    egui::widgets::plot::Line::new(self.sheet_points.clone())
+                        .color(Color32::GRAY)
+                        .name("Sheet"),
+                );
+            }
+            for (contour, points) in self.sketch.contours.iter().zip(&self.points_to_plot) {
+                let layer = layers.iter().position(|layer| *layer == contour.layer).unwrap_or(0);
+                plot_ui.line(
+                    egui::widgets::plot::Line::new(points.clone())
+                        .color(layer_color(layer))
+                        .name(&contour.layer),
+                );
+            }
+            for points in &self.toolpath_points {
+                plot_ui.line(
+                    egui::widgets::plot::Line::new(points.clone())
+                        .color(Color32::GOLD)
+                        .style(LineStyle::dashed_dense())
+                        .name("Toolpath"),
+                );
+            }
+            for points in &self.lead_points {
+                plot_ui.line(
+                    egui::widgets::plot::Line::new(points.clone())
+                        .color(Color32::LIGHT_RED)
+                        .name("Leads"),
+                );
+            }
+            if self.show_cut_order && !self.rapids.is_empty() {
+                plot_ui.arrows(
+                    Arrows::new(
+                        self.rapids.iter().map(|rapid| rapid[0]).collect::<Vec<_>>(),
+                        self.rapids.iter().map(|rapid| rapid[1]).collect::<Vec<_>>(),
+                    )
+                    .color(Color32::GRAY)
+                    .name("Cut order"),
+                );
+                for (number, start) in self.cut_starts.iter().enumerate() {
+                    plot_ui.text(
+                        Text::new(PlotPoint::new(start[0], start[1]), (number + 1).to_string())
+                            .color(Color32::WHITE)
+                            .name("Cut order"),
+                    );
+                }
+            }
+            if !tab_points.is_empty() {
+                plot_ui.points(
+                    Points::new(tab_points)
+                        .shape(MarkerShape::Square)
+                        .radius(4.0)
+                        .color(Color32::RED)
+                        .name("Tabs"),
+                );
+            }
+            plot_ui.plot_clicked().then(|| plot_ui.pointer_coordinate()).flatten()
+        }).inner;
+
+        if let (true, Some(point)) = (self.placing_tabs, clicked) {
+            self.toggle_tab([point.x, point.y]);
+        }
+
+        if ui_place_tabs.map_or(false, |button| button.clicked()) {
+            self.place_tabs();
+        }
+
+        if ui_clear_tabs.map_or(false, |button| button.clicked()) {
+            self.tab_middles.iter_mut().for_each(Vec::clear);
+            self.set_program();
+        }
+
+        if ui_nest.map_or(false, |button| button.clicked()) {
+            self.nest();
+        }
+
+        if ui_unnest.map_or(false, |button| button.clicked()) {
+            if let Some(source) = self.nest_source.take() {
+                self.set_sketch(source);
+                self.sheet_points.clear();
+                self.nest_status.clear();
+            }
+        }
+
+        if !self.nest_status.is_empty() {
+            ui.label(self.nest_status.as_str());
+        }
+
+        let offset = self.tool_diameter / 2.0;
+
+        let cad_file_arc = Arc::clone(&self.cad_file);
+        let cad_file_name_arc = Arc::clone(&self.cad_file_name);
+
+        let filepicker_future = async move {
+            let filepicker = AsyncFileDialog::new()
+                .add_filter(
+                    "Cut files (zip, gcode, nc, ngc, svg, dxf)",
+                    &["zip", "gcode", "nc", "ngc", "svg", "dxf"],
+                )
+                .pick_file()
+                .await
+                .expect("no file has been selected");
+
+            *cad_file_name_arc.lock().unwrap() = filepicker.file_name();
+            let mut cad_file = cad_file_arc.lock().unwrap();
+            *cad_file = filepicker.read().await;
+        };
+
+        if ui_toolpath_shrink.clicked() {
+            let contours = shrink_toolpath(&self.sketch.contours, offset);
+            self.set_sketch(Sketch { contours, ..self.sketch });
+        }
+
+        if ui_toolpath_grow.clicked() {
+            let contours = grow_toolpath(&self.sketch.contours, offset);
+            self.set_sketch(Sketch { contours, ..self.sketch });
+        }
+
+        if ui_fit_arcs.clicked() {
+            let count = |contours: &[Contour]| -> usize {
+                contours.iter().map(|contour| toolpath_sketch::segments(&contour.polyline).len()).sum()
+            };
+            let contours: Vec<Contour> = self
+                .sketch
+                .contours
+                .iter()
+                .map(|contour| Contour {
+                    layer: contour.layer.clone(),
+                    polyline: toolpath_sketch::fit_arcs(&contour.polyline, self.arc_tolerance),
+                })
+                .collect();
+            self.arc_status = format!("{} segments became {}", count(&self.sketch.contours), count(&contours));
+            self.set_sketch(Sketch { contours, ..self.sketch });
+        }
+
+        if !self.arc_status.is_empty() {
+            ui.label(self.arc_status.as_str());
+        }
+
+        if ui_toolpath_status_on.clicked() {
+            execute(status_on());
+        }
+
+        if ui_toolpath_status_off.clicked() {
+            execute(status_off());
+        }
+
+        if ui_toolpath_relay_on.clicked() {
+            execute(relay_on());
+        }
+
+        if ui_toolpath_relay_off.clicked() {
+            execute(relay_off());
+        }
+
+        if ui_toolpath_send.clicked() && !self.program.is_empty() {
+            let lines = self.program.iter().map(ToString::to_string).collect();
+            execute(stream_program(lines, Arc::clone(&self.stream_status)));
+        } else if ui_toolpath_send.clicked() {
+            let lines = self.points_to_plot.iter().flatten().map(|point| {
+                GCode::G0 {
+                    x: Some(point[0] as f32),
+                    y: Some(point[1] as f32),
+                    z: Some(0.0),
+                    e: Some(0.0),
+                    f: Some(100.0),
+                }
+                .to_string()
+            }).collect();
+            execute(stream_program(lines, Arc::clone(&self.stream_status)));
+        }
+
+        if let Ok(status) = self.stream_status.lock() {
+            if !status.is_empty() {
+                ui.label(status.as_str());
+            }
+        }
+
+        if !self.import_status.is_empty() {
+            ui.label(self.import_status.as_str());
+        }
+
+        if ui_toolpath_plan.clicked() {
+            let tree = Tree::new(&self.sketch.contours, PLOT_TOLERANCE);
+            let toolpath = match self.operation {
+                Operation::Profile => {
+                    let compensation = toolpath_kerf::compensate(&self.sketch.contours, &tree, offset);
+                    self.plan_status = if compensation.too_small.is_empty() {
+                        String::new()
+                    } else {
+                        format!("{} holes are too small for the tool", compensation.too_small.len())
+                    };
+                    let mut contours = compensation.contours;
+                    if self.has_leads() {
+                        for contour in contours.iter_mut().filter(|contour| contour.polyline.is_closed) {
+                            let along = toolpath_lead::pierce_point(&contour.polyline, self.leads.corner_angle);
+                            contour.polyline = toolpath_lead::starting_at(&contour.polyline, along);
+                        }
+                    }
+                    contours
+                }
+                Operation::Pocket => {
+                    let paths =
+                        toolpath_pocket::pocket(&self.sketch.contours, &tree, offset, &self.pocket, PLOT_TOLERANCE);
+                    self.plan_status = if paths.is_empty() {
+                        "No closed contours the tool fits inside".to_owned()
+                    } else {
+                        String::new()
+                    };
+                    paths
+                        .into_iter()
+                        .map(|polyline| Contour { layer: String::new(), polyline })
+                        .collect()
+                }
+            };
+            self.set_toolpath(toolpath);
+            self.set_program();
+        }
+
+        if !self.plan_status.is_empty() {
+            ui.label(self.plan_status.as_str());
+        }
+
+        if ui_open_file.clicked() {
+            execute(filepicker_future);
+        }
+
+        let name = self
+            .cad_file_name
+            .lock()
+            .map(|name| name.to_lowercase())
+            .unwrap_or_default();
+        let is_gcode = name.ends_with(".gcode") || name.ends_with(".nc") || name.ends_with(".ngc");
+
+        let cad_file = self
+            .cad_file
+            .lock()
+            .map(|mut file| std::mem::take(&mut *file))
+            .unwrap_or_default();
+        if !cad_file.is_empty() {
+            let sketch = if is_gcode {
+                gcode_sketch(&String::from_utf8_lossy(&cad_file))
+            } else if name.ends_with(".svg") {
+                toolpath_svg::import(&String::from_utf8_lossy(&cad_file), self.svg_dpi, IMPORT_TOLERANCE)
+                    .map_err(|err| err.to_string())
+            } else {
+                dxf_sketch(&cad_file)
+            };
+            match sketch {
+                Ok(mut sketch) => {
+                    self.import_status = if is_gcode {
+                        String::new()
+                    } else {
+                        import_summary(&sketch)
+                    };
+                    sketch.to_millimeters();
+                    self.nest_source = None;
+                    self.sheet_points.clear();
+                    self.nest_status.clear();
+                    self.arc_status.clear();
+                    self.set_sketch(sketch);
+                }
+                Err(err) => self.import_status = format!("Error: the file could not be loaded: {}", err),
+            }
+        }
+    }
+}
+
+/// How many contours a drawing holds, on which layers and in what units.
+fn import_summary(sketch: &Sketch) -> String {
+    let layers: Vec<&str> = sketch.layers().into_iter().filter(|layer| !layer.is_empty()).collect();
+    if layers.is_empty() {
+        format!("{} contours ({})", sketch.contours.len(), sketch.units)
+    } else {
+        format!(
+            "{} contours on layers {} ({})",
+            sketch.contours.len(),
+            layers.join(", "),
+            sketch.units,
+        )
+    }
+}
+
+/// A color per layer, spread around the hue wheel.
+fn layer_color(layer: usize) -> Color32 {
+    let hue = (layer as f32 * 0.618_034).fract();
+    ecolor::Hsva::new(hue, 0.8, 0.9, 1.0).into()
+}
+
+async fn status_on() -> () {
+    // Replace with your actual endpoint
+    let url = "http://alumina/queue";
+
+    // Define the plain text data to send (adjust as needed)
+    let data = "status_on";
+
+    // Make the POST request
+    let client = reqwest::Client::new();
+    let response = client.post(url).body(data).send().await;
+}
+
+async fn status_off() -> () {
+    // Replace with your actual endpoint
+    let url = "http://alumina/queue";
+
+    // Define the plain text data to send (adjust as needed)
+    let data = "status_off";
+
+    // Make the POST request
+    let client = reqwest::Client::new();
+    let response = client.post(url).body(data).send().await;
+}
+
+async fn relay_on() -> () {
+    // Replace with your actual endpoint
+    let url = "http://alumina/queue";
+
+    // Define the plain text data to send (adjust as needed)
+    let data = "relay_on";
+
+    // Make the POST request
+    let client = reqwest::Client::new();
+    let response = client.post(url).body(data).send().await;
+}
+
+async fn relay_off() -> () {
+    // Replace with your actual endpoint
+    let url = "http://alumina/queue";
+
+    // Define the plain text data to send (adjust as needed)
+    let data = "relay_off";
+
+    // Make the POST request
+    let client = reqwest::Client::new();
+    let response = client.post(url).body(data).send().await;
+}
+
+
+/// Failed requests in a row before giving up on the machine.
+const MAX_STREAM_FAILURES: usize = 10;
+
+/// First and longest wait between status polls while the planner is full, in milliseconds.
+const MIN_POLL_DELAY: i32 = 20;
+const MAX_POLL_DELAY: i32 = 500;
+
+/// Streams lines to the machine's queue in order, resending whatever gets lost, and keeps
+/// `status` up to date for the UI.
+async fn stream_program(lines: Vec<String>, status: Arc<Mutex<String>>) {
+    let url = "http://alumina/queue";
+    let client = reqwest::Client::new();
+    let mut sender = Sender::new(lines);
+    let mut failures = 0;
+    let mut poll_delay = MIN_POLL_DELAY;
+
+    let set_status = |text: String| {
+        if let Ok(mut status) = status.lock() {
+            *status = text;
+        }
+    };
+
+    while !sender.is_done() {
+        // With no room in the planner, poll until lines have run, backing off while they don't.
+        let request = match sender.next_request() {
+            Some(body) => {
+                poll_delay = MIN_POLL_DELAY;
+                client.post(url).body(body)
+            }
+            None => {
+                pause(poll_delay).await;
+                poll_delay = (poll_delay * 2).min(MAX_POLL_DELAY);
+                client.get(url)
+            }
+        };
+        let reply = match request.send().await {
+            Ok(response) => response.text().await.ok().and_then(|text| text.trim().parse().ok()),
+            Err(_) => None,
+        };
+        match reply {
+            Some(reply) => {
+                failures = 0;
+                if let Err(err) = sender.handle_reply(reply) {
+                    set_status(format!("Error: {}", err));
+                    return;
+                }
+            }
+            None => {
+                failures += 1;
+                if failures == MAX_STREAM_FAILURES {
+                    set_status("Error: the machine stopped answering".to_owned());
+                    return;
+                }
+                sender.handle_failure();
+            }
+        }
+        let (sent, total) = sender.progress();
+        set_status(format!("Sent {} of {} lines", sent, total));
+    }
+}
+
+/// The path a G-code program cuts, as a single open contour.
+fn gcode_sketch(text: &str) -> Result<Sketch, String> {
+    let points = gcode_points(text)?;
+    let segments: Vec<Segment> = points
+        .windows(2)
+        .map(|pair| Segment::line(pair[0], pair[1]))
+        .collect();
+    let contours = if segments.is_empty() {
+        vec![]
+    } else {
+        vec![Contour {
+            layer: String::new(),
+            polyline: toolpath_sketch::polyline(&segments, false),
+        }]
+    };
+    Ok(Sketch {
+        contours,
+        units: toolpath_sketch::Units::Millimeters,
+    })
+}
+
+fn dxf_sketch(bytes: &[u8]) -> Result<Sketch, String> {
+    let drawing = Drawing::load(&mut &bytes[..]).map_err(|err| err.to_string())?;
+    Ok(toolpath_dxf::import(&drawing, IMPORT_TOLERANCE))
+}
+
+/// The path a G-code program cuts, cutter compensation applied and arcs split into segments no
+/// more than 0.01 mm off.
+fn gcode_points(text: &str) -> Result<Vec<[f64; 2]>, String> {
+    let blocks = gcode_macro::expand(text).map_err(|err| err.to_string())?;
+    let moves = Interpreter::default().run(&blocks);
+    let moves = gcode_compensation::compensate(&moves).map_err(|err| err.to_string())?;
+    let moves = gcode_arc::linearize(&moves, 0.01);
+    let mut points = vec![];
+    for motion in moves.iter().filter(|motion| !matches!(motion.kind, MoveKind::Dwell { .. })) {
+        if points.is_empty() {
+            points.push([motion.start[0] as f64, motion.start[1] as f64]);
+        }
+        points.push([motion.end[0] as f64, motion.end[1] as f64]);
+    }
+    Ok(points)
+}
+
+fn shrink_toolpath(contours: &[Contour], offset: f64) -> Vec<Contour> {
+    contours.iter().flat_map(|contour| {
+        contour.polyline.parallel_offset(offset).into_iter().map(|polyline| Contour {
+            layer: contour.layer.clone(),
+            polyline,
+        })
+    }).collect::<Vec<_>>()
+}
+
+fn grow_toolpath(contours: &[Contour], offset: f64) -> Vec<Contour> {
+    shrink_toolpath(contours, -offset)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn execute<F: Future<Output = ()> + Send + 'static>(f: F) {
+    // this is stupid... use any executor of your choice instead
+    std::thread::spawn(move || futures::executor::block_on(f));
+}
+#[cfg(target_arch = "wasm32")]
+fn execute<F: Future<Output = ()> + 'static>(f: F) {
+    wasm_bindgen_futures::spawn_local(f);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn pause(millis: i32) {
+    // `execute` gives every future its own thread
+    std::thread::sleep(std::time::Duration::from_millis(millis as u64));
+}
+#[cfg(target_arch = "wasm32")]
+async fn pause(millis: i32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let scheduled = web_sys::window().and_then(|window| {
+            window
+                .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+                .ok()
+        });
+        if scheduled.is_none() {
+            resolve.call0(&wasm_bindgen::JsValue::NULL).ok();
+        }
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await.ok();
+}
\ No newline at end of file
@@ -0,0 +1,526 @@
+//! Reads the geometry of a DXF drawing into a [`Sketch`].
+//!
+//! Lines, arcs, circles and polylines keep their arcs as bulges. Splines and ellipses have no
+//! exact arc form and are flattened to within the tolerance. Block inserts are expanded, arrays
+//! and nesting included. Entities on layer `0` inside a block take the insert's layer, as they
+//! do when the drawing is plotted.
+//!
+//! Drawings are taken to lie in the XY plane. Entities extruded along -Z, which is how some
+//! programs store mirrored arcs and polylines, are mirrored back.
+
+use std::f64::consts::TAU;
+
+use dxf::entities::{Entity, EntityType, Insert};
+use dxf::enums::Units as DxfUnits;
+use dxf::{Drawing, Point, Vector};
+
+use super::toolpath_sketch::{self, Piece, Segment, Sketch, Transform, Units};
+
+/// Block inserts nested deeper than this are left out, which also stops blocks that insert
+/// themselves.
+const MAX_NESTING: usize = 16;
+
+/// The drawing's geometry, grouped by layer. `tolerance` is how far apart two ends may be and
+/// still join, and how far a flattened spline or ellipse may stray from the curve.
+pub fn import(drawing: &Drawing, tolerance: f64) -> Sketch {
+    let mut pieces = vec![];
+    for entity in drawing.entities() {
+        add_entity(
+            drawing,
+            entity,
+            &Transform::IDENTITY,
+            "",
+            0,
+            tolerance,
+            &mut pieces,
+        );
+    }
+
+    Sketch {
+        contours: toolpath_sketch::contours(&pieces, tolerance),
+        units: units(drawing.header.default_drawing_units),
+    }
+}
+
+fn units(units: DxfUnits) -> Units {
+    match units {
+        DxfUnits::Millimeters => Units::Millimeters,
+        DxfUnits::Centimeters => Units::Centimeters,
+        DxfUnits::Meters => Units::Meters,
+        DxfUnits::Inches => Units::Inches,
+        DxfUnits::Feet => Units::Feet,
+        _ => Units::Unitless,
+    }
+}
+
+fn xy(point: &Point) -> [f64; 2] {
+    [point.x, point.y]
+}
+
+/// The mirroring that takes an entity's object coordinates to the drawing's.
+fn ocs(normal: &Vector) -> Transform {
+    if normal.z < 0.0 {
+        Transform([-1.0, 0.0, 0.0, 1.0, 0.0, 0.0])
+    } else {
+        Transform::IDENTITY
+    }
+}
+
+/// Adds the outlines of `entity`, placed by `transform`. `block_layer` is the layer of the
+/// insert the entity is drawn through, if any.
+fn add_entity(
+    drawing: &Drawing,
+    entity: &Entity,
+    transform: &Transform,
+    block_layer: &str,
+    depth: usize,
+    tolerance: f64,
+    pieces: &mut Vec<Piece>,
+) {
+    let layer = if entity.common.layer == "0" && !block_layer.is_empty() {
+        block_layer
+    } else {
+        &entity.common.layer
+    };
+    let mut add = |segments: Vec<Segment>, closed: bool, local: &Transform| {
+        let placed = transform.then(local);
+        let segments = placed.segments(&segments, tolerance);
+        if !segments.is_empty() {
+            pieces.push(Piece {
+                layer: layer.to_owned(),
+                segments,
+                closed,
+            });
+        }
+    };
+
+    match &entity.specific {
+        EntityType::Line(line) => {
+            add(
+                vec![Segment::line(xy(&line.p1), xy(&line.p2))],
+                false,
+                &Transform::IDENTITY,
+            );
+        }
+        EntityType::Circle(circle) => {
+            let [x, y] = xy(&circle.center);
+            let r = circle.radius;
+            let halves = vec![
+                Segment {
+                    start: [x + r, y],
+                    end: [x - r, y],
+                    bulge: 1.0,
+                },
+                Segment {
+                    start: [x - r, y],
+                    end: [x + r, y],
+                    bulge: 1.0,
+                },
+            ];
+            add(halves, true, &ocs(&circle.normal));
+        }
+        EntityType::Arc(arc) => {
+            let start = arc.start_angle.to_radians();
+            let mut sweep = (arc.end_angle - arc.start_angle)
+                .to_radians()
+                .rem_euclid(TAU);
+            if sweep == 0.0 {
+                sweep = TAU;
+            }
+            let [x, y] = xy(&arc.center);
+            let r = arc.radius;
+            let at = |angle: f64| [x + r * angle.cos(), y + r * angle.sin()];
+            // Two halves, since a single bulge cannot go all the way around.
+            let middle = start + sweep / 2.0;
+            let halves = vec![
+                Segment {
+                    start: at(start),
+                    end: at(middle),
+                    bulge: toolpath_sketch::bulge(sweep / 2.0),
+                },
+                Segment {
+                    start: at(middle),
+                    end: at(start + sweep),
+                    bulge: toolpath_sketch::bulge(sweep / 2.0),
+                },
+            ];
+            add(halves, false, &ocs(&arc.normal));
+        }
+        EntityType::LwPolyline(polyline) => {
+            let vertices: Vec<([f64; 2], f64)> = polyline
+                .vertices
+                .iter()
+                .map(|vertex| ([vertex.x, vertex.y], vertex.bulge))
+                .collect();
+            let closed = polyline.flags & 1 != 0;
+            add(
+                polyline_segments(&vertices, closed),
+                closed,
+                &ocs(&polyline.extrusion_direction),
+            );
+        }
+        EntityType::Polyline(polyline) => {
+            // Polygon and polyface meshes are surfaces, not outlines.
+            if polyline.flags & (16 | 64) != 0 {
+                return;
+            }
+            let vertices: Vec<([f64; 2], f64)> = polyline
+                .vertices()
+                // Spline frame control points are not on the curve.
+                .filter(|vertex| vertex.flags & 16 == 0)
+                .map(|vertex| (xy(&vertex.location), vertex.bulge))
+                .collect();
+            let closed = polyline.flags & 1 != 0;
+            add(
+                polyline_segments(&vertices, closed),
+                closed,
+                &ocs(&polyline.normal),
+            );
+        }
+        EntityType::Spline(spline) => {
+            let points = spline_points(spline, tolerance);
+            let closed = points.len() > 2 && points.first() == points.last();
+            add(point_segments(&points), closed, &Transform::IDENTITY);
+        }
+        EntityType::Ellipse(ellipse) => {
+            let points = ellipse_points(ellipse, tolerance);
+            let closed = points.len() > 2 && points.first() == points.last();
+            add(point_segments(&points), closed, &Transform::IDENTITY);
+        }
+        EntityType::Insert(insert) => {
+            add_insert(drawing, insert, layer, transform, depth, tolerance, pieces);
+        }
+        _ => {}
+    }
+}
+
+fn add_insert(
+    drawing: &Drawing,
+    insert: &Insert,
+    layer: &str,
+    transform: &Transform,
+    depth: usize,
+    tolerance: f64,
+    pieces: &mut Vec<Piece>,
+) {
+    if depth == MAX_NESTING {
+        return;
+    }
+    let Some(block) = drawing.blocks().find(|block| block.name == insert.name) else {
+        return;
+    };
+    let angle = insert.rotation.to_radians();
+    let scale = [insert.x_scale_factor, insert.y_scale_factor];
+    let (sin, cos) = angle.sin_cos();
+    for row in 0..insert.row_count.max(1) {
+        for column in 0..insert.column_count.max(1) {
+            // Arrays are laid out along the insert's own rotated axes.
+            let dx = f64::from(column) * insert.column_spacing;
+            let dy = f64::from(row) * insert.row_spacing;
+            let origin = [
+                insert.location.x + dx * cos - dy * sin,
+                insert.location.y + dx * sin + dy * cos,
+            ];
+            let placement = Transform::placement(origin, angle, scale, xy(&block.base_point));
+            let placement = transform.then(&ocs(&insert.extrusion_direction).then(&placement));
+            for entity in &block.entities {
+                add_entity(
+                    drawing,
+                    entity,
+                    &placement,
+                    layer,
+                    depth + 1,
+                    tolerance,
+                    pieces,
+                );
+            }
+        }
+    }
+}
+
+/// The segments between polyline vertices, each carrying the bulge to the next.
+fn polyline_segments(vertices: &[([f64; 2], f64)], closed: bool) -> Vec<Segment> {
+    let count = if closed {
+        vertices.len()
+    } else {
+        vertices.len().saturating_sub(1)
+    };
+    (0..count)
+        .map(|i| {
+            let (start, bulge) = vertices[i];
+            let (end, _) = vertices[(i + 1) % vertices.len()];
+            Segment { start, end, bulge }
+        })
+        .filter(|segment| segment.start != segment.end)
+        .collect()
+}
+
+fn point_segments(points: &[[f64; 2]]) -> Vec<Segment> {
+    points
+        .windows(2)
+        .map(|pair| Segment::line(pair[0], pair[1]))
+        .filter(|segment| segment.start != segment.end)
+        .collect()
+}
+
+/// Points along an ellipse or elliptical arc, no more than `tolerance` off it.
+fn ellipse_points(ellipse: &dxf::entities::Ellipse, tolerance: f64) -> Vec<[f64; 2]> {
+    let center = xy(&ellipse.center);
+    let major = [ellipse.major_axis.x, ellipse.major_axis.y];
+    // The minor axis is the major one turned a quarter about the normal.
+    let turn = ellipse.normal.z.signum() * ellipse.minor_axis_ratio;
+    let minor = [-major[1] * turn, major[0] * turn];
+
+    let start = ellipse.start_parameter;
+    let mut sweep = (ellipse.end_parameter - start).rem_euclid(TAU);
+    if sweep < 1e-9 {
+        sweep = TAU;
+    }
+    let radius = major[0].hypot(major[1]);
+    let max_angle = if tolerance > 0.0 && tolerance < radius {
+        2.0 * (1.0 - tolerance / radius).acos()
+    } else {
+        TAU / 8.0
+    };
+    let steps = (sweep / max_angle).ceil().max(1.0) as usize;
+    let mut points: Vec<[f64; 2]> = (0..=steps)
+        .map(|step| {
+            let t = start + sweep * step as f64 / steps as f64;
+            let (sin, cos) = t.sin_cos();
+            [
+                center[0] + major[0] * cos + minor[0] * sin,
+                center[1] + major[1] * cos + minor[1] * sin,
+            ]
+        })
+        .collect();
+    if sweep == TAU {
+        points[steps] = points[0];
+    }
+    points
+}
+
+/// Points along a spline, no more than about `tolerance` off it. Splines without control points
+/// run straight through their fit points.
+fn spline_points(spline: &dxf::entities::Spline, tolerance: f64) -> Vec<[f64; 2]> {
+    let degree = spline.degree_of_curve.max(1) as usize;
+    let knots = &spline.knot_values;
+    let count = spline.control_points.len();
+    if count <= degree || knots.len() != count + degree + 1 {
+        return spline.fit_points.iter().map(xy).collect();
+    }
+    // Weighted control points, in homogeneous coordinates.
+    let control: Vec<[f64; 3]> = spline
+        .control_points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let w = spline.weight_values.get(i).copied().unwrap_or(1.0);
+            [point.x * w, point.y * w, w]
+        })
+        .collect();
+    let at = |u: f64| de_boor(degree, knots, &control, u);
+
+    let mut points = vec![at(knots[degree])];
+    for span in degree..count {
+        let (u0, u1) = (knots[span], knots[span + 1]);
+        if u1 <= u0 {
+            continue;
+        }
+        // A few even steps first, so an S bend within a span is not mistaken for a line.
+        let steps = degree + 1;
+        for step in 0..steps {
+            let a = u0 + (u1 - u0) * step as f64 / steps as f64;
+            let b = u0 + (u1 - u0) * (step + 1) as f64 / steps as f64;
+            subdivide(&at, a, b, tolerance, 0, &mut points);
+        }
+    }
+    let last = points.len() - 1;
+    if spline.flags & 1 != 0 && toolpath_sketch::distance(points[0], points[last]) <= tolerance {
+        points[last] = points[0];
+    }
+    points
+}
+
+/// Appends points along the curve from `a` to `b`, halving until each chord's middle is within
+/// `tolerance` of the curve.
+fn subdivide(
+    at: &impl Fn(f64) -> [f64; 2],
+    a: f64,
+    b: f64,
+    tolerance: f64,
+    depth: usize,
+    points: &mut Vec<[f64; 2]>,
+) {
+    let start = *points.last().unwrap_or(&at(a));
+    let end = at(b);
+    let middle = at((a + b) / 2.0);
+    let chord_middle = [(start[0] + end[0]) / 2.0, (start[1] + end[1]) / 2.0];
+    if depth < 12 && toolpath_sketch::distance(middle, chord_middle) > tolerance {
+        subdivide(at, a, (a + b) / 2.0, tolerance, depth + 1, points);
+        subdivide(at, (a + b) / 2.0, b, tolerance, depth + 1, points);
+    } else {
+        points.push(end);
+    }
+}
+
+/// The point of a NURBS curve at parameter `u`, by de Boor's algorithm.
+fn de_boor(degree: usize, knots: &[f64], control: &[[f64; 3]], u: f64) -> [f64; 2] {
+    let mut span = degree;
+    while span + 1 < control.len() && u >= knots[span + 1] {
+        span += 1;
+    }
+    let mut d: Vec<[f64; 3]> = control[span - degree..=span].to_vec();
+    for r in 1..=degree {
+        for j in (r..=degree).rev() {
+            let i = j + span - degree;
+            let width = knots[i + degree + 1 - r] - knots[i];
+            let alpha = if width == 0.0 {
+                0.0
+            } else {
+                (u - knots[i]) / width
+            };
+            for k in 0..3 {
+                d[j][k] = (1.0 - alpha) * d[j - 1][k] + alpha * d[j][k];
+            }
+        }
+    }
+    let [x, y, w] = d[degree];
+    [x / w, y / w]
+}
+
+#[test]
+fn test_toolpath_dxf() {
+    use dxf::entities::{Arc, Circle, Ellipse, Line, LwPolyline, LwPolylineVertex, Spline};
+    use dxf::Block;
+
+    let entity = |layer: &str, specific: EntityType| {
+        let mut entity = Entity::new(specific);
+        entity.common.layer = layer.to_owned();
+        entity
+    };
+    let mut drawing = Drawing::new();
+    drawing.header.default_drawing_units = DxfUnits::Inches;
+
+    // A rounded slot from two lines and two arcs, drawn in no particular order.
+    for specific in [
+        EntityType::Line(Line::new(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(10.0, 0.0, 0.0),
+        )),
+        EntityType::Arc(Arc::new(Point::new(0.0, 1.0, 0.0), 1.0, 90.0, 270.0)),
+        EntityType::Line(Line::new(
+            Point::new(0.0, 2.0, 0.0),
+            Point::new(10.0, 2.0, 0.0),
+        )),
+        EntityType::Arc(Arc::new(Point::new(10.0, 1.0, 0.0), 1.0, 270.0, 90.0)),
+    ] {
+        drawing.add_entity(entity("outline", specific));
+    }
+
+    let plate = LwPolyline {
+        flags: 1,
+        vertices: [
+            (0.0, 0.0, 0.0),
+            (4.0, 0.0, 1.0),
+            (4.0, 4.0, 0.0),
+            (0.0, 4.0, 0.0),
+        ]
+        .into_iter()
+        .map(|(x, y, bulge)| LwPolylineVertex {
+            x,
+            y,
+            bulge,
+            ..Default::default()
+        })
+        .collect(),
+        ..Default::default()
+    };
+    drawing.add_entity(entity("plate", EntityType::LwPolyline(plate)));
+
+    let ellipse = Ellipse {
+        major_axis: Vector::new(3.0, 0.0, 0.0),
+        minor_axis_ratio: 0.5,
+        start_parameter: 0.0,
+        end_parameter: TAU,
+        ..Default::default()
+    };
+    drawing.add_entity(entity("plate", EntityType::Ellipse(ellipse)));
+
+    // A quadratic Bezier as a clamped spline.
+    let spline = Spline {
+        degree_of_curve: 2,
+        knot_values: vec![0.0, 0.0, 0.0, 1.0, 1.0, 1.0],
+        control_points: vec![
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(1.0, 2.0, 0.0),
+            Point::new(2.0, 0.0, 0.0),
+        ],
+        ..Default::default()
+    };
+    drawing.add_entity(entity("engrave", EntityType::Spline(spline)));
+
+    // A hole on layer 0 in a block, inserted twice by a 2x1 array at double size.
+    let mut block = Block::default();
+    block.entities.push(entity(
+        "0",
+        EntityType::Circle(Circle::new(Point::new(1.0, 1.0, 0.0), 0.5)),
+    ));
+    block.name = "hole".to_owned();
+    block.base_point = Point::new(1.0, 1.0, 0.0);
+    drawing.add_block(block);
+    let mut insert = Insert::default();
+    insert.name = "hole".to_owned();
+    insert.location = Point::new(20.0, 0.0, 0.0);
+    insert.x_scale_factor = 2.0;
+    insert.y_scale_factor = 2.0;
+    insert.column_count = 2;
+    insert.column_spacing = 5.0;
+    drawing.add_entity(entity("holes", EntityType::Insert(insert)));
+
+    let sketch = import(&drawing, 1e-3);
+    assert_eq!(sketch.units, Units::Inches);
+    assert_eq!(sketch.layers(), ["outline", "plate", "engrave", "holes"]);
+    assert_eq!(sketch.contours.len(), 6);
+
+    let length = |contour: &toolpath_sketch::Contour| -> f64 {
+        toolpath_sketch::segments(&contour.polyline)
+            .iter()
+            .map(Segment::length)
+            .sum()
+    };
+    let slot = &sketch.contours[0];
+    assert!(slot.polyline.is_closed);
+    assert!((length(slot) - (20.0 + TAU)).abs() < 1e-9);
+    assert!(slot.polyline.vertex_data.iter().any(|v| v.bulge != 0.0));
+
+    let plate = &sketch.contours[1];
+    assert!(plate.polyline.is_closed);
+    assert!((length(plate) - (12.0 + std::f64::consts::PI * 2.0)).abs() < 1e-9);
+
+    // Ramanujan's approximation of the perimeter of a 3 by 1.5 ellipse.
+    let ellipse = &sketch.contours[2];
+    assert!(ellipse.polyline.is_closed);
+    let (a, b) = (3.0_f64, 1.5_f64);
+    let perimeter = std::f64::consts::PI * (3.0 * (a + b) - ((3.0 * a + b) * (a + 3.0 * b)).sqrt());
+    assert!((length(ellipse) - perimeter).abs() < 0.01);
+
+    let curve = &sketch.contours[3];
+    assert!(!curve.polyline.is_closed);
+    let last = curve.polyline.vertex_data.last().unwrap();
+    assert_eq!([last.x, last.y], [2.0, 0.0]);
+    let top = curve
+        .polyline
+        .vertex_data
+        .iter()
+        .map(|v| v.y)
+        .fold(0.0, f64::max);
+    assert!((top - 1.0).abs() < 1e-3);
+
+    for (contour, x) in sketch.contours[4..].iter().zip([20.0, 25.0]) {
+        assert_eq!(contour.layer, "holes");
+        assert!(contour.polyline.is_closed);
+        assert!((length(contour) - TAU).abs() < 1e-9);
+        let v = &contour.polyline.vertex_data[0];
+        assert!((v.x - (x + 1.0)).abs() < 1e-9 && v.y.abs() < 1e-9);
+    }
+}
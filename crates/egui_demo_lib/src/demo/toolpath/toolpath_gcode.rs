@@ -0,0 +1,498 @@
+//! Turns planned cuts into [`GCode`] moves for the machine.
+//!
+//! Every cut starts from the safe height: a rapid to its start, a plunge to its depth, then
+//! `G1` lines and `G2`/`G3` arcs along the polyline, and a rapid back up. Lasers and plasma
+//! cutters go around once instead, piercing with their beam at the start of every stretch.
+
+use cavalier_contours::polyline::Polyline;
+
+use super::toolpath_sketch::{self, Segment};
+use super::toolpath_tabs;
+use crate::demo::gcode::GCode;
+
+/// What does the cutting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Machine {
+    Mill,
+    Laser,
+    Plasma,
+}
+
+impl Machine {
+    /// Whether it cuts with a beam that goes through the sheet in one pass.
+    pub fn is_beam(self) -> bool {
+        matches!(self, Self::Laser | Self::Plasma)
+    }
+}
+
+/// The settings of `ToolType::Laser` in the ESP32-C3 firmware that bear on the moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Laser {
+    pub power: f32,
+    pub max_power: f32,
+    pub min_power: f32,
+}
+
+impl Default for Laser {
+    fn default() -> Self {
+        Self {
+            power: 800.0,
+            max_power: 1000.0,
+            min_power: 0.0,
+        }
+    }
+}
+
+/// The settings of `ToolType::PlasmaCutter` in the ESP32-C3 firmware that bear on the moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plasma {
+    pub power: f32,
+    pub max_power: f32,
+    pub min_power: f32,
+
+    /// How long the arc burns through before moving, in seconds.
+    pub pierce_delay: f32,
+
+    /// The torch's height above the sheet while cutting, in mm.
+    pub cut_height: f32,
+
+    /// How far the torch rises above the cut height between cuts, in mm.
+    pub hop_distance: f32,
+}
+
+impl Default for Plasma {
+    fn default() -> Self {
+        Self {
+            power: 1000.0,
+            max_power: 1000.0,
+            min_power: 0.0,
+            pierce_delay: 0.5,
+            cut_height: 1.5,
+            hop_distance: 5.0,
+        }
+    }
+}
+
+/// How a beam starts and stops each cut.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Beam {
+    /// The `S` word switching the beam on.
+    pub power: u32,
+
+    /// How long to wait after switching on before moving, in seconds.
+    pub pierce_delay: f64,
+
+    /// The height to cut at, and to travel at between cuts, if the head moves in Z.
+    pub heights: Option<(f64, f64)>,
+}
+
+impl From<Laser> for Beam {
+    fn from(laser: Laser) -> Self {
+        Self {
+            power: laser.power.clamp(laser.min_power, laser.max_power).round() as u32,
+            pierce_delay: 0.0,
+            heights: None,
+        }
+    }
+}
+
+impl From<Plasma> for Beam {
+    fn from(plasma: Plasma) -> Self {
+        let cut_height = f64::from(plasma.cut_height);
+        Self {
+            power: plasma
+                .power
+                .clamp(plasma.min_power, plasma.max_power)
+                .round() as u32,
+            pierce_delay: f64::from(plasma.pierce_delay),
+            heights: Some((cut_height, cut_height + f64::from(plasma.hop_distance))),
+        }
+    }
+}
+
+/// Heights and speeds shared by every operation, in mm and mm/min.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Feeds {
+    /// Height above the work for rapid moves.
+    pub safe_z: f64,
+
+    /// How deep the finished cut goes, below zero.
+    pub depth: f64,
+
+    /// How much deeper each pass goes than the last.
+    pub step_down: f64,
+
+    pub feed: f64,
+    pub plunge_feed: f64,
+
+    /// Spindle speed for a mill, in rpm.
+    pub spindle_speed: u32,
+}
+
+impl Default for Feeds {
+    fn default() -> Self {
+        Self {
+            safe_z: 5.0,
+            depth: 3.0,
+            step_down: 1.0,
+            feed: 600.0,
+            plunge_feed: 200.0,
+            spindle_speed: 12000,
+        }
+    }
+}
+
+impl Feeds {
+    /// The heights of the passes, from the first to the full depth.
+    pub fn levels(&self) -> Vec<f64> {
+        if self.depth <= 0.0 {
+            return vec![0.0];
+        }
+        let passes = if self.step_down > 0.0 {
+            (self.depth / self.step_down - 1e-9).ceil().max(1.0) as usize
+        } else {
+            1
+        };
+        (1..=passes)
+            .map(|pass| -(self.depth * pass as f64 / passes as f64))
+            .collect()
+    }
+}
+
+/// A run of the tool along a polyline at one height.
+#[derive(Clone, Debug)]
+pub struct Cut {
+    pub polyline: Polyline<f64>,
+    pub z: f64,
+
+    /// Stretches left as holding tabs, as distances along the polyline.
+    pub tabs: Vec<[f64; 2]>,
+
+    /// The height a mill rises to over the tabs.
+    pub tab_z: f64,
+}
+
+/// Each path cut at every level in turn, all of a level before going deeper. A beam cuts each
+/// path once. `tabs` holds the stretches of each path to leave as tabs, `tab_height` high.
+pub fn cuts(
+    paths: &[Polyline<f64>],
+    tabs: &[Vec<[f64; 2]>],
+    tab_height: f64,
+    feeds: &Feeds,
+    machine: Machine,
+) -> Vec<Cut> {
+    let levels = if machine.is_beam() {
+        vec![0.0]
+    } else {
+        feeds.levels()
+    };
+    let tab_z = (tab_height - feeds.depth).min(0.0);
+    levels
+        .into_iter()
+        .flat_map(|z| {
+            paths.iter().enumerate().map(move |(index, polyline)| Cut {
+                polyline: polyline.clone(),
+                z,
+                tabs: tabs.get(index).cloned().unwrap_or_default(),
+                tab_z,
+            })
+        })
+        .collect()
+}
+
+/// The moves that make the cuts in order, with the spindle running from the first retract to
+/// the last. `beam` is only used by lasers and plasma cutters.
+pub fn program(cuts: &[Cut], feeds: &Feeds, machine: Machine, beam: &Beam) -> Vec<GCode> {
+    if machine.is_beam() {
+        return beam_program(cuts, feeds, beam);
+    }
+    let mut codes = vec![
+        GCode::G21,
+        GCode::G90,
+        retract(feeds),
+        GCode::M3 {
+            s: Some(feeds.spindle_speed),
+        },
+    ];
+    for cut in cuts {
+        let segments = toolpath_sketch::segments(&cut.polyline);
+        let Some(first) = segments.first() else {
+            continue;
+        };
+        codes.push(rapid_to(first.start));
+        let mut z = feeds.safe_z;
+        let mut feed = None;
+        for (run, tab) in toolpath_tabs::split(&cut.polyline, &cut.tabs) {
+            let height = if tab { cut.z.max(cut.tab_z) } else { cut.z };
+            if height != z {
+                // Moving in Z sets the plunge feed, so the next cutting move sets it back.
+                codes.push(plunge(height, feeds));
+                feed = Some(feeds.feed as f32);
+                z = height;
+            }
+            for segment in &run {
+                codes.push(segment_code(segment, feed.take()));
+            }
+        }
+        codes.push(retract(feeds));
+    }
+    codes.push(GCode::M5 {});
+    codes
+}
+
+/// The moves that make the cuts with the beam, off over the tabs and between cuts. Every
+/// stretch of cutting starts with a pierce.
+fn beam_program(cuts: &[Cut], feeds: &Feeds, beam: &Beam) -> Vec<GCode> {
+    let height = |z: f64| GCode::G0 {
+        x: None,
+        y: None,
+        z: Some(z as f32),
+        e: None,
+        f: None,
+    };
+    let mut codes = vec![GCode::G21, GCode::G90];
+    if let Some((_, travel)) = beam.heights {
+        codes.push(height(travel));
+    }
+    for cut in cuts {
+        for (run, tab) in toolpath_tabs::split(&cut.polyline, &cut.tabs) {
+            let Some(first) = run.first() else {
+                continue;
+            };
+            if tab {
+                continue;
+            }
+            codes.push(rapid_to(first.start));
+            if let Some((cut_height, _)) = beam.heights {
+                codes.push(height(cut_height));
+            }
+            codes.push(GCode::M3 {
+                s: Some(beam.power),
+            });
+            if beam.pierce_delay > 0.0 {
+                codes.push(GCode::G4 {
+                    p: Some((beam.pierce_delay * 1000.0) as f32),
+                    s: None,
+                });
+            }
+            let mut feed = Some(feeds.feed as f32);
+            for segment in &run {
+                codes.push(segment_code(segment, feed.take()));
+            }
+            codes.push(GCode::M5 {});
+            if let Some((_, travel)) = beam.heights {
+                codes.push(height(travel));
+            }
+        }
+    }
+    codes
+}
+
+fn retract(feeds: &Feeds) -> GCode {
+    GCode::G0 {
+        x: None,
+        y: None,
+        z: Some(feeds.safe_z as f32),
+        e: None,
+        f: None,
+    }
+}
+
+fn rapid_to(point: [f64; 2]) -> GCode {
+    GCode::G0 {
+        x: Some(point[0] as f32),
+        y: Some(point[1] as f32),
+        z: None,
+        e: None,
+        f: None,
+    }
+}
+
+fn plunge(z: f64, feeds: &Feeds) -> GCode {
+    GCode::G1 {
+        x: None,
+        y: None,
+        z: Some(z as f32),
+        e: None,
+        f: Some(feeds.plunge_feed as f32),
+    }
+}
+
+/// A `G1` line or, for an arc, a `G2` or `G3` with its center relative to the start.
+pub fn segment_code(segment: &Segment, f: Option<f32>) -> GCode {
+    let (x, y) = (Some(segment.end[0] as f32), Some(segment.end[1] as f32));
+    let Some((center, _)) = segment.arc() else {
+        return GCode::G1 {
+            x,
+            y,
+            z: None,
+            e: None,
+            f,
+        };
+    };
+    let i = Some((center[0] - segment.start[0]) as f32);
+    let j = Some((center[1] - segment.start[1]) as f32);
+    if segment.bulge < 0.0 {
+        GCode::G2 {
+            x,
+            y,
+            z: None,
+            i,
+            j,
+            k: None,
+            r: None,
+            p: None,
+            e: None,
+            f,
+        }
+    } else {
+        GCode::G3 {
+            x,
+            y,
+            z: None,
+            i,
+            j,
+            k: None,
+            r: None,
+            p: None,
+            e: None,
+            f,
+        }
+    }
+}
+
+#[test]
+fn test_toolpath_gcode() {
+    use crate::demo::gcode::gcode_lint::{lint, Limits};
+
+    let feeds = Feeds {
+        depth: 2.5,
+        step_down: 1.5,
+        ..Feeds::default()
+    };
+    assert_eq!(feeds.levels(), [-1.25, -2.5]);
+    let feeds = Feeds {
+        depth: 2.0,
+        ..Feeds::default()
+    };
+    assert_eq!(feeds.levels(), [-1.0, -2.0]);
+
+    // Lines, and half circles turning either way.
+    let slot = toolpath_sketch::polyline(
+        &[
+            Segment::line([0.0, 0.0], [10.0, 0.0]),
+            Segment {
+                start: [10.0, 0.0],
+                end: [10.0, 4.0],
+                bulge: 1.0,
+            },
+            Segment {
+                start: [10.0, 4.0],
+                end: [0.0, 4.0],
+                bulge: 0.0,
+            },
+            Segment {
+                start: [0.0, 4.0],
+                end: [0.0, 0.0],
+                bulge: -1.0,
+            },
+        ],
+        true,
+    );
+    let mill = cuts(&[slot.clone()], &[], 0.0, &feeds, Machine::Mill);
+    assert_eq!(mill.len(), 2);
+    let lines: Vec<String> = program(&mill[..1], &feeds, Machine::Mill, &Beam::default())
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "G21",
+            "G90",
+            "G0 Z5",
+            "M3 S12000",
+            "G0 X0 Y0",
+            "G1 Z-1 F200",
+            "G1 X10 Y0 F600",
+            "G3 X10 Y4 I0 J2",
+            "G1 X0 Y4",
+            "G2 X0 Y0 I0 J-2",
+            "G0 Z5",
+            "M5",
+        ]
+    );
+    // The program's own output passes the linter.
+    let diagnostics = lint(&lines.join("\n"), &Limits::default());
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    // A tab 1.5 high on the first line: the first pass runs under its top and rises over it.
+    let tabs = [vec![[2.0, 8.0]]];
+    let mill = cuts(&[slot.clone()], &tabs, 1.5, &feeds, Machine::Mill);
+    let lines: Vec<String> = program(&mill, &feeds, Machine::Mill, &Beam::default())
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        lines[4..11],
+        [
+            "G0 X0 Y0",
+            "G1 Z-1 F200",
+            "G1 X2 Y0 F600",
+            "G1 Z-0.5 F200",
+            "G1 X8 Y0 F600",
+            "G1 Z-1 F200",
+            "G1 X10 Y0 F600",
+        ]
+    );
+
+    // A beam cuts once, off over the tab.
+    let laser = cuts(&[slot], &tabs, 1.5, &feeds, Machine::Laser);
+    assert_eq!(laser.len(), 1);
+    let lines: Vec<String> = program(&laser, &feeds, Machine::Laser, &Laser::default().into())
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "G21",
+            "G90",
+            "G0 X0 Y0",
+            "M3 S800",
+            "G1 X2 Y0 F600",
+            "M5",
+            "G0 X8 Y0",
+            "M3 S800",
+            "G1 X10 Y0 F600",
+            "G3 X10 Y4 I0 J2",
+            "G1 X0 Y4",
+            "G2 X0 Y0 I0 J-2",
+            "M5",
+        ]
+    );
+
+    // A plasma torch drops to its cut height and waits for the pierce.
+    let plasma = program(
+        &laser[..1],
+        &feeds,
+        Machine::Plasma,
+        &Plasma::default().into(),
+    );
+    let lines: Vec<String> = plasma.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        lines[..9],
+        [
+            "G21",
+            "G90",
+            "G0 Z6.5",
+            "G0 X0 Y0",
+            "G0 Z1.5",
+            "M3 S1000",
+            "G4 P500",
+            "G1 X2 Y0 F600",
+            "M5",
+        ]
+    );
+    assert_eq!(lines[9], "G0 Z6.5");
+}
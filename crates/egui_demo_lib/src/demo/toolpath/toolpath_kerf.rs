@@ -0,0 +1,83 @@
+//! Tool and kerf compensation: the path the center of the tool follows so that the cut edge
+//! lands on the drawn one.
+//!
+//! Parts are cut around the outside and holes from the inside, as told by the contours'
+//! [`Tree`]. Open contours have no inside, so the tool follows them as drawn.
+
+use cavalier_contours::polyline::PlineSource;
+
+use super::toolpath_sketch::{self, Contour};
+use super::toolpath_tree::Tree;
+
+#[derive(Clone, Debug, Default)]
+pub struct Compensation {
+    /// The tool's path around each contour, in the contours' order.
+    pub contours: Vec<Contour>,
+
+    /// Holes too small for the tool to fit, which are left out.
+    pub too_small: Vec<usize>,
+}
+
+/// Offsets closed contours by `radius` away from the material of the parts.
+pub fn compensate(contours: &[Contour], tree: &Tree, radius: f64) -> Compensation {
+    let mut compensation = Compensation::default();
+    for (index, contour) in contours.iter().enumerate() {
+        if radius == 0.0 || tree.depths[index].is_none() {
+            compensation.contours.push(contour.clone());
+            continue;
+        }
+        // Offsets go to the left, which is inward on a counterclockwise contour.
+        let counterclockwise = toolpath_sketch::area(&contour.polyline) > 0.0;
+        let inward = if counterclockwise { radius } else { -radius };
+        let offset = if tree.is_hole(index) { inward } else { -inward };
+        let polylines = contour.polyline.parallel_offset(offset);
+        if polylines.is_empty() {
+            compensation.too_small.push(index);
+        }
+        compensation
+            .contours
+            .extend(polylines.into_iter().map(|polyline| Contour {
+                layer: contour.layer.clone(),
+                polyline,
+            }));
+    }
+    compensation
+}
+
+#[test]
+fn test_toolpath_kerf() {
+    use super::toolpath_sketch::Segment;
+
+    // A counterclockwise plate with a clockwise hole, and a line to engrave.
+    let loop_through = |corners: &[[f64; 2]]| Contour {
+        layer: "cut".to_owned(),
+        polyline: toolpath_sketch::polyline(
+            &(0..corners.len())
+                .map(|i| Segment::line(corners[i], corners[(i + 1) % corners.len()]))
+                .collect::<Vec<_>>(),
+            true,
+        ),
+    };
+    let contours = vec![
+        loop_through(&[[0.0, 0.0], [50.0, 0.0], [50.0, 30.0], [0.0, 30.0]]),
+        loop_through(&[[10.0, 10.0], [10.0, 20.0], [20.0, 20.0], [20.0, 10.0]]),
+        Contour {
+            layer: "engrave".to_owned(),
+            polyline: toolpath_sketch::polyline(
+                &[Segment::line([30.0, 10.0], [40.0, 20.0])],
+                false,
+            ),
+        },
+    ];
+    let tree = Tree::new(&contours, 0.01);
+    let compensation = compensate(&contours, &tree, 1.5);
+    assert!(compensation.too_small.is_empty());
+    assert_eq!(compensation.contours.len(), 3);
+
+    let area = |index: usize| toolpath_sketch::area(&compensation.contours[index].polyline).abs();
+    assert!((area(0) - 53.0 * 33.0).abs() < 2.5);
+    assert!((area(1) - 7.0 * 7.0).abs() < 1e-6);
+    assert_eq!(compensation.contours[1].layer, "cut");
+    let engraving = &compensation.contours[2].polyline.vertex_data;
+    assert_eq!((engraving[0].x, engraving[1].y), (30.0, 20.0));
+}
@@ -0,0 +1,308 @@
+//! Lead-ins and lead-outs for lasers and plasma cutters.
+//!
+//! Piercing leaves a crater wider than the kerf, so the beam pierces off the contour on the
+//! scrap side and runs onto it, goes around, cuts a little past where it joined to close the
+//! loop cleanly, and runs off into the scrap again. Pierces land away from sharp corners, where
+//! a crater would spoil the part most.
+
+use cavalier_contours::polyline::Polyline;
+
+use super::toolpath_sketch::{self, Segment};
+use super::toolpath_tabs;
+use super::toolpath_tree;
+
+/// How the beam runs onto and off the contour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Straight, square to the contour.
+    Line,
+
+    /// A quarter circle meeting the contour tangentially.
+    Arc,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Leads {
+    pub style: Style,
+
+    /// How far from the contour the beam pierces, in mm.
+    pub lead_in: f64,
+
+    /// How far from the contour the beam ends up, in mm.
+    pub lead_out: f64,
+
+    /// How far past the pierce point the beam goes again before leaving, in mm.
+    pub overcut: f64,
+
+    /// The turn in degrees that makes a corner too sharp to pierce near.
+    pub corner_angle: f64,
+}
+
+impl Default for Leads {
+    fn default() -> Self {
+        Self {
+            style: Style::Arc,
+            lead_in: 3.0,
+            lead_out: 2.0,
+            overcut: 1.0,
+            corner_angle: 30.0,
+        }
+    }
+}
+
+/// The runs onto and off a closed contour. `lead_out` includes the overcut.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Led {
+    pub lead_in: Vec<Segment>,
+    pub lead_out: Vec<Segment>,
+}
+
+impl Led {
+    /// The whole cut as one open polyline: onto the contour, around it and off again.
+    pub fn path(&self, contour: &Polyline<f64>) -> Polyline<f64> {
+        let segments: Vec<Segment> = self
+            .lead_in
+            .iter()
+            .copied()
+            .chain(toolpath_sketch::segments(contour))
+            .chain(self.lead_out.iter().copied())
+            .collect();
+        toolpath_sketch::polyline(&segments, false)
+    }
+
+    pub fn lead_in_length(&self) -> f64 {
+        self.lead_in.iter().map(Segment::length).sum()
+    }
+}
+
+/// The direction of travel at the start of a segment, or at its end.
+fn tangent(segment: &Segment, at_end: bool) -> [f64; 2] {
+    let Some((center, _)) = segment.arc() else {
+        let (dx, dy) = (
+            segment.end[0] - segment.start[0],
+            segment.end[1] - segment.start[1],
+        );
+        let length = dx.hypot(dy);
+        return [dx / length, dy / length];
+    };
+    let point = if at_end { segment.end } else { segment.start };
+    let (rx, ry) = (point[0] - center[0], point[1] - center[1]);
+    let radius = rx.hypot(ry);
+    // Square to the radius, turning the way the arc does.
+    if segment.bulge > 0.0 {
+        [-ry / radius, rx / radius]
+    } else {
+        [ry / radius, -rx / radius]
+    }
+}
+
+/// The distance along a closed polyline to pierce at: the middle of the longest stretch
+/// between corners turning more than `corner_angle` degrees, or the start if there are none.
+pub fn pierce_point(polyline: &Polyline<f64>, corner_angle: f64) -> f64 {
+    let segments: Vec<Segment> = toolpath_sketch::segments(polyline)
+        .into_iter()
+        .filter(|segment| segment.length() > 0.0)
+        .collect();
+    let Some(last) = segments.last() else {
+        return 0.0;
+    };
+    let limit = corner_angle.to_radians().cos();
+    let mut corners = Vec::new();
+    let mut along = 0.0;
+    let mut previous = tangent(last, true);
+    for segment in &segments {
+        let next = tangent(segment, false);
+        if previous[0] * next[0] + previous[1] * next[1] < limit {
+            corners.push(along);
+        }
+        previous = tangent(segment, true);
+        along += segment.length();
+    }
+    let length = along;
+
+    let Some(&first) = corners.first() else {
+        return 0.0;
+    };
+    let mut best = (0.0, 0.0);
+    for (index, &corner) in corners.iter().enumerate() {
+        let next = corners.get(index + 1).copied().unwrap_or(first + length);
+        if next - corner > best.1 {
+            best = ((corner + next) / 2.0, next - corner);
+        }
+    }
+    best.0 % length
+}
+
+/// The same closed polyline, starting `along` its length.
+pub fn starting_at(polyline: &Polyline<f64>, along: f64) -> Polyline<f64> {
+    let runs = toolpath_tabs::split(polyline, &[[along, along]]);
+    let segments: Vec<Segment> = runs.iter().rev().flat_map(|(run, _)| run.clone()).collect();
+    toolpath_sketch::polyline(&segments, polyline.is_closed)
+}
+
+/// Leads for a closed polyline starting at its pierce point, with the scrap inside it or
+/// outside. A lead that would cross the contour is shortened, and dropped if that fails; arcs
+/// are flattened to within `tolerance` to tell.
+pub fn leads(polyline: &Polyline<f64>, scrap_inside: bool, leads: &Leads, tolerance: f64) -> Led {
+    let segments = toolpath_sketch::segments(polyline);
+    let (Some(first), true) = (segments.first(), polyline.is_closed) else {
+        return Led::default();
+    };
+    let counterclockwise = toolpath_sketch::area(polyline) > 0.0;
+    let scrap_left = scrap_inside == counterclockwise;
+    let outline = toolpath_sketch::points(polyline, tolerance);
+    let in_scrap = |point: [f64; 2]| toolpath_tree::contains(&outline, point) == scrap_inside;
+
+    // Onto the contour at its start.
+    let start = first.start;
+    let lead_in = shortened(leads.lead_in, |length| {
+        let lead = lead(
+            start,
+            tangent(first, false),
+            scrap_left,
+            length,
+            leads.style,
+            true,
+        );
+        (in_scrap(lead.start) && in_scrap(lead.midpoint())).then_some(lead)
+    });
+
+    // Past the start by the overcut, and off.
+    let mut lead_out: Vec<Segment> = toolpath_tabs::split(polyline, &[[0.0, leads.overcut]])
+        .into_iter()
+        .find(|(_, over)| *over)
+        .map(|(run, _)| run)
+        .unwrap_or_default();
+    let (point, direction) = match lead_out.last() {
+        Some(end) => (end.end, tangent(end, true)),
+        None => (start, tangent(first, false)),
+    };
+    let off = shortened(leads.lead_out, |length| {
+        let lead = lead(point, direction, scrap_left, length, leads.style, false);
+        (in_scrap(lead.end) && in_scrap(lead.midpoint())).then_some(lead)
+    });
+    lead_out.extend(off);
+
+    Led {
+        lead_in: lead_in.into_iter().collect(),
+        lead_out,
+    }
+}
+
+/// The first lead `make` accepts, halving its length a few times.
+fn shortened(length: f64, make: impl Fn(f64) -> Option<Segment>) -> Option<Segment> {
+    (0..4)
+        .map(|halvings| length / f64::from(1 << halvings))
+        .filter(|&length| length > 0.0)
+        .find_map(make)
+}
+
+/// A lead of `length` meeting `point` heading along `direction`, from the scrap side if it
+/// runs onto the contour, or into it if it runs off.
+fn lead(
+    point: [f64; 2],
+    direction: [f64; 2],
+    scrap_left: bool,
+    length: f64,
+    style: Style,
+    onto: bool,
+) -> Segment {
+    let normal = if scrap_left {
+        [-direction[1], direction[0]]
+    } else {
+        [direction[1], -direction[0]]
+    };
+    let off = [point[0] + normal[0] * length, point[1] + normal[1] * length];
+    match style {
+        Style::Line if onto => Segment::line(off, point),
+        Style::Line => Segment::line(point, off),
+        Style::Arc => {
+            // A quarter circle around a center on the scrap side, turning towards it.
+            let center = off;
+            let bulge = toolpath_sketch::bulge(std::f64::consts::FRAC_PI_2);
+            let bulge = if scrap_left { bulge } else { -bulge };
+            let sign = if onto { -1.0 } else { 1.0 };
+            let far = [
+                center[0] + sign * direction[0] * length,
+                center[1] + sign * direction[1] * length,
+            ];
+            if onto {
+                Segment {
+                    start: far,
+                    end: point,
+                    bulge,
+                }
+            } else {
+                Segment {
+                    start: point,
+                    end: far,
+                    bulge,
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_toolpath_lead() {
+    // A 40 by 20 counterclockwise rectangle starting at a corner.
+    let corners = [[0.0, 0.0], [40.0, 0.0], [40.0, 20.0], [0.0, 20.0]];
+    let rectangle = toolpath_sketch::polyline(
+        &(0..4)
+            .map(|i| Segment::line(corners[i], corners[(i + 1) % 4]))
+            .collect::<Vec<_>>(),
+        true,
+    );
+    // The middle of a long side, away from the corners.
+    let along = pierce_point(&rectangle, 30.0);
+    assert_eq!(along, 20.0);
+    let rotated = starting_at(&rectangle, along);
+    assert_eq!(rotated.vertex_data.len(), 5);
+    assert_eq!(
+        (rotated.vertex_data[0].x, rotated.vertex_data[0].y),
+        (20.0, 0.0)
+    );
+    assert!((toolpath_sketch::area(&rotated) - 800.0).abs() < 1e-9);
+
+    // Around a part the scrap is outside, below the bottom edge.
+    let led = leads(&rotated, false, &Leads::default(), 0.01);
+    let lead_in = led.lead_in[0];
+    assert!(toolpath_sketch::distance(lead_in.start, [17.0, -3.0]) < 1e-9);
+    assert_eq!(lead_in.end, [20.0, 0.0]);
+    assert!(lead_in.bulge < 0.0);
+    // Over the overcut to x = 21, then a quarter circle off.
+    assert_eq!(led.lead_out[0], Segment::line([20.0, 0.0], [21.0, 0.0]));
+    assert!(toolpath_sketch::distance(led.lead_out[1].end, [23.0, -2.0]) < 1e-9);
+    let path = led.path(&rotated);
+    assert!(!path.is_closed);
+    assert!(
+        (toolpath_tabs::length(&path) - (120.0 + 1.0 + 2.5 * std::f64::consts::PI)).abs() < 1e-9
+    );
+
+    // In a hole the scrap is inside, and a lead too long for it is shortened.
+    let hole = Leads {
+        style: Style::Line,
+        lead_in: 30.0,
+        ..Leads::default()
+    };
+    let led = leads(&rotated, true, &hole, 0.01);
+    assert_eq!(led.lead_in[0], Segment::line([20.0, 15.0], [20.0, 0.0]));
+
+    // A circle has no corners, so it pierces at its start.
+    let circle = toolpath_sketch::polyline(
+        &[
+            Segment {
+                start: [5.0, 0.0],
+                end: [-5.0, 0.0],
+                bulge: 1.0,
+            },
+            Segment {
+                start: [-5.0, 0.0],
+                end: [5.0, 0.0],
+                bulge: 1.0,
+            },
+        ],
+        true,
+    );
+    assert_eq!(pierce_point(&circle, 30.0), 0.0);
+}
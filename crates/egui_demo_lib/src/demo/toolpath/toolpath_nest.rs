@@ -0,0 +1,681 @@
+//! Nesting: laying out copies of the parts of a drawing on a sheet.
+//!
+//! Each contour at the top of the [`Tree`] is a part, along with everything inside it. Parts go
+//! largest first, each to the place on the sheet where its top ends up lowest, then furthest
+//! left. The places tried are the sheet's corners and the spots just right of and just above the
+//! parts already laid out, each slid down and then left as far as it goes. Parts keep clear of each other and of the
+//! sheet's edge by growing their outlines by half the spacing, and by keeping the grown outlines
+//! half the spacing inside the sheet.
+
+use cavalier_contours::polyline::{PlineSource, Polyline};
+
+use super::toolpath_sketch::{self, Contour, Transform};
+use super::toolpath_tree::{self, Tree};
+
+/// How finely a part slides towards the corner, in steps halving the distance.
+const SLIDE_STEPS: usize = 24;
+
+/// The turns a part may be laid out at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rotations {
+    None,
+    /// Upright or upside down.
+    Half,
+    /// Any quarter turn.
+    Quarter,
+    /// Any multiple of this many degrees.
+    Step(f64),
+}
+
+/// The way the grain of the sheet must run through the parts, which are drawn with their
+/// grain along x.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grain {
+    Free,
+    AlongX,
+    AlongY,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Nest {
+    /// How many of each part to lay out.
+    pub copies: usize,
+
+    /// The gap between parts, and between a part and the edge of the sheet, in mm.
+    pub spacing: f64,
+
+    pub rotations: Rotations,
+    pub grain: Grain,
+}
+
+impl Default for Nest {
+    fn default() -> Self {
+        Self {
+            copies: 1,
+            spacing: 5.0,
+            rotations: Rotations::Quarter,
+            grain: Grain::Free,
+        }
+    }
+}
+
+impl Nest {
+    /// The turns allowed, in radians.
+    pub fn angles(&self) -> Vec<f64> {
+        let degrees: Vec<f64> = match self.rotations {
+            Rotations::None => vec![0.0],
+            Rotations::Half => vec![0.0, 180.0],
+            Rotations::Quarter => vec![0.0, 90.0, 180.0, 270.0],
+            Rotations::Step(step) if step > 0.0 => (0..)
+                .map(|turn| turn as f64 * step)
+                .take_while(|&angle| angle < 360.0 - 1e-9)
+                .collect(),
+            Rotations::Step(_) => vec![0.0],
+        };
+        let along = |base: f64| {
+            let kept: Vec<f64> = degrees
+                .iter()
+                .copied()
+                .filter(|angle| {
+                    ((angle - base) / 180.0 - ((angle - base) / 180.0).round()).abs() < 1e-9
+                })
+                .collect();
+            if kept.is_empty() {
+                vec![base]
+            } else {
+                kept
+            }
+        };
+        let degrees = match self.grain {
+            Grain::Free => degrees,
+            Grain::AlongX => along(0.0),
+            Grain::AlongY => along(90.0),
+        };
+        degrees.into_iter().map(f64::to_radians).collect()
+    }
+}
+
+/// A part and what it needs to be laid out.
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub contours: Vec<Contour>,
+
+    /// The outside of the part grown by half the spacing, flattened.
+    pub outline: Vec<[f64; 2]>,
+
+    /// The area of material the part takes, holes left out.
+    pub area: f64,
+}
+
+/// The parts of a drawing, and how many open contours lie outside all of them. Arcs are
+/// flattened to within `tolerance`.
+pub fn parts(
+    contours: &[Contour],
+    tree: &Tree,
+    spacing: f64,
+    tolerance: f64,
+) -> (Vec<Part>, usize) {
+    let root = |mut index: usize| {
+        while let Some(parent) = tree.parents[index] {
+            index = parent;
+        }
+        index
+    };
+    let roots: Vec<usize> = (0..contours.len())
+        .filter(|&index| tree.depths[index] == Some(0))
+        .collect();
+    let outlines: Vec<Vec<[f64; 2]>> = roots
+        .iter()
+        .map(|&index| toolpath_sketch::points(&contours[index].polyline, tolerance))
+        .collect();
+
+    let mut members = vec![vec![]; roots.len()];
+    let mut loose = 0;
+    for (index, contour) in contours.iter().enumerate() {
+        let part = if tree.depths[index].is_some() {
+            roots.iter().position(|&other| other == root(index))
+        } else {
+            let start = contour
+                .polyline
+                .vertex_data
+                .first()
+                .map_or([0.0, 0.0], |vertex| [vertex.x, vertex.y]);
+            outlines
+                .iter()
+                .position(|outline| toolpath_tree::contains(outline, start))
+        };
+        match part {
+            Some(part) => members[part].push(index),
+            None => loose += 1,
+        }
+    }
+
+    let parts = roots
+        .iter()
+        .zip(members)
+        .map(|(&root, members)| {
+            let outside = &contours[root].polyline;
+            let area = members
+                .iter()
+                .filter_map(|&index| {
+                    let depth = tree.depths[index]?;
+                    let area = toolpath_sketch::area(&contours[index].polyline).abs();
+                    Some(if depth % 2 == 0 { area } else { -area })
+                })
+                .sum();
+            Part {
+                contours: members
+                    .iter()
+                    .map(|&index| contours[index].clone())
+                    .collect(),
+                outline: toolpath_sketch::points(&grown(outside, spacing / 2.0), tolerance),
+                area,
+            }
+        })
+        .collect();
+    (parts, loose)
+}
+
+/// A closed polyline grown outward by `distance`.
+fn grown(polyline: &Polyline<f64>, distance: f64) -> Polyline<f64> {
+    if distance <= 0.0 {
+        return polyline.clone();
+    }
+    // Offsets go to the left, which is inward on a counterclockwise polyline.
+    let offset = if toolpath_sketch::area(polyline) > 0.0 {
+        -distance
+    } else {
+        distance
+    };
+    polyline
+        .parallel_offset(offset)
+        .into_iter()
+        .max_by(|a, b| {
+            toolpath_sketch::area(a)
+                .abs()
+                .total_cmp(&toolpath_sketch::area(b).abs())
+        })
+        .unwrap_or_else(|| polyline.clone())
+}
+
+/// Where a copy of a part goes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub part: usize,
+    pub transform: Transform,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Nesting {
+    pub placements: Vec<Placement>,
+
+    /// Copies that found no room on the sheet.
+    pub unplaced: usize,
+
+    /// The share of the sheet's area the parts take up.
+    pub utilization: f64,
+}
+
+/// An outline turned and moved so its bounding box starts at the origin.
+struct Shape {
+    points: Vec<[f64; 2]>,
+    size: [f64; 2],
+
+    /// A point inside the outline, to tell when one outline lies wholly within another.
+    inside: [f64; 2],
+
+    angle: f64,
+    corner: [f64; 2],
+}
+
+impl Shape {
+    fn new(outline: &[[f64; 2]], angle: f64) -> Option<Self> {
+        let (sin, cos) = angle.sin_cos();
+        let turned: Vec<[f64; 2]> = outline
+            .iter()
+            .map(|&[x, y]| [cos * x - sin * y, sin * x + cos * y])
+            .collect();
+        let ([left, bottom], [right, top]) = bounds(&turned)?;
+        let points: Vec<[f64; 2]> = turned
+            .iter()
+            .map(|&[x, y]| [x - left, y - bottom])
+            .collect();
+        let inside = inside_point(&points)?;
+        Some(Self {
+            points,
+            size: [right - left, top - bottom],
+            inside,
+            angle,
+            corner: [left, bottom],
+        })
+    }
+
+    fn moved(&self, [x, y]: [f64; 2]) -> Placed {
+        Placed {
+            points: self.points.iter().map(|p| [p[0] + x, p[1] + y]).collect(),
+            low: [x, y],
+            high: [x + self.size[0], y + self.size[1]],
+            inside: [self.inside[0] + x, self.inside[1] + y],
+        }
+    }
+}
+
+/// An outline where it lies on the sheet.
+struct Placed {
+    points: Vec<[f64; 2]>,
+    low: [f64; 2],
+    high: [f64; 2],
+    inside: [f64; 2],
+}
+
+fn bounds(points: &[[f64; 2]]) -> Option<([f64; 2], [f64; 2])> {
+    let first = *points.first()?;
+    Some(points.iter().fold((first, first), |(low, high), p| {
+        (
+            [low[0].min(p[0]), low[1].min(p[1])],
+            [high[0].max(p[0]), high[1].max(p[1])],
+        )
+    }))
+}
+
+/// The middle of the first stretch inside a closed outline, across its middle.
+fn inside_point(points: &[[f64; 2]]) -> Option<[f64; 2]> {
+    let ([_, bottom], [_, top]) = bounds(points)?;
+    // Just off the middle, so as not to run through a vertex of a symmetric outline.
+    let y = bottom + (top - bottom) * 0.500_3;
+    let mut crossings: Vec<f64> = points
+        .windows(2)
+        .filter(|pair| (pair[0][1] > y) != (pair[1][1] > y))
+        .map(|pair| {
+            let ([x1, y1], [x2, y2]) = (pair[0], pair[1]);
+            x1 + (y - y1) * (x2 - x1) / (y2 - y1)
+        })
+        .collect();
+    crossings.sort_by(f64::total_cmp);
+    (crossings.len() >= 2).then(|| [(crossings[0] + crossings[1]) / 2.0, y])
+}
+
+/// The middles of the stretches of the line from `a` to `b` between where it crosses `outline`.
+/// Each stretch lies wholly inside the outline or wholly outside.
+fn middles(a: [f64; 2], b: [f64; 2], outline: &[[f64; 2]]) -> Vec<[f64; 2]> {
+    const EPSILON: f64 = 1e-9;
+    let (r0, r1) = (b[0] - a[0], b[1] - a[1]);
+    let mut cuts = vec![0.0, 1.0];
+    for pair in outline.windows(2) {
+        let (c, d) = (pair[0], pair[1]);
+        let (s0, s1) = (d[0] - c[0], d[1] - c[1]);
+        let denominator = r0 * s1 - r1 * s0;
+        if denominator.abs() < EPSILON {
+            continue;
+        }
+        let (dx, dy) = (c[0] - a[0], c[1] - a[1]);
+        let t = (dx * s1 - dy * s0) / denominator;
+        let u = (dx * r1 - dy * r0) / denominator;
+        if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+            cuts.push(t);
+        }
+    }
+    cuts.sort_by(f64::total_cmp);
+    cuts.windows(2)
+        .filter(|pair| pair[1] - pair[0] > EPSILON)
+        .map(|pair| {
+            let t = (pair[0] + pair[1]) / 2.0;
+            [a[0] + t * r0, a[1] + t * r1]
+        })
+        .collect()
+}
+
+/// Whether the edges of `a` run anywhere more than `margin` outside `outline`.
+fn sticks_out(a: &[[f64; 2]], outline: &[[f64; 2]], margin: f64) -> bool {
+    a.windows(2).any(|edge| {
+        middles(edge[0], edge[1], outline)
+            .into_iter()
+            .any(|middle| {
+                !toolpath_tree::contains(outline, middle) && !near(outline, middle, margin)
+            })
+    })
+}
+
+/// Whether the edges of `a` run anywhere inside `outline`, other than along it.
+fn runs_into(a: &[[f64; 2]], outline: &[[f64; 2]]) -> bool {
+    a.windows(2).any(|edge| {
+        middles(edge[0], edge[1], outline)
+            .into_iter()
+            .any(|middle| toolpath_tree::contains(outline, middle) && !near(outline, middle, 1e-6))
+    })
+}
+
+/// Whether two closed outlines overlap, other than by touching.
+fn overlap(a: &Placed, b: &Placed) -> bool {
+    if !boxes_overlap((a.low, a.high), (b.low, b.high)) {
+        return false;
+    }
+    // Outlines lying one on the other have no edge inside the other, but a point inside.
+    toolpath_tree::contains(&b.points, a.inside)
+        || toolpath_tree::contains(&a.points, b.inside)
+        || runs_into(&a.points, &b.points)
+        || runs_into(&b.points, &a.points)
+}
+
+/// Lays out copies of the parts on the sheet inside the closed outline `sheet`.
+pub fn nest(parts: &[Part], sheet: &[[f64; 2]], settings: &Nest) -> Nesting {
+    let Some((sheet_low, sheet_high)) = bounds(sheet) else {
+        return Nesting {
+            unplaced: parts.len() * settings.copies,
+            ..Nesting::default()
+        };
+    };
+    // The outlines are grown by half the spacing already; the rest keeps them off the edge.
+    let margin = settings.spacing / 2.0;
+    let inside_sheet = |placed: &Placed| {
+        placed.low[0] >= sheet_low[0] + margin
+            && placed.low[1] >= sheet_low[1] + margin
+            && placed.high[0] <= sheet_high[0] - margin
+            && placed.high[1] <= sheet_high[1] - margin
+            && placed.points.iter().all(|&point| {
+                (toolpath_tree::contains(sheet, point) || near(sheet, point, 0.0))
+                    && clear(sheet, point, margin)
+            })
+            && !sticks_out(&placed.points, sheet, 0.0)
+            // A corner of the sheet poking towards an edge between its ends.
+            && sheet.iter().all(|&corner| clear(&placed.points, corner, margin))
+    };
+
+    let angles = settings.angles();
+    let shapes: Vec<Vec<Shape>> = parts
+        .iter()
+        .map(|part| {
+            angles
+                .iter()
+                .filter_map(|&angle| Shape::new(&part.outline, angle))
+                .collect()
+        })
+        .collect();
+    let mut queue: Vec<usize> = (0..parts.len())
+        .flat_map(|part| std::iter::repeat(part).take(settings.copies))
+        .collect();
+    queue.sort_by(|&a, &b| parts[b].area.total_cmp(&parts[a].area));
+
+    let mut nesting = Nesting::default();
+    let mut placed: Vec<Placed> = Vec::new();
+    for part in queue {
+        let fits = |shape: &Shape, at: [f64; 2]| {
+            let (low, high) = (at, [at[0] + shape.size[0], at[1] + shape.size[1]]);
+            if low[0] < sheet_low[0] + margin
+                || low[1] < sheet_low[1] + margin
+                || high[0] > sheet_high[0] - margin
+                || high[1] > sheet_high[1] - margin
+            {
+                return false;
+            }
+            let candidate = shape.moved(at);
+            !placed
+                .iter()
+                .filter(|other| boxes_overlap((low, high), (other.low, other.high)))
+                .any(|other| overlap(&candidate, other))
+                && inside_sheet(&candidate)
+        };
+        let corners = corners(sheet, margin, &placed);
+
+        let mut best: Option<(f64, f64, &Shape, [f64; 2])> = None;
+        for shape in &shapes[part] {
+            for &[x, y] in &corners {
+                if !fits(shape, [x, y]) {
+                    continue;
+                }
+                let y = slide(y, sheet_low[1] + margin, |y| fits(shape, [x, y]));
+                let x = slide(x, sheet_low[0] + margin, |x| fits(shape, [x, y]));
+                let top = y + shape.size[1];
+                if best.map_or(true, |(best_top, best_x, _, _)| {
+                    top < best_top - 1e-9 || top < best_top + 1e-9 && x < best_x
+                }) {
+                    best = Some((top, x, shape, [x, y]));
+                }
+            }
+        }
+
+        match best {
+            Some((_, _, shape, at)) => {
+                placed.push(shape.moved(at));
+                let origin = [at[0] - shape.corner[0], at[1] - shape.corner[1]];
+                nesting.placements.push(Placement {
+                    part,
+                    transform: Transform::placement(origin, shape.angle, [1.0, 1.0], [0.0, 0.0]),
+                });
+            }
+            None => nesting.unplaced += 1,
+        }
+    }
+
+    let sheet_area = polygon_area(sheet).abs();
+    if sheet_area > 0.0 {
+        let used: f64 = nesting
+            .placements
+            .iter()
+            .map(|placement| parts[placement.part].area)
+            .sum();
+        nesting.utilization = used / sheet_area;
+    }
+    nesting
+}
+
+/// The places to try a part's lower left corner at: the sheet's corners and, for each part laid
+/// out, just right of it and just above it, level with its bottom, its left and the sheet's
+/// edge. Places inside a part laid out are left out.
+fn corners(sheet: &[[f64; 2]], margin: f64, placed: &[Placed]) -> Vec<[f64; 2]> {
+    let Some((sheet_low, _)) = bounds(sheet) else {
+        return vec![];
+    };
+    let (left, bottom) = (sheet_low[0] + margin, sheet_low[1] + margin);
+    let mut xs: Vec<f64> = sheet.iter().map(|point| point[0] + margin).collect();
+    let mut ys: Vec<f64> = sheet.iter().map(|point| point[1] + margin).collect();
+    for values in [&mut xs, &mut ys] {
+        values.sort_by(f64::total_cmp);
+        values.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+    }
+    let mut corners: Vec<[f64; 2]> = xs
+        .iter()
+        .flat_map(|&x| ys.iter().map(move |&y| [x, y]))
+        .chain(placed.iter().flat_map(|other| {
+            [
+                [other.high[0], other.low[1]],
+                [other.high[0], bottom],
+                [other.low[0], other.high[1]],
+                [left, other.high[1]],
+            ]
+        }))
+        .filter(|&[x, y]| {
+            !placed.iter().any(|other| {
+                other.low[0] < x - 1e-9
+                    && x + 1e-9 < other.high[0]
+                    && other.low[1] < y - 1e-9
+                    && y + 1e-9 < other.high[1]
+                    && toolpath_tree::contains(&other.points, [x, y])
+            })
+        })
+        .collect();
+    corners.sort_by(|a, b| a[1].total_cmp(&b[1]).then(a[0].total_cmp(&b[0])));
+    corners.dedup_by(|a, b| (a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9);
+    corners
+}
+
+fn boxes_overlap(a: ([f64; 2], [f64; 2]), b: ([f64; 2], [f64; 2])) -> bool {
+    a.0[0] < b.1[0] && b.0[0] < a.1[0] && a.0[1] < b.1[1] && b.0[1] < a.1[1]
+}
+
+/// How far from `from` towards `limit` a valid position goes, `from` being valid.
+fn slide(from: f64, limit: f64, valid: impl Fn(f64) -> bool) -> f64 {
+    if limit >= from {
+        return from;
+    }
+    if valid(limit) {
+        return limit;
+    }
+    let (mut good, mut bad) = (from, limit);
+    for _ in 0..SLIDE_STEPS {
+        let middle = (good + bad) / 2.0;
+        if valid(middle) {
+            good = middle;
+        } else {
+            bad = middle;
+        }
+    }
+    good
+}
+
+fn near(outline: &[[f64; 2]], point: [f64; 2], distance: f64) -> bool {
+    outline.windows(2).any(|edge| {
+        toolpath_sketch::distance_to_segment(point, edge[0], edge[1]) <= distance + 1e-9
+    })
+}
+
+/// Whether `point` is at least `distance` from every edge of `outline`.
+fn clear(outline: &[[f64; 2]], point: [f64; 2], distance: f64) -> bool {
+    outline.windows(2).all(|edge| {
+        toolpath_sketch::distance_to_segment(point, edge[0], edge[1]) >= distance - 1e-9
+    })
+}
+
+fn polygon_area(points: &[[f64; 2]]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| (pair[0][0] * pair[1][1] - pair[1][0] * pair[0][1]) / 2.0)
+        .sum()
+}
+
+/// The contours of every copy laid out.
+pub fn contours(parts: &[Part], nesting: &Nesting, tolerance: f64) -> Vec<Contour> {
+    nesting
+        .placements
+        .iter()
+        .flat_map(|placement| {
+            parts[placement.part].contours.iter().map(move |contour| {
+                let segments = placement
+                    .transform
+                    .segments(&toolpath_sketch::segments(&contour.polyline), tolerance);
+                Contour {
+                    layer: contour.layer.clone(),
+                    polyline: toolpath_sketch::polyline(&segments, contour.polyline.is_closed),
+                }
+            })
+        })
+        .collect()
+}
+
+/// A `width` by `height` sheet with its corner at the origin, as a closed outline.
+pub fn rectangle(width: f64, height: f64) -> Vec<[f64; 2]> {
+    vec![
+        [0.0, 0.0],
+        [width, 0.0],
+        [width, height],
+        [0.0, height],
+        [0.0, 0.0],
+    ]
+}
+
+#[test]
+fn test_toolpath_nest() {
+    use super::toolpath_sketch::Segment;
+
+    let rectangle_contour = |x: f64, y: f64, width: f64, height: f64| {
+        let corners = [
+            [x, y],
+            [x + width, y],
+            [x + width, y + height],
+            [x, y + height],
+        ];
+        Contour {
+            layer: "cut".to_owned(),
+            polyline: toolpath_sketch::polyline(
+                &(0..4)
+                    .map(|i| Segment::line(corners[i], corners[(i + 1) % 4]))
+                    .collect::<Vec<_>>(),
+                true,
+            ),
+        }
+    };
+    // A 40 by 10 plate with a hole, and a line off on its own.
+    let contours = vec![
+        rectangle_contour(100.0, 100.0, 40.0, 10.0),
+        rectangle_contour(105.0, 102.0, 5.0, 5.0),
+        Contour {
+            layer: "engrave".to_owned(),
+            polyline: toolpath_sketch::polyline(&[Segment::line([0.0, 0.0], [1.0, 1.0])], false),
+        },
+    ];
+    let tree = Tree::new(&contours, 0.01);
+    let (parts, loose) = parts(&contours, &tree, 0.0, 0.01);
+    assert_eq!((parts.len(), loose), (1, 1));
+    assert_eq!(parts[0].contours.len(), 2);
+    assert!((parts[0].area - 375.0).abs() < 1e-9);
+
+    // Five fit in a 40 by 55 sheet lying flat; the sixth does not.
+    let settings = Nest {
+        copies: 6,
+        spacing: 0.0,
+        rotations: Rotations::None,
+        grain: Grain::Free,
+    };
+    let nesting = nest(&parts, &rectangle(40.0, 55.0), &settings);
+    assert_eq!((nesting.placements.len(), nesting.unplaced), (5, 1));
+    assert!((nesting.utilization - 5.0 * 375.0 / 2200.0).abs() < 1e-9);
+    let laid_out = super::toolpath_nest::contours(&parts, &nesting, 0.01);
+    assert_eq!(laid_out.len(), 10);
+    let first = &laid_out[0].polyline.vertex_data[0];
+    assert!(first.x.abs() < 1e-6 && first.y.abs() < 1e-6);
+
+    // Turned a quarter, they stand side by side in a short, wide sheet.
+    let settings = Nest {
+        copies: 3,
+        rotations: Rotations::Quarter,
+        ..settings
+    };
+    let nesting = nest(&parts, &rectangle(30.0, 40.0), &settings);
+    assert_eq!(nesting.unplaced, 0);
+    // The grain along x rules out the quarter turns.
+    let grain = Nest {
+        grain: Grain::AlongX,
+        ..settings
+    };
+    assert_eq!(grain.angles().len(), 2);
+    assert_eq!(
+        nest(&parts, &rectangle(30.0, 40.0), &grain)
+            .placements
+            .len(),
+        0
+    );
+
+    // An L-shaped remnant takes parts in both legs.
+    let remnant = vec![
+        [0.0, 0.0],
+        [45.0, 0.0],
+        [45.0, 25.0],
+        [10.0, 25.0],
+        [10.0, 70.0],
+        [0.0, 70.0],
+        [0.0, 0.0],
+    ];
+    let settings = Nest {
+        copies: 3,
+        ..settings
+    };
+    let nesting = nest(&parts, &remnant, &settings);
+    assert_eq!((nesting.placements.len(), nesting.unplaced), (3, 0));
+
+    // With 2 mm spacing a part keeps 2 mm from the sheet's edge, and a 40 by 10 part needs a
+    // sheet of at least 44 by 14.
+    let (parts, _) = self::parts(&contours, &tree, 2.0, 0.01);
+    let settings = Nest {
+        copies: 1,
+        spacing: 2.0,
+        rotations: Rotations::None,
+        grain: Grain::Free,
+    };
+    let nesting = nest(&parts, &rectangle(44.5, 14.5), &settings);
+    assert_eq!(nesting.placements.len(), 1);
+    let laid_out = super::toolpath_nest::contours(&parts, &nesting, 0.01);
+    let first = &laid_out[0].polyline.vertex_data[0];
+    assert!((first.x - 2.0).abs() < 1e-6 && (first.y - 2.0).abs() < 1e-6);
+    assert_eq!(nest(&parts, &rectangle(43.5, 14.5), &settings).unplaced, 1);
+    assert_eq!(nest(&parts, &rectangle(44.5, 13.5), &settings).unplaced, 1);
+}
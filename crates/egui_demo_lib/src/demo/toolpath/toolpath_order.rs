@@ -0,0 +1,237 @@
+//! The order to cut contours in.
+//!
+//! Whatever lies inside a contour is cut before it, since once a part is cut free it can shift
+//! or drop and take its holes out of place. Among the contours that are free to go next, the
+//! one starting nearest to where the last one ended goes first. A plasma torch dragged over a
+//! part already cut can catch on it if the part has tipped, so rapids over cut parts may be put
+//! off for as long as another choice remains.
+
+use cavalier_contours::polyline::Polyline;
+
+use super::toolpath_sketch;
+use super::toolpath_tree::{self, Tree};
+
+/// A path to cut, as it will be cut.
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub path: Polyline<f64>,
+
+    /// The contour that must be cut after this one, if any.
+    pub parent: Option<usize>,
+
+    /// The outline of the part it cuts free, or `None` for a hole or an open path.
+    pub part: Option<Vec<[f64; 2]>>,
+}
+
+/// The jobs for each contour, cut along `paths`, which may differ from the contours by their
+/// leads. Open contours go with the smallest closed contour around their start. Arcs are
+/// flattened to within `tolerance` to tell what lies inside what.
+pub fn jobs(
+    contours: &[Polyline<f64>],
+    paths: &[Polyline<f64>],
+    tree: &Tree,
+    tolerance: f64,
+) -> Vec<Job> {
+    let outlines: Vec<Option<Vec<[f64; 2]>>> = contours
+        .iter()
+        .map(|contour| {
+            contour
+                .is_closed
+                .then(|| toolpath_sketch::points(contour, tolerance))
+        })
+        .collect();
+    contours
+        .iter()
+        .zip(paths)
+        .enumerate()
+        .map(|(index, (contour, path))| {
+            let parent = if contour.is_closed {
+                tree.parents[index]
+            } else {
+                let start = contour
+                    .vertex_data
+                    .first()
+                    .map_or([0.0, 0.0], |vertex| [vertex.x, vertex.y]);
+                outlines
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(other, outline)| {
+                        let outline = outline.as_ref()?;
+                        toolpath_tree::contains(outline, start)
+                            .then(|| (other, toolpath_sketch::area(&contours[other]).abs()))
+                    })
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(other, _)| other)
+            };
+            let part = if tree.depths[index].is_some() && !tree.is_hole(index) {
+                outlines[index].clone()
+            } else {
+                None
+            };
+            Job {
+                path: path.clone(),
+                parent,
+                part,
+            }
+        })
+        .collect()
+}
+
+fn ends(path: &Polyline<f64>) -> Option<([f64; 2], [f64; 2])> {
+    let first = path.vertex_data.first()?;
+    let last = if path.is_closed {
+        first
+    } else {
+        path.vertex_data.last()?
+    };
+    Some(([first.x, first.y], [last.x, last.y]))
+}
+
+/// The order to cut the jobs in, starting from `start`.
+pub fn order(jobs: &[Job], start: [f64; 2], avoid_cut_parts: bool) -> Vec<usize> {
+    let mut waiting: Vec<usize> = vec![0; jobs.len()];
+    for job in jobs {
+        if let Some(parent) = job.parent {
+            waiting[parent] += 1;
+        }
+    }
+    let mut done = vec![false; jobs.len()];
+    let mut cut_parts: Vec<&[[f64; 2]]> = Vec::new();
+    let mut order = Vec::with_capacity(jobs.len());
+    let mut position = start;
+    while order.len() < jobs.len() {
+        let next = (0..jobs.len())
+            .filter(|&index| !done[index] && waiting[index] == 0)
+            .map(|index| {
+                let begin = ends(&jobs[index].path).map_or(position, |(begin, _)| begin);
+                let over = if avoid_cut_parts {
+                    cut_parts
+                        .iter()
+                        .filter(|part| passes_over(position, begin, part))
+                        .count()
+                } else {
+                    0
+                };
+                (index, over, toolpath_sketch::distance(position, begin))
+            })
+            .min_by(|a, b| a.1.cmp(&b.1).then(a.2.total_cmp(&b.2)));
+        // A loop in the parents would leave nothing free to go; cut the rest as they come.
+        let index = match next {
+            Some((index, _, _)) => index,
+            None => (0..jobs.len()).find(|&index| !done[index]).unwrap_or(0),
+        };
+        done[index] = true;
+        order.push(index);
+        if let Some(parent) = jobs[index].parent {
+            waiting[parent] = waiting[parent].saturating_sub(1);
+        }
+        if let Some(part) = &jobs[index].part {
+            cut_parts.push(part);
+        }
+        if let Some((_, end)) = ends(&jobs[index].path) {
+            position = end;
+        }
+    }
+    order
+}
+
+/// Whether the straight move from `a` to `b` runs over the inside of the polygon through
+/// `outline`, other than by touching it.
+pub fn passes_over(a: [f64; 2], b: [f64; 2], outline: &[[f64; 2]]) -> bool {
+    const EPSILON: f64 = 1e-9;
+    let (r0, r1) = (b[0] - a[0], b[1] - a[1]);
+    let mut cuts = vec![0.0, 1.0];
+    for pair in outline.windows(2) {
+        let (c, d) = (pair[0], pair[1]);
+        let (s0, s1) = (d[0] - c[0], d[1] - c[1]);
+        let denominator = r0 * s1 - r1 * s0;
+        if denominator.abs() < EPSILON {
+            continue;
+        }
+        let (dx, dy) = (c[0] - a[0], c[1] - a[1]);
+        let t = (dx * s1 - dy * s0) / denominator;
+        let u = (dx * r1 - dy * r0) / denominator;
+        if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+            cuts.push(t);
+        }
+    }
+    cuts.sort_by(f64::total_cmp);
+    // Between crossings the move is wholly inside or outside, so its middle tells.
+    cuts.windows(2)
+        .filter(|pair| pair[1] - pair[0] > EPSILON)
+        .any(|pair| {
+            let t = (pair[0] + pair[1]) / 2.0;
+            let middle = [a[0] + t * r0, a[1] + t * r1];
+            let on_outline = outline.windows(2).any(|edge| {
+                toolpath_sketch::distance_to_segment(middle, edge[0], edge[1]) < EPSILON.sqrt()
+            });
+            !on_outline && toolpath_tree::contains(outline, middle)
+        })
+}
+
+#[test]
+fn test_toolpath_order() {
+    use super::toolpath_sketch::{Contour, Segment};
+
+    let square = |x: f64, y: f64, size: f64| {
+        let corners = [[x, y], [x + size, y], [x + size, y + size], [x, y + size]];
+        toolpath_sketch::polyline(
+            &(0..4)
+                .map(|i| Segment::line(corners[i], corners[(i + 1) % 4]))
+                .collect::<Vec<_>>(),
+            true,
+        )
+    };
+    // Two plates in a row, each with a hole, and a line engraved on the far one.
+    let contours = vec![
+        square(0.0, 0.0, 20.0),
+        square(100.0, 0.0, 20.0),
+        square(5.0, 5.0, 5.0),
+        square(105.0, 5.0, 5.0),
+        toolpath_sketch::polyline(&[Segment::line([112.0, 12.0], [118.0, 18.0])], false),
+    ];
+    let tree = Tree::new(
+        &contours
+            .iter()
+            .map(|polyline| Contour {
+                layer: String::new(),
+                polyline: polyline.clone(),
+            })
+            .collect::<Vec<_>>(),
+        0.01,
+    );
+    let plates = jobs(&contours, &contours, &tree, 0.01);
+    assert_eq!(plates[4].parent, Some(1));
+    assert!(plates[0].part.is_some() && plates[2].part.is_none());
+
+    // Holes first, then their plate, nearest first.
+    assert_eq!(order(&plates, [0.0, 0.0], false), [2, 0, 3, 4, 1]);
+    assert_eq!(order(&plates, [200.0, 0.0], false), [4, 3, 1, 2, 0]);
+
+    // Starting off to the left of a plate that is already cut, the way to the right plate's
+    // hole runs over it.
+    let plate = &plates[0].part.clone().unwrap();
+    assert!(passes_over([-10.0, 10.0], [105.0, 5.0], plate));
+    assert!(!passes_over([0.0, 0.0], [100.0, 0.0], plate));
+    assert!(!passes_over([-10.0, 30.0], [105.0, 30.0], plate));
+
+    // From the corner of a plate just cut, the nearer plate lies across it.
+    let contours = vec![
+        square(0.0, 0.0, 20.0),
+        square(-40.0, 0.0, 5.0),
+        square(30.0, 10.0, 5.0),
+    ];
+    let tree = Tree::new(
+        &contours
+            .iter()
+            .map(|polyline| Contour {
+                layer: String::new(),
+                polyline: polyline.clone(),
+            })
+            .collect::<Vec<_>>(),
+        0.01,
+    );
+    let jobs = jobs(&contours, &contours, &tree, 0.01);
+    assert_eq!(order(&jobs, [0.0, -1.0], false), [0, 2, 1]);
+    assert_eq!(order(&jobs, [0.0, -1.0], true), [0, 1, 2]);
+}
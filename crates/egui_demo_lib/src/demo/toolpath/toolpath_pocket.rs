@@ -0,0 +1,300 @@
+//! Clearing the material inside closed contours.
+//!
+//! Each contour at even depth in the [`Tree`] bounds a pocket, and the contours directly inside
+//! it are islands the tool goes around. The tool either follows offsets of the walls inward
+//! from the middle out, or runs back and forth in rows and then goes once around the walls.
+
+use cavalier_contours::polyline::Polyline;
+use cavalier_contours::shape_algorithms::{Shape, ShapeOffsetOptions};
+
+use super::toolpath_sketch::{self, Contour, Segment};
+use super::toolpath_tree::{self, Tree};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Loops parallel to the walls, one stepover apart.
+    Offset,
+
+    /// Rows one stepover apart, joined end to end where the way between them is clear.
+    Zigzag,
+
+    /// Rows one stepover apart, all cut the same way.
+    Raster,
+}
+
+/// Which way the tool runs along the material, for a spindle turning clockwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// With the material on the right of the tool.
+    Climb,
+
+    /// With the material on the left of the tool.
+    Conventional,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pocket {
+    pub strategy: Strategy,
+    pub direction: Direction,
+
+    /// How far apart neighboring passes are, in mm.
+    pub stepover: f64,
+}
+
+impl Default for Pocket {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::Offset,
+            direction: Direction::Climb,
+            stepover: 1.0,
+        }
+    }
+}
+
+/// The paths of a tool of `radius` clearing every pocket, in the order to cut them. Arcs are
+/// flattened to within `tolerance` to lay out rows.
+pub fn pocket(
+    contours: &[Contour],
+    tree: &Tree,
+    radius: f64,
+    settings: &Pocket,
+    tolerance: f64,
+) -> Vec<Polyline<f64>> {
+    let mut paths = Vec::new();
+    for (index, contour) in contours.iter().enumerate() {
+        if tree.depths[index].map_or(true, |depth| depth % 2 == 1) {
+            continue;
+        }
+        // Walls run counterclockwise and islands clockwise, so offsets go into the pocket.
+        let boundary = oriented(&contour.polyline, true);
+        let islands = tree
+            .children(index)
+            .map(|island| oriented(&contours[island].polyline, false));
+        let shape = Shape::from_plines(std::iter::once(boundary).chain(islands));
+        let walls = loops(&shape.parallel_offset(radius, ShapeOffsetOptions::default()));
+        if walls.is_empty() {
+            continue;
+        }
+
+        let mut cleared = match settings.strategy {
+            Strategy::Offset => offsets(&shape, radius, settings.stepover),
+            Strategy::Zigzag | Strategy::Raster => {
+                let mut rows = rows(&walls, settings, tolerance);
+                rows.extend(walls);
+                rows
+            }
+        };
+        if settings.direction == Direction::Conventional {
+            for path in &mut cleared {
+                *path = toolpath_sketch::reversed(path);
+            }
+        }
+        paths.extend(cleared);
+    }
+    paths
+}
+
+/// The polyline running counterclockwise, or clockwise.
+fn oriented(polyline: &Polyline<f64>, counterclockwise: bool) -> Polyline<f64> {
+    if (toolpath_sketch::area(polyline) > 0.0) == counterclockwise {
+        polyline.clone()
+    } else {
+        toolpath_sketch::reversed(polyline)
+    }
+}
+
+fn loops(shape: &Shape<f64>) -> Vec<Polyline<f64>> {
+    shape
+        .ccw_plines
+        .iter()
+        .chain(&shape.cw_plines)
+        .map(|indexed| indexed.polyline.clone())
+        .collect()
+}
+
+/// Offsets of the walls from the innermost out, ending with the walls themselves.
+fn offsets(shape: &Shape<f64>, radius: f64, stepover: f64) -> Vec<Polyline<f64>> {
+    let mut levels = Vec::new();
+    loop {
+        let offset = radius + levels.len() as f64 * stepover;
+        let level = loops(&shape.parallel_offset(offset, ShapeOffsetOptions::default()));
+        if level.is_empty() || stepover <= 0.0 {
+            levels.push(level);
+            break;
+        }
+        levels.push(level);
+    }
+    levels.into_iter().rev().flatten().collect()
+}
+
+/// Rows across the area inside `walls`, from the bottom up. Climb milling runs the rows
+/// towards -x, so the material not yet cut lies on the right.
+fn rows(walls: &[Polyline<f64>], settings: &Pocket, tolerance: f64) -> Vec<Polyline<f64>> {
+    let outlines: Vec<Vec<[f64; 2]>> = walls
+        .iter()
+        .map(|wall| toolpath_sketch::points(wall, tolerance))
+        .collect();
+    let edges: Vec<([f64; 2], [f64; 2])> = outlines
+        .iter()
+        .flat_map(|points| points.windows(2).map(|pair| (pair[0], pair[1])))
+        .collect();
+    let (bottom, top) = edges
+        .iter()
+        .map(|(a, _)| a[1])
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), y| {
+            (low.min(y), high.max(y))
+        });
+    if bottom >= top {
+        return Vec::new();
+    }
+
+    // Rows evenly spaced no more than a stepover apart, half a spacing in from the extremes.
+    let count = ((top - bottom) / settings.stepover.max(tolerance))
+        .ceil()
+        .max(1.0) as usize;
+    let spacing = (top - bottom) / count as f64;
+    let spans: Vec<(f64, Vec<[f64; 2]>)> = (0..count)
+        .map(|row| {
+            let y = bottom + (row as f64 + 0.5) * spacing;
+            let mut crossings: Vec<f64> = edges
+                .iter()
+                .filter(|([_, y1], [_, y2])| (*y1 > y) != (*y2 > y))
+                .map(|([x1, y1], [x2, y2])| x1 + (y - y1) * (x2 - x1) / (y2 - y1))
+                .collect();
+            crossings.sort_by(f64::total_cmp);
+            let spans = crossings.chunks_exact(2).map(|pair| [pair[0], pair[1]]);
+            (y, spans.collect())
+        })
+        .collect();
+
+    let leftward = settings.direction == Direction::Climb;
+    let row_segment = |y: f64, [left, right]: [f64; 2], leftward: bool| {
+        if leftward {
+            Segment::line([right, y], [left, y])
+        } else {
+            Segment::line([left, y], [right, y])
+        }
+    };
+    if settings.strategy == Strategy::Raster {
+        return spans
+            .iter()
+            .flat_map(|(y, spans)| {
+                spans.iter().map(move |&span| {
+                    toolpath_sketch::polyline(&[row_segment(*y, span, leftward)], false)
+                })
+            })
+            .collect();
+    }
+
+    // Zigzag: start each path at the lowest row left, and keep going up a row while there is
+    // a span whose near end can be reached without crossing a wall. The way up often runs
+    // along a wall, so its middle may lie on one rather than inside.
+    let clear = |a: [f64; 2], b: [f64; 2]| {
+        let middle = [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+        let on_wall = edges
+            .iter()
+            .any(|&(c, d)| toolpath_sketch::distance_to_segment(middle, c, d) <= tolerance);
+        let inside = outlines
+            .iter()
+            .filter(|points| toolpath_tree::contains(points, middle))
+            .count()
+            % 2
+            == 1;
+        (on_wall || inside) && !edges.iter().any(|&(c, d)| crosses(a, b, c, d))
+    };
+    let mut left: Vec<Vec<[f64; 2]>> = spans.iter().map(|(_, spans)| spans.clone()).collect();
+    let mut paths = Vec::new();
+    while let Some(first) = left.iter().position(|spans| !spans.is_empty()) {
+        let mut leftward = leftward;
+        let mut segments = vec![row_segment(spans[first].0, left[first].remove(0), leftward)];
+        for (row, y) in spans.iter().map(|(y, _)| *y).enumerate().skip(first + 1) {
+            let end = segments[segments.len() - 1].end;
+            let next = left[row]
+                .iter()
+                .position(|&[low, high]| clear(end, [if leftward { low } else { high }, y]));
+            let Some(next) = next else {
+                break;
+            };
+            leftward = !leftward;
+            let segment = row_segment(y, left[row].remove(next), leftward);
+            segments.push(Segment::line(end, segment.start));
+            segments.push(segment);
+        }
+        paths.push(toolpath_sketch::polyline(&segments, false));
+    }
+    paths
+}
+
+/// Whether segment `ab` crosses `cd` somewhere other than at its own ends.
+fn crosses(a: [f64; 2], b: [f64; 2], c: [f64; 2], d: [f64; 2]) -> bool {
+    const EPSILON: f64 = 1e-9;
+    let (r, s) = ([b[0] - a[0], b[1] - a[1]], [d[0] - c[0], d[1] - c[1]]);
+    let denominator = r[0] * s[1] - r[1] * s[0];
+    if denominator.abs() < EPSILON {
+        return false;
+    }
+    let (dx, dy) = (c[0] - a[0], c[1] - a[1]);
+    let t = (dx * s[1] - dy * s[0]) / denominator;
+    let u = (dx * r[1] - dy * r[0]) / denominator;
+    EPSILON < t && t < 1.0 - EPSILON && (0.0..=1.0).contains(&u)
+}
+
+#[test]
+fn test_toolpath_pocket() {
+    let square = |x: f64, y: f64, size: f64, counterclockwise: bool| {
+        let mut corners = vec![[x, y], [x + size, y], [x + size, y + size], [x, y + size]];
+        if !counterclockwise {
+            corners.reverse();
+        }
+        Contour {
+            layer: String::new(),
+            polyline: toolpath_sketch::polyline(
+                &(0..4)
+                    .map(|i| Segment::line(corners[i], corners[(i + 1) % 4]))
+                    .collect::<Vec<_>>(),
+                true,
+            ),
+        }
+    };
+    // A clockwise pocket 20 across with an island in the middle.
+    let contours = vec![square(0.0, 0.0, 20.0, false), square(8.0, 8.0, 4.0, true)];
+    let tree = Tree::new(&contours, 0.01);
+    let mut settings = Pocket {
+        stepover: 2.0,
+        ..Pocket::default()
+    };
+
+    // Offsets: rings 2 apart between the walls and the island, the walls last and climbing.
+    let paths = pocket(&contours, &tree, 1.0, &settings, 0.01);
+    let (wall, island) = (&paths[paths.len() - 2], &paths[paths.len() - 1]);
+    assert!((toolpath_sketch::area(wall) - 18.0 * 18.0).abs() < 1e-6);
+    // The island's corners may come out rounded.
+    assert!((toolpath_sketch::area(island) + 6.0 * 6.0).abs() < 1.0);
+    assert!(toolpath_sketch::area(&paths[0]).abs() < 18.0 * 18.0);
+
+    settings.direction = Direction::Conventional;
+    let paths = pocket(&contours, &tree, 1.0, &settings, 0.01);
+    assert!(toolpath_sketch::area(&paths[paths.len() - 2]) < 0.0);
+
+    // Raster: each row is split by the island, and runs towards -x when climbing.
+    settings.strategy = Strategy::Raster;
+    settings.direction = Direction::Climb;
+    let paths = pocket(&contours, &tree, 1.0, &settings, 0.01);
+    let rows = &paths[..paths.len() - 2];
+    assert_eq!(rows.len(), 9 + 3);
+    assert!(rows.iter().all(|row| {
+        let vertices = &row.vertex_data;
+        vertices.len() == 2 && vertices[1].x < vertices[0].x
+    }));
+
+    // Zigzag: the rows join into a path up each side of the island.
+    settings.strategy = Strategy::Zigzag;
+    let paths = pocket(&contours, &tree, 1.0, &settings, 0.01);
+    let rows = &paths[..paths.len() - 2];
+    assert_eq!(rows.len(), 3);
+    let segments: usize = rows
+        .iter()
+        .map(|row| toolpath_sketch::segments(row).len())
+        .sum();
+    assert_eq!(segments, 2 * (9 + 3) - rows.len());
+}
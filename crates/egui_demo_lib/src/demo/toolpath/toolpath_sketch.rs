@@ -0,0 +1,685 @@
+//! Imported drawings as `cavalier_contours` polylines, one contour per closed shape or open path.
+//!
+//! Arcs stay arcs: a vertex's bulge is the tangent of a quarter of the arc's sweep to the next
+//! vertex, positive counterclockwise, so circles and arcs reach the toolpath without being split
+//! into chords. Loose lines and arcs that touch end to end are chained into single contours.
+
+use std::fmt;
+
+use cavalier_contours::polyline::{PlineVertex, Polyline};
+
+use crate::demo::gcode::gcode_arc;
+
+/// Length units a drawing declares.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Units {
+    /// The drawing does not say, which usually means millimetres.
+    #[default]
+    Unitless,
+    Millimeters,
+    Centimeters,
+    Meters,
+    Inches,
+    Feet,
+}
+
+impl Units {
+    /// Millimetres per unit, or `None` for [`Units::Unitless`].
+    pub fn millimeters(self) -> Option<f64> {
+        match self {
+            Self::Unitless => None,
+            Self::Millimeters => Some(1.0),
+            Self::Centimeters => Some(10.0),
+            Self::Meters => Some(1000.0),
+            Self::Inches => Some(25.4),
+            Self::Feet => Some(304.8),
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Unitless => "unitless",
+            Self::Millimeters => "mm",
+            Self::Centimeters => "cm",
+            Self::Meters => "m",
+            Self::Inches => "in",
+            Self::Feet => "ft",
+        })
+    }
+}
+
+/// One shape or path of a drawing.
+#[derive(Clone, Debug)]
+pub struct Contour {
+    /// The layer it was drawn on, empty if the format has none.
+    pub layer: String,
+
+    pub polyline: Polyline<f64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Sketch {
+    pub contours: Vec<Contour>,
+    pub units: Units,
+}
+
+impl Sketch {
+    /// The layers in use, in the order they first appear.
+    pub fn layers(&self) -> Vec<&str> {
+        let mut layers: Vec<&str> = vec![];
+        for contour in &self.contours {
+            if !layers.contains(&contour.layer.as_str()) {
+                layers.push(&contour.layer);
+            }
+        }
+        layers
+    }
+
+    /// Scales the drawing to millimetres, taking a unitless drawing to be in them already.
+    pub fn to_millimeters(&mut self) {
+        if let Some(scale) = self.units.millimeters() {
+            for contour in &mut self.contours {
+                for vertex in &mut contour.polyline.vertex_data {
+                    vertex.x *= scale;
+                    vertex.y *= scale;
+                }
+            }
+            self.units = Units::Millimeters;
+        }
+    }
+}
+
+/// A straight line, or an arc when `bulge` is not zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub start: [f64; 2],
+    pub end: [f64; 2],
+
+    /// The tangent of a quarter of the sweep, positive counterclockwise.
+    pub bulge: f64,
+}
+
+impl Segment {
+    pub fn line(start: [f64; 2], end: [f64; 2]) -> Self {
+        Self {
+            start,
+            end,
+            bulge: 0.0,
+        }
+    }
+
+    /// The same segment, run from its end to its start.
+    pub fn reversed(self) -> Self {
+        Self {
+            start: self.end,
+            end: self.start,
+            bulge: -self.bulge,
+        }
+    }
+
+    pub fn length(&self) -> f64 {
+        let chord = distance(self.start, self.end);
+        match self.arc() {
+            Some((_, radius)) => radius * 4.0 * self.bulge.atan().abs(),
+            None => chord,
+        }
+    }
+
+    /// The center and radius of an arc, or `None` for a line.
+    pub fn arc(&self) -> Option<([f64; 2], f64)> {
+        if self.bulge == 0.0 || self.start == self.end {
+            return None;
+        }
+        let b = self.bulge;
+        let (dx, dy) = (self.end[0] - self.start[0], self.end[1] - self.start[1]);
+        let radius = dx.hypot(dy) * (1.0 + b * b) / (4.0 * b.abs());
+        // The center sits off the middle of the chord, on its left for a counterclockwise arc
+        // of less than half a turn.
+        let f = (1.0 - b * b) / (4.0 * b);
+        let center = [
+            (self.start[0] + self.end[0]) / 2.0 - dy * f,
+            (self.start[1] + self.end[1]) / 2.0 + dx * f,
+        ];
+        Some((center, radius))
+    }
+
+    /// The point halfway along the segment.
+    pub fn midpoint(&self) -> [f64; 2] {
+        // An arc's middle sits off the chord's by the bulge times half the chord, to the right.
+        let (dx, dy) = (self.end[0] - self.start[0], self.end[1] - self.start[1]);
+        [
+            (self.start[0] + self.end[0] + self.bulge * dy) / 2.0,
+            (self.start[1] + self.end[1] - self.bulge * dx) / 2.0,
+        ]
+    }
+
+    /// The stretch of the segment between two fractions of its length.
+    pub fn part(&self, from: f64, to: f64) -> Self {
+        if from == 0.0 && to == 1.0 {
+            return *self;
+        }
+        let Some((center, radius)) = self.arc() else {
+            let at = |t: f64| {
+                [
+                    self.start[0] + t * (self.end[0] - self.start[0]),
+                    self.start[1] + t * (self.end[1] - self.start[1]),
+                ]
+            };
+            return Self::line(at(from), at(to));
+        };
+        let sweep = 4.0 * self.bulge.atan();
+        let start_angle = (self.start[1] - center[1]).atan2(self.start[0] - center[0]);
+        let at = |t: f64| {
+            let angle = start_angle + sweep * t;
+            [
+                center[0] + radius * angle.cos(),
+                center[1] + radius * angle.sin(),
+            ]
+        };
+        // The ends stay exact, so the parts still join up with their neighbors.
+        Self {
+            start: if from == 0.0 { self.start } else { at(from) },
+            end: if to == 1.0 { self.end } else { at(to) },
+            bulge: bulge(sweep * (to - from)),
+        }
+    }
+
+    /// Points along the segment, from just after `start` to `end`, no more than `tolerance` off
+    /// an arc.
+    pub fn flatten(&self, tolerance: f64, out: &mut Vec<[f64; 2]>) {
+        let Some((center, radius)) = self.arc() else {
+            out.push(self.end);
+            return;
+        };
+        let sweep = 4.0 * self.bulge.atan();
+        let max_angle = if tolerance > 0.0 && tolerance < radius {
+            2.0 * (1.0 - tolerance / radius).acos()
+        } else {
+            std::f64::consts::FRAC_PI_2
+        };
+        let steps = (sweep.abs() / max_angle).ceil().max(1.0) as usize;
+        let start_angle = (self.start[1] - center[1]).atan2(self.start[0] - center[0]);
+        for step in 1..steps {
+            let angle = start_angle + sweep * step as f64 / steps as f64;
+            out.push([
+                center[0] + radius * angle.cos(),
+                center[1] + radius * angle.sin(),
+            ]);
+        }
+        out.push(self.end);
+    }
+}
+
+pub fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+/// How far `point` lies from the nearest point of the line segment from `a` to `b`.
+pub fn distance_to_segment(point: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (along, across) = (
+        [b[0] - a[0], b[1] - a[1]],
+        [point[0] - a[0], point[1] - a[1]],
+    );
+    let length = along[0] * along[0] + along[1] * along[1];
+    let t = if length > 0.0 {
+        ((across[0] * along[0] + across[1] * along[1]) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    distance(point, [a[0] + t * along[0], a[1] + t * along[1]])
+}
+
+/// The bulge of an arc sweeping `angle` radians, positive counterclockwise.
+pub fn bulge(angle: f64) -> f64 {
+    (angle / 4.0).tan()
+}
+
+/// The segments of a polyline, including the closing one.
+pub fn segments(polyline: &Polyline<f64>) -> Vec<Segment> {
+    let vertices = &polyline.vertex_data;
+    let count = if polyline.is_closed {
+        vertices.len()
+    } else {
+        vertices.len().saturating_sub(1)
+    };
+    (0..count)
+        .map(|i| {
+            let (a, b) = (vertices[i], vertices[(i + 1) % vertices.len()]);
+            Segment {
+                start: [a.x, a.y],
+                end: [b.x, b.y],
+                bulge: a.bulge,
+            }
+        })
+        .collect()
+}
+
+/// The area a closed polyline encloses, positive if it runs counterclockwise.
+pub fn area(polyline: &Polyline<f64>) -> f64 {
+    segments(polyline)
+        .iter()
+        .map(|segment| {
+            let [x1, y1] = segment.start;
+            let [x2, y2] = segment.end;
+            // An arc adds the area between it and its chord, which lies to the right of the
+            // chord when the arc is counterclockwise.
+            let cap = segment.arc().map_or(0.0, |(_, radius)| {
+                let sweep = 4.0 * segment.bulge.atan();
+                radius * radius / 2.0 * (sweep - sweep.sin())
+            });
+            (x1 * y2 - x2 * y1) / 2.0 + cap
+        })
+        .sum()
+}
+
+/// The polyline through segments that each start where the last one ended.
+pub fn polyline(segments: &[Segment], closed: bool) -> Polyline<f64> {
+    let mut vertex_data: Vec<PlineVertex<f64>> = segments
+        .iter()
+        .map(|segment| PlineVertex {
+            x: segment.start[0],
+            y: segment.start[1],
+            bulge: segment.bulge,
+        })
+        .collect();
+    if let (false, Some(last)) = (closed, segments.last()) {
+        vertex_data.push(PlineVertex {
+            x: last.end[0],
+            y: last.end[1],
+            bulge: 0.0,
+        });
+    }
+    Polyline {
+        vertex_data,
+        is_closed: closed,
+    }
+}
+
+/// The same polyline run the other way.
+pub fn reversed(polyline: &Polyline<f64>) -> Polyline<f64> {
+    let segments: Vec<Segment> = segments(polyline)
+        .into_iter()
+        .rev()
+        .map(Segment::reversed)
+        .collect();
+    self::polyline(&segments, polyline.is_closed)
+}
+
+/// The same polyline with each run of short lines that stays within `tolerance` of one circle
+/// made a single arc, see [`gcode_arc::fit_arcs`]. Arcs already in it stay as they are. On a
+/// closed polyline a run over the start is fitted as two.
+pub fn fit_arcs(polyline: &Polyline<f64>, tolerance: f64) -> Polyline<f64> {
+    let mut fitted = Vec::new();
+    let mut run = Vec::new();
+    for segment in segments(polyline) {
+        if segment.bulge == 0.0 {
+            run.push(segment);
+        } else {
+            fitted.extend(fit_run(&run, tolerance));
+            run.clear();
+            fitted.push(segment);
+        }
+    }
+    fitted.extend(fit_run(&run, tolerance));
+    self::polyline(&fitted, polyline.is_closed)
+}
+
+/// Lines that each start where the last one ended, with arcs in place of the runs that fit.
+fn fit_run(run: &[Segment], tolerance: f64) -> Vec<Segment> {
+    let Some(first) = run.first() else {
+        return vec![];
+    };
+    let points: Vec<[f64; 2]> = std::iter::once(first.start)
+        .chain(run.iter().map(|segment| segment.end))
+        .collect();
+    // Relative to the start, single precision keeps far more than the tolerance.
+    let origin = first.start;
+    let local: Vec<[f32; 2]> = points
+        .iter()
+        .map(|p| [(p[0] - origin[0]) as f32, (p[1] - origin[1]) as f32])
+        .collect();
+
+    let mut fitted = Vec::new();
+    let mut from = 0;
+    for piece in gcode_arc::fit_arcs(&local, tolerance as f32) {
+        let to = piece.end();
+        match piece {
+            gcode_arc::Piece::Line { .. } => fitted.extend_from_slice(&run[from..to]),
+            gcode_arc::Piece::Arc {
+                center, clockwise, ..
+            } => {
+                let center = [
+                    origin[0] + f64::from(center[0]),
+                    origin[1] + f64::from(center[1]),
+                ];
+                let angle = |p: [f64; 2]| (p[1] - center[1]).atan2(p[0] - center[0]);
+                let mut sweep = angle(points[to]) - angle(points[from]);
+                if clockwise && sweep > 0.0 {
+                    sweep -= std::f64::consts::TAU;
+                } else if !clockwise && sweep < 0.0 {
+                    sweep += std::f64::consts::TAU;
+                }
+                fitted.push(Segment {
+                    start: points[from],
+                    end: points[to],
+                    bulge: bulge(sweep),
+                });
+            }
+        }
+        from = to;
+    }
+    fitted
+}
+
+/// Points along a polyline for drawing it, back to the first one if it is closed.
+pub fn points(polyline: &Polyline<f64>, tolerance: f64) -> Vec<[f64; 2]> {
+    let mut points: Vec<[f64; 2]> = polyline
+        .vertex_data
+        .first()
+        .map(|vertex| [vertex.x, vertex.y])
+        .into_iter()
+        .collect();
+    for segment in segments(polyline) {
+        segment.flatten(tolerance, &mut points);
+    }
+    points
+}
+
+/// Joins paths whose ends lie within `tolerance` of each other, turning some around if need be,
+/// and closes the ones that come back to where they started. Each path is a run of segments
+/// that each start where the last one ended.
+pub fn chain(paths: Vec<Vec<Segment>>, tolerance: f64) -> Vec<Polyline<f64>> {
+    let near = |a: [f64; 2], b: [f64; 2]| distance(a, b) <= tolerance;
+    let closes = |path: &[Segment]| path.len() > 1 && near(path[path.len() - 1].end, path[0].start);
+    let reverse = |path: Vec<Segment>| -> Vec<Segment> {
+        path.into_iter().rev().map(Segment::reversed).collect()
+    };
+
+    let mut rest: Vec<Vec<Segment>> = paths
+        .into_iter()
+        .map(|path| {
+            path.into_iter()
+                .filter(|segment| segment.start != segment.end)
+                .collect::<Vec<_>>()
+        })
+        .filter(|path| !path.is_empty())
+        .collect();
+    rest.reverse();
+
+    let mut polylines = vec![];
+    while let Some(mut path) = rest.pop() {
+        // Grow at the end, then at the start, until nothing more joins.
+        while !closes(&path) {
+            let end = path[path.len() - 1].end;
+            let Some(index) = rest
+                .iter()
+                .position(|next| near(next[0].start, end) || near(next[next.len() - 1].end, end))
+            else {
+                break;
+            };
+            let next = rest.remove(index);
+            let mut next = if near(next[0].start, end) {
+                next
+            } else {
+                reverse(next)
+            };
+            next[0].start = end;
+            path.extend(next);
+        }
+        while !closes(&path) {
+            let start = path[0].start;
+            let Some(index) = rest.iter().position(|previous| {
+                near(previous[previous.len() - 1].end, start) || near(previous[0].start, start)
+            }) else {
+                break;
+            };
+            let previous = rest.remove(index);
+            let mut previous = if near(previous[previous.len() - 1].end, start) {
+                previous
+            } else {
+                reverse(previous)
+            };
+            let last = previous.len() - 1;
+            previous[last].end = start;
+            previous.extend(path);
+            path = previous;
+        }
+        let closed = closes(&path);
+        polylines.push(polyline(&path, closed));
+    }
+    polylines
+}
+
+/// The outline of one element of a drawing, before loose ends are chained.
+pub struct Piece {
+    pub layer: String,
+    pub segments: Vec<Segment>,
+    pub closed: bool,
+}
+
+/// `[x, y]` to `[a x + b y + e, c x + d y + f]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform(pub [f64; 6]);
+
+impl Transform {
+    pub const IDENTITY: Self = Self([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    /// Moves, rotates by `angle` radians and scales, in the order a block insert does them.
+    pub fn placement(origin: [f64; 2], angle: f64, scale: [f64; 2], base: [f64; 2]) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let [sx, sy] = scale;
+        let (a, b, c, d) = (cos * sx, -sin * sy, sin * sx, cos * sy);
+        Self([
+            a,
+            b,
+            c,
+            d,
+            origin[0] - a * base[0] - b * base[1],
+            origin[1] - c * base[0] - d * base[1],
+        ])
+    }
+
+    /// `self` applied after `inner`.
+    pub fn then(&self, inner: &Self) -> Self {
+        let [a, b, c, d, e, f] = self.0;
+        let [p, q, r, s, t, u] = inner.0;
+        Self([
+            a * p + b * r,
+            a * q + b * s,
+            c * p + d * r,
+            c * q + d * s,
+            a * t + b * u + e,
+            c * t + d * u + f,
+        ])
+    }
+
+    pub fn apply(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let [a, b, c, d, e, f] = self.0;
+        [a * x + b * y + e, c * x + d * y + f]
+    }
+
+    /// Whether circles stay circles: no stretching and no skew.
+    pub fn is_conformal(&self) -> bool {
+        let [a, b, c, d, ..] = self.0;
+        let scale = a.hypot(c);
+        let close = |x: f64, y: f64| (x - y).abs() <= 1e-9 * scale.max(1.0);
+        close(a.hypot(c), b.hypot(d)) && close(a * b + c * d, 0.0)
+    }
+
+    pub fn is_mirroring(&self) -> bool {
+        let [a, b, c, d, ..] = self.0;
+        a * d - b * c < 0.0
+    }
+
+    /// The segments moved, with arcs flattened if they would no longer be circular.
+    pub fn segments(&self, segments: &[Segment], tolerance: f64) -> Vec<Segment> {
+        if *self == Self::IDENTITY {
+            return segments.to_vec();
+        }
+        if self.is_conformal() {
+            let sign = if self.is_mirroring() { -1.0 } else { 1.0 };
+            return segments
+                .iter()
+                .map(|segment| Segment {
+                    start: self.apply(segment.start),
+                    end: self.apply(segment.end),
+                    bulge: segment.bulge * sign,
+                })
+                .collect();
+        }
+        // Stretching makes the arcs ellipses, so the tolerance shrinks with the largest scale.
+        let [a, b, c, d, ..] = self.0;
+        let stretch = a.hypot(c).max(b.hypot(d)).max(1e-12);
+        let mut out = vec![];
+        for segment in segments {
+            let mut points = vec![];
+            segment.flatten(tolerance / stretch, &mut points);
+            let mut start = self.apply(segment.start);
+            for point in points {
+                let end = self.apply(point);
+                out.push(Segment::line(start, end));
+                start = end;
+            }
+        }
+        out
+    }
+}
+
+/// Contours by layer, in the order the layers first appear: closed pieces as they are, then
+/// the open ones chained.
+pub fn contours(pieces: &[Piece], tolerance: f64) -> Vec<Contour> {
+    let mut contours = vec![];
+    let mut layers: Vec<String> = vec![];
+    for piece in pieces {
+        if !layers.contains(&piece.layer) {
+            layers.push(piece.layer.clone());
+        }
+    }
+    for layer in layers {
+        let mut open = vec![];
+        for piece in pieces.iter().filter(|piece| piece.layer == layer) {
+            if piece.closed {
+                contours.push(Contour {
+                    layer: layer.clone(),
+                    polyline: polyline(&piece.segments, true),
+                });
+            } else {
+                open.push(piece.segments.clone());
+            }
+        }
+        for polyline in chain(open, tolerance) {
+            contours.push(Contour {
+                layer: layer.clone(),
+                polyline,
+            });
+        }
+    }
+
+    contours
+}
+
+#[test]
+fn test_toolpath_sketch() {
+    let quarter = Segment {
+        start: [1.0, 0.0],
+        end: [0.0, 1.0],
+        bulge: bulge(std::f64::consts::FRAC_PI_2),
+    };
+    let (center, radius) = quarter.arc().unwrap();
+    assert!(distance(center, [0.0, 0.0]) < 1e-12);
+    assert!((radius - 1.0).abs() < 1e-12);
+    assert!((quarter.length() - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+    let (center, _) = quarter.reversed().arc().unwrap();
+    assert!(distance(center, [0.0, 0.0]) < 1e-12);
+
+    let mut points = vec![];
+    quarter.flatten(0.01, &mut points);
+    assert!(points.len() > 2);
+    assert_eq!(points.last(), Some(&[0.0, 1.0]));
+    assert!(points
+        .iter()
+        .all(|point| (distance(*point, center) - 1.0).abs() < 1e-12));
+
+    // A slot drawn as loose lines and arcs, one of them backwards and one end a hair off.
+    let paths = vec![
+        vec![Segment::line([0.0, 0.0], [10.0, 0.0])],
+        vec![Segment::line([0.0, 2.0], [10.0, 2.0])],
+        vec![Segment {
+            start: [10.0, 0.0],
+            end: [10.0, 2.0],
+            bulge: 1.0,
+        }],
+        vec![Segment {
+            start: [0.0, 0.0],
+            end: [0.0, 2.0 + 1e-7],
+            bulge: -1.0,
+        }],
+        vec![Segment::line([20.0, 0.0], [30.0, 0.0])],
+    ];
+    let polylines = chain(paths, 1e-6);
+    assert_eq!(polylines.len(), 2);
+    let slot = &polylines[0];
+    assert!(slot.is_closed);
+    assert_eq!(slot.vertex_data.len(), 4);
+    let length: f64 = segments(slot).iter().map(Segment::length).sum();
+    assert!((length - (20.0 + 2.0 * std::f64::consts::PI)).abs() < 1e-5);
+    assert!(!polylines[1].is_closed);
+    assert_eq!(polylines[1].vertex_data.len(), 2);
+
+    assert!((quarter.midpoint()[0] - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-12);
+    assert!((area(slot) - (20.0 + std::f64::consts::PI)).abs() < 1e-5);
+
+    let mut sketch = Sketch {
+        contours: vec![Contour {
+            layer: "cut".to_owned(),
+            polyline: slot.clone(),
+        }],
+        units: Units::Inches,
+    };
+    sketch.to_millimeters();
+    assert_eq!(sketch.units, Units::Millimeters);
+    assert_eq!(sketch.contours[0].polyline.vertex_data[1].x, 254.0);
+    assert_eq!(sketch.layers(), ["cut"]);
+
+    // Half a circle drawn clockwise in short lines, far from the origin, then a line on.
+    let center = [1000.0, 1000.0];
+    let mut lines: Vec<Segment> = (0..32)
+        .map(|step| {
+            let at = |step: f64| {
+                let angle = -std::f64::consts::PI * step / 32.0;
+                [
+                    center[0] + 10.0 * angle.cos(),
+                    center[1] + 10.0 * angle.sin(),
+                ]
+            };
+            Segment::line(at(f64::from(step)), at(f64::from(step + 1)))
+        })
+        .collect();
+    lines.push(Segment::line([990.0, 1000.0], [980.0, 1000.0]));
+    let fitted = segments(&fit_arcs(&polyline(&lines, false), 0.05));
+    assert_eq!(fitted.len(), 2);
+    assert_eq!(
+        (fitted[0].start, fitted[1].end),
+        ([1010.0, 1000.0], [980.0, 1000.0])
+    );
+    assert!((fitted[0].bulge + 1.0).abs() < 1e-6);
+    let (center, radius) = fitted[0].arc().unwrap();
+    assert!(distance(center, [1000.0, 1000.0]) < 1e-6 && (radius - 10.0).abs() < 1e-6);
+    assert_eq!(fitted[1].bulge, 0.0);
+
+    // A dense outline, as a mesh slice gives, comes down to an arc and a few stray lines.
+    let circle: Vec<Segment> = (0..20_000)
+        .map(|step| {
+            let at = |step: f64| {
+                let angle = std::f64::consts::TAU * step / 20_000.0;
+                [50.0 * angle.cos(), 50.0 * angle.sin()]
+            };
+            Segment::line(at(f64::from(step)), at(f64::from(step + 1)))
+        })
+        .collect();
+    let fitted = segments(&fit_arcs(&polyline(&circle, true), 0.01));
+    assert!(fitted.len() < 10);
+    assert!(fitted[0].bulge > 0.0);
+}
@@ -0,0 +1,1138 @@
+//! Reads the geometry of an SVG drawing into a [`Sketch`] in millimetres.
+//!
+//! Paths and the basic shapes are read through groups, nested `<svg>` elements and `<use>`
+//! references, each with its `transform`. The root element's `viewBox`, `width` and `height`
+//! put the drawing on the page: physical units keep their size and a pixel is `1 / dpi` inches.
+//! Y is turned to point up, with the bottom of the page at zero.
+//!
+//! Circles, ellipses and arcs stay arcs wherever the transforms keep them circular. Bézier
+//! curves become pairs of arcs that meet without a corner, within the tolerance. Inkscape layers
+//! become the contours' layers. Fills, strokes, clipping and text are ignored, and elements
+//! hidden with `display: none` are left out.
+
+use std::collections::HashMap;
+use std::f64::consts::{PI, TAU};
+use std::fmt;
+
+use super::toolpath_sketch::{self, Piece, Segment, Sketch, Transform, Units};
+
+/// The pixel size most programs assume, and the one CSS fixes.
+pub const DEFAULT_DPI: f64 = 96.0;
+
+/// `<use>` references nested deeper than this are left out, which also stops ones that refer to
+/// themselves.
+const MAX_NESTING: usize = 16;
+
+/// Bézier curves are split no more than this many times over when they are fitted with arcs.
+const MAX_SUBDIVISION: usize = 16;
+
+/// How many stretches a Bézier curve is checked in against the arcs fitted to it.
+const BIARC_SAMPLES: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgError {
+    /// 1-based line.
+    pub line: usize,
+
+    pub kind: SvgErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SvgErrorKind {
+    /// The file is not well-formed XML.
+    Malformed(String),
+
+    /// The root element is not `<svg>`.
+    NotSvg,
+
+    /// A `d` or `points` attribute that cannot be read.
+    InvalidPath(String),
+
+    InvalidTransform(String),
+}
+
+impl fmt::Display for SvgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.line)?;
+        match &self.kind {
+            SvgErrorKind::Malformed(message) => write!(f, "malformed XML: {}", message),
+            SvgErrorKind::NotSvg => write!(f, "not an SVG document"),
+            SvgErrorKind::InvalidPath(message) => write!(f, "invalid path data: {}", message),
+            SvgErrorKind::InvalidTransform(message) => write!(f, "invalid transform: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SvgError {}
+
+/// The drawing's geometry, grouped by layer. A pixel is `1 / dpi` inches, and `tolerance`, in
+/// millimetres, is how far apart two ends may be and still join, and how far a curve drawn
+/// with lines or arcs may stray from the original.
+pub fn import(text: &str, dpi: f64, tolerance: f64) -> Result<Sketch, SvgError> {
+    // Inkscape and Illustrator write entity declarations into the doctype.
+    let options = roxmltree::ParsingOptions { allow_dtd: true };
+    let document =
+        roxmltree::Document::parse_with_options(text, options).map_err(|error| SvgError {
+            line: error.pos().row as usize,
+            kind: SvgErrorKind::Malformed(error.to_string()),
+        })?;
+    let root = document.root_element();
+    if root.tag_name().name() != "svg" {
+        return Err(SvgError {
+            line: line(&root),
+            kind: SvgErrorKind::NotSvg,
+        });
+    }
+    let mut ids = HashMap::new();
+    index(root, &mut ids);
+
+    let mut importer = Importer {
+        ids,
+        dpi,
+        tolerance,
+        pieces: vec![],
+    };
+    importer.children(root, &page(&root, dpi), "", 0)?;
+
+    Ok(Sketch {
+        contours: toolpath_sketch::contours(&importer.pieces, tolerance),
+        units: Units::Millimeters,
+    })
+}
+
+/// Namespace of Inkscape's own attributes, which mark layers and name them.
+const INKSCAPE_NS: &str = "http://www.inkscape.org/namespaces/inkscape";
+
+/// Namespace of `xlink:href`, which SVG 1.1 uses for references.
+const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+
+type Element<'a> = roxmltree::Node<'a, 'a>;
+
+/// A presentation property, from the `style` attribute or else an attribute of its own.
+fn property<'a>(element: &Element<'a>, name: &str) -> Option<&'a str> {
+    let style = element.attribute("style").unwrap_or_default();
+    style
+        .split(';')
+        .filter_map(|declaration| declaration.split_once(':'))
+        .find(|(key, _)| key.trim() == name)
+        .map(|(_, value)| value.trim())
+        .or_else(|| element.attribute(name).map(str::trim))
+}
+
+fn attribute_length(element: &Element, name: &str, dpi: f64) -> Option<f64> {
+    element.attribute(name).and_then(|value| length(value, dpi))
+}
+
+fn line(element: &Element) -> usize {
+    element.document().text_pos_at(element.range().start).row as usize
+}
+
+fn index<'a>(element: Element<'a>, ids: &mut HashMap<&'a str, Element<'a>>) {
+    for element in element.descendants().filter(Element::is_element) {
+        if let Some(id) = element.attribute("id") {
+            ids.entry(id).or_insert(element);
+        }
+    }
+}
+
+/// A length in user units, where a pixel is one.
+fn length(value: &str, dpi: f64) -> Option<f64> {
+    let value = value.trim();
+    let units = [
+        ("px", 1.0),
+        ("mm", dpi / 25.4),
+        ("cm", dpi / 2.54),
+        ("in", dpi),
+        ("pt", dpi / 72.0),
+        ("pc", dpi / 6.0),
+        ("", 1.0),
+    ];
+    units.iter().find_map(|(suffix, scale)| {
+        let number = value.strip_suffix(suffix)?.trim_end();
+        number.parse::<f64>().ok().map(|number| number * scale)
+    })
+}
+
+/// Where the root element's user units land on the page, in millimetres with y up.
+fn page(root: &Element, dpi: f64) -> Transform {
+    let view_box = root.attribute("viewBox").and_then(view_box);
+    let width = attribute_length(root, "width", dpi);
+    let height = attribute_length(root, "height", dpi);
+    let (width, height) = match (width, height, view_box) {
+        (Some(width), Some(height), _) => (width, height),
+        (Some(width), None, Some([_, _, w, h])) => (width, width * h / w),
+        (None, Some(height), Some([_, _, w, h])) => (height * w / h, height),
+        (_, _, Some([_, _, w, h])) => (w, h),
+        (width, height, None) => (width.unwrap_or_default(), height.unwrap_or_default()),
+    };
+    let fit = view_box.map_or(Transform::IDENTITY, |view_box| {
+        fit(
+            view_box,
+            [width, height],
+            root.attribute("preserveAspectRatio"),
+        )
+    });
+    let mm = 25.4 / dpi;
+    Transform([mm, 0.0, 0.0, -mm, 0.0, height * mm]).then(&fit)
+}
+
+/// A `viewBox`, if it has a size.
+fn view_box(value: &str) -> Option<[f64; 4]> {
+    match numbers(value).ok()?[..] {
+        [x, y, w, h] if w > 0.0 && h > 0.0 => Some([x, y, w, h]),
+        _ => None,
+    }
+}
+
+/// Maps a view box onto a viewport of `size` at the origin, as `preserveAspectRatio` says.
+fn fit([x, y, w, h]: [f64; 4], size: [f64; 2], preserve: Option<&str>) -> Transform {
+    let (sx, sy) = (size[0] / w, size[1] / h);
+    let mut words = preserve
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|word| *word != "defer");
+    let align = words.next().unwrap_or("xMidYMid");
+    if align == "none" {
+        return Transform([sx, 0.0, 0.0, sy, -x * sx, -y * sy]);
+    }
+    let scale = if words.next() == Some("slice") {
+        sx.max(sy)
+    } else {
+        sx.min(sy)
+    };
+    let share = |min: &str, max: &str| {
+        if align.contains(min) {
+            0.0
+        } else if align.contains(max) {
+            1.0
+        } else {
+            0.5
+        }
+    };
+    Transform([
+        scale,
+        0.0,
+        0.0,
+        scale,
+        (size[0] - w * scale) * share("xMin", "xMax") - x * scale,
+        (size[1] - h * scale) * share("YMin", "YMax") - y * scale,
+    ])
+}
+
+fn translation(x: f64, y: f64) -> Transform {
+    Transform([1.0, 0.0, 0.0, 1.0, x, y])
+}
+
+/// A `transform` attribute, its operations applied right to left.
+fn parse_transform(value: &str) -> Result<Transform, String> {
+    let mut transform = Transform::IDENTITY;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let (Some(open), Some(close)) = (rest.find('('), rest.find(')')) else {
+            return Err(format!("{:?} is not an operation", rest));
+        };
+        let name = rest[..open].trim();
+        let arguments = numbers(rest.get(open + 1..close).unwrap_or_default())?;
+        let operation = match (name, &arguments[..]) {
+            ("matrix", &[a, b, c, d, e, f]) => Transform([a, c, b, d, e, f]),
+            ("translate", &[x]) => translation(x, 0.0),
+            ("translate", &[x, y]) => translation(x, y),
+            ("scale", &[scale]) => Transform::placement([0.0; 2], 0.0, [scale; 2], [0.0; 2]),
+            ("scale", &[sx, sy]) => Transform::placement([0.0; 2], 0.0, [sx, sy], [0.0; 2]),
+            ("rotate", &[angle]) => {
+                Transform::placement([0.0; 2], angle.to_radians(), [1.0; 2], [0.0; 2])
+            }
+            ("rotate", &[angle, x, y]) => {
+                Transform::placement([x, y], angle.to_radians(), [1.0; 2], [x, y])
+            }
+            ("skewX", &[angle]) => Transform([1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0]),
+            ("skewY", &[angle]) => Transform([1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0]),
+            _ => return Err(format!("{:?} is not an operation", rest[..=close].trim())),
+        };
+        transform = transform.then(&operation);
+        rest = rest[close + 1..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+    Ok(transform)
+}
+
+/// Numbers separated by whitespace or commas, or simply run together as in `1.5.5-2`.
+fn numbers(value: &str) -> Result<Vec<f64>, String> {
+    let mut data = PathData::new(value);
+    let mut numbers = vec![];
+    while !data.at_end() {
+        numbers.push(data.number()?);
+    }
+    Ok(numbers)
+}
+
+/// A cursor over path data or another list of numbers.
+struct PathData<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PathData<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            bytes: text.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n' | b',') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.pos == self.bytes.len()
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        self.skip_separators();
+        let start = self.pos;
+        if let Some(b'-' | b'+') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+        let mut digits = self.digits();
+        if self.bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            digits += self.digits();
+        }
+        if digits == 0 {
+            return Err(self.unexpected());
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(self.pos) {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+            if let Some(b'-' | b'+') = self.bytes.get(self.pos) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                self.pos = mantissa_end;
+            }
+        }
+        let number = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or_default();
+        number
+            .parse()
+            .map_err(|_| format!("{:?} is not a number", number))
+    }
+
+    fn point(&mut self) -> Result<[f64; 2], String> {
+        Ok([self.number()?, self.number()?])
+    }
+
+    /// An arc flag, which may run into what follows it.
+    fn flag(&mut self) -> Result<bool, String> {
+        self.skip_separators();
+        let flag = match self.bytes.get(self.pos) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(flag)
+    }
+
+    fn unexpected(&self) -> String {
+        match self.bytes.get(self.pos) {
+            Some(&byte) => format!("unexpected {:?} at {}", byte as char, self.pos + 1),
+            None => "unexpected end".to_owned(),
+        }
+    }
+}
+
+/// A piece of an outline, in the coordinates of the element it belongs to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Curve {
+    Line([f64; 2], [f64; 2]),
+
+    /// A cubic Bézier through its four control points.
+    Cubic([[f64; 2]; 4]),
+
+    /// Part of the ellipse with `radii` turned by `rotation`, from the point at parameter angle
+    /// `start` through `sweep` radians, positive toward +y from +x.
+    Arc {
+        center: [f64; 2],
+        radii: [f64; 2],
+        rotation: f64,
+        start: f64,
+        sweep: f64,
+    },
+}
+
+impl Curve {
+    /// The elliptical arc of a path's `A` command, from `from` to `to`.
+    fn arc(
+        from: [f64; 2],
+        radii: [f64; 2],
+        rotation: f64,
+        large: bool,
+        positive: bool,
+        to: [f64; 2],
+    ) -> Self {
+        let [mut rx, mut ry] = radii.map(f64::abs);
+        if rx == 0.0 || ry == 0.0 {
+            return Self::Line(from, to);
+        }
+        // From the SVG specification's endpoint to center conversion.
+        let (sin, cos) = rotation.sin_cos();
+        let (dx, dy) = ((from[0] - to[0]) / 2.0, (from[1] - to[1]) / 2.0);
+        let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+        let lambda = (x1 / rx).powi(2) + (y1 / ry).powi(2);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+        let numerator = (rx * ry).powi(2) - (rx * y1).powi(2) - (ry * x1).powi(2);
+        let denominator = (rx * y1).powi(2) + (ry * x1).powi(2);
+        let sign = if large == positive { -1.0 } else { 1.0 };
+        let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+        let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+        let center = [
+            cos * cx1 - sin * cy1 + (from[0] + to[0]) / 2.0,
+            sin * cx1 + cos * cy1 + (from[1] + to[1]) / 2.0,
+        ];
+        let start = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+        let end = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+        let mut sweep = end - start;
+        if positive && sweep < 0.0 {
+            sweep += TAU;
+        } else if !positive && sweep > 0.0 {
+            sweep -= TAU;
+        }
+        Self::Arc {
+            center,
+            radii: [rx, ry],
+            rotation,
+            start,
+            sweep,
+        }
+    }
+
+    fn ellipse(center: [f64; 2], radii: [f64; 2], start: f64, sweep: f64) -> Self {
+        Self::Arc {
+            center,
+            radii,
+            rotation: 0.0,
+            start,
+            sweep,
+        }
+    }
+}
+
+/// A path's subpath or a shape's outline.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Outline {
+    curves: Vec<Curve>,
+    closed: bool,
+}
+
+/// The outlines a `d` attribute draws.
+fn parse_path(d: &str) -> Result<Vec<Outline>, String> {
+    let mut data = PathData::new(d);
+    let mut outlines = vec![];
+    let mut outline = Outline::default();
+    let mut point = [0.0, 0.0];
+    let mut start = point;
+    // The control point an `S` or `T` command reflects, when the previous command has one.
+    let mut cubic_control = None;
+    let mut quadratic_control = None;
+    let mut command = None;
+
+    while !data.at_end() {
+        let byte = data.bytes[data.pos];
+        let letter = if byte.is_ascii_alphabetic() {
+            data.pos += 1;
+            byte
+        } else {
+            // Numbers repeat the last command, except after a close.
+            match command {
+                Some(letter) if !matches!(letter, b'Z' | b'z') => letter,
+                _ => return Err(data.unexpected()),
+            }
+        };
+        if command.is_none() && !matches!(letter, b'M' | b'm') {
+            return Err("a path starts with a move".to_owned());
+        }
+        command = Some(letter);
+        let relative = letter.is_ascii_lowercase();
+        let origin = if relative { point } else { [0.0, 0.0] };
+        let at = |[x, y]: [f64; 2]| [origin[0] + x, origin[1] + y];
+        let reflect = |control: Option<[f64; 2]>| {
+            control.map_or(point, |[x, y]| [2.0 * point[0] - x, 2.0 * point[1] - y])
+        };
+
+        let uppercase = letter.to_ascii_uppercase();
+        let curve = match uppercase {
+            b'M' => {
+                if !outline.curves.is_empty() {
+                    outlines.push(std::mem::take(&mut outline));
+                }
+                point = at(data.point()?);
+                start = point;
+                // Further pairs are lines.
+                command = Some(if relative { b'l' } else { b'L' });
+                None
+            }
+            b'L' => Some(Curve::Line(point, at(data.point()?))),
+            b'H' => Some(Curve::Line(point, [data.number()? + origin[0], point[1]])),
+            b'V' => Some(Curve::Line(point, [point[0], data.number()? + origin[1]])),
+            b'C' => {
+                let (c1, c2, end) = (at(data.point()?), at(data.point()?), at(data.point()?));
+                Some(Curve::Cubic([point, c1, c2, end]))
+            }
+            b'S' => {
+                let c1 = reflect(cubic_control);
+                let (c2, end) = (at(data.point()?), at(data.point()?));
+                Some(Curve::Cubic([point, c1, c2, end]))
+            }
+            b'Q' | b'T' => {
+                let control = if uppercase == b'Q' {
+                    at(data.point()?)
+                } else {
+                    reflect(quadratic_control)
+                };
+                let end = at(data.point()?);
+                quadratic_control = Some(control);
+                Some(quadratic(point, control, end))
+            }
+            b'A' => {
+                let radii = [data.number()?, data.number()?];
+                let rotation = data.number()?.to_radians();
+                let (large, positive) = (data.flag()?, data.flag()?);
+                let end = at(data.point()?);
+                if end != point {
+                    let arc = Curve::arc(point, radii, rotation, large, positive, end);
+                    outline.curves.push(arc);
+                    point = end;
+                }
+                None
+            }
+            b'Z' => {
+                if !outline.curves.is_empty() {
+                    if point != start {
+                        outline.curves.push(Curve::Line(point, start));
+                    }
+                    outline.closed = true;
+                    outlines.push(std::mem::take(&mut outline));
+                }
+                point = start;
+                None
+            }
+            _ => return Err(format!("unknown command {:?}", letter as char)),
+        };
+
+        cubic_control = match curve {
+            Some(Curve::Cubic([_, _, control, _])) if matches!(uppercase, b'C' | b'S') => {
+                Some(control)
+            }
+            _ => None,
+        };
+        if !matches!(uppercase, b'Q' | b'T') {
+            quadratic_control = None;
+        }
+        if let Some(curve) = curve {
+            if let Curve::Line(_, end) | Curve::Cubic([.., end]) = curve {
+                point = end;
+            }
+            outline.curves.push(curve);
+        }
+    }
+    if !outline.curves.is_empty() {
+        outlines.push(outline);
+    }
+    Ok(outlines)
+}
+
+/// The cubic that traces a quadratic Bézier.
+fn quadratic(start: [f64; 2], control: [f64; 2], end: [f64; 2]) -> Curve {
+    let toward = |from: [f64; 2]| {
+        [
+            from[0] + 2.0 / 3.0 * (control[0] - from[0]),
+            from[1] + 2.0 / 3.0 * (control[1] - from[1]),
+        ]
+    };
+    Curve::Cubic([start, toward(start), toward(end), end])
+}
+
+/// Arcs along a cubic Bézier, no more than `tolerance` off it, each meeting the next at the same
+/// heading and the first and last meeting the curve's ends at its own.
+///
+/// A pair of arcs, a biarc, fits the ends and their directions. The pair is the one whose
+/// tangents from the ends to where the arcs meet are equally long. Where it strays too far, either
+/// way, or an arc turns more than half a circle, the curve is split in half and each half fitted
+/// in turn.
+fn biarcs(points: [[f64; 2]; 4], tolerance: f64, depth: usize, out: &mut Vec<Segment>) {
+    let [p0, p1, p2, p3] = points;
+    // The curve stays within the hull of its control points.
+    let off_chord = |p: [f64; 2]| toolpath_sketch::distance_to_segment(p, p0, p3);
+    if off_chord(p1).max(off_chord(p2)) <= tolerance {
+        out.push(Segment::line(p0, p3));
+        return;
+    }
+    let fitted = biarc(points).filter(|arcs| {
+        let near_arcs = |point: [f64; 2]| {
+            arcs.iter()
+                .map(|arc| distance_to_arc(arc, point))
+                .fold(f64::INFINITY, f64::min)
+                <= tolerance
+        };
+        arcs.iter().all(|arc| arc.bulge.abs() <= 1.0)
+            && (1..BIARC_SAMPLES)
+                .all(|i| near_arcs(cubic_at(points, i as f64 / BIARC_SAMPLES as f64)))
+            // The arcs' quarters, and where they meet.
+            && arcs.iter().all(|arc| {
+                [0.25, 0.5, 0.75, 1.0]
+                    .iter()
+                    .all(|&t| distance_to_cubic(points, arc.part(0.0, t).end) <= tolerance)
+            })
+    });
+    match fitted {
+        Some(arcs) => out.extend(arcs),
+        None if depth == MAX_SUBDIVISION => out.push(Segment::line(p0, p3)),
+        None => {
+            let mid = |a: [f64; 2], b: [f64; 2]| [(a[0] + b[0]) / 2.0, (a[1] + b[1]) / 2.0];
+            let (a, b, c) = (mid(p0, p1), mid(p1, p2), mid(p2, p3));
+            let (d, e) = (mid(a, b), mid(b, c));
+            let middle = mid(d, e);
+            biarcs([p0, a, d, middle], tolerance, depth + 1, out);
+            biarcs([middle, e, c, p3], tolerance, depth + 1, out);
+        }
+    }
+}
+
+/// The point at parameter `t` of a cubic Bézier.
+fn cubic_at([p0, p1, p2, p3]: [[f64; 2]; 4], t: f64) -> [f64; 2] {
+    let u = 1.0 - t;
+    let weights = [u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t];
+    [0, 1].map(|axis| {
+        weights[0] * p0[axis]
+            + weights[1] * p1[axis]
+            + weights[2] * p2[axis]
+            + weights[3] * p3[axis]
+    })
+}
+
+/// How far `point` is from the nearest point of a cubic Bézier.
+fn distance_to_cubic(points: [[f64; 2]; 4], point: [f64; 2]) -> f64 {
+    let distance = |t: f64| toolpath_sketch::distance(cubic_at(points, t), point);
+    let step = 1.0 / BIARC_SAMPLES as f64;
+    let samples: Vec<f64> = (0..=BIARC_SAMPLES)
+        .map(|i| distance(i as f64 * step))
+        .collect();
+    // Narrow in on each sample nearer than its neighbors, between the samples either side, as
+    // the nearest of them need not be nearest to the curve where it crosses itself.
+    (0..samples.len())
+        .filter(|&i| {
+            (i == 0 || samples[i] <= samples[i - 1])
+                && samples.get(i + 1).map_or(true, |&next| samples[i] <= next)
+        })
+        .map(|i| {
+            let t = i as f64 * step;
+            let (mut low, mut high) = ((t - step).max(0.0), (t + step).min(1.0));
+            for _ in 0..40 {
+                let (a, b) = (low + (high - low) / 3.0, high - (high - low) / 3.0);
+                if distance(a) < distance(b) {
+                    high = b;
+                } else {
+                    low = a;
+                }
+            }
+            distance((low + high) / 2.0).min(samples[i])
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// The two arcs from the start of a cubic Bézier to its end that leave and arrive the way it
+/// does, or `None` if it has no direction at an end.
+fn biarc(points: [[f64; 2]; 4]) -> Option<[Segment; 2]> {
+    let [p0, p1, p2, p3] = points;
+    let unit = |from: [f64; 2], to: [f64; 2]| {
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let length = dx.hypot(dy);
+        (length > 0.0).then_some([dx / length, dy / length])
+    };
+    let dot = |a: [f64; 2], b: [f64; 2]| a[0] * b[0] + a[1] * b[1];
+    // A control point on an end leaves its direction to the next one along.
+    let t0 = unit(p0, p1).or_else(|| unit(p0, p2))?;
+    let t1 = unit(p2, p3).or_else(|| unit(p1, p3))?;
+    let v = [p3[0] - p0[0], p3[1] - p0[1]];
+    let t = [t0[0] + t1[0], t0[1] + t1[1]];
+
+    // The length of the tangents, solving |v - d (t0 + t1)| = 2 d.
+    let denominator = 2.0 * (1.0 - dot(t0, t1));
+    let d = if denominator.abs() < 1e-12 {
+        let along = dot(v, t1);
+        if along.abs() < 1e-12 {
+            return None;
+        }
+        dot(v, v) / (4.0 * along)
+    } else {
+        let vt = dot(v, t);
+        (-vt + (vt * vt + denominator * dot(v, v)).sqrt()) / denominator
+    };
+    let joint = [
+        (p0[0] + d * t0[0] + p3[0] - d * t1[0]) / 2.0,
+        (p0[1] + d * t0[1] + p3[1] - d * t1[1]) / 2.0,
+    ];
+    // The second arc, run backwards, leaves the end against the curve's direction there.
+    Some([
+        arc_leaving(p0, t0, joint),
+        arc_leaving(p3, [-t1[0], -t1[1]], joint).reversed(),
+    ])
+}
+
+/// The arc from `start`, heading along the unit vector `direction`, to `end`.
+fn arc_leaving(start: [f64; 2], direction: [f64; 2], end: [f64; 2]) -> Segment {
+    let chord = [end[0] - start[0], end[1] - start[1]];
+    // An arc turns through twice the angle between its tangent and its chord.
+    let angle = (direction[0] * chord[1] - direction[1] * chord[0])
+        .atan2(direction[0] * chord[0] + direction[1] * chord[1]);
+    Segment {
+        start,
+        end,
+        bulge: toolpath_sketch::bulge(2.0 * angle),
+    }
+}
+
+/// How far `point` is from the nearest point of a segment.
+fn distance_to_arc(segment: &Segment, point: [f64; 2]) -> f64 {
+    let Some((center, radius)) = segment.arc() else {
+        return toolpath_sketch::distance_to_segment(point, segment.start, segment.end);
+    };
+    let angle = |p: [f64; 2]| (p[1] - center[1]).atan2(p[0] - center[0]);
+    let sweep = 4.0 * segment.bulge.atan();
+    // How far around from the start the point lies, the way the arc turns.
+    let around = ((angle(point) - angle(segment.start)) * sweep.signum()).rem_euclid(TAU);
+    if around <= sweep.abs() {
+        (toolpath_sketch::distance(point, center) - radius).abs()
+    } else {
+        toolpath_sketch::distance(point, segment.start)
+            .min(toolpath_sketch::distance(point, segment.end))
+    }
+}
+
+/// The outline of a basic shape, or `None` for any other element.
+fn shape(element: &Element, dpi: f64) -> Result<Option<Vec<Outline>>, String> {
+    let length = |name: &str| attribute_length(element, name, dpi).unwrap_or_default();
+    let closed = |curves: Vec<Curve>| {
+        Some(vec![Outline {
+            curves,
+            closed: true,
+        }])
+    };
+    Ok(match element.tag_name().name() {
+        "path" => Some(parse_path(element.attribute("d").unwrap_or_default())?),
+        "line" => Some(vec![Outline {
+            curves: vec![Curve::Line(
+                [length("x1"), length("y1")],
+                [length("x2"), length("y2")],
+            )],
+            closed: false,
+        }]),
+        "polyline" | "polygon" => {
+            let points = numbers(element.attribute("points").unwrap_or_default())?;
+            let curves = points
+                .chunks_exact(2)
+                .collect::<Vec<_>>()
+                .windows(2)
+                .map(|pair| Curve::Line([pair[0][0], pair[0][1]], [pair[1][0], pair[1][1]]))
+                .collect();
+            let closed = element.tag_name().name() == "polygon";
+            let mut outline = Outline { curves, closed };
+            if let (true, Some(Curve::Line(first, _)), Some(&Curve::Line(_, last))) = (
+                closed,
+                outline.curves.first().copied(),
+                outline.curves.last(),
+            ) {
+                outline.curves.push(Curve::Line(last, first));
+            }
+            Some(vec![outline])
+        }
+        "circle" => {
+            let r = length("r");
+            (r > 0.0).then(|| {
+                vec![Outline {
+                    curves: vec![Curve::ellipse(
+                        [length("cx"), length("cy")],
+                        [r, r],
+                        0.0,
+                        TAU,
+                    )],
+                    closed: true,
+                }]
+            })
+        }
+        "ellipse" => {
+            let radii = [length("rx"), length("ry")];
+            if radii[0] > 0.0 && radii[1] > 0.0 {
+                closed(vec![Curve::ellipse(
+                    [length("cx"), length("cy")],
+                    radii,
+                    0.0,
+                    TAU,
+                )])
+            } else {
+                Some(vec![])
+            }
+        }
+        "rect" => {
+            let (x, y, w, h) = (length("x"), length("y"), length("width"), length("height"));
+            if w <= 0.0 || h <= 0.0 {
+                return Ok(Some(vec![]));
+            }
+            let (rx, ry) = match (
+                attribute_length(element, "rx", dpi),
+                attribute_length(element, "ry", dpi),
+            ) {
+                (Some(rx), Some(ry)) => (rx, ry),
+                (Some(r), None) | (None, Some(r)) => (r, r),
+                (None, None) => (0.0, 0.0),
+            };
+            let (rx, ry) = (rx.clamp(0.0, w / 2.0), ry.clamp(0.0, h / 2.0));
+            if rx == 0.0 || ry == 0.0 {
+                let corners = [[x, y], [x + w, y], [x + w, y + h], [x, y + h]];
+                closed(
+                    (0..4)
+                        .map(|i| Curve::Line(corners[i], corners[(i + 1) % 4]))
+                        .collect(),
+                )
+            } else {
+                let (left, right, top, bottom) = (x + rx, x + w - rx, y + ry, y + h - ry);
+                let corner = |center, start| Curve::ellipse(center, [rx, ry], start, PI / 2.0);
+                closed(vec![
+                    Curve::Line([left, y], [right, y]),
+                    corner([right, top], -PI / 2.0),
+                    Curve::Line([x + w, top], [x + w, bottom]),
+                    corner([right, bottom], 0.0),
+                    Curve::Line([right, y + h], [left, y + h]),
+                    corner([left, bottom], PI / 2.0),
+                    Curve::Line([x, bottom], [x, top]),
+                    corner([left, top], PI),
+                ])
+            }
+        }
+        _ => None,
+    })
+}
+
+struct Importer<'a> {
+    ids: HashMap<&'a str, Element<'a>>,
+    dpi: f64,
+    tolerance: f64,
+    pieces: Vec<Piece>,
+}
+
+impl<'a> Importer<'a> {
+    fn error(&self, element: &Element, kind: SvgErrorKind) -> SvgError {
+        SvgError {
+            line: line(element),
+            kind,
+        }
+    }
+
+    fn children(
+        &mut self,
+        element: Element<'a>,
+        transform: &Transform,
+        layer: &str,
+        depth: usize,
+    ) -> Result<(), SvgError> {
+        for child in element.children().filter(Element::is_element) {
+            self.element(child, transform, layer, depth)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the outlines of `element` and what it contains. `transform` takes the parent's user
+    /// units to the page, and `depth` counts the `<use>` references followed to get here.
+    fn element(
+        &mut self,
+        element: Element<'a>,
+        transform: &Transform,
+        layer: &str,
+        depth: usize,
+    ) -> Result<(), SvgError> {
+        if property(&element, "display") == Some("none") {
+            return Ok(());
+        }
+        let transform = match element.attribute("transform") {
+            Some(value) => transform.then(&parse_transform(value).map_err(|message| {
+                self.error(&element, SvgErrorKind::InvalidTransform(message))
+            })?),
+            None => *transform,
+        };
+
+        match element.tag_name().name() {
+            "g" | "a" | "switch" => {
+                let layer = match element.attribute((INKSCAPE_NS, "groupmode")) {
+                    Some("layer") => element
+                        .attribute((INKSCAPE_NS, "label"))
+                        .or_else(|| element.attribute("id"))
+                        .unwrap_or(layer),
+                    _ => layer,
+                };
+                self.children(element, &transform, layer, depth)
+            }
+            "svg" => {
+                let x = attribute_length(&element, "x", self.dpi).unwrap_or_default();
+                let y = attribute_length(&element, "y", self.dpi).unwrap_or_default();
+                let view_box = element.attribute("viewBox").and_then(view_box);
+                let inner = match view_box {
+                    Some(view_box @ [_, _, w, h]) => {
+                        let size = [
+                            attribute_length(&element, "width", self.dpi).unwrap_or(w),
+                            attribute_length(&element, "height", self.dpi).unwrap_or(h),
+                        ];
+                        let fit = fit(view_box, size, element.attribute("preserveAspectRatio"));
+                        translation(x, y).then(&fit)
+                    }
+                    None => translation(x, y),
+                };
+                self.children(element, &transform.then(&inner), layer, depth)
+            }
+            "use" => {
+                let target = element
+                    .attribute("href")
+                    .or_else(|| element.attribute((XLINK_NS, "href")))
+                    .and_then(|href| href.strip_prefix('#'))
+                    .and_then(|id| self.ids.get(id).copied());
+                let Some(target) = target else {
+                    return Ok(());
+                };
+                if depth == MAX_NESTING {
+                    return Ok(());
+                }
+                let x = attribute_length(&element, "x", self.dpi).unwrap_or_default();
+                let y = attribute_length(&element, "y", self.dpi).unwrap_or_default();
+                let transform = transform.then(&translation(x, y));
+                if target.tag_name().name() == "symbol" {
+                    self.children(target, &transform, layer, depth + 1)
+                } else {
+                    self.element(target, &transform, layer, depth + 1)
+                }
+            }
+            _ => {
+                let outlines = shape(&element, self.dpi)
+                    .map_err(|message| self.error(&element, SvgErrorKind::InvalidPath(message)))?;
+                for outline in outlines.unwrap_or_default() {
+                    self.add(&outline, &transform, layer);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn add(&mut self, outline: &Outline, transform: &Transform, layer: &str) {
+        let mut segments = vec![];
+        for curve in &outline.curves {
+            match *curve {
+                Curve::Line(start, end) => {
+                    segments.push(Segment::line(transform.apply(start), transform.apply(end)));
+                }
+                Curve::Cubic(points) => {
+                    let points = points.map(|point| transform.apply(point));
+                    biarcs(points, self.tolerance, 0, &mut segments);
+                }
+                Curve::Arc {
+                    center,
+                    radii,
+                    rotation,
+                    start,
+                    sweep,
+                } => {
+                    // Arcs of a unit circle, each no more than half a turn, stretched onto the
+                    // ellipse.
+                    let ellipse = Transform::placement(center, rotation, radii, [0.0; 2]);
+                    let steps = (sweep.abs() / PI).ceil().max(1.0) as usize;
+                    let step = sweep / steps as f64;
+                    let unit: Vec<Segment> = (0..steps)
+                        .map(|i| {
+                            let (from, to) =
+                                (start + step * i as f64, start + step * (i + 1) as f64);
+                            Segment {
+                                start: [from.cos(), from.sin()],
+                                end: [to.cos(), to.sin()],
+                                bulge: toolpath_sketch::bulge(step),
+                            }
+                        })
+                        .collect();
+                    segments.extend(transform.then(&ellipse).segments(&unit, self.tolerance));
+                }
+            }
+        }
+
+        // Each piece starts exactly where the one before it ended.
+        let mut end = None;
+        for segment in &mut segments {
+            if let Some(end) = end {
+                segment.start = end;
+            }
+            end = Some(segment.end);
+        }
+        segments.retain(|segment| segment.start != segment.end);
+        if !segments.is_empty() {
+            self.pieces.push(Piece {
+                layer: layer.to_owned(),
+                segments,
+                closed: outline.closed,
+            });
+        }
+    }
+}
+
+#[test]
+fn test_toolpath_svg() {
+    let svg = r##"<?xml version="1.0" encoding="UTF-8"?>
+<!-- A 100 x 50 mm page drawn at two user units to the millimetre. -->
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape"
+     xmlns:xlink="http://www.w3.org/1999/xlink" width="100mm" height="50mm" viewBox="0 0 200 100">
+  <defs>
+    <circle id="hole" r="4"/>
+  </defs>
+  <g inkscape:groupmode="layer" inkscape:label="Holes &amp; slots" transform="translate(20, 20)">
+    <use xlink:href="#hole"/>
+    <use href="#hole" x="20"/>
+    <path d="M40-4h20a4 4 0 0 1 0 8H40A4 4 0 0 1 40-4z"/>
+  </g>
+  <g inkscape:groupmode="layer" inkscape:label="Outline">
+    <rect x="0" y="0" width="200" height="100" rx="10"/>
+    <path d="M 100 60 C 120 60 120 90 100 90"/>
+    <path d="M 100 90 Q 80 75 100 60"/>
+  </g>
+  <g style="display:none"><rect width="10" height="10"/></g>
+  <ellipse cx="150" cy="50" rx="20" ry="10" transform="rotate(90 150 50)"/>
+</svg>"##;
+    let sketch = import(svg, DEFAULT_DPI, 0.001).unwrap();
+    assert_eq!(sketch.units, Units::Millimeters);
+    assert_eq!(sketch.layers(), ["Holes & slots", "Outline", ""]);
+    assert_eq!(sketch.contours.len(), 6);
+
+    let perimeter = |index: usize| -> f64 {
+        toolpath_sketch::segments(&sketch.contours[index].polyline)
+            .iter()
+            .map(Segment::length)
+            .sum()
+    };
+    let close = |a: f64, b: f64, tolerance: f64| (a - b).abs() <= tolerance;
+
+    // Holes of 2 mm radius with their centres 10 mm from the page's left and 40 mm from its
+    // bottom, kept as arcs.
+    let hole = &sketch.contours[0].polyline;
+    assert!(hole.is_closed);
+    assert_eq!(hole.vertex_data.len(), 2);
+    let (center, radius) = toolpath_sketch::segments(hole)[0].arc().unwrap();
+    assert!(toolpath_sketch::distance(center, [10.0, 40.0]) < 1e-9);
+    assert!(close(radius, 2.0, 1e-9));
+    let (center, _) = toolpath_sketch::segments(&sketch.contours[1].polyline)[0]
+        .arc()
+        .unwrap();
+    assert!(toolpath_sketch::distance(center, [20.0, 40.0]) < 1e-9);
+
+    // A 10 mm slot with round ends.
+    assert!(sketch.contours[2].polyline.is_closed);
+    assert!(close(perimeter(2), 20.0 + 4.0 * PI, 1e-9));
+
+    // The page outline with rounded corners, and a closed curve chained from two open paths.
+    assert!(close(perimeter(3), 2.0 * (90.0 + 40.0) + 10.0 * PI, 1e-9));
+    assert!(sketch.contours[4].polyline.is_closed);
+
+    // The curves are a few dozen arcs, meeting without a corner except where the two paths join,
+    // and never more than the tolerance off the cubic.
+    let curve = toolpath_sketch::segments(&sketch.contours[4].polyline);
+    assert!(curve.len() < 50 && curve.iter().all(|segment| segment.bulge != 0.0));
+    let heading = |segment: &Segment, at_end: bool| {
+        let chord = (segment.end[1] - segment.start[1]).atan2(segment.end[0] - segment.start[0]);
+        let half_sweep = 2.0 * segment.bulge.atan();
+        if at_end {
+            chord + half_sweep
+        } else {
+            chord - half_sweep
+        }
+    };
+    let corners = (0..curve.len())
+        .filter(|&i| {
+            let turn = heading(&curve[(i + 1) % curve.len()], false) - heading(&curve[i], true);
+            ((turn + PI).rem_euclid(TAU) - PI).abs() > 1e-6
+        })
+        .count();
+    assert_eq!(corners, 2);
+    let cubic = [[50.0, 20.0], [60.0, 20.0], [60.0, 5.0], [50.0, 5.0]];
+    assert!((0..=100).all(|i| {
+        let point = cubic_at(cubic, f64::from(i) / 100.0);
+        curve
+            .iter()
+            .map(|segment| distance_to_arc(segment, point))
+            .fold(f64::INFINITY, f64::min)
+            <= 0.001 + 1e-9
+    }));
+
+    // A 20 x 10 mm ellipse turned upright, flattened to within the tolerance.
+    let ellipse = &sketch.contours[5].polyline;
+    let (mut min, mut max) = ([f64::MAX; 2], [f64::MIN; 2]);
+    for point in toolpath_sketch::points(ellipse, 0.001) {
+        for axis in 0..2 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    assert!(close(max[0] - min[0], 10.0, 0.002));
+    assert!(close(max[1] - min[1], 20.0, 0.002));
+
+    // A curve running past the end of its chord and back, and one whose arcs could swing wide
+    // between the points checked along it, fitted both ways round: the curve near the arcs, and
+    // the arcs near the curve.
+    for cubic in [
+        [[0.0, 0.0], [60.0, 0.0], [-50.0, 0.0], [10.0, 0.0]],
+        [[0.0, 0.0], [6.0, -9.0], [-6.0, 7.0], [-1.0, 0.0]],
+    ] {
+        let mut arcs = vec![];
+        biarcs(cubic, 1.0, 0, &mut arcs);
+        let along: Vec<[f64; 2]> = (0..=1000)
+            .map(|i| cubic_at(cubic, f64::from(i) / 1000.0))
+            .collect();
+        assert!(along.iter().all(|&point| {
+            arcs.iter()
+                .map(|segment| distance_to_arc(segment, point))
+                .fold(f64::INFINITY, f64::min)
+                <= 1.0 + 1e-9
+        }));
+        assert!(arcs.iter().all(|segment| {
+            (0..=20).all(|i| {
+                let point = segment.part(0.0, f64::from(i) / 20.0).end;
+                along
+                    .iter()
+                    .map(|&on| toolpath_sketch::distance(on, point))
+                    .fold(f64::INFINITY, f64::min)
+                    <= 1.01
+            })
+        }));
+    }
+
+    assert_eq!(parse_path("M0,0L10,0 10,10").unwrap()[0].curves.len(), 2,);
+    assert_eq!(numbers("1.5.5-2e1,3").unwrap(), [1.5, 0.5, -20.0, 3.0]);
+    assert!(parse_path("L 10 10").is_err());
+    assert_eq!(length("1in", 96.0), Some(96.0));
+    assert_eq!(
+        import("<svg><path d='M 0 0 L 1'/></svg>", DEFAULT_DPI, 0.001)
+            .unwrap_err()
+            .line,
+        1
+    );
+    assert!(import("<svg><g></svg>", DEFAULT_DPI, 0.001).is_err());
+    assert_eq!(
+        import("<html/>", DEFAULT_DPI, 0.001).unwrap_err().kind,
+        SvgErrorKind::NotSvg
+    );
+}
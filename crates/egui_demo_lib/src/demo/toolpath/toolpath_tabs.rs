@@ -0,0 +1,212 @@
+//! Holding tabs: short stretches of a profile left partly or wholly uncut, so the part stays
+//! attached to the sheet until it is broken out.
+//!
+//! Tabs are kept as distances along the tool's path to their middles, which survive changes to
+//! their width. A mill rises over them and a laser or plasma turns its beam off.
+
+use cavalier_contours::polyline::Polyline;
+
+use super::toolpath_sketch::{self, Segment};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    /// This many tabs, evenly spaced, on each contour.
+    Count(usize),
+
+    /// Tabs about this far apart, in mm.
+    Spacing(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tabs {
+    pub placement: Placement,
+
+    /// The length of the tab along the contour, in mm.
+    pub width: f64,
+
+    /// How much material a mill leaves standing under the tab, in mm.
+    pub height: f64,
+}
+
+impl Default for Tabs {
+    fn default() -> Self {
+        Self {
+            placement: Placement::Count(4),
+            width: 5.0,
+            height: 1.0,
+        }
+    }
+}
+
+pub fn length(polyline: &Polyline<f64>) -> f64 {
+    toolpath_sketch::segments(polyline)
+        .iter()
+        .map(Segment::length)
+        .sum()
+}
+
+/// The middles of evenly spaced tabs, as distances along the polyline.
+pub fn place(polyline: &Polyline<f64>, placement: Placement) -> Vec<f64> {
+    let length = length(polyline);
+    let count = match placement {
+        Placement::Count(count) => count,
+        Placement::Spacing(spacing) if spacing > 0.0 => {
+            (length / spacing).round().max(1.0) as usize
+        }
+        Placement::Spacing(_) => 0,
+    };
+    (0..count)
+        .map(|tab| (tab as f64 + 0.5) * length / count as f64)
+        .collect()
+}
+
+/// How far along the polyline the point on it nearest to `point` lies, and how far off it
+/// `point` is. Arcs are flattened to within `tolerance`.
+pub fn nearest(polyline: &Polyline<f64>, point: [f64; 2], tolerance: f64) -> Option<(f64, f64)> {
+    let mut nearest: Option<(f64, f64)> = None;
+    let mut along = 0.0;
+    for segment in toolpath_sketch::segments(polyline) {
+        let mut points = vec![segment.start];
+        segment.flatten(tolerance, &mut points);
+        for pair in points.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+            let chord = dx.hypot(dy);
+            let t = if chord > 0.0 {
+                (((point[0] - a[0]) * dx + (point[1] - a[1]) * dy) / (chord * chord))
+                    .clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let off = toolpath_sketch::distance(point, [a[0] + t * dx, a[1] + t * dy]);
+            if nearest.map_or(true, |(_, best)| off < best) {
+                nearest = Some((along + t * chord, off));
+            }
+            along += chord;
+        }
+    }
+    nearest
+}
+
+/// The point `along` the polyline.
+pub fn point_at(polyline: &Polyline<f64>, along: f64) -> Option<[f64; 2]> {
+    let segments = toolpath_sketch::segments(polyline);
+    let mut start = 0.0;
+    for segment in &segments {
+        let length = segment.length();
+        if along <= start + length && length > 0.0 {
+            return Some(segment.part(0.0, ((along - start) / length).max(0.0)).end);
+        }
+        start += length;
+    }
+    segments.last().map(|segment| segment.end)
+}
+
+/// The stretches the tabs cover, as sorted distances along the polyline that do not overlap.
+/// On a closed polyline a tab over the start is split in two.
+pub fn spans(polyline: &Polyline<f64>, middles: &[f64], width: f64) -> Vec<[f64; 2]> {
+    let length = length(polyline);
+    let mut spans = Vec::new();
+    for &middle in middles {
+        let (from, to) = (middle - width / 2.0, middle + width / 2.0);
+        if polyline.is_closed && from < 0.0 {
+            spans.push([from + length, length]);
+        }
+        if polyline.is_closed && to > length {
+            spans.push([0.0, to - length]);
+        }
+        spans.push([from.max(0.0), to.min(length)]);
+    }
+    spans.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+    let mut merged: Vec<[f64; 2]> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span[0] <= last[1] => last[1] = last[1].max(span[1]),
+            _ => merged.push(span),
+        }
+    }
+    merged.retain(|span| span[1] > span[0]);
+    merged
+}
+
+/// The polyline in runs from start to end, each marked whether it lies over a tab.
+pub fn split(polyline: &Polyline<f64>, spans: &[[f64; 2]]) -> Vec<(Vec<Segment>, bool)> {
+    let segments = toolpath_sketch::segments(polyline);
+    let length: f64 = segments.iter().map(Segment::length).sum();
+    let mut stretches = Vec::new();
+    let mut from = 0.0;
+    for span in spans {
+        stretches.push((from, span[0], false));
+        stretches.push((span[0], span[1], true));
+        from = span[1];
+    }
+    stretches.push((from, length, false));
+
+    stretches
+        .into_iter()
+        .filter(|(from, to, _)| to > from)
+        .map(|(from, to, tab)| {
+            let mut run = Vec::new();
+            let mut start = 0.0;
+            for segment in &segments {
+                let end = start + segment.length();
+                let (a, b) = (from.max(start), to.min(end));
+                if b > a {
+                    let length = end - start;
+                    run.push(segment.part((a - start) / length, (b - start) / length));
+                }
+                start = end;
+            }
+            (run, tab)
+        })
+        .filter(|(run, _)| !run.is_empty())
+        .collect()
+}
+
+#[test]
+fn test_toolpath_tabs() {
+    // A 40 by 20 rectangle, 120 around.
+    let corners = [[0.0, 0.0], [40.0, 0.0], [40.0, 20.0], [0.0, 20.0]];
+    let rectangle = toolpath_sketch::polyline(
+        &(0..4)
+            .map(|i| Segment::line(corners[i], corners[(i + 1) % 4]))
+            .collect::<Vec<_>>(),
+        true,
+    );
+    assert_eq!(
+        place(&rectangle, Placement::Count(4)),
+        [15.0, 45.0, 75.0, 105.0]
+    );
+    assert_eq!(place(&rectangle, Placement::Spacing(50.0)).len(), 2);
+
+    let (along, off) = nearest(&rectangle, [41.0, 5.0], 0.01).unwrap();
+    assert!((along - 45.0).abs() < 1e-9 && (off - 1.0).abs() < 1e-9);
+    assert_eq!(point_at(&rectangle, 45.0), Some([40.0, 5.0]));
+
+    // A tab over the start wraps around, and overlapping tabs merge.
+    let spans = spans(&rectangle, &[1.0, 60.0, 63.0], 4.0);
+    assert_eq!(spans, [[0.0, 3.0], [58.0, 65.0], [119.0, 120.0]]);
+
+    let runs = split(&rectangle, &spans);
+    let kinds: Vec<bool> = runs.iter().map(|(_, tab)| *tab).collect();
+    assert_eq!(kinds, [true, false, true, false, true]);
+    assert_eq!(runs[0].0, [Segment::line([0.0, 0.0], [3.0, 0.0])]);
+    assert_eq!(runs[1].0.len(), 2);
+    let total: f64 = runs
+        .iter()
+        .flat_map(|(run, _)| run)
+        .map(Segment::length)
+        .sum();
+    assert!((total - 120.0).abs() < 1e-9);
+
+    // An arc splits into arcs on the same circle.
+    let half = Segment {
+        start: [10.0, 0.0],
+        end: [-10.0, 0.0],
+        bulge: 1.0,
+    };
+    let quarter = half.part(0.0, 0.5);
+    assert!(toolpath_sketch::distance(quarter.end, [0.0, 10.0]) < 1e-9);
+    assert!((quarter.bulge - toolpath_sketch::bulge(std::f64::consts::FRAC_PI_2)).abs() < 1e-12);
+}
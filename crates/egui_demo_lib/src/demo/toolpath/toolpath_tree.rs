@@ -0,0 +1,157 @@
+//! Which closed contours lie inside which.
+//!
+//! Contours at even depth are the outsides of parts, or islands left standing inside a hole;
+//! contours at odd depth are holes. Open contours are not part of the tree.
+
+use super::toolpath_sketch::{self, Contour};
+
+/// How closed contours nest, by index into the contours it was built from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tree {
+    /// The contour each one lies directly inside, if any.
+    pub parents: Vec<Option<usize>>,
+
+    /// How many contours each one lies inside, or `None` for an open contour.
+    pub depths: Vec<Option<usize>>,
+}
+
+impl Tree {
+    /// Arcs are flattened to within `tolerance` to tell what lies inside what. A contour is
+    /// inside another if the middle of its first segment is.
+    pub fn new(contours: &[Contour], tolerance: f64) -> Self {
+        let outlines: Vec<Option<(f64, Vec<[f64; 2]>)>> = contours
+            .iter()
+            .map(|contour| {
+                let polyline = &contour.polyline;
+                (polyline.is_closed && polyline.vertex_data.len() > 1).then(|| {
+                    (
+                        toolpath_sketch::area(polyline).abs(),
+                        toolpath_sketch::points(polyline, tolerance),
+                    )
+                })
+            })
+            .collect();
+
+        let mut parents = vec![None; contours.len()];
+        for (index, contour) in contours.iter().enumerate() {
+            let Some((area, _)) = outlines[index] else {
+                continue;
+            };
+            let Some(first) = toolpath_sketch::segments(&contour.polyline).first().copied() else {
+                continue;
+            };
+            let inside = first.midpoint();
+            // The smallest contour around it is the one it lies directly inside.
+            parents[index] = outlines
+                .iter()
+                .enumerate()
+                .filter_map(|(other, outline)| {
+                    let (other_area, points) = outline.as_ref()?;
+                    (other != index && *other_area > area && contains(points, inside))
+                        .then_some((other, *other_area))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(other, _)| other);
+        }
+
+        let depths = (0..contours.len())
+            .map(|index| {
+                outlines[index].as_ref()?;
+                let mut depth = 0;
+                let mut current = index;
+                while let Some(parent) = parents[current] {
+                    depth += 1;
+                    current = parent;
+                }
+                Some(depth)
+            })
+            .collect();
+        Self { parents, depths }
+    }
+
+    /// Whether a contour is a hole in the material around it.
+    pub fn is_hole(&self, index: usize) -> bool {
+        self.depths[index].map_or(false, |depth| depth % 2 == 1)
+    }
+
+    /// The contours lying directly inside `index`.
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.parents.len()).filter(move |&child| self.parents[child] == Some(index))
+    }
+}
+
+/// Whether a point lies inside the polygon through `points`, by the even-odd rule.
+pub fn contains(points: &[[f64; 2]], [x, y]: [f64; 2]) -> bool {
+    let mut inside = false;
+    let mut previous = match points.last() {
+        Some(&last) => last,
+        None => return false,
+    };
+    for &point in points {
+        let ([x1, y1], [x2, y2]) = (previous, point);
+        if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+            inside = !inside;
+        }
+        previous = point;
+    }
+    inside
+}
+
+#[test]
+fn test_toolpath_tree() {
+    use super::toolpath_sketch::Segment;
+
+    let square = |x: f64, y: f64, size: f64| Contour {
+        layer: String::new(),
+        polyline: toolpath_sketch::polyline(
+            &[
+                Segment::line([x, y], [x + size, y]),
+                Segment::line([x + size, y], [x + size, y + size]),
+                Segment::line([x + size, y + size], [x, y + size]),
+                Segment::line([x, y + size], [x, y]),
+            ],
+            true,
+        ),
+    };
+    let circle = |x: f64, y: f64, r: f64| Contour {
+        layer: String::new(),
+        polyline: toolpath_sketch::polyline(
+            &[
+                Segment {
+                    start: [x - r, y],
+                    end: [x + r, y],
+                    bulge: 1.0,
+                },
+                Segment {
+                    start: [x + r, y],
+                    end: [x - r, y],
+                    bulge: 1.0,
+                },
+            ],
+            true,
+        ),
+    };
+    let open = Contour {
+        layer: String::new(),
+        polyline: toolpath_sketch::polyline(&[Segment::line([1.0, 1.0], [2.0, 2.0])], false),
+    };
+
+    // A plate with a round hole holding an island, a square hole, and a second part.
+    let contours = vec![
+        circle(20.0, 20.0, 5.0),
+        square(0.0, 0.0, 100.0),
+        square(60.0, 60.0, 10.0),
+        circle(20.0, 20.0, 2.0),
+        square(200.0, 0.0, 10.0),
+        open,
+    ];
+    let tree = Tree::new(&contours, 0.01);
+    assert_eq!(tree.parents, [Some(1), None, Some(1), Some(0), None, None]);
+    assert_eq!(
+        tree.depths,
+        [Some(1), Some(0), Some(1), Some(2), Some(0), None]
+    );
+    assert!(tree.is_hole(0) && tree.is_hole(2));
+    assert!(!tree.is_hole(3) && !tree.is_hole(5));
+    assert_eq!(tree.children(1).collect::<Vec<_>>(), [0, 2]);
+}
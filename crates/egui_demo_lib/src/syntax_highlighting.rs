@@ -25,7 +25,11 @@ pub fn code_view_ui(ui: &mut egui::Ui, mut code: &str) {
 pub fn highlight(ctx: &egui::Context, theme: &CodeTheme, code: &str, language: &str) -> LayoutJob {
     impl egui::util::cache::ComputerMut<(&CodeTheme, &str, &str), LayoutJob> for Highlighter {
         fn compute(&mut self, (theme, code, lang): (&CodeTheme, &str, &str)) -> LayoutJob {
-            self.highlight(theme, code, lang)
+            if is_gcode(lang) {
+                highlight_gcode(theme, code)
+            } else {
+                self.highlight(theme, code, lang)
+            }
         }
     }
 
@@ -40,7 +44,7 @@ pub fn highlight(ctx: &egui::Context, theme: &CodeTheme, code: &str, language: &
 
 // ----------------------------------------------------------------------------
 
-#[cfg(not(feature = "syntect"))]
+// Also used for G-code when compiled with syntect.
 #[derive(Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[derive(enum_map::Enum)]
@@ -169,6 +173,30 @@ impl CodeTheme {
     }
 }
 
+fn simple_formats(dark_mode: bool) -> enum_map::EnumMap<TokenType, egui::TextFormat> {
+    let font_id = egui::FontId::monospace(10.0);
+    use egui::{Color32, TextFormat};
+    if dark_mode {
+        enum_map::enum_map![
+            TokenType::Comment => TextFormat::simple(font_id.clone(), Color32::from_gray(120)),
+            TokenType::Keyword => TextFormat::simple(font_id.clone(), Color32::from_rgb(255, 100, 100)),
+            TokenType::Literal => TextFormat::simple(font_id.clone(), Color32::from_rgb(87, 165, 171)),
+            TokenType::StringLiteral => TextFormat::simple(font_id.clone(), Color32::from_rgb(109, 147, 226)),
+            TokenType::Punctuation => TextFormat::simple(font_id.clone(), Color32::LIGHT_GRAY),
+            TokenType::Whitespace => TextFormat::simple(font_id.clone(), Color32::TRANSPARENT),
+        ]
+    } else {
+        enum_map::enum_map![
+            TokenType::Comment => TextFormat::simple(font_id.clone(), Color32::GRAY),
+            TokenType::Keyword => TextFormat::simple(font_id.clone(), Color32::from_rgb(235, 0, 0)),
+            TokenType::Literal => TextFormat::simple(font_id.clone(), Color32::from_rgb(153, 134, 255)),
+            TokenType::StringLiteral => TextFormat::simple(font_id.clone(), Color32::from_rgb(37, 203, 105)),
+            TokenType::Punctuation => TextFormat::simple(font_id.clone(), Color32::DARK_GRAY),
+            TokenType::Whitespace => TextFormat::simple(font_id.clone(), Color32::TRANSPARENT),
+        ]
+    }
+}
+
 #[cfg(feature = "syntect")]
 impl CodeTheme {
     pub fn dark() -> Self {
@@ -185,6 +213,11 @@ impl CodeTheme {
         }
     }
 
+    /// The formats G-code is highlighted with, which syntect has no syntax for.
+    fn token_formats(&self) -> enum_map::EnumMap<TokenType, egui::TextFormat> {
+        simple_formats(self.dark_mode)
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui) {
         egui::widgets::global_dark_light_mode_buttons(ui);
 
@@ -199,38 +232,23 @@ impl CodeTheme {
 #[cfg(not(feature = "syntect"))]
 impl CodeTheme {
     pub fn dark() -> Self {
-        let font_id = egui::FontId::monospace(10.0);
-        use egui::{Color32, TextFormat};
         Self {
             dark_mode: true,
-            formats: enum_map::enum_map![
-                TokenType::Comment => TextFormat::simple(font_id.clone(), Color32::from_gray(120)),
-                TokenType::Keyword => TextFormat::simple(font_id.clone(), Color32::from_rgb(255, 100, 100)),
-                TokenType::Literal => TextFormat::simple(font_id.clone(), Color32::from_rgb(87, 165, 171)),
-                TokenType::StringLiteral => TextFormat::simple(font_id.clone(), Color32::from_rgb(109, 147, 226)),
-                TokenType::Punctuation => TextFormat::simple(font_id.clone(), Color32::LIGHT_GRAY),
-                TokenType::Whitespace => TextFormat::simple(font_id.clone(), Color32::TRANSPARENT),
-            ],
+            formats: simple_formats(true),
         }
     }
 
     pub fn light() -> Self {
-        let font_id = egui::FontId::monospace(10.0);
-        use egui::{Color32, TextFormat};
         Self {
             dark_mode: false,
-            #[cfg(not(feature = "syntect"))]
-            formats: enum_map::enum_map![
-                TokenType::Comment => TextFormat::simple(font_id.clone(), Color32::GRAY),
-                TokenType::Keyword => TextFormat::simple(font_id.clone(), Color32::from_rgb(235, 0, 0)),
-                TokenType::Literal => TextFormat::simple(font_id.clone(), Color32::from_rgb(153, 134, 255)),
-                TokenType::StringLiteral => TextFormat::simple(font_id.clone(), Color32::from_rgb(37, 203, 105)),
-                TokenType::Punctuation => TextFormat::simple(font_id.clone(), Color32::DARK_GRAY),
-                TokenType::Whitespace => TextFormat::simple(font_id.clone(), Color32::TRANSPARENT),
-            ],
+            formats: simple_formats(false),
         }
     }
 
+    fn token_formats(&self) -> enum_map::EnumMap<TokenType, egui::TextFormat> {
+        self.formats.clone()
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal_top(|ui| {
             let selected_id = egui::Id::null();
@@ -504,3 +522,216 @@ fn is_keyword(word: &str) -> bool {
             | "while"
     )
 }
+
+// ----------------------------------------------------------------------------
+// G-code has its own highlighter, with or without syntect, so the firmware's wasm build gets it.
+
+fn is_gcode(language: &str) -> bool {
+    matches!(
+        language.to_ascii_lowercase().as_str(),
+        "gcode" | "g-code" | "g" | "nc" | "ngc"
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GcodeToken {
+    /// `G`, `M`, `T` and `O` words, and O-word keywords such as `sub` or `while`.
+    Command,
+
+    /// Axis, arc and extruder words: `X Y Z A B C U V W E I J K R`.
+    Axis,
+
+    /// `F` and `S` words.
+    FeedSpeed,
+
+    /// Every other word, such as `P`, `Q`, `L`, `D` or `H`.
+    Parameter,
+
+    /// `N` words.
+    LineNumber,
+
+    /// `*` and the digits after it.
+    Checksum,
+
+    /// `;` to the end of the line, or `( )`.
+    Comment,
+
+    /// `"quoted"` text.
+    String,
+
+    Whitespace,
+
+    /// `%`, `#`, brackets, operators and anything else.
+    Punctuation,
+}
+
+/// The kind of code token a G-code token is shown as.
+fn gcode_token_type(token: GcodeToken) -> TokenType {
+    match token {
+        GcodeToken::Command => TokenType::Keyword,
+        GcodeToken::Axis
+        | GcodeToken::FeedSpeed
+        | GcodeToken::Parameter
+        | GcodeToken::LineNumber
+        | GcodeToken::Checksum => TokenType::Literal,
+        GcodeToken::Comment => TokenType::Comment,
+        GcodeToken::String => TokenType::StringLiteral,
+        GcodeToken::Whitespace => TokenType::Whitespace,
+        GcodeToken::Punctuation => TokenType::Punctuation,
+    }
+}
+
+fn highlight_gcode(theme: &CodeTheme, text: &str) -> LayoutJob {
+    let formats = theme.token_formats();
+    let mut job = LayoutJob::default();
+    for (token, range) in gcode_tokens(text) {
+        job.append(&text[range], 0.0, formats[gcode_token_type(token)].clone());
+    }
+    job
+}
+
+/// Splits G-code into tokens covering the whole text.
+fn gcode_tokens(text: &str) -> Vec<(GcodeToken, std::ops::Range<usize>)> {
+    let bytes = text.as_bytes();
+    let line_end = |pos: usize| text[pos..].find('\n').map_or(text.len(), |end| pos + end);
+    let run = |mut pos: usize, f: fn(u8) -> bool| {
+        while pos < bytes.len() && f(bytes[pos]) {
+            pos += 1;
+        }
+        pos
+    };
+
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let c = bytes[pos];
+        let (token, end) = match c {
+            b';' => (GcodeToken::Comment, line_end(pos)),
+            b'(' => {
+                let end = text[pos..]
+                    .find(')')
+                    .map_or(usize::MAX, |end| pos + end + 1)
+                    .min(line_end(pos));
+                (GcodeToken::Comment, end)
+            }
+            b'"' => {
+                // `""` is a quote inside the string.
+                let mut end = pos + 1;
+                loop {
+                    end = run(end, |b| b != b'"' && b != b'\n');
+                    if bytes.get(end) == Some(&b'"') {
+                        end += 1;
+                        if bytes.get(end) == Some(&b'"') {
+                            end += 1;
+                            continue;
+                        }
+                    }
+                    break;
+                }
+                (GcodeToken::String, end)
+            }
+            b'*' => (GcodeToken::Checksum, run(pos + 1, |b| b.is_ascii_digit())),
+            _ if c.is_ascii_digit() => (
+                GcodeToken::Punctuation,
+                run(pos, |b| b.is_ascii_digit() || b == b'.'),
+            ),
+            _ if c.is_ascii_whitespace() => (
+                GcodeToken::Whitespace,
+                run(pos, |b| b.is_ascii_whitespace()),
+            ),
+            _ if c.is_ascii_alphabetic() => {
+                let letters = run(pos, |b| b.is_ascii_alphabetic());
+                if letters - pos > 1 {
+                    let keyword = text[pos..letters].to_ascii_lowercase();
+                    let token = if is_gcode_keyword(&keyword) {
+                        GcodeToken::Command
+                    } else {
+                        GcodeToken::Punctuation
+                    };
+                    (token, letters)
+                } else {
+                    let end = run(pos + 1, |b| {
+                        b.is_ascii_digit() || matches!(b, b'.' | b'-' | b'+')
+                    });
+                    let token = match c.to_ascii_uppercase() {
+                        b'G' | b'M' | b'T' | b'O' => GcodeToken::Command,
+                        b'N' => GcodeToken::LineNumber,
+                        b'F' | b'S' => GcodeToken::FeedSpeed,
+                        b'X' | b'Y' | b'Z' | b'A' | b'B' | b'C' | b'U' | b'V' | b'W' | b'E'
+                        | b'I' | b'J' | b'K' | b'R' => GcodeToken::Axis,
+                        _ => GcodeToken::Parameter,
+                    };
+                    (token, end)
+                }
+            }
+            _ => {
+                let width = text[pos..].chars().next().map_or(1, char::len_utf8);
+                (GcodeToken::Punctuation, pos + width)
+            }
+        };
+        tokens.push((token, pos..end));
+        pos = end;
+    }
+    tokens
+}
+
+/// Keywords of O-word program flow.
+fn is_gcode_keyword(word: &str) -> bool {
+    matches!(
+        word,
+        "sub"
+            | "endsub"
+            | "call"
+            | "return"
+            | "if"
+            | "elseif"
+            | "else"
+            | "endif"
+            | "while"
+            | "endwhile"
+            | "do"
+            | "repeat"
+            | "endrepeat"
+            | "break"
+            | "continue"
+    )
+}
+
+#[test]
+fn test_gcode_tokens() {
+    use GcodeToken::*;
+
+    let text = "N10 G1 x1.5 Y-2 F300 S1000 P2 (cut) ; done *71\n\
+                M117 \"say \"\"hi\"\"\"\n\
+                o100 if [#1 GT 0]";
+    let tokens: Vec<_> = gcode_tokens(text)
+        .into_iter()
+        .filter(|(token, _)| *token != Whitespace)
+        .map(|(token, range)| (token, &text[range]))
+        .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            (LineNumber, "N10"),
+            (Command, "G1"),
+            (Axis, "x1.5"),
+            (Axis, "Y-2"),
+            (FeedSpeed, "F300"),
+            (FeedSpeed, "S1000"),
+            (Parameter, "P2"),
+            (Comment, "(cut)"),
+            (Comment, "; done *71"),
+            (Command, "M117"),
+            (String, "\"say \"\"hi\"\"\""),
+            (Command, "o100"),
+            (Command, "if"),
+            (Punctuation, "["),
+            (Punctuation, "#"),
+            (Punctuation, "1"),
+            (Punctuation, "GT"),
+            (Punctuation, "0"),
+            (Punctuation, "]"),
+        ]
+    );
+    assert_eq!(gcode_tokens("G1*42")[1], (Checksum, 2..5));
+}
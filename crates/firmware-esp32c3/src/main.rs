@@ -4,15 +4,16 @@ use embedded_svc::{http::Method, http::Headers, io::Write};
 use esp_idf_hal::{
     i2c::{I2cConfig, I2cDriver},
     prelude::*,
-    gpio::OutputPin
+    gpio::{Output, OutputPin, Pin, PinDriver}
 };
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
     http::server::{Configuration, EspHttpServer},
+    timer::EspTaskTimerService,
     tls::X509,
 };
 use std::{
-    sync::{Arc, Mutex},
+    sync::{mpsc, Arc, Mutex},
     thread::sleep,
     time::Duration,
     collections::HashMap,
@@ -20,6 +21,8 @@ use std::{
 use embedded_svc::io::Read;
 use wifi::wifi;
 use stepgen::Stepgen;
+use gcode_stream::Receiver;
+use planner::Planner;
 
 // If using the `binstart` feature of `esp-idf-sys`, always keep this module imported
 use esp_idf_sys as _;
@@ -29,6 +32,9 @@ pub mod planner;
 pub mod serial;
 pub mod pins;
 
+/// Lines queued for the planner or running on it, the window the UI may stream into.
+const PLANNER_SLOTS: usize = 16;
+
 #[toml_cfg::toml_config]
 pub struct Config {
     #[default("")]
@@ -319,11 +325,16 @@ fn main() -> Result<()> {
         Ok(())
     })?;
 
-    server.fn_handler("/queue", Method::Get, |request| {  // respond with queue status
-        let queue: Vec<i32> = vec![];
+    // Streamed G-code lines, checked and in order, waiting for the planner
+    let stream_main = Arc::new(Mutex::new(Receiver::new(PLANNER_SLOTS)));
+    let stream_status = stream_main.clone();
+    let stream = stream_main.clone();
 
-        let response = request.into_response(200, Some("Queue: "), &[("Content-Type", "text/ron")]);
-        response?.flush()?;
+    server.fn_handler("/queue", Method::Get, move|request| {  // respond with queue status: last line queued and free slots
+        let reply = stream_status.lock().unwrap().status().to_string();
+
+        let mut response = request.into_response(200, Some("Queue: "), &[("Content-Type", "text/plain")])?;
+        response.write_all(reply.as_bytes())?;
         Ok(())
     })?;
 
@@ -331,17 +342,32 @@ fn main() -> Result<()> {
 
         let header = request.header("Accept").unwrap().to_string();
 
-        // Create a buffer to store the payload
-        let mut buffer = [0; 1024];  // Adjust the size as needed
-
-        // Read the payload into the buffer
-        let bytes_read = request.read(&mut buffer)?;
+        // Read the whole payload, however long
+        let mut body = Vec::new();
+        let mut buffer = [0; 1024];
+        loop {
+            let bytes_read = request.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            body.extend_from_slice(&buffer[..bytes_read]);
+        }
 
         // Convert the bytes to a string
-        let payload = std::str::from_utf8(&buffer[..bytes_read])?;
+        let payload = std::str::from_utf8(&body)?;
 
         println!("Received payload: {}", payload);
 
+        if payload.starts_with('N') {
+            // Streamed G-code frames: queue what arrived intact and in order, and tell the
+            // sender how far we got and how much room is left
+            let reply = stream.lock().unwrap().receive(payload).to_string();
+
+            let mut response = request.into_response(200, Some("OK"), &[("Content-Type", "text/plain")])?;
+            response.write_all(reply.as_bytes())?;
+            return Ok(());
+        }
+
         match payload.trim() {
             "status_on" => {
                 println!("Turning status LED on");
@@ -728,10 +754,98 @@ fn main() -> Result<()> {
 
     println!("Server awaiting connection");
 
+    // Step and direction outputs: X on D2/D3, Y on D4/D5, Z on D6/D7
+    let (x_step, x_dir) = (d2_main.clone(), d3_main.clone());
+    let (y_step, y_dir) = (d4_main.clone(), d5_main.clone());
+    let (z_step, z_dir) = (d6_main.clone(), d7_main.clone());
+    let step: Step = Arc::new(Mutex::new(move |axis: usize, forward: bool| match axis {
+        0 => pulse(&x_step, &x_dir, forward),
+        1 => pulse(&y_step, &y_dir, forward),
+        _ => pulse(&z_step, &z_dir, forward),
+    }));
+    let timers = EspTaskTimerService::new()?;
+    let mut planner = Planner::new();
+
     // Prevent program from exiting
     loop {
-        sleep(Duration::from_millis(1000));
+        // Run streamed lines in order, freeing each slot once its move is done
+        let line = stream_main.lock().unwrap().pop();
+        match line {
+            Some(line) => {
+                if let Some(motion) = planner.plan(&line) {
+                    if let Err(err) = run(&timers, &motion, &step) {
+                        // Drop the rest of the stream; the sender sees the fault and resets
+                        println!("Error:{}: {}", line, err);
+                        stream_main.lock().unwrap().fail();
+                    }
+                }
+                stream_main.lock().unwrap().finish();
+            },
+            None => sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+/// Steps `axis` once, forwards or backwards.
+type Step = Arc<Mutex<dyn FnMut(usize, bool) -> Result<()> + Send>>;
+
+/// Shortest period esp_timer will run a periodic timer at.
+const MIN_TICK: Duration = Duration::from_micros(50);
+
+/// Runs a move on a periodic timer, blocking until it is done without holding the core.
+fn run(timers: &EspTaskTimerService, motion: &planner::Move, step: &Step) -> Result<()> {
+    let ticks = motion.ticks();
+    if ticks == 0 {
+        sleep(motion.duration);
+        return Ok(());
+    }
+    let (done, finished) = mpsc::channel();
+    let mut pending = planner::Ticks::new(motion);
+    let mut stopped = false;
+    let step = step.clone();
+    let timer = timers.timer(move || {
+        if stopped {
+            return;
+        }
+        let result = match pending.next() {
+            Some(tick) => tick
+                .iter()
+                .enumerate()
+                .filter_map(|(axis, forward)| forward.map(|forward| (axis, forward)))
+                .try_for_each(|(axis, forward)| (step.lock().unwrap())(axis, forward)),
+            None => {
+                stopped = true;
+                let _ = done.send(Ok(()));
+                return;
+            }
+        };
+        if result.is_err() {
+            stopped = true;
+            let _ = done.send(result);
+        }
+    })?;
+    timer.every((motion.duration / ticks).max(MIN_TICK))?;
+    let result = finished.recv()?;
+    timer.cancel()?;
+    result
+}
+
+/// One step pulse on `step`, after setting `dir` for the direction.
+fn pulse<S: Pin, D: Pin>(
+    step: &Mutex<PinDriver<'static, S, Output>>,
+    dir: &Mutex<PinDriver<'static, D, Output>>,
+    forward: bool,
+) -> Result<()> {
+    let mut dir = dir.lock().unwrap();
+    if forward {
+        dir.set_high()?;
+    } else {
+        dir.set_low()?;
     }
+    let mut step = step.lock().unwrap();
+    step.set_high()?;
+    step.set_low()?;
+    Ok(())
 }
 
 fn temperature(val: f32) -> String {
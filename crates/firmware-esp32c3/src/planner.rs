@@ -0,0 +1,195 @@
+// Turns streamed G-code lines into stepper moves and splits them into step timer ticks.
+//
+// Understands G0/G1 moves, G4 dwells, G20/G21 units, G90/G91 distance mode and G92 position
+// resets on X, Y and Z. Anything else is accepted and takes no time.
+
+use std::time::Duration;
+
+/// Axes the planner drives, in the order [`Ticks`] numbers them.
+pub const AXES: [char; 3] = ['X', 'Y', 'Z'];
+
+/// Steps per millimetre of each axis.
+const STEPS_PER_MM: [f32; 3] = [80.0, 80.0, 400.0];
+
+/// Feedrate for rapids, and for feeds until a program sets one, in mm/min.
+const RAPID_FEEDRATE: f32 = 3000.0;
+
+/// The motion of one line.
+pub struct Move {
+    /// Signed steps on each axis.
+    pub steps: [i32; 3],
+    /// How long the move, or the dwell, takes.
+    pub duration: Duration,
+}
+
+pub struct Planner {
+    /// Machine position in steps.
+    position: [i32; 3],
+    /// G91 is in effect.
+    relative: bool,
+    /// Millimetres per program unit.
+    scale: f32,
+    /// Current G1 feedrate in mm/min.
+    feedrate: f32,
+}
+
+impl Planner {
+    pub fn new() -> Self {
+        Self {
+            position: [0; 3],
+            relative: false,
+            scale: 1.0,
+            feedrate: RAPID_FEEDRATE,
+        }
+    }
+
+    /// Applies one line and returns the motion it asks for, if any.
+    pub fn plan(&mut self, line: &str) -> Option<Move> {
+        let words = words(line);
+        let word = |letter: char| words.iter().find(|(l, _)| *l == letter).map(|(_, v)| *v);
+
+        let mut motion = None;
+        for (letter, number) in &words {
+            if *letter != 'G' {
+                continue;
+            }
+            match *number as u32 {
+                0 | 1 => motion = Some(*number as u32),
+                4 => {
+                    let seconds = word('P').map(|p| p / 1000.0).or(word('S')).unwrap_or(0.0);
+                    return Some(Move {
+                        steps: [0; 3],
+                        duration: Duration::from_secs_f32(seconds.max(0.0)),
+                    });
+                }
+                20 => self.scale = 25.4,
+                21 => self.scale = 1.0,
+                90 => self.relative = false,
+                91 => self.relative = true,
+                92 => {
+                    for (axis, letter) in AXES.iter().enumerate() {
+                        if let Some(value) = word(*letter) {
+                            self.position[axis] = self.to_steps(axis, value);
+                        }
+                    }
+                    return None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(f) = word('F') {
+            self.feedrate = f * self.scale;
+        }
+
+        let motion = motion?;
+        let mut steps = [0; 3];
+        for (axis, letter) in AXES.iter().enumerate() {
+            if let Some(value) = word(*letter) {
+                let target = if self.relative {
+                    self.position[axis] + self.to_steps(axis, value)
+                } else {
+                    self.to_steps(axis, value)
+                };
+                steps[axis] = target - self.position[axis];
+                self.position[axis] = target;
+            }
+        }
+
+        let distance = steps
+            .iter()
+            .zip(STEPS_PER_MM)
+            .map(|(steps, per_mm)| (*steps as f32 / per_mm).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let feedrate = if motion == 0 {
+            RAPID_FEEDRATE
+        } else {
+            self.feedrate
+        };
+        Some(Move {
+            steps,
+            duration: Duration::from_secs_f32(distance / feedrate.max(1.0) * 60.0),
+        })
+    }
+
+    fn to_steps(&self, axis: usize, value: f32) -> i32 {
+        (value * self.scale * STEPS_PER_MM[axis]).round() as i32
+    }
+}
+
+impl Move {
+    /// The most steps any axis takes, one tick of the step timer each.
+    pub fn ticks(&self) -> u32 {
+        self.steps
+            .iter()
+            .map(|steps| steps.unsigned_abs())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// The steps of a move, spread evenly over its ticks: for each tick, which axes step and
+/// whether forwards.
+pub struct Ticks {
+    steps: [i32; 3],
+    total: u32,
+    done: u32,
+    error: [u32; 3],
+}
+
+impl Ticks {
+    pub fn new(motion: &Move) -> Self {
+        Self {
+            steps: motion.steps,
+            total: motion.ticks(),
+            done: 0,
+            error: [0; 3],
+        }
+    }
+}
+
+impl Iterator for Ticks {
+    type Item = [Option<bool>; 3];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done == self.total {
+            return None;
+        }
+        self.done += 1;
+        let mut tick = [None; 3];
+        for axis in 0..AXES.len() {
+            self.error[axis] += self.steps[axis].unsigned_abs();
+            if self.error[axis] >= self.total {
+                self.error[axis] -= self.total;
+                tick[axis] = Some(self.steps[axis] > 0);
+            }
+        }
+        Some(tick)
+    }
+}
+
+/// Letter and number words of a line, without comments.
+fn words(line: &str) -> Vec<(char, f32)> {
+    let line = line.split(';').next().unwrap_or_default();
+    let mut words = vec![];
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c == '(' {
+            while chars.next().map_or(false, |(_, c)| c != ')') {}
+        } else if c.is_ascii_alphabetic() {
+            let mut end = start + 1;
+            while let Some((index, c)) = chars.peek() {
+                if c.is_ascii_digit() || "+-.".contains(*c) {
+                    end = index + 1;
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if let Ok(number) = line[start + 1..end].parse() {
+                words.push((c.to_ascii_uppercase(), number));
+            }
+        }
+    }
+    words
+}
@@ -0,0 +1,108 @@
+//! The framing of one line: `N<sequence> <line>*<checksum>`.
+//!
+//! The checksum is the XOR of every byte before the `*`, as in Marlin and RepRapFirmware, so the
+//! G-code parser accepts frames as they are.
+
+use std::fmt;
+
+/// Sent with sequence number 0 to restart numbering: the next line is 1.
+pub const RESET: &str = "M110";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FrameError {
+    /// No `N` word at the start.
+    MissingSequence,
+
+    /// No `*` checksum at the end.
+    MissingChecksum,
+
+    ChecksumMismatch {
+        computed: u8,
+        found: u8,
+    },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSequence => write!(f, "frame has no N sequence number"),
+            Self::MissingChecksum => write!(f, "frame has no * checksum"),
+            Self::ChecksumMismatch { computed, found } => {
+                write!(f, "checksum is {} but the frame says {}", computed, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, b| sum ^ b)
+}
+
+/// Frames one line of G-code, without its `;` comment.
+pub fn encode(sequence: u32, line: &str) -> String {
+    let line = strip_comment(line);
+    let body = format!("N{} {}", sequence, line);
+    let sum = checksum(body.as_bytes());
+    format!("{}*{}", body, sum)
+}
+
+/// A line up to its `;` comment, trimmed.
+///
+/// A `;` inside a `"quoted"` parameter (with `""` for a quote) or a `( )` comment is kept, as the
+/// G-code parser reads it.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut parenthesis = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' if !parenthesis => quoted = !quoted,
+            '(' if !quoted => parenthesis = true,
+            ')' if !quoted => parenthesis = false,
+            ';' if !quoted && !parenthesis => return line[..index].trim(),
+            _ => {}
+        }
+    }
+    line.trim()
+}
+
+/// The sequence number and line of a frame.
+pub fn decode(frame: &str) -> Result<(u32, &str), FrameError> {
+    let frame = frame.trim();
+    let star = frame.rfind('*').ok_or(FrameError::MissingChecksum)?;
+    let (body, found) = (&frame[..star], &frame[star + 1..]);
+    let found: u8 = found.parse().map_err(|_| FrameError::MissingChecksum)?;
+    let computed = checksum(body.as_bytes());
+    if computed != found {
+        return Err(FrameError::ChecksumMismatch { computed, found });
+    }
+
+    let rest = body.strip_prefix('N').ok_or(FrameError::MissingSequence)?;
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let sequence = rest[..digits]
+        .parse()
+        .map_err(|_| FrameError::MissingSequence)?;
+    Ok((sequence, rest[digits..].trim()))
+}
+
+#[test]
+fn test_frame() {
+    let frame = encode(12, "G1 X10 Y-2.5 F300 ; cut");
+    assert_eq!(frame, "N12 G1 X10 Y-2.5 F300*74");
+    assert_eq!(decode(&frame), Ok((12, "G1 X10 Y-2.5 F300")));
+    assert_eq!(
+        decode("N12 G1 X10 Y-2.5 F301*74"),
+        Err(FrameError::ChecksumMismatch {
+            computed: 75,
+            found: 74
+        })
+    );
+    assert_eq!(decode("G1 X10*15"), Err(FrameError::MissingSequence));
+    assert_eq!(decode("N12 G1 X10"), Err(FrameError::MissingChecksum));
+
+    let frame = encode(3, "M117 \"a;b \"\"c\"\"\" (x;y) ; note");
+    assert_eq!(decode(&frame), Ok((3, "M117 \"a;b \"\"c\"\"\" (x;y)")));
+}
@@ -0,0 +1,90 @@
+//! Line-numbered, checksummed G-code streaming with windowed flow control and resend.
+//!
+//! The host side is a [`Sender`], the firmware side a [`Receiver`]. Requests carry one or more
+//! [`frame`]s, one per line, and every request gets a [`Reply`] saying how far the receiver has
+//! got. Neither end does any I/O, so the same code runs over HTTP, serial or a test harness.
+//!
+//! Firmware that only speaks its own serial protocol gets a [`SerialSender`] instead.
+
+use std::fmt;
+use std::str::FromStr;
+
+pub mod frame;
+pub mod receiver;
+pub mod sender;
+pub mod serial;
+
+pub use receiver::Receiver;
+pub use sender::Sender;
+pub use serial::SerialSender;
+
+/// The receiver's answer to every request, and to status polls.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Reply {
+    /// `ok <last> <free>`: every line up to and including `last` is queued.
+    Ok { last: u32, free: usize },
+
+    /// `rs <from> <free>`: line `from` was corrupt or missing, send again from there.
+    Resend { from: u32, free: usize },
+
+    /// `er <line> <free>`: the machine could not run line `line` and dropped the lines after it.
+    /// Nothing more is queued until the sender resets the stream.
+    Fault { line: u32, free: usize },
+}
+
+impl Reply {
+    /// Free planner slots, the most lines the sender may have in flight.
+    pub fn free(&self) -> usize {
+        match *self {
+            Self::Ok { free, .. } | Self::Resend { free, .. } | Self::Fault { free, .. } => free,
+        }
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok { last, free } => write!(f, "ok {} {}", last, free),
+            Self::Resend { from, free } => write!(f, "rs {} {}", from, free),
+            Self::Fault { line, free } => write!(f, "er {} {}", line, free),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseReplyError(pub String);
+
+impl fmt::Display for ParseReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not a stream reply: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseReplyError {}
+
+impl FromStr for Reply {
+    type Err = ParseReplyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseReplyError(s.to_owned());
+        let mut words = s.split_whitespace();
+        let kind = words.next().ok_or_else(error)?;
+        let line = words
+            .next()
+            .and_then(|w| w.parse().ok())
+            .ok_or_else(error)?;
+        let free = words
+            .next()
+            .and_then(|w| w.parse().ok())
+            .ok_or_else(error)?;
+        if words.next().is_some() {
+            return Err(error());
+        }
+        match kind {
+            "ok" => Ok(Self::Ok { last: line, free }),
+            "rs" => Ok(Self::Resend { from: line, free }),
+            "er" => Ok(Self::Fault { line, free }),
+            _ => Err(error()),
+        }
+    }
+}
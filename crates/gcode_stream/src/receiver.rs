@@ -0,0 +1,115 @@
+//! The firmware end: checks frames and queues lines strictly in order.
+
+use std::collections::VecDeque;
+
+use crate::frame::{self, RESET};
+use crate::Reply;
+
+/// Queues received lines for the planner, in sequence order and each exactly once.
+#[derive(Clone, Debug)]
+pub struct Receiver {
+    /// Sequence number of the next line to queue.
+    expected: u32,
+
+    /// Planner slots: the most lines queued or running at once.
+    capacity: usize,
+
+    lines: VecDeque<String>,
+
+    /// Lines handed out by [`Self::pop`] whose moves have not finished yet.
+    running: usize,
+
+    /// Sequence number of the last line handed out by [`Self::pop`].
+    popped: u32,
+
+    /// The line the planner could not run, until the sender resets the stream.
+    fault: Option<u32>,
+}
+
+impl Receiver {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            expected: 1,
+            capacity,
+            lines: VecDeque::with_capacity(capacity),
+            running: 0,
+            popped: 0,
+            fault: None,
+        }
+    }
+
+    /// Handles one request holding frames separated by newlines.
+    ///
+    /// Lines already queued are skipped, so a sender may always resend. A corrupt frame or a gap
+    /// asks for a resend and drops the rest of the request. Frames that do not fit are dropped
+    /// too; the reply tells the sender where to carry on. After a fault only a reset is taken.
+    pub fn receive(&mut self, request: &str) -> Reply {
+        for text in request.lines().filter(|text| !text.trim().is_empty()) {
+            let Ok((sequence, line)) = frame::decode(text) else {
+                return self.resend();
+            };
+            if line == RESET {
+                self.expected = sequence.wrapping_add(1);
+                self.fault = None;
+            } else if self.fault.is_some() {
+                break;
+            } else if sequence < self.expected {
+                // Already queued: the reply to an earlier request was lost.
+            } else if sequence > self.expected {
+                return self.resend();
+            } else if self.free() > 0 {
+                self.lines.push_back(line.to_owned());
+                self.expected += 1;
+            } else {
+                break;
+            }
+        }
+        self.status()
+    }
+
+    /// How far the receiver has got, the answer to a status poll.
+    pub fn status(&self) -> Reply {
+        if let Some(line) = self.fault {
+            return Reply::Fault {
+                line,
+                free: self.free(),
+            };
+        }
+        Reply::Ok {
+            last: self.expected.wrapping_sub(1),
+            free: self.free(),
+        }
+    }
+
+    fn resend(&self) -> Reply {
+        Reply::Resend {
+            from: self.expected,
+            free: self.free(),
+        }
+    }
+
+    /// Free planner slots.
+    pub fn free(&self) -> usize {
+        self.capacity - self.lines.len() - self.running
+    }
+
+    /// The next line for the planner. Its slot stays taken until [`Self::finish`].
+    pub fn pop(&mut self) -> Option<String> {
+        let line = self.lines.pop_front()?;
+        self.popped = self.expected.wrapping_sub(self.lines.len() as u32 + 1);
+        self.running += 1;
+        Some(line)
+    }
+
+    /// The planner is done with the oldest line it popped, so its slot is free again.
+    pub fn finish(&mut self) {
+        self.running = self.running.saturating_sub(1);
+    }
+
+    /// The planner could not run the last line it popped. The lines queued after it are
+    /// dropped, and the sender is told until it resets the stream.
+    pub fn fail(&mut self) {
+        self.fault = Some(self.popped);
+        self.lines.clear();
+    }
+}
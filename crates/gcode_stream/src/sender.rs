@@ -0,0 +1,122 @@
+//! The host end: sends lines in windows the receiver has room for, and resends on failure.
+
+use std::fmt;
+
+use crate::frame::{self, RESET};
+use crate::Reply;
+
+/// Lines sent in one request at most, whatever the receiver has room for.
+pub const MAX_BATCH: usize = 32;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StreamError {
+    /// The receiver has fewer lines than it acknowledged before, so it must have restarted and
+    /// the lines in its planner are gone.
+    Restarted { acked: usize, reported: usize },
+
+    /// The machine could not run a line, and stopped taking more.
+    Fault { line: usize },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Restarted { acked, reported } => write!(
+                f,
+                "the machine restarted: it had {} lines but now reports {}",
+                acked, reported
+            ),
+            Self::Fault { line } => write!(f, "the machine could not run line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Streams a program to one [`crate::Receiver`].
+///
+/// Call [`Self::next_request`] and send what it returns, then pass the reply to
+/// [`Self::handle_reply`] or, if the request failed, call [`Self::handle_failure`]. When there
+/// is nothing to send the receiver is full: poll its status instead.
+#[derive(Clone, Debug)]
+pub struct Sender {
+    lines: Vec<String>,
+
+    /// Lines the receiver has queued. Line `n` has sequence number `n + 1`.
+    acked: usize,
+
+    /// Free slots in the receiver when it last replied.
+    free: usize,
+
+    /// Whether the receiver has restarted its numbering for this program.
+    synced: bool,
+}
+
+impl Sender {
+    pub fn new(lines: Vec<String>) -> Self {
+        Self {
+            lines,
+            acked: 0,
+            free: 0,
+            synced: false,
+        }
+    }
+
+    /// The next request to send, or `None` when done or when the receiver is full.
+    ///
+    /// Starts with a lone reset, then sends from the first line not yet acknowledged.
+    pub fn next_request(&self) -> Option<String> {
+        if !self.synced {
+            return Some(frame::encode(0, RESET));
+        }
+        let count = self.free.min(MAX_BATCH).min(self.lines.len() - self.acked);
+        if count == 0 {
+            return None;
+        }
+        let frames: Vec<String> = (self.acked..self.acked + count)
+            .map(|index| frame::encode(index as u32 + 1, &self.lines[index]))
+            .collect();
+        Some(frames.join("\n"))
+    }
+
+    pub fn handle_reply(&mut self, reply: Reply) -> Result<(), StreamError> {
+        let done = match reply {
+            Reply::Ok { last, .. } => last as usize,
+            Reply::Resend { from, .. } => from.saturating_sub(1) as usize,
+            Reply::Fault { line, .. } => {
+                return Err(StreamError::Fault {
+                    line: line as usize,
+                })
+            }
+        };
+        self.free = reply.free();
+        if !self.synced {
+            // Only a fresh receiver proves the reset arrived, not one left over from before.
+            self.synced = done == 0;
+        } else if done < self.acked {
+            return Err(StreamError::Restarted {
+                acked: self.acked,
+                reported: done,
+            });
+        } else {
+            self.acked = done.min(self.lines.len());
+        }
+        Ok(())
+    }
+
+    /// The request or its reply got lost. Everything unacknowledged goes again, which is safe
+    /// because the receiver skips lines it already has.
+    pub fn handle_failure(&mut self) {
+        // Send at least one line to learn how things stand.
+        self.free = self.free.max(1);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.synced && self.acked == self.lines.len()
+    }
+
+    /// Lines acknowledged and the total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.acked, self.lines.len())
+    }
+}
@@ -0,0 +1,265 @@
+//! The host end of a plain serial link, as Marlin, RepRapFirmware and grbl speak it: one line at
+//! a time, each answered with `ok` before the next goes out.
+//!
+//! Framed lines carry a sequence number and checksum. A framed line that arrives damaged or out
+//! of order gets `Resend: <n>` and then `ok`, after which sending goes on from line `n`. grbl
+//! takes no framing and answers a line it cannot run with `error:<code>` instead of `ok`.
+//!
+//! Like [`crate::Sender`], this does no I/O: write what [`SerialSender::next_line`] returns and
+//! pass every line the firmware prints to [`SerialSender::handle_line`].
+
+use std::fmt;
+
+use crate::frame::{self, RESET};
+
+/// What one line printed by the firmware means.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Response {
+    /// `ok`, possibly followed by more, as in `ok T:210.0 /210.0`.
+    Ok,
+
+    /// `Resend: <n>` or `rs N<n>`: send again from line `n`.
+    Resend(u32),
+
+    /// `Error:<message>`, which Marlin and RepRapFirmware follow with a resend or an `ok`.
+    Error(String),
+
+    /// grbl's `error:<code>`, which answers the line in place of `ok`.
+    Rejected(String),
+
+    /// `start`: the firmware has just booted.
+    Start,
+
+    /// Anything else: echoes, temperatures, `busy:` notices.
+    Message(String),
+}
+
+impl Response {
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        if lower == "ok" || lower.starts_with("ok ") {
+            return Self::Ok;
+        }
+        if lower == "start" {
+            return Self::Start;
+        }
+        for prefix in ["resend:", "rs "] {
+            if let Some(rest) = lower.strip_prefix(prefix) {
+                let digits = rest.trim().trim_start_matches('n');
+                if let Ok(from) = digits.parse() {
+                    return Self::Resend(from);
+                }
+            }
+        }
+        if lower.starts_with("error:") {
+            let message = line["error:".len()..].trim();
+            return if !message.is_empty() && message.bytes().all(|b| b.is_ascii_digit()) {
+                Self::Rejected(message.to_owned())
+            } else {
+                Self::Error(message.to_owned())
+            };
+        }
+        Self::Message(line.to_owned())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SerialError {
+    /// The firmware booted in the middle of the program and lost its place.
+    Restarted { acked: usize },
+
+    /// grbl refused line `line`, counting from 1.
+    Rejected { line: usize, code: String },
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Restarted { acked } => {
+                write!(f, "the machine restarted after {} lines", acked)
+            }
+            Self::Rejected { line, code } => {
+                write!(f, "the machine refused line {} with error {}", line, code)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SerialError {}
+
+/// Sends a program over a serial link, one line per `ok`.
+#[derive(Clone, Debug)]
+pub struct SerialSender {
+    /// The program without comments or blank lines, which firmware does not answer.
+    lines: Vec<String>,
+
+    /// Send sequence numbers and checksums, starting with a reset.
+    framed: bool,
+
+    /// Lines answered with `ok`. Line `n` has sequence number `n + 1`.
+    acked: usize,
+
+    /// Whether the last line written is still waiting for its answer.
+    waiting: bool,
+
+    /// Where to go on after the `ok` that follows a resend request.
+    resend: Option<usize>,
+
+    /// Whether the firmware has restarted its numbering. Unframed links have nothing to restart.
+    synced: bool,
+}
+
+impl SerialSender {
+    pub fn new(lines: Vec<String>, framed: bool) -> Self {
+        let lines = lines
+            .iter()
+            .map(|line| frame::strip_comment(line))
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect();
+        Self {
+            lines,
+            framed,
+            acked: 0,
+            waiting: false,
+            resend: None,
+            synced: !framed,
+        }
+    }
+
+    /// The next line to write, without its newline, or `None` while the last one waits for its
+    /// answer or when done.
+    pub fn next_line(&mut self) -> Option<String> {
+        if self.waiting || self.is_done() {
+            return None;
+        }
+        self.waiting = true;
+        Some(if !self.synced {
+            frame::encode(0, RESET)
+        } else if self.framed {
+            frame::encode(self.acked as u32 + 1, &self.lines[self.acked])
+        } else {
+            self.lines[self.acked].clone()
+        })
+    }
+
+    /// Takes in one line printed by the firmware and says what it was.
+    pub fn handle_line(&mut self, line: &str) -> Result<Response, SerialError> {
+        let response = Response::parse(line);
+        match &response {
+            Response::Ok if self.waiting => {
+                self.waiting = false;
+                match self.resend.take() {
+                    Some(from) if self.synced => self.acked = from,
+                    // The reset itself was garbled, so it goes again.
+                    Some(_) => {}
+                    None if !self.synced => self.synced = true,
+                    None => self.acked += 1,
+                }
+            }
+            Response::Resend(from) if self.framed => {
+                let from = (*from as usize).saturating_sub(1);
+                self.resend = Some(from.min(self.acked));
+            }
+            Response::Rejected(code) if self.waiting => {
+                self.waiting = false;
+                return Err(SerialError::Rejected {
+                    line: self.acked + 1,
+                    code: code.clone(),
+                });
+            }
+            Response::Start if self.synced && (self.acked > 0 || self.waiting) => {
+                return Err(SerialError::Restarted { acked: self.acked });
+            }
+            Response::Start => {
+                // Booted while the reset was on its way: send it again.
+                self.waiting = false;
+                self.resend = None;
+            }
+            _ => {}
+        }
+        Ok(response)
+    }
+
+    /// Nothing came back for too long. A framed line goes again, since the firmware asks for
+    /// whatever it already had to be resent. An unframed one would run twice, so it keeps waiting.
+    pub fn handle_timeout(&mut self) {
+        if self.framed {
+            self.waiting = false;
+            self.resend = None;
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.synced && !self.waiting && self.acked == self.lines.len()
+    }
+
+    /// Lines acknowledged and the total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.acked, self.lines.len())
+    }
+}
+
+#[test]
+fn test_serial() {
+    assert_eq!(Response::parse("ok T:210.0 /210.0"), Response::Ok);
+    assert_eq!(Response::parse("Resend: 5"), Response::Resend(5));
+    assert_eq!(Response::parse("rs N5"), Response::Resend(5));
+    assert_eq!(
+        Response::parse("Error:checksum mismatch, Last Line: 4"),
+        Response::Error("checksum mismatch, Last Line: 4".to_owned())
+    );
+    assert_eq!(
+        Response::parse("error:20"),
+        Response::Rejected("20".to_owned())
+    );
+    assert_eq!(
+        Response::parse("echo:busy: processing"),
+        Response::Message("echo:busy: processing".to_owned())
+    );
+
+    let program = vec!["G28 ; home".to_owned(), String::new(), "G1 X10".to_owned()];
+    let mut sender = SerialSender::new(program.clone(), true);
+    assert_eq!(sender.next_line(), Some(frame::encode(0, RESET)));
+    assert_eq!(sender.next_line(), None);
+    sender.handle_line("ok").unwrap();
+    assert_eq!(sender.next_line(), Some(frame::encode(1, "G28")));
+    sender.handle_line("echo:busy: processing").unwrap();
+    sender.handle_line("ok").unwrap();
+    assert_eq!(sender.next_line(), Some(frame::encode(2, "G1 X10")));
+
+    // Line 2 arrives damaged and is asked for again.
+    sender
+        .handle_line("Error:checksum mismatch, Last Line: 1")
+        .unwrap();
+    sender.handle_line("Resend: 2").unwrap();
+    sender.handle_line("ok").unwrap();
+    assert_eq!(sender.next_line(), Some(frame::encode(2, "G1 X10")));
+
+    // Its answer is lost, so it goes again after the timeout.
+    sender.handle_timeout();
+    assert_eq!(sender.next_line(), Some(frame::encode(2, "G1 X10")));
+    sender.handle_line("ok").unwrap();
+    assert!(sender.is_done());
+    assert_eq!(sender.progress(), (2, 2));
+    assert_eq!(
+        sender.handle_line("start"),
+        Err(SerialError::Restarted { acked: 2 })
+    );
+
+    // grbl: no framing, no resending and errors in place of `ok`.
+    let mut sender = SerialSender::new(program, false);
+    assert_eq!(sender.next_line(), Some("G28".to_owned()));
+    sender.handle_timeout();
+    assert_eq!(sender.next_line(), None);
+    sender.handle_line("ok").unwrap();
+    assert_eq!(sender.next_line(), Some("G1 X10".to_owned()));
+    assert_eq!(
+        sender.handle_line("error:20"),
+        Err(SerialError::Rejected {
+            line: 2,
+            code: "20".to_owned()
+        })
+    );
+}
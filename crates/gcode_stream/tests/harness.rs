@@ -0,0 +1,202 @@
+//! Streams programs through a link that loses, corrupts and repeats requests and replies, and
+//! checks the receiver queues every line once, in order.
+
+use gcode_stream::{Receiver, Reply, Sender};
+
+/// A small xorshift generator, so failures repeat.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// True one time in `n`.
+    fn one_in(&mut self, n: u64) -> bool {
+        self.next() % n == 0
+    }
+}
+
+/// What can go wrong with one request, one time in `odds` each.
+struct Link {
+    rng: Rng,
+    odds: u64,
+}
+
+impl Link {
+    /// Delivers a request, possibly damaged, and its reply, unless either is lost.
+    fn exchange(&mut self, receiver: &mut Receiver, request: Option<String>) -> Option<Reply> {
+        let Some(mut request) = request else {
+            return Some(receiver.status());
+        };
+        if self.rng.one_in(self.odds) {
+            return None;
+        }
+        if self.rng.one_in(self.odds) && !request.is_empty() {
+            // Flip a bit of one byte, keeping the text ASCII.
+            let mut bytes = request.into_bytes();
+            let index = (self.rng.next() % bytes.len() as u64) as usize;
+            bytes[index] ^= 1 << (self.rng.next() % 7);
+            request = String::from_utf8(bytes).unwrap();
+        }
+        if self.rng.one_in(self.odds) {
+            // Dropped frames leave a gap.
+            let lines: Vec<&str> = request.lines().collect();
+            let skip = (self.rng.next() % lines.len() as u64) as usize;
+            request = lines
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != skip)
+                .map(|(_, line)| *line)
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        let reply = receiver.receive(&request);
+        if self.rng.one_in(self.odds) {
+            // The request arrived but the reply did not: the sender will repeat it.
+            return None;
+        }
+        Some(reply)
+    }
+}
+
+fn program(length: usize) -> Vec<String> {
+    (0..length)
+        .map(|index| format!("G1 X{} Y{} F{}", index, index * 2, 100 + index))
+        .collect()
+}
+
+/// Streams `lines`, with the planner running `speed` lines per exchange.
+fn stream(lines: &[String], capacity: usize, speed: usize, link: &mut Link) -> Vec<String> {
+    let mut sender = Sender::new(lines.to_vec());
+    let mut receiver = Receiver::new(capacity);
+    let mut executed = vec![];
+    let mut exchanges = 0;
+    while !sender.is_done() {
+        exchanges += 1;
+        assert!(exchanges < 100_000, "stuck at {:?}", sender.progress());
+
+        match link.exchange(&mut receiver, sender.next_request()) {
+            Some(reply) => sender.handle_reply(reply).unwrap(),
+            None => sender.handle_failure(),
+        }
+        for _ in 0..speed {
+            executed.extend(run(&mut receiver));
+        }
+    }
+    executed.extend(std::iter::from_fn(|| run(&mut receiver)));
+    executed
+}
+
+/// Pops the next line and finishes its move straight away.
+fn run(receiver: &mut Receiver) -> Option<String> {
+    let line = receiver.pop()?;
+    receiver.finish();
+    Some(line)
+}
+
+#[test]
+fn perfect_link() {
+    let lines = program(500);
+    let mut link = Link {
+        rng: Rng(1),
+        odds: u64::MAX,
+    };
+    assert_eq!(stream(&lines, 16, 4, &mut link), lines);
+}
+
+#[test]
+fn lossy_link() {
+    let lines = program(2000);
+    for seed in 1..20 {
+        let mut link = Link {
+            rng: Rng(seed),
+            odds: 5,
+        };
+        assert_eq!(stream(&lines, 8, 3, &mut link), lines, "seed {}", seed);
+    }
+}
+
+#[test]
+fn slow_planner() {
+    // The planner frees one slot every few exchanges, so the sender has to wait for room.
+    let lines = program(100);
+    let mut sender = Sender::new(lines.clone());
+    let mut receiver = Receiver::new(4);
+    let mut executed = vec![];
+    let mut tick = 0;
+    while !sender.is_done() {
+        let reply = match sender.next_request() {
+            Some(request) => {
+                assert!(request.lines().count() <= receiver.free().max(1));
+                receiver.receive(&request)
+            }
+            None => receiver.status(),
+        };
+        sender.handle_reply(reply).unwrap();
+        tick += 1;
+        if tick % 3 == 0 {
+            if let Some(line) = receiver.pop() {
+                // The slot stays taken while the move runs.
+                let free = receiver.free();
+                executed.push(line);
+                receiver.finish();
+                assert_eq!(receiver.free(), free + 1);
+            }
+        }
+    }
+    executed.extend(std::iter::from_fn(|| run(&mut receiver)));
+    assert_eq!(executed, lines);
+}
+
+#[test]
+fn restarted_receiver() {
+    let lines = program(10);
+    let mut sender = Sender::new(lines);
+    let mut receiver = Receiver::new(4);
+    for _ in 0..2 {
+        let reply = receiver.receive(&sender.next_request().unwrap());
+        sender.handle_reply(reply).unwrap();
+    }
+    assert_eq!(sender.progress(), (4, 10));
+
+    // The receiver is full, so the sender polls, and finds a fresh one.
+    assert_eq!(sender.next_request(), None);
+    let reply = Receiver::new(4).status();
+    assert_eq!(reply, Reply::Ok { last: 0, free: 4 });
+    assert!(sender.handle_reply(reply).is_err());
+}
+
+#[test]
+fn failed_line() {
+    let lines = program(10);
+    let mut sender = Sender::new(lines);
+    let mut receiver = Receiver::new(4);
+    for _ in 0..2 {
+        let reply = receiver.receive(&sender.next_request().unwrap());
+        sender.handle_reply(reply).unwrap();
+    }
+
+    // The second line fails: the rest are dropped and nothing more is taken.
+    run(&mut receiver);
+    receiver.pop();
+    receiver.fail();
+    receiver.finish();
+    assert_eq!(receiver.pop(), None);
+    assert_eq!(receiver.status(), Reply::Fault { line: 2, free: 4 });
+    assert_eq!(
+        receiver.receive(&gcode_stream::frame::encode(5, "G0 X5")),
+        Reply::Fault { line: 2, free: 4 }
+    );
+    assert_eq!("er 2 4".parse(), Ok(Reply::Fault { line: 2, free: 4 }));
+    assert!(sender.handle_reply(receiver.status()).is_err());
+
+    // A new program starts afresh.
+    let mut sender = Sender::new(program(3));
+    let reply = receiver.receive(&sender.next_request().unwrap());
+    assert_eq!(reply, Reply::Ok { last: 0, free: 4 });
+    sender.handle_reply(reply).unwrap();
+}